@@ -11,7 +11,7 @@ use crate::{
         self, model::Builder, 
     }, context::GatewayContext, instance::{
         service::{AddressInterface, CacheType, TokioBindCpuType, TokioSettings, TokioType}, GatewayInstance
-    }, modules::{cache::http_cache_cell::HttpCacheKey, upgrade::UpgradeProfile, CommonModule, ModuleType, Modules, PipeTask}, servers::GatewayServer 
+    }, modules::{auth::AuthFile, upgrade::UpgradeProfile, CommonModule, ModuleType, Modules, PipeTask}, servers::GatewayServer 
 };
 use crate::error::{RResult, GatewayError};
 
@@ -215,39 +215,83 @@ impl RockGateway {
         Ok(())
     }
     /*
-    start memory_cache expire clear thread
+    start memory_cache and disk_cache expire clear threads
      */
     fn start_memory_cache_clearthread(&self) -> RResult<()> {
         let instance = self.get_gateway_instance()?;
         for ct in &instance.service.cache {
             match ct {
-                CacheType::Memory { clear_time_interval, .. } => {
+                CacheType::Memory { clear_time_interval, store } => {
                     if clear_time_interval.clone() != std::time::Duration::from_nanos(0) {
-                        let mut caches = Vec::new();
+                        let cache = store.clone();
                         let clear_time_interval_for_clear_thread = clear_time_interval.clone();
-                        for (_, route) in &instance.routes {
-                            caches.push(route.memory_cache_shared.clone());
-                        }
                         let _ = std::thread::Builder::new().name("MEMORY_CACHE_CLEAR".to_string()).spawn(move || {
                             loop {
-                                for cache in &caches {
-                                    cache.lock().retain(|k, _| {
-                                        if HttpCacheKey::is_expire(k) {
-                                            return false;
-                                        }
-                                        true
-                                    });
-                                }
+                                cache.clear_expired();
                                 std::thread::sleep(clear_time_interval_for_clear_thread.clone());
                             }
                         }).unwrap();
                     }
                 },
+                CacheType::Disk { clear_time_interval, store } => {
+                    if !clear_time_interval.is_zero() {
+                        let cache = store.clone();
+                        let interval = *clear_time_interval;
+                        let _ = std::thread::Builder::new().name("DISK_CACHE_CLEAR".to_string()).spawn(move || {
+                            loop {
+                                std::thread::sleep(interval);
+                                cache.clear_expired();
+                            }
+                        }).unwrap();
+                    }
+                },
                 _ => {},
             }
         }
         Ok(())
     }
+    /*
+    start the thread that logs the upstream connection pool stats of the hosts
+     */
+    fn start_pool_stats_thread(&self) -> RResult<()> {
+        let instance = self.get_gateway_instance()?;
+        for (hosts_name, hosts) in &instance.hosts {
+            if let Some(stats_interval) = hosts.pool.settings.stats_interval {
+                if stats_interval.is_zero() {
+                    continue;
+                }
+                let hosts_name = hosts_name.clone();
+                let stats = hosts.pool.stats.clone();
+                let _ = std::thread::Builder::new().name("POOL_STATS".to_string()).spawn(move || {
+                    loop {
+                        std::thread::sleep(stats_interval);
+                        let (created, open, active, idle) = stats.snapshot();
+                        log::info!("pool stats hosts:{} created:{} open:{} active:{} idle:{}", hosts_name, created, open, active, idle);
+                    }
+                }).unwrap();
+            }
+        }
+        Ok(())
+    }
+    /*
+    start the thread that drops the full buckets of the ratelimiters, a key seen once (a header, an api key, a claim) does not stay in memory
+     */
+    fn start_ratelimiter_clear_thread(&self) -> RResult<()> {
+        let instance = self.get_gateway_instance()?;
+        let ratelimiters = instance.service.ratelimiter.iter().chain(instance.routes.values().filter_map(|route| route.ratelimiter.as_ref())).cloned().collect::<Vec<_>>();
+        if ratelimiters.is_empty() {
+            return Ok(());
+        }
+        let _ = std::thread::Builder::new().name("RATELIMITER_CLEAR".to_string()).spawn(move || {
+            loop {
+                std::thread::sleep(crate::util::r#const::RATELIMITER_CLEAR_INTERVAL);
+                for ratelimiter in &ratelimiters {
+                    ratelimiter.retain_recent();
+                }
+            }
+        }).unwrap();
+        Ok(())
+    }
     pub fn spawn<F: std::future::Future<Output=()> + Send + 'static>(&self, f: F) -> RResult<()> {
         // the first option
         // self.runtime.with(|raw| {
@@ -281,6 +325,8 @@ pub(crate) trait RockGatewayEngin {
     fn load(&self, config_file: &str) -> RResult<()>;
     fn run(&mut self, start: std::time::Instant) -> RResult<()>;
     fn run_service(&mut self) -> RResult<()>;
+    fn start_black_white_list_watch(&self) -> RResult<()>;
+    fn start_auth_watch(&self) -> RResult<()>;
 }
 impl RockGatewayEngin for Arc<RockGateway> {
     fn load(&self, config_file: &str) -> RResult<()> {
@@ -293,6 +339,10 @@ impl RockGatewayEngin for Arc<RockGateway> {
     fn run(&mut self, start: std::time::Instant) -> RResult<()> {
         self.run_service()?;
         self.start_memory_cache_clearthread()?;
+        self.start_pool_stats_thread()?;
+        self.start_ratelimiter_clear_thread()?;
+        self.start_black_white_list_watch()?;
+        self.start_auth_watch()?;
         println!("Service started:{:#?}ms", start.elapsed().as_millis());
         self.wait()?;
         log::info!("Over Gateway Server!!!");
@@ -310,4 +360,88 @@ impl RockGatewayEngin for Arc<RockGateway> {
         }
         Ok(())
     }
-}
\ No newline at end of file
+    /*
+    Every second drop the runtime entries of the black and white lists whose ttl is over,
+    and every list_watch_interval swap in the lists of the files that have been modified.
+     */
+    fn start_black_white_list_watch(&self) -> RResult<()> {
+        let watch_interval = self.get_gateway_instance()?.service.list_watch_interval;
+        let gateway = self.clone();
+        self.spawn(async move {
+            let mut last_watch = std::time::Instant::now();
+            loop {
+                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                let watch = !watch_interval.is_zero() && last_watch.elapsed() >= watch_interval;
+                if watch {
+                    last_watch = std::time::Instant::now();
+                }
+                let instance = match gateway.get_instance() {
+                    Ok(instance) => instance,
+                    Err(_) => { continue; }
+                };
+                for (name, profile) in crate::modules::blackandwhitelist::all_lists(&instance) {
+                    let (changed, expired) = {
+                        let profile = profile.read().await;
+                        (watch && profile.files_changed(), profile.has_expired())
+                    };
+                    if !changed && !expired {
+                        continue;
+                    }
+                    let mut profile = profile.write().await;
+                    if expired {
+                        profile.expire();
+                    }
+                    if changed {
+                        match profile.reload_files() {
+                            Ok(_) => { log::info!("black_white_list {} reloaded", name); }
+                            Err(e) => { log::error!("black_white_list {} reload failed, the old list is kept:{}", name, e); }
+                        }
+                    }
+                }
+            }
+        })
+    }
+    /* every list_watch_interval swap in the jwks files of jwt_auth, the htpasswd files of basic_auth and the key files of api_key that have been modified */
+    fn start_auth_watch(&self) -> RResult<()> {
+        let watch_interval = self.get_gateway_instance()?.service.list_watch_interval;
+        if watch_interval.is_zero() {
+            return Ok(());
+        }
+        let gateway = self.clone();
+        self.spawn(async move {
+            loop {
+                tokio::time::sleep(watch_interval).await;
+                let instance = match gateway.get_instance() {
+                    Ok(instance) => instance,
+                    Err(_) => { continue; }
+                };
+                for (name, file) in crate::modules::auth::all_auth_files(&instance) {
+                    let reloaded = match file {
+                        AuthFile::Jwks(profile) => {
+                            if !profile.read().await.jwks_changed() {
+                                continue;
+                            }
+                            profile.write().await.reload_jwks()
+                        }
+                        AuthFile::Htpasswd(profile) => {
+                            if !profile.read().await.file_changed() {
+                                continue;
+                            }
+                            profile.write().await.reload_file()
+                        }
+                        AuthFile::ApiKeys(profile) => {
+                            if !profile.read().await.file_changed() {
+                                continue;
+                            }
+                            profile.write().await.reload_file()
+                        }
+                    };
+                    match reloaded {
+                        Ok(_) => { log::info!("{} auth file reloaded", name); }
+                        Err(e) => { log::error!("{} auth file reload failed, the old one is kept:{}", name, e); }
+                    }
+                }
+            }
+        })
+    }
+}
@@ -6,16 +6,20 @@ file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use crate::{client::ClientProvider, error::{AsyncResult, PipeError, PipeErrorKind, RResult, GatewayError}, gateway_err};
 
-use super::{ClientHandlerInterface, HttpsRedirect, RequestContent, ResponseContent};
+use std::sync::Arc;
+
+use super::{pool::{ActiveGuard, PoolStats}, ClientHandlerInterface, HttpsRedirect, RequestContent, ResponseContent};
 
 #[derive(Debug, Clone)]
 pub(crate) struct HttpClient {
     pub(crate) redirect: HttpsRedirect,
+    pub(crate) stats: Arc<PoolStats>,
 }
 impl HttpClient {
     pub(crate) fn send(&self, request: RequestContent, timeout: std::time::Duration) -> AsyncResult<RResult<ResponseContent>> {
         let async_block = async move {
             if let RequestContent::Http(req) = request {
+                let _active = ActiveGuard::new(&self.stats);
                 // println!("HttpClient:request:{:#?}", req);
                 match tokio::time::timeout(timeout, self.redirect.request(req)).await {
                     Ok(ret) => {
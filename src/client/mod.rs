@@ -13,14 +13,15 @@ use hyper::body::Incoming;
 use crate::{entitys::buf::DataBuf, gateway_err, util::j_unsafecell::JUnsafeCell};
 use crate::error::{RResult, AsyncResult, BuilderErrorKind, BuilderError, GatewayError};
 
-use self::{http::HttpClient, tcp::{sender::TcpSenderConnection, TcpClient}};
+use self::{http::HttpClient, pool::{CountedConnector, UpstreamPool}, tcp::{sender::TcpSenderConnection, TcpClient}};
 use self::https::HttpsClient;
 
 pub mod https;
 pub mod http;
 pub mod tcp;
+pub mod pool;
 
-type HttpsRedirect = hyper_util::client::legacy::Client<hyper_rustls::HttpsConnector<hyper_util::client::legacy::connect::HttpConnector>, Full<Bytes>>;
+type HttpsRedirect = hyper_util::client::legacy::Client<hyper_rustls::HttpsConnector<CountedConnector>, Full<Bytes>>;
 
 #[derive(Debug, Clone)]
 pub(crate) enum ClientProvider {
@@ -45,6 +46,7 @@ pub(crate) enum RequestContent {
         Arc<tokio::sync::mpsc::Sender<DataBuf>>,
         Arc<tokio::sync::mpsc::Sender<DataBuf>>,
         Arc<tokio::sync::RwLock<tokio::sync::mpsc::Receiver<DataBuf>>>,
+        Option<Vec<u8>>,    //PROXY protocol header written once the connection is made
     )),
 }
 pub(crate) enum ResponseContent {
@@ -54,12 +56,16 @@ pub(crate) enum ResponseContent {
 
 impl ClientProvider {
     pub(crate) fn new(client_type: &str, buf_size: usize) -> RResult<Self> {
+        Self::with_pool(client_type, buf_size, &UpstreamPool::default())
+    }
+    /* the http and https clients share the connections of the pool */
+    pub(crate) fn with_pool(client_type: &str, buf_size: usize, pool: &UpstreamPool) -> RResult<Self> {
         match client_type {
             crate::util::r#const::HTTP => {
-                Ok(Self::Http { client_handler: HttpClient{ redirect: ClientProvider::make_https_client() }, })
+                Ok(Self::Http { client_handler: HttpClient{ redirect: pool.client.clone(), stats: pool.stats.clone() }, })
             }
             crate::util::r#const::HTTPS => {
-                Ok(Self::Https { client_handler: HttpsClient{ redirect: ClientProvider::make_https_client() }, })
+                Ok(Self::Https { client_handler: HttpsClient{ redirect: pool.client.clone(), stats: pool.stats.clone() }, })
             }
             crate::util::r#const::TCP => {
                 Ok(Self::Tcp { client_handler: TcpClient{ redirect: ClientProvider::make_tcp_client( buf_size), } })
@@ -86,17 +92,8 @@ impl ClientProvider {
         -> Arc<TcpSenderConnection> {
         Arc::new(TcpSenderConnection::new(buf_size))
     }
-    pub(crate) fn make_https_client() 
-        -> hyper_util::client::legacy::Client<hyper_rustls::HttpsConnector<hyper_util::client::legacy::connect::HttpConnector>, Full<Bytes>> {
-        let connection = hyper_rustls::HttpsConnectorBuilder::new()
-            .with_webpki_roots()
-            .https_or_http()
-            .enable_http1()
-            .enable_http2()
-            .build();
-        hyper_util::client::legacy::Client::builder(
-            crate::servers::tokiort::TokioExecutor::new()
-        ).build(connection)
+    pub(crate) fn make_https_client() -> HttpsRedirect {
+        UpstreamPool::default().client
     }
     pub(crate) async fn parse_response(response: Response<Incoming>) -> RResult<(StatusCode, Version, HeaderMap, Option<Bytes>)> {
         let (parts, mut incoming) = response.into_parts();
@@ -0,0 +1,181 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public
+License, v. 2.0. If a copy of the MPL was not distributed with this
+file, You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+use std::{future::Future, pin::Pin, sync::{atomic::{AtomicI64, AtomicU64, Ordering}, Arc}, task::{Context, Poll}, time::Duration};
+
+use http_body_util::Full;
+use hyper::{body::Bytes, Uri};
+use hyper_util::{client::legacy::connect::{Connected, Connection, HttpConnector}, rt::{TokioIo, TokioTimer}};
+use tokio::net::TcpStream;
+
+use super::HttpsRedirect;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum PoolVersion {
+    Auto,   //http2 if the host_point agrees through ALPN, otherwise http1
+    Http1,
+    Http2,
+}
+/* The settings of the upstream connection pool of one hosts. */
+#[derive(Debug, Clone)]
+pub(crate) struct PoolSettings {
+    pub(crate) max_idle_per_host: usize,
+    pub(crate) idle_timeout: Option<Duration>,
+    pub(crate) version: PoolVersion,
+    pub(crate) keepalive: Option<Duration>,
+    pub(crate) nodelay: bool,
+    pub(crate) connect_timeout: Option<Duration>,
+    pub(crate) stats_interval: Option<Duration>,
+}
+impl Default for PoolSettings {
+    fn default() -> Self {
+        Self {
+            max_idle_per_host: usize::MAX,
+            idle_timeout: Some(Duration::from_secs(90)),
+            version: PoolVersion::Auto,
+            keepalive: None,
+            nodelay: false,
+            connect_timeout: None,
+            stats_interval: None,
+        }
+    }
+}
+#[derive(Debug, Default)]
+pub(crate) struct PoolStats {
+    pub(crate) created: AtomicU64,  //connections created since the start
+    pub(crate) open: AtomicI64,     //connections currently open
+    pub(crate) active: AtomicI64,   //requests currently in flight
+}
+impl PoolStats {
+    /* (created, open, active, idle). With http2 several requests share one connection, so idle is only an estimate. */
+    pub(crate) fn snapshot(&self) -> (u64, i64, i64, i64) {
+        let created = self.created.load(Ordering::Relaxed);
+        let open = self.open.load(Ordering::Relaxed);
+        let active = self.active.load(Ordering::Relaxed);
+        let idle = if open > active { open - active } else { 0 };
+        (created, open, active, idle)
+    }
+}
+/* The client shared by every dispatche that sends requests to the same hosts. Cloning shares the connections. */
+#[derive(Debug, Clone)]
+pub(crate) struct UpstreamPool {
+    pub(crate) client: HttpsRedirect,
+    pub(crate) stats: Arc<PoolStats>,
+    pub(crate) settings: PoolSettings,
+}
+impl UpstreamPool {
+    pub(crate) fn new(settings: PoolSettings) -> Self {
+        let stats = Arc::new(PoolStats::default());
+        let mut http_connector = HttpConnector::new();
+        http_connector.enforce_http(false);
+        http_connector.set_keepalive(settings.keepalive);
+        http_connector.set_nodelay(settings.nodelay);
+        http_connector.set_connect_timeout(settings.connect_timeout);
+        let connector = CountedConnector { inner: http_connector, stats: stats.clone() };
+        let builder = hyper_rustls::HttpsConnectorBuilder::new()
+            .with_webpki_roots()
+            .https_or_http();
+        let connection = match settings.version {
+            PoolVersion::Auto => { builder.enable_http1().enable_http2().wrap_connector(connector) }
+            PoolVersion::Http1 => { builder.enable_http1().wrap_connector(connector) }
+            PoolVersion::Http2 => { builder.enable_http2().wrap_connector(connector) }
+        };
+        let mut client_builder = hyper_util::client::legacy::Client::builder(
+            crate::servers::tokiort::TokioExecutor::new()
+        );
+        client_builder
+            .pool_timer(TokioTimer::new())
+            .pool_max_idle_per_host(settings.max_idle_per_host)
+            .pool_idle_timeout(settings.idle_timeout)
+            .http2_only(settings.version == PoolVersion::Http2);
+        let client = client_builder.build::<_, Full<Bytes>>(connection);
+        Self { client, stats, settings }
+    }
+}
+impl Default for UpstreamPool {
+    fn default() -> Self {
+        Self::new(PoolSettings::default())
+    }
+}
+/* Wraps the HttpConnector to count the connections of the pool. */
+#[derive(Debug, Clone)]
+pub(crate) struct CountedConnector {
+    inner: HttpConnector,
+    stats: Arc<PoolStats>,
+}
+impl tower_service::Service<Uri> for CountedConnector {
+    type Response = CountedStream;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<CountedStream, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(|e| e.into())
+    }
+    fn call(&mut self, dst: Uri) -> Self::Future {
+        let connecting = self.inner.call(dst);
+        let stats = self.stats.clone();
+        Box::pin(async move {
+            let io = connecting.await.map_err(|e| -> BoxError { e.into() })?;
+            stats.created.fetch_add(1, Ordering::Relaxed);
+            stats.open.fetch_add(1, Ordering::Relaxed);
+            Ok(CountedStream { inner: io, stats })
+        })
+    }
+}
+#[derive(Debug)]
+pub(crate) struct CountedStream {
+    inner: TokioIo<TcpStream>,
+    stats: Arc<PoolStats>,
+}
+impl Drop for CountedStream {
+    fn drop(&mut self) {
+        self.stats.open.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+impl Connection for CountedStream {
+    fn connected(&self) -> Connected {
+        self.inner.connected()
+    }
+}
+impl hyper::rt::Read for CountedStream {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: hyper::rt::ReadBufCursor<'_>) -> Poll<Result<(), std::io::Error>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+impl hyper::rt::Write for CountedStream {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize, std::io::Error>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), std::io::Error>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), std::io::Error>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+    fn poll_write_vectored(mut self: Pin<&mut Self>, cx: &mut Context<'_>, bufs: &[std::io::IoSlice<'_>]) -> Poll<Result<usize, std::io::Error>> {
+        Pin::new(&mut self.inner).poll_write_vectored(cx, bufs)
+    }
+}
+/* Keeps the number of requests in flight while it is alive. */
+pub(crate) struct ActiveGuard {
+    stats: Arc<PoolStats>,
+}
+impl ActiveGuard {
+    pub(crate) fn new(stats: &Arc<PoolStats>) -> Self {
+        stats.active.fetch_add(1, Ordering::Relaxed);
+        Self { stats: stats.clone() }
+    }
+}
+impl Drop for ActiveGuard {
+    fn drop(&mut self) {
+        self.stats.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -26,9 +26,10 @@ impl TcpClient {
                 data_buf, 
                 sender, 
                 in_tx, 
-                in_rx)) = request {
+                in_rx,
+                proxy_header)) = request {
                 if !redirect.is_connected().await {
-                    redirect.connect(addr).await?;
+                    redirect.connect(addr, proxy_header).await?;
                     let read_loop = self.redirect.clone();
                     tokio::task::spawn(async move {
                         match read_loop.start_read_loop(sender, in_rx, timeout).await {
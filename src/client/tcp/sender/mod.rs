@@ -40,7 +40,7 @@ impl TcpSenderConnection {
         *self_stream = None;
         *self_dst_addr = None;
     }
-    pub(crate) async fn connect(&self, dst_addr: SocketAddr) -> RResult<()> {
+    pub(crate) async fn connect(&self, dst_addr: SocketAddr, proxy_header: Option<Vec<u8>>) -> RResult<()> {
         let mut self_stream = self.stream.write().await;
         let mut self_dst_addr = self.dst_addr.write().await;
         *self_dst_addr = Some(dst_addr);
@@ -48,7 +48,10 @@ impl TcpSenderConnection {
         match tokio::time::timeout(std::time::Duration::from_millis(1000 * 5), TcpStream::connect(self_dst_addr.unwrap())).await {
             Ok(connect_ret) => {
                 match connect_ret {
-                    Ok(stream) => {
+                    Ok(mut stream) => {
+                        if let Some(header) = proxy_header {
+                            stream.write_all(&header).await?;
+                        }
                         *self_stream = Some(stream);
                     },
                     Err(e) => {
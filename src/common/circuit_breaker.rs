@@ -0,0 +1,290 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public
+License, v. 2.0. If a copy of the MPL was not distributed with this
+file, You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+use std::{sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+/* The settings of the circuit breaker shared by all the host_points of one hosts. */
+#[derive(Debug)]
+pub(crate) struct BreakerProfile {
+    pub(crate) consecutive_5xx: Option<u32>,    //trip after so many 5xx responses in a row
+    pub(crate) error_rate: Option<u32>,         //trip when the percentage of failures within the window reaches this value
+    pub(crate) window: Duration,
+    pub(crate) min_requests: u32,               //the error_rate is only judged when the window has at least so many requests
+    pub(crate) half_open_trials: u32,           //how many trial requests are let through in half-open, and how many must succeed to close again
+    pub(crate) max_ejection_time: Duration,
+}
+impl Default for BreakerProfile {
+    fn default() -> Self {
+        Self {
+            consecutive_5xx: None,
+            error_rate: None,
+            window: crate::util::r#const::BREAKER_WINDOW,
+            min_requests: crate::util::r#const::BREAKER_MIN_REQUESTS,
+            half_open_trials: 1,
+            max_ejection_time: crate::util::r#const::BREAKER_MAX_EJECTION_TIME,
+        }
+    }
+}
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+#[derive(Debug)]
+struct BreakerStatus {
+    state: CircuitState,
+    consecutive_5xx: u32,
+    window_start: Instant,
+    window_total: u32,
+    window_failed: u32,
+    half_open_since: Instant,
+    half_open_in_flight: u32,
+    half_open_success: u32,
+    ejections: u32,
+}
+#[derive(Debug)]
+pub(crate) struct CircuitBreaker {
+    profile: Arc<BreakerProfile>,
+    status: Mutex<BreakerStatus>,
+}
+impl CircuitBreaker {
+    pub(crate) fn new(profile: Arc<BreakerProfile>) -> Self {
+        let now = Instant::now();
+        Self {
+            profile,
+            status: Mutex::new(BreakerStatus {
+                state: CircuitState::Closed,
+                consecutive_5xx: 0,
+                window_start: now,
+                window_total: 0,
+                window_failed: 0,
+                half_open_since: now,
+                half_open_in_flight: 0,
+                half_open_success: 0,
+                ejections: 0,
+            }),
+        }
+    }
+    pub(crate) fn state(&self) -> CircuitState {
+        self.status.lock().unwrap().state
+    }
+    /* whether the balancer may choose this host_point right now */
+    pub(crate) fn available(&self) -> bool {
+        let mut status = self.status.lock().unwrap();
+        match status.state {
+            CircuitState::Closed => { true }
+            CircuitState::Open => { false }
+            CircuitState::HalfOpen => {
+                /* a trial request that never reported back (e.g. the client went away) must not block the host_point forever */
+                if status.half_open_in_flight >= self.profile.half_open_trials && status.half_open_since.elapsed() > self.profile.window {
+                    status.half_open_in_flight = 0;
+                    status.half_open_since = Instant::now();
+                }
+                status.half_open_in_flight < self.profile.half_open_trials
+            }
+        }
+    }
+    /* called once the balancer has chosen this host_point */
+    pub(crate) fn acquire(&self) {
+        let mut status = self.status.lock().unwrap();
+        if status.state == CircuitState::HalfOpen {
+            status.half_open_in_flight += 1;
+        }
+    }
+    pub(crate) fn on_success(&self) {
+        let mut status = self.status.lock().unwrap();
+        match status.state {
+            CircuitState::Closed => {
+                status.consecutive_5xx = 0;
+                self.roll_window(&mut status);
+                status.window_total += 1;
+            }
+            CircuitState::HalfOpen => {
+                status.half_open_in_flight = status.half_open_in_flight.saturating_sub(1);
+                status.half_open_success += 1;
+                if status.half_open_success >= self.profile.half_open_trials {
+                    log::info!("circuit breaker closed");
+                    status.state = CircuitState::Closed;
+                    status.consecutive_5xx = 0;
+                    status.window_start = Instant::now();
+                    status.window_total = 0;
+                    status.window_failed = 0;
+                }
+            }
+            CircuitState::Open => {}
+        }
+    }
+    /*
+    server_error: the failure is a 5xx response, otherwise it is a network error.
+    return true if the host_point has to be ejected.
+     */
+    pub(crate) fn on_failure(&self, server_error: bool) -> bool {
+        let mut status = self.status.lock().unwrap();
+        match status.state {
+            CircuitState::Closed => {
+                self.roll_window(&mut status);
+                status.window_total += 1;
+                status.window_failed += 1;
+                if server_error {
+                    status.consecutive_5xx += 1;
+                    if let Some(c) = self.profile.consecutive_5xx {
+                        if status.consecutive_5xx >= c {
+                            return true;
+                        }
+                    }
+                }
+                if let Some(rate) = self.profile.error_rate {
+                    if status.window_total >= self.profile.min_requests && status.window_failed * 100 >= rate * status.window_total {
+                        return true;
+                    }
+                }
+                false
+            }
+            CircuitState::HalfOpen => {    // any failure during the trial opens the circuit again
+                true
+            }
+            CircuitState::Open => { false }
+        }
+    }
+    /* switch to open and return how long the host_point stays ejected. The time doubles for every ejection in a row. */
+    pub(crate) fn open(&self, fail_timeout: Duration) -> Duration {
+        let mut status = self.status.lock().unwrap();
+        status.state = CircuitState::Open;
+        status.ejections += 1;
+        let exp = (status.ejections - 1).min(16);
+        let max = if fail_timeout > self.profile.max_ejection_time { fail_timeout } else { self.profile.max_ejection_time };
+        let ejection_time = fail_timeout.saturating_mul(1 << exp);
+        if ejection_time > max { max } else { ejection_time }
+    }
+    pub(crate) fn half_open(&self) {
+        let mut status = self.status.lock().unwrap();
+        status.state = CircuitState::HalfOpen;
+        status.half_open_since = Instant::now();
+        status.half_open_in_flight = 0;
+        status.half_open_success = 0;
+    }
+    /* a whole window without any failure forgives one previous ejection */
+    fn roll_window(&self, status: &mut BreakerStatus) {
+        if status.window_start.elapsed() >= self.profile.window {
+            if status.window_failed == 0 {
+                status.ejections = status.ejections.saturating_sub(1);
+            }
+            status.window_start = Instant::now();
+            status.window_total = 0;
+            status.window_failed = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker(profile: BreakerProfile) -> CircuitBreaker {
+        CircuitBreaker::new(Arc::new(profile))
+    }
+
+    #[test]
+    fn consecutive_5xx() {
+        let breaker = breaker(BreakerProfile { consecutive_5xx: Some(3), ..Default::default() });
+        assert!(!breaker.on_failure(true));
+        assert!(!breaker.on_failure(true));
+        breaker.on_success();
+        assert!(!breaker.on_failure(true));
+        assert!(!breaker.on_failure(true));
+        /* a network error is no 5xx */
+        assert!(!breaker.on_failure(false));
+        assert!(breaker.on_failure(true));
+    }
+    #[test]
+    fn error_rate_waits_for_min_requests() {
+        let breaker = breaker(BreakerProfile { error_rate: Some(50), ..Default::default() });
+        /* one blip of a fresh window */
+        assert!(!breaker.on_failure(false));
+        for _ in 0..9 {
+            breaker.on_success();
+        }
+        for _ in 0..9 {
+            assert!(!breaker.on_failure(false));
+        }
+        /* 10 failed of 20 */
+        assert!(breaker.on_failure(false));
+    }
+    #[test]
+    fn error_rate_below() {
+        let breaker = breaker(BreakerProfile { error_rate: Some(50), min_requests: 4, ..Default::default() });
+        for _ in 0..3 {
+            breaker.on_success();
+        }
+        assert!(!breaker.on_failure(false));
+        assert!(!breaker.on_failure(false));
+        assert!(breaker.on_failure(false));
+    }
+    #[test]
+    fn half_open() {
+        let breaker = breaker(BreakerProfile { half_open_trials: 2, ..Default::default() });
+        assert!(breaker.available());
+        breaker.open(Duration::from_secs(1));
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(!breaker.available());
+        breaker.half_open();
+        for _ in 0..2 {
+            assert!(breaker.available());
+            breaker.acquire();
+        }
+        /* the trials are all out */
+        assert!(!breaker.available());
+        breaker.on_success();
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        breaker.on_success();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert!(breaker.available());
+    }
+    #[test]
+    fn half_open_failure() {
+        let breaker = breaker(BreakerProfile::default());
+        breaker.open(Duration::from_secs(1));
+        assert!(!breaker.on_failure(true));
+        breaker.half_open();
+        breaker.acquire();
+        assert!(breaker.on_failure(false));
+    }
+    #[test]
+    fn lost_trial() {
+        let breaker = breaker(BreakerProfile { window: Duration::from_millis(20), ..Default::default() });
+        breaker.open(Duration::from_secs(1));
+        breaker.half_open();
+        breaker.acquire();
+        assert!(!breaker.available());
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(breaker.available());
+    }
+    #[test]
+    fn ejection_doubles() {
+        let breaker = breaker(BreakerProfile { max_ejection_time: Duration::from_secs(10), ..Default::default() });
+        let fail_timeout = Duration::from_secs(3);
+        assert_eq!(breaker.open(fail_timeout), Duration::from_secs(3));
+        assert_eq!(breaker.open(fail_timeout), Duration::from_secs(6));
+        assert_eq!(breaker.open(fail_timeout), Duration::from_secs(10));
+        assert_eq!(breaker.open(fail_timeout), Duration::from_secs(10));
+        /* a fail_timeout over the max is kept */
+        assert_eq!(breaker.open(Duration::from_secs(20)), Duration::from_secs(20));
+    }
+    #[test]
+    fn clean_window_forgives() {
+        let breaker = breaker(BreakerProfile { window: Duration::from_millis(20), ..Default::default() });
+        let fail_timeout = Duration::from_secs(1);
+        breaker.open(fail_timeout);
+        breaker.open(fail_timeout);
+        breaker.half_open();
+        breaker.on_success();
+        std::thread::sleep(Duration::from_millis(30));
+        breaker.on_success();
+        /* one of the two ejections is forgiven */
+        assert_eq!(breaker.open(fail_timeout), Duration::from_secs(2));
+    }
+}
@@ -6,21 +6,49 @@ file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use crate::error::RResult;
 
-#[derive(Debug)]
+/*
+The content type of a file from its extension: the content_types of the route out, then mime_guess, then the table of new().
+charset is added to the text types that have none.
+ */
+#[derive(Debug, Clone)]
 pub(crate) struct ContentTypeAndExtension {
     map: std::collections::HashMap<String, String>,
+    overrides: std::collections::HashMap<String, String>,
+    charset: Option<String>,
 }
 impl ContentTypeAndExtension {
     pub(crate) fn take_content_type(&self, extension: &str) -> Option<String> {
-        match self.map.get(extension) {
-            Some(content_type) => {
-                Some(content_type.clone())
-            }
-            None => {
-                None
-            }
+        let extension = extension.to_ascii_lowercase();
+        if let Some(content_type) = self.overrides.get(&extension) {
+            return Some(content_type.clone());
+        }
+        if let Some(content_type) = mime_guess::from_ext(extension.trim_start_matches('.')).first_raw() {
+            return Some(content_type.to_string());
+        }
+        self.map.get(&extension).cloned()
+    }
+    /* the Content-Type header, application/octet-stream when the extension is unknown */
+    pub(crate) fn content_type(&self, extension: Option<&str>) -> String {
+        let content_type = extension.and_then(|ext| self.take_content_type(&(".".to_string() + ext)))
+            .unwrap_or_else(|| mime_guess::mime::APPLICATION_OCTET_STREAM.to_string());
+        match &self.charset {
+            Some(charset) if Self::is_text(&content_type) => { format!("{}; charset={}", content_type, charset) }
+            _ => { content_type }
         }
     }
+    fn is_text(content_type: &str) -> bool {
+        let Ok(mime) = content_type.parse::<mime_guess::mime::Mime>() else { return false; };
+        if mime.get_param(mime_guess::mime::CHARSET).is_some() {
+            return false;
+        }
+        mime.type_() == mime_guess::mime::TEXT
+            || (mime.type_() == mime_guess::mime::APPLICATION && matches!(mime.subtype().as_str(), "javascript" | "json" | "xml"))
+            || mime.suffix().is_some_and(|s| s == mime_guess::mime::JSON || s == mime_guess::mime::XML)
+    }
+    /* overrides: {".wasm": "application/wasm"}, lowercase with the dot */
+    pub(crate) fn with_settings(overrides: std::collections::HashMap<String, String>, charset: Option<String>) -> Self {
+        Self { overrides, charset, ..Self::new() }
+    }
     pub(crate) fn new() -> Self {
         let mut map = std::collections::HashMap::new();
         map.insert(".*".to_string(), "application/octet-stream".to_string());
@@ -466,6 +494,6 @@ impl ContentTypeAndExtension {
             ".xap".to_string(),
             "application/x-silverlight-app".to_string(),
         );
-        Self { map }
+        Self { map, overrides: std::collections::HashMap::new(), charset: None }
     }
 }
@@ -4,7 +4,7 @@ License, v. 2.0. If a copy of the MPL was not distributed with this
 file, You can obtain one at https://mozilla.org/MPL/2.0/.
 */
 
-use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
 use crate::error::RResult;
 use tokio::io::AsyncReadExt;
@@ -15,11 +15,33 @@ pub(crate) struct FileSystem {
     base_path: String,
     is_root_dir: bool,
 }
+/* modified is for the ETag and Last-Modified of the file server */
+pub(crate) struct FileContent {
+    pub(crate) content: Vec<u8>,
+    pub(crate) modified: Option<std::time::SystemTime>,
+    pub(crate) path: String,                        //the file asked, not its .br or .gz
+    pub(crate) listing: Option<&'static str>,       //the extension of the autoindex given instead of a directory
+    pub(crate) encoding: Option<&'static str>,      //of the .br or .gz read instead
+}
+pub(crate) enum FileRead {
+    Found(FileContent),
+    NotFound,
+    Forbidden,          //out of the root, or a directory without index_file nor autoindex
+}
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum AutoIndex {
+    Html,
+    Json,
+}
+/* the unreserved characters of a url are kept in the autoindex links */
+const HREF: &percent_encoding::AsciiSet = &percent_encoding::NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_').remove(b'~');
 #[derive(Debug, Clone)]
 enum FileSystemType {
     Dir {
-        file_path_map: HashMap<String, std::fs::FileType>,
+        root: PathBuf,              //canonical
         index_file: String,
+        autoindex: Option<AutoIndex>,
+        follow_symlinks: bool,      //to outside of the root
     },
     File {
         file_name: String,
@@ -31,22 +53,9 @@ impl Default for FileSystemType {
     }
 }
 impl FileSystem {
-    pub(crate) fn new(base_path: &str, index_file: &str) -> RResult<Self> {
-        let mut file_path_map = HashMap::new();
+    pub(crate) fn new(base_path: &str, index_file: &str, autoindex: Option<AutoIndex>, follow_symlinks: bool) -> RResult<Self> {
         let file_type = if std::fs::metadata(base_path)?.is_dir() {
-            for entry in std::fs::read_dir(base_path)? {
-                let entry = entry?;
-                let file_type = entry.file_type()?;
-                let path = entry.path();
-                let is_dir = file_type.is_dir();
-                let path = path.to_str().unwrap();
-                file_path_map.insert(path.to_string(), file_type);
-                if is_dir {
-                    FileSystem::recursive_read(path, &mut file_path_map)?
-                }
-            }
-            let index_file = base_path.to_string() + "/" + index_file;
-            FileSystemType::Dir { file_path_map, index_file }
+            FileSystemType::Dir { root: std::fs::canonicalize(base_path)?, index_file: index_file.to_string(), autoindex, follow_symlinks }
         } else {
             FileSystemType::File { file_name: base_path.to_string() }
         };
@@ -56,72 +65,262 @@ impl FileSystem {
             is_root_dir: false,
         })
     }
-    fn recursive_read(path: &str, map: &mut HashMap<String, std::fs::FileType>) -> RResult<()> {
-        for entry in std::fs::read_dir(path)? {
-            let entry = entry?;
-            let file_type = entry.file_type()?;
-            let path = entry.path();
-            let is_dir = file_type.is_dir();
-            let path = path.to_str().unwrap();
-            map.insert(path.to_string(), file_type);
-            if is_dir {
-                FileSystem::recursive_read(path, map)?
-            }
-        }
-        Ok(())
-    }
     pub(crate) fn is_root_dir(&self) -> bool {
         self.is_root_dir
     }
     pub(crate) fn base_path(&self) -> &str {
         &self.base_path
     }
-    pub(crate) async fn read_file(&self, file_path: &str) -> RResult<Option<Vec<u8>>> {
-        match &self.file_type {
+    /*
+    The path of the request under root, looked up on each request. %2e%2e is .. too, a .. is refused before the disk is read,
+    and what the canonical path (symlinks followed) puts out of the root is refused unless follow_symlinks.
+     */
+    fn join(root: &Path, uri_path: &str) -> Option<PathBuf> {
+        let decoded = percent_encoding::percent_decode_str(uri_path).decode_utf8().ok()?;
+        let mut path = root.to_path_buf();
+        for segment in decoded.split('/') {
+            match segment {
+                "" | "." => {}
+                ".." => { return None; }
+                segment if segment.contains('\0') => { return None; }
+                segment => { path.push(segment); }
+            }
+        }
+        Some(path)
+    }
+    async fn contained(root: &Path, path: &Path, follow_symlinks: bool) -> RResult<Option<std::fs::Metadata>> {
+        let canonical = match tokio::fs::canonicalize(path).await {
+            Ok(canonical) => { canonical }
+            Err(e) if matches!(e.kind(), std::io::ErrorKind::NotFound | std::io::ErrorKind::NotADirectory) => { return Ok(None); }
+            Err(e) => { return Err(e.into()); }
+        };
+        if !follow_symlinks && !canonical.starts_with(root) {
+            return Ok(None);
+        }
+        Ok(Some(tokio::fs::metadata(&canonical).await?))
+    }
+    /*
+    encodings: [("br", ".br"), ("gzip", ".gz")], the first of them found next to the file is read instead of it.
+    A directory gives its index_file, or the autoindex.
+     */
+    pub(crate) async fn read_file(&self, uri_path: &str, encodings: &[(&'static str, &str)]) -> RResult<FileRead> {
+        let (root, index_file, autoindex, follow_symlinks) = match &self.file_type {
             FileSystemType::File { file_name } => {
-                let mut buffer = Vec::new();
-                let mut file = tokio::fs::File::open(file_name).await?;
-                file.read_to_end(&mut buffer).await?;
-                return Ok(Some(buffer));
+                return FileSystem::read_found(file_name.clone(), encodings, None, true).await;
             }
-            FileSystemType::Dir { file_path_map, index_file } => {
-                let is_dir = match file_path_map.get(file_path) {
-                    Some(file_type) => {
-                        file_type.is_dir()
-                    }
-                    None => {
-                        if file_path == self.base_path {
-                            return Box::pin(self.read_file(&index_file)).await;
-                        }
-                        return Ok(None);
-                    }
-                };
-                if is_dir {
-                    let mut dir = tokio::fs::read_dir(file_path).await?;
-                    let mut buffer = String::new();
-                    loop {
-                        match dir.next_entry().await? {
-                            Some(sub_f_d) => {
-                                let path_buf = sub_f_d.path();
-                                let path = path_buf.to_str().unwrap();
-                                buffer += path;
-                                buffer += ",";
-                            }
-                            None => { break; }
-                        }
-                    }
-                    if !buffer.is_empty() {
-                        buffer = (&buffer[0..buffer.len()-1]).to_string();
+            FileSystemType::Dir { root, index_file, autoindex, follow_symlinks } => { (root, index_file, autoindex, *follow_symlinks) }
+        };
+        let path = match FileSystem::join(root, uri_path) {
+            Some(path) => { path }
+            None => { return Ok(FileRead::Forbidden); }
+        };
+        let metadata = match FileSystem::contained(root, &path, follow_symlinks).await? {
+            Some(metadata) => { metadata }
+            None if tokio::fs::symlink_metadata(&path).await.is_ok() => { return Ok(FileRead::Forbidden); }
+            None => { return Ok(FileRead::NotFound); }
+        };
+        if !metadata.is_dir() {
+            return FileSystem::read_found(path.to_string_lossy().to_string(), encodings, Some(root), follow_symlinks).await;
+        }
+        let index = path.join(index_file);
+        if FileSystem::contained(root, &index, follow_symlinks).await?.is_some_and(|m| m.is_file()) {
+            return FileSystem::read_found(index.to_string_lossy().to_string(), encodings, Some(root), follow_symlinks).await;
+        }
+        match autoindex {
+            Some(autoindex) => {
+                let (content, listing) = FileSystem::autoindex(root, &path, uri_path, *autoindex, follow_symlinks).await?;
+                Ok(FileRead::Found(FileContent { content, modified: None, path: path.to_string_lossy().to_string(), listing: Some(listing), encoding: None }))
+            }
+            None => { Ok(FileRead::Forbidden) }
+        }
+    }
+    /* root is None for a root_path that is a file */
+    async fn read_found(path: String, encodings: &[(&'static str, &str)], root: Option<&Path>, follow_symlinks: bool) -> RResult<FileRead> {
+        for (encoding, suffix) in encodings {
+            let sidecar = path.clone() + suffix;
+            let found = match root {
+                Some(root) => { FileSystem::contained(root, Path::new(&sidecar), follow_symlinks).await?.is_some_and(|m| m.is_file()) }
+                None => { tokio::fs::metadata(&sidecar).await.is_ok_and(|m| m.is_file()) }
+            };
+            if found {
+                let (content, modified) = FileSystem::read(&sidecar).await?;
+                return Ok(FileRead::Found(FileContent { content, modified, path, listing: None, encoding: Some(encoding) }));
+            }
+        }
+        let (content, modified) = FileSystem::read(&path).await?;
+        Ok(FileRead::Found(FileContent { content, modified, path, listing: None, encoding: None }))
+    }
+    /* the names relative to the directory, as nginx: directories first, no dot files */
+    async fn autoindex(root: &Path, dir: &Path, uri_path: &str, autoindex: AutoIndex, follow_symlinks: bool) -> RResult<(Vec<u8>, &'static str)> {
+        let mut entries = Vec::new();
+        let mut read_dir = tokio::fs::read_dir(dir).await?;
+        while let Some(entry) = read_dir.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') {
+                continue;
+            }
+            /* a broken symlink, or one out of the root, is left out */
+            if let Ok(Some(metadata)) = FileSystem::contained(root, &entry.path(), follow_symlinks).await {
+                entries.push((name, metadata.is_dir(), metadata.len(), metadata.modified().ok()));
+            }
+        }
+        entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let date = |modified: Option<std::time::SystemTime>, format: &str| {
+            modified.map(|m| chrono::DateTime::<chrono::Utc>::from(m).format(format).to_string()).unwrap_or_default()
+        };
+        match autoindex {
+            AutoIndex::Json => {
+                let list = entries.iter().map(|(name, is_dir, size, modified)| {
+                    let mut entry = serde_json::json!({ "name": name, "type": if *is_dir { "directory" } else { "file" }, "mtime": date(*modified, "%a, %d %b %Y %H:%M:%S GMT") });
+                    if !is_dir {
+                        entry["size"] = serde_json::json!(size);
                     }
-                    return Ok(Some(buffer.as_bytes().to_vec()));
-                } else {
-                    let mut buffer = Vec::new();
-                    let mut file = tokio::fs::File::open(file_path).await?;
-                    file.read_to_end(&mut buffer).await?;
-                    return Ok(Some(buffer));
+                    entry
+                }).collect::<Vec<serde_json::Value>>();
+                Ok((serde_json::Value::Array(list).to_string().into_bytes(), "json"))
+            }
+            AutoIndex::Html => {
+                let escape = |s: &str| s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;");
+                let base = if uri_path.ends_with('/') { uri_path.to_string() } else { uri_path.to_string() + "/" };
+                let title = escape(&percent_encoding::percent_decode_str(&base).decode_utf8_lossy());
+                let mut html = format!("<html>\n<head><title>Index of {}</title></head>\n<body>\n<h1>Index of {}</h1><hr><pre>", title, title);
+                if let Some((parent, _)) = base.trim_end_matches('/').rsplit_once('/') {
+                    html += &format!("<a href=\"{}/\">../</a>\n", escape(parent));
                 }
+                for (name, is_dir, size, modified) in &entries {
+                    let href = percent_encoding::utf8_percent_encode(name, HREF).to_string();
+                    let (href, name) = if *is_dir { (href + "/", name.clone() + "/") } else { (href, name.clone()) };
+                    let size = if *is_dir { "-".to_string() } else { size.to_string() };
+                    html += &format!("<a href=\"{}{}\">{}</a>{} {} {:>19}\n", escape(&base), href, escape(&name), " ".repeat(50usize.saturating_sub(name.chars().count())), date(*modified, "%d-%b-%Y %H:%M"), size);
+                }
+                html += "</pre><hr></body>\n</html>\n";
+                Ok((html.into_bytes(), "html"))
+            }
+        }
+    }
+    async fn read(path: &str) -> RResult<(Vec<u8>, Option<std::time::SystemTime>)> {
+        let mut buffer = Vec::new();
+        let mut file = tokio::fs::File::open(path).await?;
+        file.read_to_end(&mut buffer).await?;
+        let modified = file.metadata().await?.modified().ok();
+        Ok((buffer, modified))
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* root/a.txt, root/sub/b.txt, root/inner -> root/a.txt, root/link -> out/secret.txt, root/linkdir -> out, out/secret.txt */
+    struct Tree {
+        base: PathBuf,
+        root: PathBuf,
+    }
+    impl Tree {
+        fn new(name: &str) -> Self {
+            let base = std::env::temp_dir().join(format!("rwp_file_system_{}_{}", std::process::id(), name));
+            let _ = std::fs::remove_dir_all(&base);
+            std::fs::create_dir_all(base.join("root/sub")).unwrap();
+            std::fs::create_dir_all(base.join("out")).unwrap();
+            std::fs::write(base.join("root/a.txt"), "a").unwrap();
+            std::fs::write(base.join("root/sub/b.txt"), "b").unwrap();
+            std::fs::write(base.join("out/secret.txt"), "secret").unwrap();
+            #[cfg(unix)]
+            {
+                std::os::unix::fs::symlink(base.join("root/a.txt"), base.join("root/inner")).unwrap();
+                std::os::unix::fs::symlink(base.join("out/secret.txt"), base.join("root/link")).unwrap();
+                std::os::unix::fs::symlink(base.join("out"), base.join("root/linkdir")).unwrap();
             }
+            let root = std::fs::canonicalize(base.join("root")).unwrap();
+            Self { base, root }
+        }
+        fn file_system(&self, follow_symlinks: bool) -> FileSystem {
+            FileSystem::new(self.root.to_str().unwrap(), "index.html", None, follow_symlinks).unwrap()
+        }
+    }
+    impl Drop for Tree {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.base);
+        }
+    }
+    /* the content, or the status */
+    async fn read(file_system: &FileSystem, uri_path: &str) -> Result<Vec<u8>, u16> {
+        match file_system.read_file(uri_path, &[]).await.unwrap() {
+            FileRead::Found(file) => { Ok(file.content) }
+            FileRead::NotFound => { Err(404) }
+            FileRead::Forbidden => { Err(403) }
+        }
+    }
+
+    #[test]
+    fn join() {
+        let root = Path::new("/srv/www");
+        assert_eq!(FileSystem::join(root, "/a/b.txt"), Some(PathBuf::from("/srv/www/a/b.txt")));
+        assert_eq!(FileSystem::join(root, "/a/./b.txt"), Some(PathBuf::from("/srv/www/a/b.txt")));
+        assert_eq!(FileSystem::join(root, "//a//b.txt"), Some(PathBuf::from("/srv/www/a/b.txt")));
+        assert_eq!(FileSystem::join(root, "/a%20b.txt"), Some(PathBuf::from("/srv/www/a b.txt")));
+        assert_eq!(FileSystem::join(root, "/"), Some(PathBuf::from("/srv/www")));
+        assert_eq!(FileSystem::join(root, "/..%2e"), Some(PathBuf::from("/srv/www/...")));
+    }
+    #[test]
+    fn join_traversal() {
+        let root = Path::new("/srv/www");
+        for uri_path in ["/..", "/../etc/passwd", "/a/../../etc/passwd", "/a/..", "/%2e%2e/etc/passwd", "/%2E%2e/etc/passwd", "/.%2e/etc/passwd",
+            "/a%2f..%2f..%2fetc%2fpasswd", "/a/%2f../etc/passwd", "/..%2fetc/passwd"] {
+            assert_eq!(FileSystem::join(root, uri_path), None, "{}", uri_path);
         }
-        
     }
-}
\ No newline at end of file
+    #[test]
+    fn join_nul() {
+        let root = Path::new("/srv/www");
+        assert_eq!(FileSystem::join(root, "/a.txt%00.html"), None);
+        assert_eq!(FileSystem::join(root, "/a.txt\0.html"), None);
+        assert_eq!(FileSystem::join(root, "/%ff"), None);
+    }
+    #[tokio::test]
+    async fn contained() {
+        let tree = Tree::new("contained");
+        let root = &tree.root;
+        assert!(FileSystem::contained(root, &root.join("a.txt"), false).await.unwrap().is_some_and(|m| m.is_file()));
+        assert!(FileSystem::contained(root, &root.join("sub"), false).await.unwrap().is_some_and(|m| m.is_dir()));
+        assert!(FileSystem::contained(root, &root.join("none.txt"), false).await.unwrap().is_none());
+        assert!(FileSystem::contained(root, &root.join("a.txt/x"), false).await.unwrap().is_none());
+        assert!(FileSystem::contained(root, &root.join("sub/../../out/secret.txt"), false).await.unwrap().is_none());
+    }
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn contained_symlinks() {
+        let tree = Tree::new("contained_symlinks");
+        let root = &tree.root;
+        assert!(FileSystem::contained(root, &root.join("inner"), false).await.unwrap().is_some());
+        assert!(FileSystem::contained(root, &root.join("link"), false).await.unwrap().is_none());
+        assert!(FileSystem::contained(root, &root.join("linkdir/secret.txt"), false).await.unwrap().is_none());
+        assert!(FileSystem::contained(root, &root.join("link"), true).await.unwrap().is_some_and(|m| m.is_file()));
+        assert!(FileSystem::contained(root, &root.join("linkdir/secret.txt"), true).await.unwrap().is_some_and(|m| m.is_file()));
+    }
+    #[tokio::test]
+    async fn read_file_traversal() {
+        let tree = Tree::new("read_file_traversal");
+        let file_system = tree.file_system(false);
+        assert_eq!(read(&file_system, "/a.txt").await, Ok(b"a".to_vec()));
+        assert_eq!(read(&file_system, "/sub/b.txt").await, Ok(b"b".to_vec()));
+        assert_eq!(read(&file_system, "/none.txt").await, Err(404));
+        for uri_path in ["/../out/secret.txt", "/sub/../../out/secret.txt", "/%2e%2e/out/secret.txt", "/sub%2f..%2f..%2fout%2fsecret.txt", "/a.txt%00"] {
+            assert_eq!(read(&file_system, uri_path).await, Err(403), "{}", uri_path);
+        }
+        /* a directory without index_file nor autoindex */
+        assert_eq!(read(&file_system, "/sub/").await, Err(403));
+    }
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn read_file_symlinks() {
+        let tree = Tree::new("read_file_symlinks");
+        let file_system = tree.file_system(false);
+        assert_eq!(read(&file_system, "/inner").await, Ok(b"a".to_vec()));
+        assert_eq!(read(&file_system, "/link").await, Err(403));
+        assert_eq!(read(&file_system, "/linkdir/secret.txt").await, Err(403));
+        let file_system = tree.file_system(true);
+        assert_eq!(read(&file_system, "/inner").await, Ok(b"a".to_vec()));
+        assert_eq!(read(&file_system, "/link").await, Ok(b"secret".to_vec()));
+        assert_eq!(read(&file_system, "/linkdir/secret.txt").await, Ok(b"secret".to_vec()));
+    }
+}
@@ -0,0 +1,110 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public
+License, v. 2.0. If a copy of the MPL was not distributed with this
+file, You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+use std::collections::HashMap;
+
+use base64::Engine;
+use md5::{Digest, Md5};
+
+use crate::error::RResult;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum HtpasswdHash {
+    Bcrypt(String),     //$2y$ $2a$ $2b$
+    Sha(Vec<u8>),       //{SHA}
+    Apr1 { salt: String, hash: String },    //$apr1$
+}
+impl HtpasswdHash {
+    fn parse(s: &str) -> Option<Self> {
+        if s.starts_with("$2y$") || s.starts_with("$2a$") || s.starts_with("$2b$") {
+            Some(HtpasswdHash::Bcrypt(s.to_string()))
+        } else if let Some(sha) = s.strip_prefix("{SHA}") {
+            base64::engine::general_purpose::STANDARD.decode(sha).ok().map(HtpasswdHash::Sha)
+        } else if let Some(apr1) = s.strip_prefix("$apr1$") {
+            let (salt, hash) = apr1.split_once('$')?;
+            Some(HtpasswdHash::Apr1 { salt: salt.to_string(), hash: hash.to_string() })
+        } else {
+            None
+        }
+    }
+    /* bcrypt is slow on purpose, call it out of the async threads */
+    pub(crate) fn is_slow(&self) -> bool {
+        matches!(self, HtpasswdHash::Bcrypt(_))
+    }
+    pub(crate) fn verify(&self, password: &str) -> bool {
+        match self {
+            HtpasswdHash::Bcrypt(hash) => {
+                bcrypt::verify(password, hash).unwrap_or(false)
+            }
+            HtpasswdHash::Sha(hash) => {
+                let digest = ring::digest::digest(&ring::digest::SHA1_FOR_LEGACY_USE_ONLY, password.as_bytes());
+                constant_time_eq(digest.as_ref(), hash)
+            }
+            HtpasswdHash::Apr1 { salt, hash } => {
+                constant_time_eq(apr1(password.as_bytes(), salt.as_bytes()).as_bytes(), hash.as_bytes())
+            }
+        }
+    }
+}
+/* user:hash per line, # for comments. A line with another hash (crypt, plain) is skipped. */
+pub(crate) fn parse_htpasswd(data: &str) -> RResult<HashMap<String, HtpasswdHash>> {
+    let mut users = HashMap::new();
+    for (n, line) in data.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (user, hash) = line.split_once(':').ok_or_else(|| format!("htpasswd line {}: user:hash expected", n + 1))?;
+        match HtpasswdHash::parse(hash) {
+            Some(hash) => { users.insert(user.to_string(), hash); }
+            None => {
+                log::warn!("htpasswd line {}: user {} skipped, only bcrypt, {{SHA}} and apr1 hashes are supported", n + 1, user);
+            }
+        }
+    }
+    Ok(users)
+}
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+/* the md5 crypt of apache, the hash part of $apr1$salt$hash */
+fn apr1(password: &[u8], salt: &[u8]) -> String {
+    let salt = &salt[..salt.len().min(8)];
+    let alt = Md5::new().chain_update(password).chain_update(salt).chain_update(password).finalize();
+    let mut ctx = Md5::new().chain_update(password).chain_update(b"$apr1$").chain_update(salt);
+    let mut left = password.len();
+    while left > 0 {
+        ctx.update(&alt[..left.min(16)]);
+        left = left.saturating_sub(16);
+    }
+    let mut i = password.len();
+    while i > 0 {
+        if i & 1 == 1 { ctx.update([0u8]) } else { ctx.update(&password[..1]) }
+        i >>= 1;
+    }
+    let mut sum = ctx.finalize();
+    for i in 0..1000 {
+        let mut ctx = Md5::new();
+        if i & 1 == 1 { ctx.update(password) } else { ctx.update(sum) }
+        if i % 3 != 0 { ctx.update(salt) }
+        if i % 7 != 0 { ctx.update(password) }
+        if i & 1 == 1 { ctx.update(sum) } else { ctx.update(password) }
+        sum = ctx.finalize();
+    }
+    const ITOA64: &[u8] = b"./0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+    let mut ret = String::with_capacity(22);
+    let mut to64 = |mut v: u32, n: usize| {
+        for _ in 0..n {
+            ret.push(ITOA64[(v & 0x3f) as usize] as char);
+            v >>= 6;
+        }
+    };
+    for (a, b, c) in [(0, 6, 12), (1, 7, 13), (2, 8, 14), (3, 9, 15), (4, 10, 5)] {
+        to64((sum[a] as u32) << 16 | (sum[b] as u32) << 8 | sum[c] as u32, 4);
+    }
+    to64(sum[11] as u32, 2);
+    ret
+}
@@ -5,13 +5,13 @@ file, You can obtain one at https://mozilla.org/MPL/2.0/.
 */
 
 use bytes::Bytes;
-use http::{header, HeaderMap, HeaderName, Response, StatusCode, Version};
+use http::{header, HeaderMap, HeaderName, HeaderValue, Method, Response, StatusCode, Version};
 use http_body_util::Full;
 use package_info::PackageInfo;
 
 use crate::{error::RResult, util::gateway_info::CargoPackageInfo};
 
-use super::{content_type::ContentTypeAndExtension, file_system::{self, FileSystem}};
+use super::{content_type::ContentTypeAndExtension, file_system::{self, FileRead, FileSystem}};
 
 pub(crate) struct HttpFile {
     pub(crate) file_content: Vec<u8>,
@@ -24,48 +24,31 @@ impl HttpFile {
         (self.status, self.http_headers, self.file_content.into())
     }
     pub(crate) async fn file_response_structure(
-        file_path: &str, 
+        uri_path: &str, 
         file_system: &FileSystem, 
         ctae: &ContentTypeAndExtension, 
+        precompressed: bool,
+        method: &Method,
         request_header: &HeaderMap) -> RResult<Self> {
-        let mut file_content = file_system.read_file(file_path).await?;
-        let status = if file_content.is_some() { StatusCode::OK } else { StatusCode::NOT_FOUND };
-        let file_extension = if file_content.is_some() {
-            let path = match std::path::Path::new(file_path).extension().map(|o| {
-                let tmp = o.to_str().unwrap();
-                let tmp = tmp.trim();
-                if tmp != "" {
-                    let tmp = tmp.split("?").into_iter().collect::<Vec<&str>>();
-                    let tmp = tmp.get(0).unwrap();
-                    return *tmp;
-                } else {
-                    return "";
-                }
-            }) {
-                Some(extension) => { 
-                    Some(extension.to_string()) 
-                }
-                None => { None }
-            };
-            path
-        } else { None };
-        let file_name = match std::path::Path::new(file_path).file_name().map(|o| {
-            let tmp = o.to_str().unwrap();
-            let tmp = tmp.trim();
-            if tmp != "" {
-                let tmp = tmp.split("?").into_iter().collect::<Vec<&str>>();
-                let tmp = tmp.get(0).unwrap();
-                return *tmp;
-            } else {
-                return "";
-            }
-        }) {
-            Some(f_n) => { f_n.to_string() }
-            None => { "rock_waypoint".to_string() }
+        /* foo.js.br or foo.js.gz for foo.js, the first the client accepts */
+        let encodings = if precompressed {
+            [("br", ".br"), ("gzip", ".gz")].into_iter().filter(|(encoding, _)| accepts_encoding(request_header, encoding)).collect::<Vec<(&'static str, &str)>>()
+        } else { Vec::new() };
+        let (file, status) = match file_system.read_file(uri_path, &encodings).await? {
+            FileRead::Found(file) => { (Some(file), StatusCode::OK) }
+            FileRead::NotFound => { (None, StatusCode::NOT_FOUND) }
+            FileRead::Forbidden => { (None, StatusCode::FORBIDDEN) }
         };
+        let file_extension = file.as_ref().and_then(|f| {
+            match f.listing {
+                Some(listing) => { Some(listing.to_string()) }
+                None => { std::path::Path::new(&f.path).extension().and_then(|o| o.to_str()).map(|o| o.trim().to_string()).filter(|o| !o.is_empty()) }
+            }
+        });
         /* start building the header */
         let mut http_headers = HeaderMap::new();
-        if let Some(fc) = &file_content {
+        let file_content = if let Some(file) = file {
+            let fc = &file.content;
             if request_header.get(header::CONNECTION).is_some_and(|v| {
                 v.to_str().unwrap().trim() == "keep-alive"
             }) {
@@ -73,77 +56,338 @@ impl HttpFile {
             }
             http_headers.insert(HeaderName::from_static("server"), CargoPackageInfo::name().unwrap().to_string().parse().unwrap());
             http_headers.insert(header::CONTENT_LENGTH, fc.len().to_string().parse().unwrap());
-            if let Some(ext) = &file_extension {
-                http_headers.insert(header::CONTENT_TYPE, 
-                    ctae.take_content_type((".".to_string() + ext).as_str())
-                            .unwrap_or_else(||mime_guess::mime::APPLICATION_OCTET_STREAM.to_string()).parse().unwrap()
-                );
-                if ext.eq_ignore_ascii_case("png") ||
-                    ext.eq_ignore_ascii_case("gif") ||
-                    ext.eq_ignore_ascii_case("svg") ||
-                    ext.eq_ignore_ascii_case("jpeg") ||
-                    ext.eq_ignore_ascii_case("bmp") ||
-                    ext.eq_ignore_ascii_case("jpg") ||
-                    ext.eq_ignore_ascii_case("woff") ||
-                    ext.eq_ignore_ascii_case("ttf")
-                {
-                    http_headers.insert(header::ACCEPT_RANGES, "bytes".to_string().parse().unwrap());
-                } 
-                if ext.eq_ignore_ascii_case("js") || ext.eq_ignore_ascii_case("css") {
-                    if request_header.get(header::ACCEPT_ENCODING).is_some_and(|v| {
-                        v.to_str().unwrap().contains("gzip")
-                    }){
-                        http_headers.insert(header::ACCEPT_RANGES, "bytes".to_string().parse().unwrap());
-                        http_headers.insert(header::CONTENT_ENCODING, "identity".to_string().parse().unwrap());
-                    } else if request_header.get(header::ACCEPT_ENCODING).is_some_and(|v| {
-                        v.to_str().unwrap().contains("br")
-                    }) {
-                        http_headers.insert(header::ACCEPT_RANGES, "bytes".to_string().parse().unwrap());
-                        http_headers.insert(header::CONTENT_ENCODING, "identity".to_string().parse().unwrap());
-                    } else {
-                        http_headers.insert(header::CONTENT_ENCODING, "identity".to_string().parse().unwrap());
-                    }
-                } 
-            } else {
-                if request_header.get(header::ACCEPT).is_some_and(|v| {
-                    v.to_str().unwrap().contains("text/html")
-                }) {
-                    http_headers.insert(header::CONTENT_TYPE, mime_guess::mime::TEXT_HTML_UTF_8.to_string().parse().unwrap());
-                    http_headers.insert(header::CONTENT_ENCODING, "identity".to_string().parse().unwrap());
-                } else if request_header.get(header::ACCEPT).is_some_and(|v| {
-                    let accept = v.to_str().unwrap().split(",").into_iter().collect::<Vec<&str>>();
-                    for a in accept {
-                        if a.starts_with("image") { return true; }
-                    }
-                    false
-                }) {
-                    http_headers.insert(header::ACCEPT_RANGES, "bytes".to_string().parse().unwrap());
-                    http_headers.insert(header::CONTENT_TYPE, "image/webp".to_string().parse().unwrap());
-                } else {
-                    http_headers.insert(header::ACCEPT_RANGES, "bytes".to_string().parse().unwrap());
-                    http_headers.insert(header::CONTENT_TYPE, mime_guess::mime::APPLICATION_OCTET_STREAM.to_string().parse().unwrap());
-                    http_headers.insert(header::CONTENT_DISPOSITION, format!("attachment:filename={:?}", file_name).parse().unwrap());
-                }
+            http_headers.insert(header::ACCEPT_RANGES, "bytes".to_string().parse().unwrap());
+            if let Some(modified) = file.modified {
+                let secs = modified.duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+                let etag = match file.encoding {
+                    Some(encoding) => { format!("\"{:x}-{:x}-{}\"", secs, fc.len(), encoding) }
+                    None => { format!("\"{:x}-{:x}\"", secs, fc.len()) }
+                };
+                http_headers.insert(header::ETAG, etag.parse().unwrap());
+                http_headers.insert(header::LAST_MODIFIED, chrono::DateTime::<chrono::Utc>::from(modified).format("%a, %d %b %Y %H:%M:%S GMT").to_string().parse().unwrap());
             }
-
-        } else { // 404
+            /* the type of foo.js for foo.js.br too */
+            http_headers.insert(header::CONTENT_TYPE, ctae.content_type(file_extension.as_deref()).parse().unwrap());
+            if precompressed && file.listing.is_none() {
+                http_headers.insert(header::VARY, HeaderValue::from_static("Accept-Encoding"));
+            }
+            if let Some(encoding) = file.encoding {
+                http_headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static(encoding));
+            }
+            file.content
+        } else { // 404, 403
+            let status_line = format!("{} {}", status.as_u16(), status.canonical_reason().unwrap_or_default());
             let body_str = format!("<html>
-            <head><title>404 Not Found</title></head>
+            <head><title>{}</title></head>
             <body>
-            <center><h1>404 Not Found</h1></center>
+            <center><h1>{}</h1></center>
             <hr><center>{:?}/{:?}</center>
             </body>
-            </html>", CargoPackageInfo::name().unwrap(), CargoPackageInfo::version().unwrap());
-            file_content = Some(body_str.as_bytes().to_vec());
+            </html>", status_line, status_line, CargoPackageInfo::name().unwrap(), CargoPackageInfo::version().unwrap());
             http_headers.insert(header::CONNECTION, "keep-alive".parse().unwrap());
             http_headers.insert(header::CONTENT_TYPE, mime_guess::mime::TEXT_HTML.to_string().parse().unwrap());
             http_headers.insert(header::CONTENT_LENGTH, body_str.len().to_string().parse().unwrap());
-        }
-        Ok(Self {
-            file_content: file_content.unwrap_or_else(|| { Vec::new() }),
+            body_str.into_bytes()
+        };
+        let http_file = Self {
+            file_content,
             file_extension,
             http_headers,
             status,
-        })
+        };
+        if http_file.status != StatusCode::OK {
+            return Ok(http_file);
+        }
+        Ok(http_file.evaluate(method, request_header))
+    }
+    /* RFC 9110 13.2.2: If-Match or If-Unmodified-Since, If-None-Match or If-Modified-Since, then If-Range and Range */
+    fn evaluate(self, method: &Method, request_header: &HeaderMap) -> Self {
+        let etag = self.http_headers.get(header::ETAG).and_then(|v| v.to_str().ok()).map(|v| v.to_string());
+        let modified = self.http_headers.get(header::LAST_MODIFIED).and_then(http_date);
+        let safe = method == Method::GET || method == Method::HEAD;
+        if let Some(if_match) = request_header.get(header::IF_MATCH) {
+            if !etag_matches(if_match, etag.as_deref(), true) {
+                return self.without_body(StatusCode::PRECONDITION_FAILED);
+            }
+        } else if let Some(since) = request_header.get(header::IF_UNMODIFIED_SINCE).and_then(http_date) {
+            if modified.is_some_and(|m| m > since) {
+                return self.without_body(StatusCode::PRECONDITION_FAILED);
+            }
+        }
+        if let Some(if_none_match) = request_header.get(header::IF_NONE_MATCH) {
+            if etag_matches(if_none_match, etag.as_deref(), false) {
+                return self.without_body(if safe { StatusCode::NOT_MODIFIED } else { StatusCode::PRECONDITION_FAILED });
+            }
+        } else if let Some(since) = request_header.get(header::IF_MODIFIED_SINCE).and_then(http_date) {
+            if safe && modified.is_some_and(|m| m <= since) {
+                return self.without_body(StatusCode::NOT_MODIFIED);
+            }
+        }
+        let range = match request_header.get(header::RANGE) {
+            Some(range) if method == Method::GET => { range }
+            _ => { return self; }
+        };
+        /* a strong etag, or exactly the Last-Modified, else the whole file */
+        if let Some(if_range) = request_header.get(header::IF_RANGE) {
+            let matched = match http_date(if_range) {
+                Some(date) => { modified == Some(date) }
+                None => { etag_matches(if_range, etag.as_deref(), true) }
+            };
+            if !matched {
+                return self;
+            }
+        }
+        match byte_ranges(range, self.file_content.len() as u64) {
+            Some(ranges) => { self.partial(ranges) }
+            None => { self }
+        }
+    }
+    /* 304 keeps the ETag and Last-Modified, not what describes the body */
+    fn without_body(mut self, status: StatusCode) -> Self {
+        for name in [header::CONTENT_TYPE, header::CONTENT_ENCODING, header::CONTENT_DISPOSITION, header::CONTENT_LENGTH] {
+            self.http_headers.remove(name);
+        }
+        if status != StatusCode::NOT_MODIFIED {
+            self.http_headers.insert(header::CONTENT_LENGTH, HeaderValue::from_static("0"));
+        }
+        self.status = status;
+        self.file_content = Vec::new();
+        self
+    }
+    fn partial(mut self, ranges: Vec<(u64, u64)>) -> Self {
+        let len = self.file_content.len();
+        if ranges.is_empty() {
+            let mut http_file = self.without_body(StatusCode::RANGE_NOT_SATISFIABLE);
+            http_file.http_headers.insert(header::CONTENT_RANGE, format!("bytes */{}", len).parse().unwrap());
+            return http_file;
+        }
+        let body = if let [(start, end)] = ranges[..] {
+            self.http_headers.insert(header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, len).parse().unwrap());
+            self.file_content[start as usize..=end as usize].to_vec()
+        } else {
+            let boundary = uuid::Uuid::new_v4().simple().to_string();
+            let content_type = self.http_headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()).unwrap_or("application/octet-stream").to_string();
+            let mut body = Vec::new();
+            for (start, end) in ranges {
+                body.extend_from_slice(format!("\r\n--{}\r\nContent-Type: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n", boundary, content_type, start, end, len).as_bytes());
+                body.extend_from_slice(&self.file_content[start as usize..=end as usize]);
+            }
+            body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+            self.http_headers.insert(header::CONTENT_TYPE, format!("multipart/byteranges; boundary={}", boundary).parse().unwrap());
+            body
+        };
+        self.http_headers.insert(header::CONTENT_LENGTH, body.len().to_string().parse().unwrap());
+        self.status = StatusCode::PARTIAL_CONTENT;
+        self.file_content = body;
+        self
+    }
+}
+/* Accept-Encoding: br;q=1.0, gzip, *;q=0 */
+fn accepts_encoding(request_header: &HeaderMap, encoding: &str) -> bool {
+    let mut any = None;
+    for v in request_header.get_all(header::ACCEPT_ENCODING).iter().filter_map(|v| v.to_str().ok()) {
+        for coding in v.split(',') {
+            let mut params = coding.split(';').map(|p| p.trim());
+            let name = params.next().unwrap_or_default();
+            let q = params.find_map(|p| p.strip_prefix("q=").or_else(|| p.strip_prefix("Q="))).map(|q| q.parse::<f32>().unwrap_or(0.0)).unwrap_or(1.0);
+            if name.eq_ignore_ascii_case(encoding) || (encoding == "gzip" && name.eq_ignore_ascii_case("x-gzip")) {
+                return q > 0.0;
+            }
+            if name == "*" {
+                any = Some(q > 0.0);
+            }
+        }
     }
-}
\ No newline at end of file
+    any.unwrap_or(false)
+}
+/* the seconds since the epoch */
+fn http_date(v: &HeaderValue) -> Option<i64> {
+    v.to_str().ok().and_then(|v| chrono::DateTime::parse_from_rfc2822(v.trim()).ok()).map(|d| d.timestamp())
+}
+/* strong: W/ never matches, as for If-Match and If-Range */
+fn etag_matches(v: &HeaderValue, etag: Option<&str>, strong: bool) -> bool {
+    let Ok(v) = v.to_str() else { return false; };
+    if v.trim() == "*" {
+        return true;
+    }
+    let Some(etag) = etag else { return false; };
+    let opaque = |tag: &str| -> Option<String> {
+        match tag.strip_prefix("W/") {
+            Some(_) if strong => { None }
+            Some(weak) => { Some(weak.to_string()) }
+            None => { Some(tag.to_string()) }
+        }
+    };
+    let Some(etag) = opaque(etag) else { return false; };
+    v.split(',').any(|tag| opaque(tag.trim()).is_some_and(|tag| tag == etag))
+}
+/*
+bytes=0-99, bytes=100-, bytes=-100, inclusive and within len.
+None when it is not a valid bytes range (the whole file is given), empty when none of them is satisfiable (416).
+ */
+fn byte_ranges(v: &HeaderValue, len: u64) -> Option<Vec<(u64, u64)>> {
+    let v = v.to_str().ok()?.trim();
+    let (unit, specs) = v.split_once('=')?;
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return None;
+    }
+    let specs = specs.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()).collect::<Vec<&str>>();
+    if specs.is_empty() || specs.len() > crate::util::r#const::FILE_SERVER_MAX_RANGES {
+        return None;
+    }
+    let mut ranges = Vec::new();
+    for spec in specs {
+        let (start, end) = spec.split_once('-')?;
+        if start.is_empty() {
+            let suffix = end.parse::<u64>().ok()?;
+            if suffix > 0 && len > 0 {
+                ranges.push((len.saturating_sub(suffix), len - 1));
+            }
+            continue;
+        }
+        let start = start.parse::<u64>().ok()?;
+        let end = if end.is_empty() { u64::MAX } else { end.parse::<u64>().ok()? };
+        if end < start {
+            return None;
+        }
+        if start < len {
+            ranges.push((start, end.min(len - 1)));
+        }
+    }
+    Some(ranges)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ETAG: &str = "\"5f5e100-a\"";
+    const LAST_MODIFIED: &str = "Sun, 13 Sep 2020 12:26:40 GMT";
+
+    fn range(v: &str, len: u64) -> Option<Vec<(u64, u64)>> {
+        byte_ranges(&HeaderValue::from_str(v).unwrap(), len)
+    }
+    fn file() -> HttpFile {
+        let mut http_headers = HeaderMap::new();
+        http_headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+        http_headers.insert(header::CONTENT_LENGTH, HeaderValue::from_static("10"));
+        http_headers.insert(header::ETAG, HeaderValue::from_static(ETAG));
+        http_headers.insert(header::LAST_MODIFIED, HeaderValue::from_static(LAST_MODIFIED));
+        HttpFile { file_content: b"0123456789".to_vec(), file_extension: Some("txt".to_string()), http_headers, status: StatusCode::OK }
+    }
+    fn get(headers: &[(HeaderName, &str)]) -> HttpFile {
+        let mut request_header = HeaderMap::new();
+        for (name, value) in headers {
+            request_header.insert(name.clone(), HeaderValue::from_str(value).unwrap());
+        }
+        file().evaluate(&Method::GET, &request_header)
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(range("bytes=0-4", 10), Some(vec![(0, 4)]));
+        assert_eq!(range("bytes=5-20", 10), Some(vec![(5, 9)]));
+        assert_eq!(range("bytes=0-0, 8-9", 10), Some(vec![(0, 0), (8, 9)]));
+        assert_eq!(range("Bytes = 2-3", 10), Some(vec![(2, 3)]));
+    }
+    #[test]
+    fn suffix_ranges() {
+        assert_eq!(range("bytes=-3", 10), Some(vec![(7, 9)]));
+        assert_eq!(range("bytes=-30", 10), Some(vec![(0, 9)]));
+        assert_eq!(range("bytes=-0", 10), Some(vec![]));
+        assert_eq!(range("bytes=-3", 0), Some(vec![]));
+    }
+    #[test]
+    fn open_ranges() {
+        assert_eq!(range("bytes=4-", 10), Some(vec![(4, 9)]));
+        assert_eq!(range("bytes=9-", 10), Some(vec![(9, 9)]));
+        assert_eq!(range("bytes=10-", 10), Some(vec![]));
+    }
+    #[test]
+    fn unsatisfiable_ranges() {
+        assert_eq!(range("bytes=10-20", 10), Some(vec![]));
+        assert_eq!(range("bytes=10-20, 30-", 10), Some(vec![]));
+        assert_eq!(range("bytes=0-", 0), Some(vec![]));
+        /* one satisfiable is enough */
+        assert_eq!(range("bytes=10-20, 0-1", 10), Some(vec![(0, 1)]));
+    }
+    #[test]
+    fn invalid_ranges() {
+        assert_eq!(range("bytes=5-4", 10), None);
+        assert_eq!(range("bytes=a-4", 10), None);
+        assert_eq!(range("bytes=4", 10), None);
+        assert_eq!(range("bytes=", 10), None);
+        assert_eq!(range("items=0-4", 10), None);
+        assert_eq!(range("0-4", 10), None);
+    }
+    #[test]
+    fn too_many_ranges() {
+        let parts = |n: usize| (0..n).map(|i| format!("{}-{}", i, i)).collect::<Vec<String>>().join(",");
+        let max = crate::util::r#const::FILE_SERVER_MAX_RANGES;
+        assert_eq!(range(&format!("bytes={}", parts(max)), 100).map(|r| r.len()), Some(max));
+        assert_eq!(range(&format!("bytes={}", parts(max + 1)), 100), None);
+        let http_file = get(&[(header::RANGE, &format!("bytes={}", parts(max + 1)))]);
+        assert_eq!(http_file.status, StatusCode::OK);
+        assert_eq!(http_file.file_content, b"0123456789");
+    }
+    #[test]
+    fn evaluate_range() {
+        let http_file = get(&[(header::RANGE, "bytes=-3")]);
+        assert_eq!(http_file.status, StatusCode::PARTIAL_CONTENT);
+        assert_eq!(http_file.file_content, b"789");
+        assert_eq!(http_file.http_headers[header::CONTENT_RANGE], "bytes 7-9/10");
+        assert_eq!(http_file.http_headers[header::CONTENT_LENGTH], "3");
+    }
+    #[test]
+    fn evaluate_multipart() {
+        let http_file = get(&[(header::RANGE, "bytes=0-1,8-")]);
+        assert_eq!(http_file.status, StatusCode::PARTIAL_CONTENT);
+        let content_type = http_file.http_headers[header::CONTENT_TYPE].to_str().unwrap().to_string();
+        let boundary = content_type.strip_prefix("multipart/byteranges; boundary=").unwrap();
+        let body = String::from_utf8(http_file.file_content).unwrap();
+        assert_eq!(body, format!("\r\n--{b}\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-1/10\r\n\r\n01\r\n--{b}\r\nContent-Type: text/plain\r\nContent-Range: bytes 8-9/10\r\n\r\n89\r\n--{b}--\r\n", b = boundary));
+    }
+    #[test]
+    fn evaluate_unsatisfiable() {
+        let http_file = get(&[(header::RANGE, "bytes=10-")]);
+        assert_eq!(http_file.status, StatusCode::RANGE_NOT_SATISFIABLE);
+        assert!(http_file.file_content.is_empty());
+        assert_eq!(http_file.http_headers[header::CONTENT_RANGE], "bytes */10");
+        assert!(http_file.http_headers.get(header::CONTENT_TYPE).is_none());
+    }
+    #[test]
+    fn range_only_for_get() {
+        let mut request_header = HeaderMap::new();
+        request_header.insert(header::RANGE, HeaderValue::from_static("bytes=0-1"));
+        let http_file = file().evaluate(&Method::HEAD, &request_header);
+        assert_eq!(http_file.status, StatusCode::OK);
+    }
+    #[test]
+    fn if_range() {
+        assert_eq!(get(&[(header::RANGE, "bytes=0-1"), (header::IF_RANGE, ETAG)]).status, StatusCode::PARTIAL_CONTENT);
+        assert_eq!(get(&[(header::RANGE, "bytes=0-1"), (header::IF_RANGE, LAST_MODIFIED)]).status, StatusCode::PARTIAL_CONTENT);
+        /* a weak etag never matches, the whole file is given */
+        let http_file = get(&[(header::RANGE, "bytes=0-1"), (header::IF_RANGE, &format!("W/{}", ETAG))]);
+        assert_eq!(http_file.status, StatusCode::OK);
+        assert_eq!(http_file.file_content, b"0123456789");
+        assert_eq!(get(&[(header::RANGE, "bytes=0-1"), (header::IF_RANGE, "\"other\"")]).status, StatusCode::OK);
+        /* only the exact date */
+        assert_eq!(get(&[(header::RANGE, "bytes=0-1"), (header::IF_RANGE, "Sun, 13 Sep 2020 12:26:41 GMT")]).status, StatusCode::OK);
+        assert_eq!(get(&[(header::RANGE, "bytes=0-1"), (header::IF_RANGE, "Sun, 13 Sep 2020 12:26:39 GMT")]).status, StatusCode::OK);
+    }
+    #[test]
+    fn conditionals() {
+        let not_modified = get(&[(header::IF_NONE_MATCH, &format!("\"x\", W/{}", ETAG))]);
+        assert_eq!(not_modified.status, StatusCode::NOT_MODIFIED);
+        assert!(not_modified.file_content.is_empty());
+        assert_eq!(not_modified.http_headers[header::ETAG], ETAG);
+        assert!(not_modified.http_headers.get(header::CONTENT_LENGTH).is_none());
+        assert_eq!(get(&[(header::IF_MODIFIED_SINCE, LAST_MODIFIED)]).status, StatusCode::NOT_MODIFIED);
+        assert_eq!(get(&[(header::IF_MODIFIED_SINCE, "Sun, 13 Sep 2020 12:26:39 GMT")]).status, StatusCode::OK);
+        /* If-None-Match wins over If-Modified-Since */
+        assert_eq!(get(&[(header::IF_NONE_MATCH, "\"x\""), (header::IF_MODIFIED_SINCE, LAST_MODIFIED)]).status, StatusCode::OK);
+        assert_eq!(get(&[(header::IF_MATCH, ETAG)]).status, StatusCode::OK);
+        assert_eq!(get(&[(header::IF_MATCH, &format!("W/{}", ETAG))]).status, StatusCode::PRECONDITION_FAILED);
+        assert_eq!(get(&[(header::IF_UNMODIFIED_SINCE, "Sun, 13 Sep 2020 12:26:39 GMT")]).status, StatusCode::PRECONDITION_FAILED);
+        assert_eq!(get(&[(header::IF_MATCH, "*")]).status, StatusCode::OK);
+    }
+}
@@ -0,0 +1,133 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public
+License, v. 2.0. If a copy of the MPL was not distributed with this
+file, You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/* An ipv4 or ipv6 network such as 10.0.0.0/8 or fd00::/8. A single address is a network with the full prefix. */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct IpCidr {
+    pub(crate) addr: IpAddr,
+    pub(crate) prefix: u8,
+}
+impl IpCidr {
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        let (ip_str, prefix_str) = match s.split_once('/') {
+            Some((ip, prefix)) => (ip, Some(prefix)),
+            None => (s, None),
+        };
+        let addr = Self::canonical(ip_str.parse::<IpAddr>().ok()?);
+        let max = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        let prefix = match prefix_str {
+            Some(p) => {
+                let p = p.parse::<u8>().ok()?;
+                /* ::ffff:10.0.0.0/104 is written with the prefix of the ipv6 form */
+                if ip_str.contains(':') && max == 32 {
+                    p.checked_sub(96)?
+                } else {
+                    p
+                }
+            }
+            None => max,
+        };
+        if prefix > max {
+            return None;
+        }
+        Some(Self { addr: Self::mask(addr, prefix), prefix })
+    }
+    pub(crate) fn contains(&self, ip: &IpAddr) -> bool {
+        let ip = Self::canonical(*ip);
+        match (self.addr, ip) {
+            (IpAddr::V4(_), IpAddr::V4(_)) | (IpAddr::V6(_), IpAddr::V6(_)) => {
+                Self::mask(ip, self.prefix) == self.addr
+            }
+            _ => false,
+        }
+    }
+    /* an ipv4-mapped ipv6 address (::ffff:a.b.c.d) is treated as the ipv4 address */
+    pub(crate) fn canonical(ip: IpAddr) -> IpAddr {
+        match ip {
+            IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+                Some(v4) => IpAddr::V4(v4),
+                None => IpAddr::V6(v6),
+            },
+            v4 => v4,
+        }
+    }
+    fn mask(ip: IpAddr, prefix: u8) -> IpAddr {
+        match ip {
+            IpAddr::V4(v4) => {
+                let bits = u32::from(v4);
+                let mask = if prefix == 0 { 0 } else { u32::MAX << (32 - prefix as u32) };
+                IpAddr::V4(Ipv4Addr::from(bits & mask))
+            }
+            IpAddr::V6(v6) => {
+                let bits = u128::from(v6);
+                let mask = if prefix == 0 { 0 } else { u128::MAX << (128 - prefix as u32) };
+                IpAddr::V6(Ipv6Addr::from(bits & mask))
+            }
+        }
+    }
+}
+impl std::fmt::Display for IpCidr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parse() {
+        let cidr = IpCidr::parse(" 10.1.2.3/8 ").unwrap();
+        assert_eq!(cidr.to_string(), "10.0.0.0/8");
+        assert_eq!(IpCidr::parse("10.1.2.3").unwrap().to_string(), "10.1.2.3/32");
+        assert_eq!(IpCidr::parse("fd00::1/8").unwrap().to_string(), "fd00::/8");
+        assert_eq!(IpCidr::parse("::1").unwrap().to_string(), "::1/128");
+        assert!(IpCidr::parse("10.0.0.0/33").is_none());
+        assert!(IpCidr::parse("fd00::/129").is_none());
+        assert!(IpCidr::parse("10.0.0.0/-1").is_none());
+        assert!(IpCidr::parse("10.0.0.0/").is_none());
+        assert!(IpCidr::parse("10.0.0/8").is_none());
+    }
+    #[test]
+    fn parse_v4_mapped() {
+        /* the prefix of the ipv6 form is rewritten to the ipv4 one */
+        assert_eq!(IpCidr::parse("::ffff:10.1.2.3/104").unwrap().to_string(), "10.0.0.0/8");
+        assert_eq!(IpCidr::parse("::ffff:10.1.2.3").unwrap().to_string(), "10.1.2.3/32");
+        assert_eq!(IpCidr::parse("::ffff:10.1.2.3/128").unwrap().to_string(), "10.1.2.3/32");
+        assert!(IpCidr::parse("::ffff:10.1.2.3/95").is_none());
+        assert!(IpCidr::parse("::ffff:10.1.2.3/129").is_none());
+    }
+    #[test]
+    fn contains() {
+        let cidr = IpCidr::parse("192.168.0.0/16").unwrap();
+        assert!(cidr.contains(&ip("192.168.255.1")));
+        assert!(cidr.contains(&ip("::ffff:192.168.0.1")));
+        assert!(!cidr.contains(&ip("192.169.0.1")));
+        assert!(!cidr.contains(&ip("fd00::1")));
+        let cidr = IpCidr::parse("fd00::/8").unwrap();
+        assert!(cidr.contains(&ip("fdff::1")));
+        assert!(!cidr.contains(&ip("fe00::1")));
+        assert!(!cidr.contains(&ip("10.0.0.1")));
+        /* /0 matches its own family only */
+        let cidr = IpCidr::parse("0.0.0.0/0").unwrap();
+        assert!(cidr.contains(&ip("1.2.3.4")));
+        assert!(!cidr.contains(&ip("::1")));
+        let cidr = IpCidr::parse("::/0").unwrap();
+        assert!(cidr.contains(&ip("::1")));
+        assert!(!cidr.contains(&ip("1.2.3.4")));
+    }
+}
@@ -4,61 +4,61 @@ License, v. 2.0. If a copy of the MPL was not distributed with this
 file, You can obtain one at https://mozilla.org/MPL/2.0/.
 */
 
-#[derive(Debug, Clone)]
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use super::ip_cidr::IpCidr;
+
+/* start_ip-end_ip, both ends included. Both ends are of the same family. */
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub(crate) struct IpRange {
-    pub(crate) start_ip: (u8, u8, u8, u8),
-    pub(crate) ip_s: u32,
-    pub(crate) end_ip: (u8, u8, u8, u8),
-    pub(crate) ip_e: u32,
+    pub(crate) start_ip: IpAddr,
+    pub(crate) end_ip: IpAddr,
 }
 impl IpRange {
-    pub(crate) fn new(a: u8, b: u8, c: u8, d: u8, e: u8, f: u8, g: u8, h: u8) -> Self {
-        let mut ip_s: u32 = 0;
-        ip_s = ip_s << 8 | a as u32;
-        ip_s = ip_s << 8 | b as u32;
-        ip_s = ip_s << 8 | c as u32;
-        ip_s = ip_s << 8 | d as u32;
-        let mut ip_e: u32 = 0;
-        ip_e = ip_e << 8 | e as u32;
-        ip_e = ip_e << 8 | f as u32;
-        ip_e = ip_e << 8 | g as u32;
-        ip_e = ip_e << 8 | h as u32;
-        Self { 
-            start_ip: (a, b, c, d, ),
-            ip_s,
-            end_ip: (e, f, g, h, ),
-            ip_e,
-        }
+    pub(crate) fn new(start_ip: IpAddr, end_ip: IpAddr) -> Option<Self> {
+        let start_ip = IpCidr::canonical(start_ip);
+        let end_ip = IpCidr::canonical(end_ip);
+        let ok = match (start_ip, end_ip) {
+            (IpAddr::V4(s), IpAddr::V4(e)) => s <= e,
+            (IpAddr::V6(s), IpAddr::V6(e)) => s <= e,
+            _ => false,
+        };
+        if ok { Some(Self { start_ip, end_ip }) } else { None }
     }
-    pub(crate) fn check(&self) -> bool {
-        if self.start_ip.0 > self.end_ip.0 {
-            return false;
-        }
-        if self.start_ip.1 > self.end_ip.1 {
-            return false;
-        }
-        if self.start_ip.2 > self.end_ip.2 {
-            return false;
+    pub(crate) fn is_in_the_range(&self, ip_addr: &IpAddr) -> bool {
+        match (self.start_ip, self.end_ip, IpCidr::canonical(*ip_addr)) {
+            (IpAddr::V4(s), IpAddr::V4(e), IpAddr::V4(ip)) => s <= ip && ip <= e,
+            (IpAddr::V6(s), IpAddr::V6(e), IpAddr::V6(ip)) => s <= ip && ip <= e,
+            _ => false,
         }
-        if self.start_ip.3 > self.end_ip.3 {
-            return false;
-        }
-        true
     }
-    pub(crate) fn is_in_the_range(&self, ip_addr: &str) -> bool {
-        if self.ip_s == 0 && self.ip_e == 0 {
-            return true;
-        }
-        let ips: Vec<u8> = ip_addr.split(".").map(|item| {
-            item.parse::<u8>().unwrap()
-        }).collect();
-        if ips.len() != 4 {
-            return false;
+    /* the fewest networks that cover exactly this range, e.g. 10.0.0.0-10.0.1.255 is 10.0.0.0/23 */
+    pub(crate) fn to_cidrs(self) -> Vec<IpCidr> {
+        let (mut start, end, bits) = match (self.start_ip, self.end_ip) {
+            (IpAddr::V4(s), IpAddr::V4(e)) => (u32::from(s) as u128, u32::from(e) as u128, 32),
+            (IpAddr::V6(s), IpAddr::V6(e)) => (u128::from(s), u128::from(e), 128),
+            _ => { return Vec::new(); }
+        };
+        let mut cidrs = Vec::new();
+        loop {
+            /* the biggest block aligned on start that does not go beyond end */
+            let mut size = if start == 0 { bits } else { start.trailing_zeros().min(bits) };
+            let block = |size: u32| if size >= 128 { u128::MAX } else { (1u128 << size) - 1 };
+            while start.checked_add(block(size)).is_none_or(|last| last > end) {
+                size -= 1;
+            }
+            let addr = if bits == 32 {
+                IpAddr::V4(Ipv4Addr::from(start as u32))
+            } else {
+                IpAddr::V6(Ipv6Addr::from(start))
+            };
+            cidrs.push(IpCidr { addr, prefix: (bits - size) as u8 });
+            let last = start + block(size);
+            if last >= end {
+                break;
+            }
+            start = last + 1;
         }
-        let mut ipt: u32 = 0;
-        for i in ips {
-            ipt = ipt << 8 | i as u32;
-        }
-        self.ip_s <= ipt && ipt <= self.ip_e
+        cidrs
     }
-}
\ No newline at end of file
+}
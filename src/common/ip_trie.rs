@@ -0,0 +1,144 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public
+License, v. 2.0. If a copy of the MPL was not distributed with this
+file, You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+use std::net::IpAddr;
+
+use super::ip_cidr::IpCidr;
+
+#[derive(Debug, Clone, Default)]
+struct TrieNode {
+    children: [u32; 2],     //index in nodes, 0 means none since the root is never a child
+    terminal: bool,         //a network ends here, everything below is matched
+}
+/* A binary prefix trie, one bit per level. The lookup costs at most 32 (ipv4) or 128 (ipv6) steps whatever the size of the list. */
+#[derive(Debug, Clone)]
+struct Trie {
+    nodes: Vec<TrieNode>,
+}
+impl Trie {
+    fn new() -> Self {
+        Self { nodes: vec![TrieNode::default()] }
+    }
+    fn insert(&mut self, bits: u128, width: u32, prefix: u32) {
+        let mut index = 0;
+        for i in 0..prefix {
+            if self.nodes[index].terminal {
+                return;     //already covered by a wider network
+            }
+            let bit = ((bits >> (width - 1 - i)) & 1) as usize;
+            let next = self.nodes[index].children[bit];
+            index = if next == 0 {
+                self.nodes.push(TrieNode::default());
+                let new_index = (self.nodes.len() - 1) as u32;
+                self.nodes[index].children[bit] = new_index;
+                new_index as usize
+            } else {
+                next as usize
+            };
+        }
+        self.nodes[index].terminal = true;
+        self.nodes[index].children = [0, 0];
+    }
+    fn contains(&self, bits: u128, width: u32) -> bool {
+        let mut index = 0;
+        for i in 0..width {
+            if self.nodes[index].terminal {
+                return true;
+            }
+            let bit = ((bits >> (width - 1 - i)) & 1) as usize;
+            match self.nodes[index].children[bit] {
+                0 => { return false; }
+                next => { index = next as usize; }
+            }
+        }
+        self.nodes[index].terminal
+    }
+}
+#[derive(Debug, Clone)]
+pub(crate) struct IpTrie {
+    v4: Trie,
+    v6: Trie,
+}
+impl Default for IpTrie {
+    fn default() -> Self {
+        Self { v4: Trie::new(), v6: Trie::new() }
+    }
+}
+impl IpTrie {
+    pub(crate) fn insert(&mut self, cidr: &IpCidr) {
+        match cidr.addr {
+            IpAddr::V4(v4) => { self.v4.insert(u32::from(v4) as u128, 32, cidr.prefix as u32) }
+            IpAddr::V6(v6) => { self.v6.insert(u128::from(v6), 128, cidr.prefix as u32) }
+        }
+    }
+    pub(crate) fn contains(&self, ip: &IpAddr) -> bool {
+        match IpCidr::canonical(*ip) {
+            IpAddr::V4(v4) => { self.v4.contains(u32::from(v4) as u128, 32) }
+            IpAddr::V6(v6) => { self.v6.contains(u128::from(v6), 128) }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trie(cidrs: &[&str]) -> IpTrie {
+        let mut trie = IpTrie::default();
+        for cidr in cidrs {
+            trie.insert(&IpCidr::parse(cidr).unwrap());
+        }
+        trie
+    }
+    fn contains(trie: &IpTrie, ip: &str) -> bool {
+        trie.contains(&ip.parse().unwrap())
+    }
+
+    #[test]
+    fn lookup() {
+        let trie = trie(&["10.0.0.0/8", "192.168.1.7", "fd00::/8"]);
+        assert!(contains(&trie, "10.255.0.1"));
+        assert!(contains(&trie, "192.168.1.7"));
+        assert!(!contains(&trie, "192.168.1.8"));
+        assert!(!contains(&trie, "11.0.0.1"));
+        assert!(contains(&trie, "fd12::1"));
+        assert!(!contains(&trie, "fe00::1"));
+        assert!(!contains(&trie, "::1"));
+        assert!(!contains(&IpTrie::default(), "10.0.0.1"));
+    }
+    #[test]
+    fn wider_after_narrower() {
+        let trie = trie(&["10.1.2.0/24", "10.1.3.4", "10.0.0.0/8", "10.2.0.0/16"]);
+        assert!(contains(&trie, "10.1.2.3"));
+        assert!(contains(&trie, "10.1.3.5"));
+        assert!(contains(&trie, "10.200.0.1"));
+        assert!(!contains(&trie, "11.1.2.3"));
+    }
+    #[test]
+    fn narrower_after_wider() {
+        let trie = trie(&["10.0.0.0/8", "10.1.2.0/24"]);
+        assert!(contains(&trie, "10.9.9.9"));
+        assert!(contains(&trie, "10.1.2.3"));
+    }
+    #[test]
+    fn v4_mapped() {
+        let trie = trie(&["10.0.0.0/8", "::ffff:192.168.0.0/112"]);
+        assert!(contains(&trie, "::ffff:10.1.2.3"));
+        assert!(contains(&trie, "192.168.3.4"));
+        assert!(contains(&trie, "::ffff:192.168.3.4"));
+        assert!(!contains(&trie, "::ffff:11.1.2.3"));
+    }
+    #[test]
+    fn zero_prefix() {
+        let v4 = trie(&["0.0.0.0/0"]);
+        assert!(contains(&v4, "1.2.3.4"));
+        assert!(contains(&v4, "::ffff:1.2.3.4"));
+        assert!(!contains(&v4, "fd00::1"));
+        let v6 = trie(&["::/0"]);
+        assert!(contains(&v6, "fd00::1"));
+        assert!(!contains(&v6, "1.2.3.4"));
+    }
+}
@@ -0,0 +1,341 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public
+License, v. 2.0. If a copy of the MPL was not distributed with this
+file, You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::Engine;
+use ring::signature;
+use serde_json::Value;
+
+use crate::error::RResult;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum JwtAlgorithm {
+    HS256,
+    RS256,
+    ES256,
+}
+impl JwtAlgorithm {
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        match s {
+            "HS256" => Some(JwtAlgorithm::HS256),
+            "RS256" => Some(JwtAlgorithm::RS256),
+            "ES256" => Some(JwtAlgorithm::ES256),
+            _ => None,
+        }
+    }
+}
+#[derive(Debug, Clone)]
+enum KeyMaterial {
+    Hmac(Vec<u8>),
+    Rsa { n: Vec<u8>, e: Vec<u8> },
+    Ec(Vec<u8>),     //uncompressed point 0x04|x|y of P-256
+}
+#[derive(Debug, Clone)]
+pub(crate) struct JwtKey {
+    kid: Option<String>,
+    algorithm: JwtAlgorithm,
+    material: KeyMaterial,
+}
+impl JwtKey {
+    pub(crate) fn secret(secret: &str) -> Self {
+        Self { kid: None, algorithm: JwtAlgorithm::HS256, material: KeyMaterial::Hmac(secret.as_bytes().to_vec()) }
+    }
+    fn verify(&self, message: &[u8], sig: &[u8]) -> bool {
+        match &self.material {
+            KeyMaterial::Hmac(secret) => {
+                let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, secret);
+                ring::hmac::verify(&key, message, sig).is_ok()
+            }
+            KeyMaterial::Rsa { n, e } => {
+                signature::RsaPublicKeyComponents { n, e }.verify(&signature::RSA_PKCS1_2048_8192_SHA256, message, sig).is_ok()
+            }
+            KeyMaterial::Ec(point) => {
+                signature::UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_FIXED, point).verify(message, sig).is_ok()
+            }
+        }
+    }
+}
+/* The keys of a JWKS document (RFC 7517). Keys of other types or curves are skipped. */
+pub(crate) fn parse_jwks(data: &[u8]) -> RResult<Vec<JwtKey>> {
+    let jwks: Value = serde_json::from_slice(data)?;
+    let keys = jwks.get("keys").and_then(|k| k.as_array()).ok_or("jwks: keys not found")?;
+    let mut ret = Vec::new();
+    for key in keys {
+        let field = |name: &str| key.get(name).and_then(|v| v.as_str());
+        let bytes = |name: &str| field(name).and_then(|v| b64(v).ok());
+        if field("use").is_some_and(|u| u != "sig") {
+            continue;
+        }
+        let kid = field("kid").map(|k| k.to_string());
+        let parsed = match field("kty") {
+            Some("oct") => {
+                bytes("k").map(|k| (JwtAlgorithm::HS256, KeyMaterial::Hmac(k)))
+            }
+            Some("RSA") => {
+                match (bytes("n"), bytes("e")) {
+                    (Some(n), Some(e)) => Some((JwtAlgorithm::RS256, KeyMaterial::Rsa { n, e })),
+                    _ => None,
+                }
+            }
+            Some("EC") if field("crv") == Some("P-256") => {
+                match (bytes("x"), bytes("y")) {
+                    (Some(x), Some(y)) if x.len() == 32 && y.len() == 32 => {
+                        let mut point = vec![0x04];
+                        point.extend_from_slice(&x);
+                        point.extend_from_slice(&y);
+                        Some((JwtAlgorithm::ES256, KeyMaterial::Ec(point)))
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        match parsed {
+            Some((algorithm, material)) => {
+                if field("alg").is_some_and(|alg| JwtAlgorithm::parse(alg) != Some(algorithm)) {
+                    log::warn!("jwks: key {:?} skipped, alg {:?} is not supported", kid, field("alg"));
+                    continue;
+                }
+                ret.push(JwtKey { kid, algorithm, material });
+            }
+            None => {
+                log::warn!("jwks: key {:?} skipped, kty {:?} is not supported or a field is missing", kid, field("kty"));
+            }
+        }
+    }
+    Ok(ret)
+}
+#[derive(Debug, Clone, Default)]
+pub(crate) struct JwtValidation {
+    pub(crate) algorithms: Vec<JwtAlgorithm>,   //empty means any algorithm of the keys
+    pub(crate) issuer: Vec<String>,
+    pub(crate) audience: Vec<String>,
+    pub(crate) leeway: Duration,
+}
+/*
+Check the signature and the registered claims, and give back the claims.
+The algorithm comes from the header of the token, but it must match the key, so a token signed with HS256 using a public key is refused.
+ */
+pub(crate) fn verify(token: &str, keys: &[JwtKey], validation: &JwtValidation) -> Result<Value, &'static str> {
+    let mut parts = token.split('.');
+    let (header, payload, sig) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(h), Some(p), Some(s), None) => (h, p, s),
+        _ => { return Err("malformed token"); }
+    };
+    let header_json: Value = b64(header).ok().and_then(|h| serde_json::from_slice(&h).ok()).ok_or("malformed header")?;
+    let algorithm = header_json.get("alg").and_then(|a| a.as_str()).and_then(JwtAlgorithm::parse).ok_or("unsupported alg")?;
+    if !validation.algorithms.is_empty() && !validation.algorithms.contains(&algorithm) {
+        return Err("unsupported alg");
+    }
+    let kid = header_json.get("kid").and_then(|k| k.as_str());
+    let sig = b64(sig).map_err(|_| "malformed signature")?;
+    let message = &token.as_bytes()[..header.len() + 1 + payload.len()];
+    let verified = keys.iter()
+        .filter(|key| key.algorithm == algorithm)
+        .filter(|key| kid.is_none() || key.kid.is_none() || key.kid.as_deref() == kid)
+        .any(|key| key.verify(message, &sig));
+    if !verified {
+        return Err("invalid signature");
+    }
+    let claims: Value = b64(payload).ok().and_then(|p| serde_json::from_slice(&p).ok()).ok_or("malformed payload")?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64();
+    let leeway = validation.leeway.as_secs_f64();
+    if let Some(exp) = claims.get("exp") {
+        if exp.as_f64().is_none_or(|exp| now > exp + leeway) {
+            return Err("token expired");
+        }
+    }
+    if let Some(nbf) = claims.get("nbf") {
+        if nbf.as_f64().is_none_or(|nbf| now + leeway < nbf) {
+            return Err("token not yet valid");
+        }
+    }
+    if !validation.issuer.is_empty() {
+        let iss = claims.get("iss").and_then(|i| i.as_str());
+        if !iss.is_some_and(|iss| validation.issuer.iter().any(|i| i == iss)) {
+            return Err("invalid issuer");
+        }
+    }
+    if !validation.audience.is_empty() {
+        let ok = match claims.get("aud") {
+            Some(Value::String(aud)) => validation.audience.contains(aud),
+            Some(Value::Array(auds)) => auds.iter().filter_map(|a| a.as_str()).any(|aud| validation.audience.iter().any(|a| a == aud)),
+            _ => false,
+        };
+        if !ok {
+            return Err("invalid audience");
+        }
+    }
+    Ok(claims)
+}
+/* a claim by its name, or by a path in nested objects such as realm_access.roles */
+pub(crate) fn claim<'a>(claims: &'a Value, name: &str) -> Option<&'a Value> {
+    if let Some(v) = claims.get(name) {
+        return Some(v);
+    }
+    name.split('.').try_fold(claims, |v, part| v.get(part))
+}
+fn b64(s: &str) -> Result<Vec<u8>, base64::DecodeError> {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(s.trim_end_matches('='))
+}
+
+#[cfg(test)]
+mod tests {
+    use ring::signature::KeyPair;
+    use serde_json::json;
+
+    use super::*;
+
+    fn enc(data: &[u8]) -> String {
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(data)
+    }
+    fn unsigned(header: &Value, claims: &Value) -> String {
+        format!("{}.{}", enc(header.to_string().as_bytes()), enc(claims.to_string().as_bytes()))
+    }
+    fn hs256(secret: &[u8], header: &Value, claims: &Value) -> String {
+        let message = unsigned(header, claims);
+        let sig = ring::hmac::sign(&ring::hmac::Key::new(ring::hmac::HMAC_SHA256, secret), message.as_bytes());
+        format!("{}.{}", message, enc(sig.as_ref()))
+    }
+    fn now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+    }
+    fn hs_token(claims: &Value) -> String {
+        hs256(b"secret", &json!({ "alg": "HS256", "typ": "JWT" }), claims)
+    }
+    fn check(token: &str, validation: &JwtValidation) -> Result<Value, &'static str> {
+        verify(token, &[JwtKey::secret("secret")], validation)
+    }
+    /* a P-256 key pair, with its jwks */
+    fn ec_key(kid: &str) -> (signature::EcdsaKeyPair, Vec<u8>) {
+        let rng = ring::rand::SystemRandom::new();
+        let pkcs8 = signature::EcdsaKeyPair::generate_pkcs8(&signature::ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let pair = signature::EcdsaKeyPair::from_pkcs8(&signature::ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+        let point = pair.public_key().as_ref();
+        let jwks = json!({ "keys": [{ "kty": "EC", "crv": "P-256", "kid": kid, "use": "sig", "x": enc(&point[1..33]), "y": enc(&point[33..]) }] });
+        (pair, jwks.to_string().into_bytes())
+    }
+    fn es256(pair: &signature::EcdsaKeyPair, kid: &str, claims: &Value) -> String {
+        let message = unsigned(&json!({ "alg": "ES256", "kid": kid }), claims);
+        let sig = pair.sign(&ring::rand::SystemRandom::new(), message.as_bytes()).unwrap();
+        format!("{}.{}", message, enc(sig.as_ref()))
+    }
+
+    #[test]
+    fn hs256_valid() {
+        let claims = check(&hs_token(&json!({ "sub": "alice", "exp": now() + 60 })), &JwtValidation::default()).unwrap();
+        assert_eq!(claims["sub"], "alice");
+        assert_eq!(check(&hs256(b"other", &json!({ "alg": "HS256" }), &json!({ "sub": "alice" })), &JwtValidation::default()), Err("invalid signature"));
+    }
+    #[test]
+    fn es256_jwks() {
+        let (pair, jwks) = ec_key("k1");
+        let keys = parse_jwks(&jwks).unwrap();
+        assert_eq!(keys.len(), 1);
+        let token = es256(&pair, "k1", &json!({ "sub": "alice" }));
+        assert_eq!(verify(&token, &keys, &JwtValidation::default()).unwrap()["sub"], "alice");
+        /* another kid is not tried */
+        let token = es256(&pair, "k2", &json!({ "sub": "alice" }));
+        assert_eq!(verify(&token, &keys, &JwtValidation::default()), Err("invalid signature"));
+        /* the algorithms allowed by the config */
+        let validation = JwtValidation { algorithms: vec![JwtAlgorithm::RS256], ..Default::default() };
+        assert_eq!(verify(&es256(&pair, "k1", &json!({})), &keys, &validation), Err("unsupported alg"));
+    }
+    #[test]
+    fn alg_key_mismatch() {
+        /* HS256 signed with the public key as the secret */
+        let (n, e) = (vec![0xc5; 256], vec![0x01, 0x00, 0x01]);
+        let jwks = json!({ "keys": [{ "kty": "RSA", "kid": "r", "n": enc(&n), "e": enc(&e) }] }).to_string();
+        let keys = parse_jwks(jwks.as_bytes()).unwrap();
+        assert_eq!(keys.len(), 1);
+        let token = hs256(&n, &json!({ "alg": "HS256", "kid": "r" }), &json!({ "sub": "mallory" }));
+        assert_eq!(verify(&token, &keys, &JwtValidation::default()), Err("invalid signature"));
+        let (_, jwks) = ec_key("e");
+        let keys = parse_jwks(&jwks).unwrap();
+        let point = match &keys[0].material { KeyMaterial::Ec(point) => point.clone(), _ => unreachable!() };
+        let token = hs256(&point, &json!({ "alg": "HS256", "kid": "e" }), &json!({ "sub": "mallory" }));
+        assert_eq!(verify(&token, &keys, &JwtValidation::default()), Err("invalid signature"));
+        /* and RS256 against the secret */
+        let token = format!("{}.{}", unsigned(&json!({ "alg": "RS256" }), &json!({})), enc(&[0; 256]));
+        assert_eq!(check(&token, &JwtValidation::default()), Err("invalid signature"));
+    }
+    #[test]
+    fn alg_none() {
+        let claims = json!({ "sub": "mallory" });
+        for alg in ["none", "None", "NONE", ""] {
+            let token = format!("{}.", unsigned(&json!({ "alg": alg }), &claims));
+            assert_eq!(check(&token, &JwtValidation::default()), Err("unsupported alg"));
+        }
+        let token = format!("{}.", unsigned(&json!({ "typ": "JWT" }), &claims));
+        assert_eq!(check(&token, &JwtValidation::default()), Err("unsupported alg"));
+        /* and a valid alg without the signature */
+        let token = format!("{}.", unsigned(&json!({ "alg": "HS256" }), &claims));
+        assert_eq!(check(&token, &JwtValidation::default()), Err("invalid signature"));
+    }
+    #[test]
+    fn malformed_token() {
+        assert_eq!(check("", &JwtValidation::default()), Err("malformed token"));
+        assert_eq!(check("a.b", &JwtValidation::default()), Err("malformed token"));
+        assert_eq!(check("a.b.c.d", &JwtValidation::default()), Err("malformed token"));
+        assert_eq!(check("!!.b.c", &JwtValidation::default()), Err("malformed header"));
+        /* a signed payload that is not json */
+        let message = format!("{}.{}", enc(br#"{"alg":"HS256"}"#), enc(b"not json"));
+        let sig = ring::hmac::sign(&ring::hmac::Key::new(ring::hmac::HMAC_SHA256, b"secret"), message.as_bytes());
+        assert_eq!(check(&format!("{}.{}", message, enc(sig.as_ref())), &JwtValidation::default()), Err("malformed payload"));
+    }
+    #[test]
+    fn exp_and_nbf() {
+        let validation = JwtValidation::default();
+        assert_eq!(check(&hs_token(&json!({ "exp": now() - 10 })), &validation), Err("token expired"));
+        assert_eq!(check(&hs_token(&json!({ "exp": "tomorrow" })), &validation), Err("token expired"));
+        assert_eq!(check(&hs_token(&json!({ "nbf": now() + 10 })), &validation), Err("token not yet valid"));
+        assert!(check(&hs_token(&json!({ "nbf": now() - 10, "exp": now() + 10 })), &validation).is_ok());
+        let leeway = JwtValidation { leeway: Duration::from_secs(30), ..Default::default() };
+        assert!(check(&hs_token(&json!({ "exp": now() - 10 })), &leeway).is_ok());
+        assert!(check(&hs_token(&json!({ "nbf": now() + 10 })), &leeway).is_ok());
+        assert_eq!(check(&hs_token(&json!({ "exp": now() - 60 })), &leeway), Err("token expired"));
+        assert_eq!(check(&hs_token(&json!({ "nbf": now() + 60 })), &leeway), Err("token not yet valid"));
+    }
+    #[test]
+    fn issuer_and_audience() {
+        let validation = JwtValidation { issuer: vec!["https://auth".to_string()], audience: vec!["api".to_string()], ..Default::default() };
+        assert!(check(&hs_token(&json!({ "iss": "https://auth", "aud": "api" })), &validation).is_ok());
+        assert!(check(&hs_token(&json!({ "iss": "https://auth", "aud": ["web", "api"] })), &validation).is_ok());
+        assert_eq!(check(&hs_token(&json!({ "iss": "https://evil", "aud": "api" })), &validation), Err("invalid issuer"));
+        assert_eq!(check(&hs_token(&json!({ "aud": "api" })), &validation), Err("invalid issuer"));
+        assert_eq!(check(&hs_token(&json!({ "iss": "https://auth", "aud": "web" })), &validation), Err("invalid audience"));
+        assert_eq!(check(&hs_token(&json!({ "iss": "https://auth", "aud": ["web"] })), &validation), Err("invalid audience"));
+        assert_eq!(check(&hs_token(&json!({ "iss": "https://auth" })), &validation), Err("invalid audience"));
+    }
+    #[test]
+    fn malformed_jwks() {
+        assert!(parse_jwks(b"not json").is_err());
+        assert!(parse_jwks(b"{}").is_err());
+        assert!(parse_jwks(br#"{"keys": {}}"#).is_err());
+        let keys = parse_jwks(json!({ "keys": [
+            { "kty": "RSA", "n": "AQAB" },                                      //no e
+            { "kty": "RSA", "n": "!!", "e": "AQAB" },                           //not base64
+            { "kty": "RSA", "n": "AQAB", "e": "AQAB", "alg": "HS256" },         //alg of another kty
+            { "kty": "RSA", "n": "AQAB", "e": "AQAB", "use": "enc" },
+            { "kty": "EC", "crv": "P-384", "x": enc(&[1; 48]), "y": enc(&[1; 48]) },
+            { "kty": "EC", "crv": "P-256", "x": enc(&[1; 31]), "y": enc(&[1; 32]) },
+            { "kty": "OKP", "crv": "Ed25519", "x": enc(&[1; 32]) },
+            "a key",
+            { "kty": "oct", "k": enc(b"secret"), "alg": "HS256" },
+        ] }).to_string().as_bytes()).unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].algorithm, JwtAlgorithm::HS256);
+    }
+    #[test]
+    fn claims() {
+        let claims = json!({ "sub": "alice", "realm_access": { "roles": ["a"] }, "a.b": 1 });
+        assert_eq!(claim(&claims, "sub"), Some(&json!("alice")));
+        assert_eq!(claim(&claims, "realm_access.roles"), Some(&json!(["a"])));
+        assert_eq!(claim(&claims, "a.b"), Some(&json!(1)));
+        assert_eq!(claim(&claims, "realm_access.none"), None);
+    }
+}
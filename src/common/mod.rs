@@ -5,8 +5,13 @@ file, You can obtain one at https://mozilla.org/MPL/2.0/.
 */
 
 pub(crate) mod max_fails_durn;
+pub(crate) mod circuit_breaker;
 pub(crate) mod ip_range;
+pub(crate) mod ip_cidr;
+pub(crate) mod ip_trie;
 pub(crate) mod ratelimiter;
+pub(crate) mod jwt;
+pub(crate) mod htpasswd;
 pub(crate) mod redis;
 pub(crate) mod http_file;
 pub(crate) mod content_type;
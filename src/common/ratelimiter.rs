@@ -4,42 +4,264 @@ License, v. 2.0. If a copy of the MPL was not distributed with this
 file, You can obtain one at https://mozilla.org/MPL/2.0/.
 */
 
-use governor::{clock::QuantaInstant, NotUntil};
+use std::time::Duration;
 
-use crate::modules::ratelimiter::RatelimiterType;
+use governor::clock::Clock;
+
+use crate::{common::redis::Redis, error::RResult, modules::ratelimiter::RatelimiterType};
+
+/*
+GCRA, the same algorithm as governor, so a key behaves the same in memory and in redis.
+The time of redis is used, the clocks of the gateways do not need to agree. Times are in microseconds.
+Returns allowed (1/0), remaining, retry_after, reset.
+ */
+const GCRA_SCRIPT: &str = r#"
+if redis.replicate_commands then redis.replicate_commands() end
+local period = tonumber(ARGV[1])
+local tau = period * tonumber(ARGV[2])
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000000 + tonumber(time[2])
+local tat = tonumber(redis.call('GET', KEYS[1])) or now
+if tat < now then tat = now end
+local new_tat = tat + period
+if new_tat - tau > now then
+    return {0, 0, new_tat - tau - now, tat - now}
+end
+redis.call('SET', KEYS[1], string.format('%d', new_tat), 'PX', math.ceil((new_tat - now) / 1000))
+return {1, math.floor((now + tau - new_tat) / period), 0, new_tat - now}
+"#;
 
 
 #[derive(Debug)]
 pub(crate) struct RatelimiterCommon {
     pub(crate) ratelimiter: 
         Option<
-            governor::RateLimiter<String, dashmap::DashMap<String, governor::state::InMemoryState>, governor::clock::QuantaClock, governor::middleware::NoOpMiddleware<governor::clock::QuantaInstant>>
+            governor::RateLimiter<String, dashmap::DashMap<String, governor::state::InMemoryState>, governor::clock::QuantaClock, governor::middleware::StateInformationMiddleware>
         >
     ,
     pub(crate) ratelimiter_type: RatelimiterType,
+    pub(crate) redis: Option<RedisRatelimiter>,     //shared by every gateway, the governor above is then only the fallback
 }
 impl Default for RatelimiterCommon {
     fn default() -> Self {
         Self {
             ratelimiter: None,
             ratelimiter_type: RatelimiterType::Service,
+            redis: None,
         }
     }
 }
 impl RatelimiterCommon {
     pub(crate) fn new(
         ratelimiter: Option<
-            governor::RateLimiter<String, dashmap::DashMap<String, governor::state::InMemoryState>, governor::clock::QuantaClock, governor::middleware::NoOpMiddleware<governor::clock::QuantaInstant>>
+            governor::RateLimiter<String, dashmap::DashMap<String, governor::state::InMemoryState>, governor::clock::QuantaClock, governor::middleware::StateInformationMiddleware>
         >,
         ratelimiter_type: RatelimiterType,
+        redis: Option<RedisRatelimiter>,
     ) -> Self {
-        Self { ratelimiter, ratelimiter_type }
+        Self { ratelimiter, ratelimiter_type, redis }
+    }
+    /* with the redis backend, the limit is shared by the whole cluster. When redis fails, the fail_policy decides. */
+    pub(crate) async fn check(&self, key: &String) -> Result<RatelimiterState, RatelimiterState> {
+        if let Some(redis) = &self.redis {
+            match tokio::time::timeout(crate::util::r#const::RATELIMITER_REDIS_TIMEOUT, redis.check_key(key)).await {
+                Ok(Ok(ret)) => { return ret; }
+                Ok(Err(e)) => { log::warn!("ratelimiter redis failed, {:?}:{}", redis.fail_policy, e); }
+                Err(_) => { log::warn!("ratelimiter redis timeout, {:?}", redis.fail_policy); }
+            }
+            match redis.fail_policy {
+                FailPolicy::Local => {}
+                FailPolicy::Open => {
+                    return Ok(RatelimiterState { limit: redis.burst, remaining: redis.burst, reset: Duration::ZERO, retry_after: Duration::ZERO });
+                }
+                FailPolicy::Closed => {
+                    return Err(RatelimiterState { limit: redis.burst, remaining: 0, reset: redis.period, retry_after: redis.period });
+                }
+            }
+        }
+        self.check_key(key)
     }
-    pub(crate) fn check_key(&self, key: &String) -> Result<(), NotUntil<QuantaInstant>> {
+    /* a full bucket is the same as no bucket, it can go */
+    pub(crate) fn retain_recent(&self) {
         if let Some(lim) = &self.ratelimiter {
-            return lim.check_key(key);
+            lim.retain_recent();
+            lim.shrink_to_fit();
+        }
+    }
+    /* Ok when the request goes through, Err when it is limited. Both tell the state of the bucket of this key. */
+    pub(crate) fn check_key(&self, key: &String) -> Result<RatelimiterState, RatelimiterState> {
+        if let Some(lim) = &self.ratelimiter {
+            match lim.check_key(key) {
+                Ok(snapshot) => {
+                    let quota = snapshot.quota();
+                    let limit = quota.burst_size().get();
+                    let remaining = snapshot.remaining_burst_capacity();
+                    Ok(RatelimiterState {
+                        limit,
+                        remaining,
+                        reset: quota.replenish_interval() * (limit - remaining),
+                        retry_after: Duration::ZERO,
+                    })
+                }
+                Err(not_until) => {
+                    let quota = not_until.quota();
+                    let limit = quota.burst_size().get();
+                    let retry_after = not_until.wait_time_from(governor::clock::QuantaClock::default().now());
+                    Err(RatelimiterState {
+                        limit,
+                        remaining: 0,
+                        reset: retry_after + quota.replenish_interval() * (limit - 1),
+                        retry_after,
+                    })
+                }
+            }
         } else {
             unreachable!("ratelimitercommon");
         }
     }
-}
\ No newline at end of file
+}
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RatelimiterState {
+    pub(crate) limit: u32,
+    pub(crate) remaining: u32,
+    pub(crate) reset: Duration,         //until the bucket is full again
+    pub(crate) retry_after: Duration,   //until the next request can go through
+}
+
+/* what to do when redis can not be reached */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum FailPolicy {
+    Local,      //limit in the memory of this gateway
+    Open,       //let everything through
+    Closed,     //reject everything
+}
+impl FailPolicy {
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        match s {
+            "local" => Some(FailPolicy::Local),
+            "open" => Some(FailPolicy::Open),
+            "closed" => Some(FailPolicy::Closed),
+            _ => None,
+        }
+    }
+}
+#[derive(Debug)]
+pub(crate) struct RedisRatelimiter {
+    pub(crate) redis: Redis,
+    pub(crate) namespace: String,   //service or routes.xx, keeps the keys of the limiters apart
+    pub(crate) period: Duration,
+    pub(crate) burst: u32,
+    pub(crate) fail_policy: FailPolicy,
+    pub(crate) script: deadpool_redis::redis::Script,
+}
+impl RedisRatelimiter {
+    pub(crate) fn new(redis: Redis, namespace: String, period: Duration, burst: u32, fail_policy: FailPolicy) -> Self {
+        Self { redis, namespace, period, burst, fail_policy, script: deadpool_redis::redis::Script::new(GCRA_SCRIPT) }
+    }
+    async fn check_key(&self, key: &str) -> RResult<Result<RatelimiterState, RatelimiterState>> {
+        let redis_key = self.redis.prefixed(&format!("ratelimiter:{}:{}", self.namespace, key));
+        let args = [self.period.as_micros().max(1) as u64, self.burst as u64];
+        let ret = self.redis.eval(&self.script, &[&redis_key], &args).await?;
+        if ret.len() != 4 {
+            return Err(format!("ratelimiter script returned {:?}", ret).into());
+        }
+        let state = RatelimiterState {
+            limit: self.burst,
+            remaining: ret[1].max(0) as u32,
+            reset: Duration::from_micros(ret[3].max(0) as u64),
+            retry_after: Duration::from_micros(ret[2].max(0) as u64),
+        };
+        Ok(if ret[0] == 1 { Ok(state) } else { Err(state) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::redis::{RedisMode, RedisSettings};
+    use crate::config::parsers::model::ratelimiter_builder::structure_ratelimiter;
+
+    /* GCRA_SCRIPT line by line, the stored tat and the clock of redis passed in */
+    fn gcra(stored: &mut Option<i64>, now: i64, period: i64, burst: i64) -> [i64; 4] {
+        let tau = period * burst;
+        let tat = stored.unwrap_or(now).max(now);
+        let new_tat = tat + period;
+        if new_tat - tau > now {
+            return [0, 0, new_tat - tau - now, tat - now];
+        }
+        *stored = Some(new_tat);
+        [1, (now + tau - new_tat) / period, 0, new_tat - now]
+    }
+    fn state(ret: Result<RatelimiterState, RatelimiterState>) -> (bool, u32, u64, u64) {
+        let (ok, state) = match ret {
+            Ok(state) => (true, state),
+            Err(state) => (false, state),
+        };
+        /* governor runs on the real clock, a few microseconds pass between two checks */
+        (ok, state.remaining, (state.retry_after.as_millis() as u64 + 5) / 10, (state.reset.as_millis() as u64 + 5) / 10)
+    }
+    fn unreachable_redis(fail_policy: FailPolicy) -> RedisRatelimiter {
+        let redis = Redis::new(RedisSettings {
+            mode: RedisMode::Single { ip: "127.0.0.1".to_string(), port: 1 },
+            db: 0,
+            username: None,
+            pwd: String::new(),
+            sentinel_pwd: None,
+            tls: None,
+            prefix: String::new(),
+        }).unwrap();
+        RedisRatelimiter::new(redis, "service".to_string(), Duration::from_secs(10), 3, fail_policy)
+    }
+
+    #[test]
+    fn gcra_burst_then_refill() {
+        let period = 1_000_000;
+        let mut tat = None;
+        assert_eq!(gcra(&mut tat, 0, period, 3), [1, 2, 0, period]);
+        assert_eq!(gcra(&mut tat, 0, period, 3), [1, 1, 0, 2 * period]);
+        assert_eq!(gcra(&mut tat, 0, period, 3), [1, 0, 0, 3 * period]);
+        assert_eq!(gcra(&mut tat, 0, period, 3), [0, 0, period, 3 * period]);
+        assert_eq!(gcra(&mut tat, period / 2, period, 3), [0, 0, period / 2, 5 * period / 2]);
+        /* one token is back after a period */
+        assert_eq!(gcra(&mut tat, period, period, 3), [1, 0, 0, 3 * period]);
+        assert_eq!(gcra(&mut tat, period, period, 3), [0, 0, period, 3 * period]);
+        /* a full bucket after burst periods, an older tat is the same as none */
+        assert_eq!(gcra(&mut tat, 10 * period, period, 3), [1, 2, 0, period]);
+    }
+    #[test]
+    fn local_matches_gcra() {
+        /* 100ms in units of 10ms */
+        let limiter = structure_ratelimiter(Duration::from_millis(100), 3, RatelimiterType::Service, None);
+        let mut tat = None;
+        let key = "k".to_string();
+        for _ in 0..5 {
+            let [allowed, remaining, retry_after, reset] = gcra(&mut tat, 0, 10, 3);
+            assert_eq!(state(limiter.check_key(&key)), (allowed == 1, remaining as u32, retry_after as u64, reset as u64));
+        }
+        /* another key has its own bucket */
+        assert_eq!(state(limiter.check_key(&"other".to_string())), (true, 2, 0, 10));
+    }
+    #[test]
+    fn fail_policy_parse() {
+        assert_eq!(FailPolicy::parse("local"), Some(FailPolicy::Local));
+        assert_eq!(FailPolicy::parse("open"), Some(FailPolicy::Open));
+        assert_eq!(FailPolicy::parse("closed"), Some(FailPolicy::Closed));
+        assert_eq!(FailPolicy::parse("Open"), None);
+    }
+    #[tokio::test]
+    async fn fail_policy() {
+        let key = "k".to_string();
+        let open = structure_ratelimiter(Duration::from_millis(100), 1, RatelimiterType::Service, Some(unreachable_redis(FailPolicy::Open)));
+        for _ in 0..3 {
+            let state = open.check(&key).await.unwrap();
+            assert_eq!((state.limit, state.remaining, state.retry_after), (3, 3, Duration::ZERO));
+        }
+        let closed = structure_ratelimiter(Duration::from_millis(100), 1, RatelimiterType::Service, Some(unreachable_redis(FailPolicy::Closed)));
+        let state = closed.check(&key).await.unwrap_err();
+        assert_eq!((state.limit, state.remaining, state.retry_after, state.reset), (3, 0, Duration::from_secs(10), Duration::from_secs(10)));
+        /* the governor of this gateway with its own quota */
+        let local = structure_ratelimiter(Duration::from_secs(100), 1, RatelimiterType::Service, Some(unreachable_redis(FailPolicy::Local)));
+        assert_eq!(local.check(&key).await.unwrap().limit, 1);
+        assert!(local.check(&key).await.is_err());
+    }
+}
@@ -4,26 +4,143 @@ License, v. 2.0. If a copy of the MPL was not distributed with this
 file, You can obtain one at https://mozilla.org/MPL/2.0/.
 */
 
-use std::fmt::Pointer;
+use std::sync::Arc;
 
-use deadpool_redis::{Config, Runtime};
+use deadpool_redis::{
+    redis::{aio::{ConnectionLike, MultiplexedConnection}, cluster_routing::{Route, RoutingInfo, SingleNodeRoutingInfo, SlotAddr}, sentinel::{SentinelClient, SentinelNodeConnectionInfo, SentinelServerType}, Cmd, ErrorKind, FromRedisValue, Pipeline, ToRedisArgs, RedisError, RedisFuture, Value},
+    ConnectionAddr, ConnectionInfo, RedisConnectionInfo, Runtime
+};
 
 use crate::error::RResult;
 
+const ADD_MEMBER_SCRIPT: &str = r#"
+local existed = redis.call('EXISTS', KEYS[1])
+redis.call('SADD', KEYS[1], ARGV[1])
+local pexpire = tonumber(ARGV[2])
+if pexpire == 0 then
+    redis.call('PERSIST', KEYS[1])
+elseif existed == 0 then
+    redis.call('PEXPIRE', KEYS[1], pexpire)
+else
+    local old = redis.call('PTTL', KEYS[1])
+    if old >= 0 and old < pexpire then
+        redis.call('PEXPIRE', KEYS[1], pexpire)
+    end
+end
+return 0
+"#;
+
+/* where redis is and how to log in, from service.cache.redis */
+#[derive(Debug, Clone)]
+pub(crate) enum RedisMode {
+    Single { ip: String, port: u16 },
+    Cluster { nodes: Vec<(String, u16)> },
+    Sentinel { master: String, nodes: Vec<(String, u16)> },
+}
+#[derive(Debug, Clone)]
+pub(crate) struct RedisSettings {
+    pub(crate) mode: RedisMode,
+    pub(crate) db: i64,
+    pub(crate) username: Option<String>,
+    pub(crate) pwd: String,
+    pub(crate) sentinel_pwd: Option<String>,    //of the sentinels themselves
+    pub(crate) tls: Option<bool>,       //Some(insecure)
+    pub(crate) prefix: String,
+}
+impl RedisSettings {
+    fn connection_info(&self, ip: &str, port: u16) -> ConnectionInfo {
+        ConnectionInfo {
+            addr: match self.tls {
+                Some(insecure) => { ConnectionAddr::TcpTls { host: ip.to_string(), port, insecure } }
+                None => { ConnectionAddr::Tcp(ip.to_string(), port) }
+            },
+            redis: self.redis_info(),
+        }
+    }
+    fn redis_info(&self) -> RedisConnectionInfo {
+        RedisConnectionInfo {
+            db: self.db,
+            username: self.username.clone(),
+            password: if self.pwd.is_empty() { None } else { Some(self.pwd.clone()) },
+        }
+    }
+}
+#[derive(Clone)]
+enum RedisPool {
+    Single(deadpool_redis::Pool),
+    Cluster(deadpool_redis::cluster::Pool),
+    Sentinel(Arc<RedisSentinel>),
+}
+/* the sentinels are asked for the master once, then again after its connection breaks or it became a replica */
+struct RedisSentinel {
+    client: tokio::sync::Mutex<SentinelClient>,
+    conn: std::sync::Mutex<Option<MultiplexedConnection>>,
+}
+impl RedisSentinel {
+    fn failed(&self, err: &RedisError) {
+        if err.is_io_error() || err.is_connection_dropped() || err.is_connection_refusal() || err.kind() == ErrorKind::ReadOnly {
+            self.conn.lock().unwrap().take();
+        }
+    }
+}
+enum RedisConn {
+    Single(deadpool_redis::Connection),
+    Cluster(deadpool_redis::cluster::Connection),
+    Sentinel(MultiplexedConnection, Arc<RedisSentinel>),
+}
+impl ConnectionLike for RedisConn {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        match self {
+            RedisConn::Single(conn) => { conn.req_packed_command(cmd) }
+            RedisConn::Cluster(conn) => { conn.req_packed_command(cmd) }
+            RedisConn::Sentinel(conn, sentinel) => {
+                Box::pin(async move {
+                    let ret = conn.req_packed_command(cmd).await;
+                    if let Err(e) = &ret {
+                        sentinel.failed(e);
+                    }
+                    ret
+                })
+            }
+        }
+    }
+    fn req_packed_commands<'a>(&'a mut self, cmd: &'a Pipeline, offset: usize, count: usize) -> RedisFuture<'a, Vec<Value>> {
+        match self {
+            RedisConn::Single(conn) => { conn.req_packed_commands(cmd, offset, count) }
+            RedisConn::Cluster(conn) => { conn.req_packed_commands(cmd, offset, count) }
+            RedisConn::Sentinel(conn, sentinel) => {
+                Box::pin(async move {
+                    let ret = conn.req_packed_commands(cmd, offset, count).await;
+                    if let Err(e) = &ret {
+                        sentinel.failed(e);
+                    }
+                    ret
+                })
+            }
+        }
+    }
+    fn get_db(&self) -> i64 {
+        match self {
+            RedisConn::Single(conn) => { conn.get_db() }
+            RedisConn::Cluster(conn) => { conn.get_db() }
+            RedisConn::Sentinel(conn, _) => { conn.get_db() }
+        }
+    }
+}
+
+#[derive(Clone)]
 pub(crate) struct Redis {
-    ip: String,
-    port: u16,
-    pwd: String,
-    prefix: String,
-    pool: deadpool_redis::Pool
+    settings: RedisSettings,
+    pool: RedisPool,
 }
 impl std::fmt::Debug for Redis {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Redis")
-        .field("ip", &self.ip)
-        .field("port", &self.port)
-        .field("pwd", &self.pwd)
-        .field("prefix", &self.prefix)
+        .field("mode", &self.settings.mode)
+        .field("db", &self.settings.db)
+        .field("username", &self.settings.username)
+        .field("tls", &self.settings.tls)
+        .field("prefix", &self.settings.prefix)
         .field("pool", &"pool")
         .finish()
     }
@@ -32,10 +149,10 @@ impl Redis {
     /*
     pexpire unit ms
      */
-    pub(crate) async fn set(&self, key: String, value: String, pexpire: Option<u128>, count: Option<i32>) -> RResult<()> {
-        let mut conn = self.pool.get().await?;
+    pub(crate) async fn set<V: ToRedisArgs>(&self, key: String, value: V, pexpire: Option<u128>, count: Option<i32>) -> RResult<()> {
+        let mut conn = self.conn().await?;
         deadpool_redis::redis::cmd("SET")
-            .arg(&[&key, &value]).query_async::<_,()>(&mut conn).await?;
+            .arg(&key).arg(value).query_async::<_,()>(&mut conn).await?;
         if let Some(p) = pexpire {
             deadpool_redis::redis::cmd("PEXPIRE")
                 .arg(&[&key, p.to_string().as_str()])
@@ -55,9 +172,9 @@ impl Redis {
         }
         Ok(())
     }
-    pub(crate) async fn get(&self, key: String, is_count: bool) -> RResult<(String, i32)> {
-        let mut conn = self.pool.get().await?;
-        let value: String = deadpool_redis::redis::cmd("GET")
+    pub(crate) async fn get<V: FromRedisValue + Default>(&self, key: String, is_count: bool) -> RResult<(V, i32)> {
+        let mut conn = self.conn().await?;
+        let value: V = deadpool_redis::redis::cmd("GET")
             .arg(&[&key]).query_async(&mut conn).await.unwrap_or_default();
         let count = if is_count {
             let count_key = self.count_key(&key);
@@ -69,7 +186,7 @@ impl Redis {
         Ok((value, count))
     }
     pub(crate) async fn del(&self, key: String, is_count: bool) -> RResult<()> {
-        let mut conn = self.pool.get().await?;
+        let mut conn = self.conn().await?;
         deadpool_redis::redis::cmd("DEL")
             .arg(&[&key]).query_async::<_, ()>(&mut conn).await.unwrap_or_default();
         if is_count {
@@ -80,7 +197,7 @@ impl Redis {
         Ok(())
     }
     pub(crate) async fn update_count(&self, key: String, pexpire: Option<u128>, count: i32) -> RResult<()> {
-        let mut conn = self.pool.get().await?;
+        let mut conn = self.conn().await?;
         let count_key = self.count_key(&key);
         deadpool_redis::redis::cmd("SET")
             .arg(&[&count_key, count.to_string().as_str()]).query_async::<_,()>(&mut conn).await?;
@@ -104,7 +221,7 @@ impl Redis {
         Ok(())
     }
     pub(crate) async fn update_expire(&self, key: String, pexpire: Option<u128>) -> RResult<()> {
-        let mut conn = self.pool.get().await?;
+        let mut conn = self.conn().await?;
         if let Some(p) = pexpire {
             deadpool_redis::redis::cmd("PEXPIRE")
                 .arg(&[&key, p.to_string().as_str()])
@@ -114,13 +231,161 @@ impl Redis {
         }
         Ok(())
     }
-    pub(crate) fn new(ip: String, port: u16, pwd: String, prefix: String) -> RResult<Self> {
-        let url = format!("redis://:{}@{}:{}", pwd, ip, port);
-        let cfg = Config::from_url(url);
-        let pool = cfg.create_pool(Some(Runtime::Tokio1))?;
-        Ok(Self { ip, port, pwd, prefix, pool })
+    /* run a lua script, EVALSHA first and the script itself when redis does not know it yet */
+    pub(crate) async fn eval(&self, script: &deadpool_redis::redis::Script, keys: &[&str], args: &[u64]) -> RResult<Vec<i64>> {
+        let mut conn = self.conn().await?;
+        let mut invocation = script.prepare_invoke();
+        for key in keys {
+            invocation.key(*key);
+        }
+        for arg in args {
+            invocation.arg(*arg);
+        }
+        Ok(invocation.invoke_async(&mut conn).await?)
+    }
+    /* every key matching the glob pattern, SCAN so that a big keyspace does not block redis */
+    pub(crate) async fn scan(&self, pattern: &str) -> RResult<Vec<String>> {
+        let mut conn = self.conn().await?;
+        /* SCAN walks the keys of one node, a cluster is walked master by master */
+        let slots = match &mut conn {
+            RedisConn::Cluster(cluster) => { Self::master_slots(cluster).await?.into_iter().map(Some).collect() }
+            _ => { vec![None] }
+        };
+        let mut ret = Vec::new();
+        for slot in slots {
+            let mut cursor: u64 = 0;
+            loop {
+                let mut scan = deadpool_redis::redis::cmd("SCAN");
+                scan.arg(cursor).arg("MATCH").arg(pattern).arg("COUNT").arg(1000);
+                let value = match (slot, &mut conn) {
+                    (Some(slot), RedisConn::Cluster(cluster)) => {
+                        cluster.route_command(&scan, RoutingInfo::SingleNode(SingleNodeRoutingInfo::SpecificNode(Route::new(slot, SlotAddr::Master)))).await?
+                    }
+                    (_, conn) => { scan.query_async::<_, Value>(conn).await? }
+                };
+                let (next, keys): (u64, Vec<String>) = deadpool_redis::redis::from_redis_value(&value)?;
+                ret.extend(keys);
+                if next == 0 {
+                    break;
+                }
+                cursor = next;
+            }
+        }
+        Ok(ret)
+    }
+    /* the first slot of every master of the cluster, a command routed to it goes to that master */
+    async fn master_slots(conn: &mut deadpool_redis::cluster::Connection) -> RResult<Vec<u16>> {
+        let value: Value = deadpool_redis::redis::cmd("CLUSTER").arg("SLOTS").query_async(conn).await?;
+        let mut masters = Vec::new();
+        let mut slots = Vec::new();
+        if let Value::Bulk(ranges) = value {
+            for range in ranges {
+                if let Value::Bulk(range) = range {
+                    /* start, end, [ip, port, id], replicas... */
+                    if let (Some(Value::Int(start)), Some(Value::Bulk(master))) = (range.first(), range.get(2)) {
+                        let addr = format!("{:?}", master.iter().take(2).collect::<Vec<_>>());
+                        if !masters.contains(&addr) {
+                            masters.push(addr);
+                            slots.push(*start as u16);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(slots)
+    }
+    pub(crate) async fn get_many(&self, keys: &[String]) -> RResult<Vec<Option<String>>> {
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut conn = self.conn().await?;
+        Ok(deadpool_redis::redis::cmd("MGET").arg(keys).query_async(&mut conn).await?)
+    }
+    /* gives how many of the keys were there, their count keys are removed with them */
+    pub(crate) async fn del_many(&self, keys: &[String], is_count: bool) -> RResult<usize> {
+        if keys.is_empty() {
+            return Ok(0);
+        }
+        let mut conn = self.conn().await?;
+        let deleted: usize = deadpool_redis::redis::cmd("DEL").arg(keys).query_async(&mut conn).await?;
+        if is_count {
+            let count_keys = keys.iter().map(|k| self.count_key(k)).collect::<Vec<_>>();
+            deadpool_redis::redis::cmd("DEL").arg(count_keys).query_async::<_, ()>(&mut conn).await?;
+        }
+        Ok(deleted)
+    }
+    pub(crate) async fn members(&self, key: &str) -> RResult<Vec<String>> {
+        let mut conn = self.conn().await?;
+        Ok(deadpool_redis::redis::cmd("SMEMBERS").arg(key).query_async(&mut conn).await?)
+    }
+    pub(crate) async fn remove_members(&self, key: &str, members: &[String]) -> RResult<()> {
+        if members.is_empty() {
+            return Ok(());
+        }
+        let mut conn = self.conn().await?;
+        deadpool_redis::redis::cmd("SREM").arg(key).arg(members).query_async::<_, ()>(&mut conn).await?;
+        Ok(())
+    }
+    /* add to a set that lives as long as the longest of its members, pexpire None is forever */
+    pub(crate) async fn add_member(&self, key: &str, member: &str, pexpire: Option<u128>) -> RResult<()> {
+        let script = deadpool_redis::redis::Script::new(ADD_MEMBER_SCRIPT);
+        let mut conn = self.conn().await?;
+        script.key(key).arg(member).arg(pexpire.unwrap_or(0) as u64).invoke_async::<_, ()>(&mut conn).await?;
+        Ok(())
+    }
+    pub(crate) fn new(settings: RedisSettings) -> RResult<Self> {
+        let pool = match &settings.mode {
+            RedisMode::Single { ip, port } => {
+                let cfg = deadpool_redis::Config::from_connection_info(settings.connection_info(ip, *port));
+                RedisPool::Single(cfg.create_pool(Some(Runtime::Tokio1))?)
+            }
+            RedisMode::Cluster { nodes } => {
+                let cfg = deadpool_redis::cluster::Config {
+                    urls: None,
+                    connections: Some(nodes.iter().map(|(ip, port)| settings.connection_info(ip, *port)).collect()),
+                    pool: None,
+                };
+                RedisPool::Cluster(cfg.create_pool(Some(Runtime::Tokio1))?)
+            }
+            RedisMode::Sentinel { master, nodes } => {
+                let sentinels = nodes.iter().map(|(ip, port)| {
+                    let mut info = settings.connection_info(ip, *port);
+                    info.redis = RedisConnectionInfo { db: 0, username: None, password: settings.sentinel_pwd.clone() };
+                    deadpool_redis::redis::ConnectionInfo::from(info)
+                }).collect::<Vec<_>>();
+                let node = SentinelNodeConnectionInfo {
+                    tls_mode: settings.tls.map(|insecure| if insecure { deadpool_redis::redis::TlsMode::Insecure } else { deadpool_redis::redis::TlsMode::Secure }),
+                    redis_connection_info: Some(settings.redis_info().into()),
+                };
+                let client = SentinelClient::build(sentinels, master.clone(), Some(node), SentinelServerType::Master)?;
+                RedisPool::Sentinel(Arc::new(RedisSentinel { client: tokio::sync::Mutex::new(client), conn: std::sync::Mutex::new(None) }))
+            }
+        };
+        Ok(Self { settings, pool })
+    }
+    async fn conn(&self) -> RResult<RedisConn> {
+        match &self.pool {
+            RedisPool::Single(pool) => { Ok(RedisConn::Single(pool.get().await?)) }
+            RedisPool::Cluster(pool) => { Ok(RedisConn::Cluster(pool.get().await?)) }
+            RedisPool::Sentinel(sentinel) => {
+                let cached = sentinel.conn.lock().unwrap().clone();
+                let conn = match cached {
+                    Some(conn) => { conn }
+                    None => {
+                        let conn = sentinel.client.lock().await.get_async_connection().await?;
+                        *sentinel.conn.lock().unwrap() = Some(conn.clone());
+                        conn
+                    }
+                };
+                Ok(RedisConn::Sentinel(conn, sentinel.clone()))
+            }
+        }
     }
     fn count_key(&self, key: &str) -> String {
-        self.prefix.clone() + key
+        self.settings.prefix.clone() + key
+    }
+    /* the keys of the gateway itself (cache, count, tags) start with the prefix */
+    pub(crate) fn prefixed(&self, key: &str) -> String {
+        self.settings.prefix.clone() + key
     }
 }
\ No newline at end of file
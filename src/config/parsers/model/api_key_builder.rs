@@ -0,0 +1,28 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public
+License, v. 2.0. If a copy of the MPL was not distributed with this
+file, You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ApiKeyAuthBuilder {
+    pub(crate) from: Option<Vec<String>>,       //header:X-Api-Key, query:api_key ...
+    pub(crate) keys: Option<Vec<ApiKeyBuilder>>,
+    pub(crate) file: Option<String>,            //a json list of keys
+    pub(crate) redis: Option<bool>,             //the keys in service.cache.redis
+}
+/* a key and its metadata, the same in the config, the file and redis */
+#[derive(Debug, Deserialize)]
+pub(crate) struct ApiKeyBuilder {
+    pub(crate) key: Option<String>,             //not in redis, the key of redis is the key
+    pub(crate) consumer: String,
+    pub(crate) routes: Option<Vec<String>>,     //the routes the key is allowed on, default all
+    pub(crate) ratelimiter: Option<ApiKeyRatelimiterBuilder>,
+}
+#[derive(Debug, Deserialize)]
+pub(crate) struct ApiKeyRatelimiterBuilder {
+    pub(crate) period: String,
+    pub(crate) burst: u32,
+}
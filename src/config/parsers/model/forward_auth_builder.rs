@@ -0,0 +1,24 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public
+License, v. 2.0. If a copy of the MPL was not distributed with this
+file, You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ForwardAuthBuilder {
+    pub(crate) out_host: String,                    //the hosts of the auth service
+    pub(crate) scheme: Option<String>,              //http(default) or https
+    pub(crate) path: Option<String>,                //put in front of the path of the request
+    pub(crate) request_headers: Option<Vec<String>>,    //sent to the auth service, default Authorization and Cookie
+    pub(crate) response_headers: Option<Vec<String>>,   //copied from a 2xx answer onto the request
+    pub(crate) body: Option<bool>,
+    pub(crate) timeout: Option<String>,
+    pub(crate) cache: Option<ForwardAuthCacheBuilder>,
+}
+#[derive(Debug, Deserialize)]
+pub(crate) struct ForwardAuthCacheBuilder {
+    pub(crate) ttl: String,
+    pub(crate) key: Vec<String>,                    //the headers the decision is keyed on
+}
@@ -6,11 +6,35 @@ file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use serde::Deserialize;
 
+use super::retry_builder::RetryBuilder;
+
 
 
 #[derive(Debug, Deserialize)]
 pub(crate) struct HostsBuilder {
     pub(crate) r#type: String,
     pub(crate) error: Option<String>,
+    pub(crate) retry: Option<RetryBuilder>,
+    pub(crate) breaker: Option<BreakerBuilder>,
+    pub(crate) pool: Option<PoolBuilder>,
     pub(crate) servers: Vec<String>,
 }
+#[derive(Debug, Deserialize)]
+pub(crate) struct BreakerBuilder {
+    pub(crate) consecutive_5xx: Option<u32>,
+    pub(crate) error_rate: Option<u32>,
+    pub(crate) window: Option<String>,
+    pub(crate) min_requests: Option<u32>,
+    pub(crate) half_open_trials: Option<u32>,
+    pub(crate) max_ejection_time: Option<String>,
+}
+#[derive(Debug, Deserialize)]
+pub(crate) struct PoolBuilder {
+    pub(crate) max_idle_per_host: Option<usize>,
+    pub(crate) idle_timeout: Option<String>,
+    pub(crate) version: Option<String>,     //auto, http1, http2
+    pub(crate) keepalive: Option<String>,
+    pub(crate) nodelay: Option<bool>,
+    pub(crate) connect_timeout: Option<String>,
+    pub(crate) stats_interval: Option<String>,
+}
@@ -0,0 +1,20 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public
+License, v. 2.0. If a copy of the MPL was not distributed with this
+file, You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct JwtAuthBuilder {
+    pub(crate) from: Option<Vec<String>>,           //header:Authorization, cookie:xx, query:xx  default header:Authorization
+    pub(crate) secret: Option<String>,              //HS256
+    pub(crate) jwks_file: Option<String>,           //HS256 RS256 ES256
+    pub(crate) algorithms: Option<Vec<String>>,
+    pub(crate) issuer: Option<Vec<String>>,
+    pub(crate) audience: Option<Vec<String>>,
+    pub(crate) leeway: Option<String>,              //clock skew of exp/nbf
+    pub(crate) forward_claims: Option<Vec<String>>, //claim=Header-Name
+    pub(crate) realm: Option<String>,
+}
@@ -9,31 +9,38 @@ pub(crate) mod hosts;
 pub(crate) mod routes;
 pub(crate) mod service;
 pub(crate) mod ratelimiter_builder;
+pub(crate) mod retry_builder;
+pub(crate) mod real_ip_builder;
+pub(crate) mod jwt_auth_builder;
+pub(crate) mod api_key_builder;
+pub(crate) mod forward_auth_builder;
 
 use std::{collections::HashMap, sync::{Arc, Mutex}};
 
-use http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+use http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode};
 use serde::{Serialize, Deserialize};
 use uuid::Uuid;
 
 use crate::{
-    client::ClientProvider, common::{content_type::ContentTypeAndExtension, file_system::FileSystem, ip_range::IpRange, ratelimiter::RatelimiterCommon, redis::Redis}, error::{
+    client::{pool::{PoolSettings, PoolVersion, UpstreamPool}, ClientProvider}, common::{circuit_breaker::{BreakerProfile, CircuitBreaker}, ip_cidr::IpCidr, jwt::{JwtAlgorithm, JwtValidation}, content_type::ContentTypeAndExtension, file_system::{AutoIndex, FileSystem}, ratelimiter::RatelimiterCommon, redis::Redis}, error::{
         ConfigError, ConfigErrorKind, GatewayError, RResult
     }, instance::{
-        errors::{Err, ErrModule, ErrTypes, Errs, ReturnTypes}, hosts::Hosts, pipes::PipeLine, routes::{
-            r#in::In, out::Out, Route
+        errors::{Err, ErrModule, ErrTypes, Errs, ReturnTypes}, hosts::Hosts, pipes::PipeLine, real_ip::RealIp, retry::{Retry, RetryOn}, routes::{
+            forwarded::Forwarded, r#in::In, out::Out, Route
         }, GatewayInstance
     }, modules::{
         balance::{Host, LoadBalanceProfile}, 
         blackandwhitelist::black_and_white_list::{BawFileOrMemory, BlackAndWhiteListInitData, BlackAndWhiteListProfile}, 
-        cache::{http_cache_cell::HttpCacheShared, CacheProfile}, 
+        cache::{cache_purge::CachePurgeProfile, http_cache_cell::HttpCacheShared, http_cache_policy::{CacheKeyTemplate, CachePolicy}, redis::{redis_cell::Compression, RedisCacheStore}, disk::DiskCacheProfile, CacheProfile}, 
         dispatche::DispatcheProfile, header::{HeaderActionKey, HeaderProfile}, 
         ratelimiter::{RatelimiterProfile, RatelimiterType}, 
+        concurrencylimit::{ConcurrencyLimit, ConcurrencyLimitProfile, ConcurrencyLimitType}, 
+        auth::{ApiKeyProfile, ApiKeyStore, BasicAuthProfile, CredentialSource, ForwardAuthCache, ForwardAuthProfile, JwtAuthProfile}, 
         ModuleType, Modules, PipeData, PipeLineEngine, PipeModule, PipeTask
-    }, util::{time_unit::TimeUnit, string_to_number}, RockGateway
+    }, servers::proxy_protocol::ProxyVersion, util::{time_unit::TimeUnit, string_to_number}, RockGateway
 };
 
-use self::{errors::{ErrBuilder, ErrType}, hosts::HostsBuilder, ratelimiter_builder::RatelimiterBuilderType, routes::{in_builder::{InBuilder, InType}, out_builder::{OutBuilder, OutType}, RoutesBuilder}, service::ServiceBuilder};
+use self::{errors::{ErrBuilder, ErrType}, hosts::{BreakerBuilder, HostsBuilder, PoolBuilder}, ratelimiter_builder::RatelimiterBuilderType, real_ip_builder::RealIpBuilder, jwt_auth_builder::JwtAuthBuilder, api_key_builder::ApiKeyAuthBuilder, forward_auth_builder::ForwardAuthBuilder, retry_builder::RetryBuilder, routes::{in_builder::{InBuilder, InType}, out_builder::{OutBuilder, OutType}, RoutesBuilder}, service::ServiceBuilder};
 
 pub(crate) trait Builder<T> {
     fn build(&self, engine: Arc<RockGateway>) -> RResult<T>;
@@ -64,7 +71,7 @@ impl Builder<GatewayInstance> for ConfigModel {
         /*
         initial routes
          */
-        let (routes, ins) = initial_routes(&self.routes, &self.pipes, &mut service, engine.get_modules(), &errors)?;
+        let (routes, ins) = initial_routes(&self.routes, &self.pipes, &mut service, &hosts, engine.get_modules(), &errors)?;
 
        
         let gateway_instance = GatewayInstance { 
@@ -82,6 +89,7 @@ fn initial_routes(
     routes_builder: &HashMap<String, RoutesBuilder>,
     pipes_setting: &HashMap<String, Vec<config::Map<String, config::Value>>>, 
     service: &mut crate::instance::service::Service,
+    hosts: &HashMap<String, Hosts>,
     modules: Modules,
     errors: &Arc<HashMap<String, Arc<Errs>>>,
 ) -> crate::error::RResult<(HashMap<String, Route>, Vec<(usize, String, String, In)>)> {
@@ -114,7 +122,7 @@ fn initial_routes(
                     None => { return Err(gateway_err!(ConfigurationFailed, "Config routes.xx.in.ranges failed", ConfigError::new(ConfigErrorKind::ROUTEIN))); }
                 };
                 let baw_type = BlackAndWhiteListInitData { blacklist: BawFileOrMemory::None, whitelist: BawFileOrMemory::Memory(ranges), };
-                In::Ip { ranges: BlackAndWhiteListProfile::new(baw_type)?, method: value.r#in.method.clone() }
+                In::Ip { ranges: Arc::new(tokio::sync::RwLock::new(BlackAndWhiteListProfile::new(baw_type)?)), method: value.r#in.method.clone() }
             }
             InType::FileIp => {
                 let file = match &value.r#in.file {
@@ -124,7 +132,7 @@ fn initial_routes(
                     None => { return Err(gateway_err!(ConfigurationFailed, "Config routes.xx.in.file failed", ConfigError::new(ConfigErrorKind::ROUTEIN))); }
                 };
                 let baw_type = BlackAndWhiteListInitData { blacklist: BawFileOrMemory::None, whitelist: BawFileOrMemory::File(file), };
-                In::IpFile { ranges: BlackAndWhiteListProfile::new(baw_type)?, method: value.r#in.method.clone() } 
+                In::IpFile { ranges: Arc::new(tokio::sync::RwLock::new(BlackAndWhiteListProfile::new(baw_type)?)), method: value.r#in.method.clone() } 
             }
         };
         let out: Out = match &value.out.r#type {
@@ -144,7 +152,17 @@ fn initial_routes(
                     Some(ref index_file) => { index_file.clone() }
                     None => { return Err(gateway_err!(ConfigurationFailed, "Config routes.xx.out.index_file failed", ConfigError::new(ConfigErrorKind::ROUTEOUT))); }
                 };
-                Out::File { file_system: FileSystem::new(&root_path, &index_file)? }
+                let content_type = initial_content_type(&value.out)?;
+                let autoindex = match value.out.autoindex.as_deref() {
+                    Some("html") => { Some(AutoIndex::Html) }
+                    Some("json") => { Some(AutoIndex::Json) }
+                    Some("off") | None => { None }
+                    Some(autoindex) => {
+                        return Err(gateway_err!(ConfigurationFailed, format!("Config routes.xx.out.autoindex:{} must be html, json or off", autoindex), ConfigError::new(ConfigErrorKind::ROUTEOUT)));
+                    }
+                };
+                let file_system = FileSystem::new(&root_path, &index_file, autoindex, value.out.follow_symlinks.unwrap_or(false))?;
+                Out::File { file_system, content_type, precompressed: value.out.precompressed.unwrap_or(false) }
             }
         };
         /* The timeout for listening to incoming data */
@@ -189,16 +207,52 @@ fn initial_routes(
                 }),
             })
         };
+        /* Retry policy at the route level */
+        let routes_retry = initial_retry(&value.retry)?;
+        /* Forwarding headers added to the requests sent to the host_points */
+        let forwarded = match &value.forwarded {
+            Some(forwarded_builder) => {
+                Forwarded {
+                    x_forwarded_for: forwarded_builder.x_forwarded_for.unwrap_or(false),
+                    x_forwarded_proto: forwarded_builder.x_forwarded_proto.unwrap_or(false),
+                    x_forwarded_host: forwarded_builder.x_forwarded_host.unwrap_or(false),
+                    forwarded: forwarded_builder.forwarded.unwrap_or(false),
+                    preserve_host: forwarded_builder.preserve_host.unwrap_or(false),
+                    via: forwarded_builder.via.clone(),
+                    max_hops: forwarded_builder.max_hops,
+                }
+            }
+            None => { Forwarded::default() }
+        };
+        /* Where the address of the client is taken from, overrides the one of the service */
+        let real_ip = initial_real_ip(&value.real_ip)?;
+        /* PROXY protocol header sent to the host_points of a tcp route */
+        let proxy_protocol = match &value.proxy_protocol {
+            Some(version) => {
+                match ProxyVersion::parse(version) {
+                    Some(v) if value.protocol == "tcp" => { Some(v) }
+                    _ => {
+                        return Err(gateway_err!(ConfigurationFailed, format!("Config routes.{}.proxy_protocol must be v1 or v2 on a tcp route", key), ConfigError::new(ConfigErrorKind::PROXYPROTOCOL)));
+                    }
+                }
+            }
+            None => { None }
+        };
         /* route ratelimiter */
         let route_ratelimiter = if let Some(ratelimiter_builder) = &value.ratelimiter {
+            let redis = ratelimiter_builder.make_redis(&format!("routes.{}", key), service)?;
             Some(match ratelimiter_builder.r#type {
                 RatelimiterBuilderType::Route => {
                     let period = TimeUnit::parse(ratelimiter_builder.period.clone());
-                    ratelimiter_builder::structure_ratelimiter(period, ratelimiter_builder.burst.clone(), RatelimiterType::Route(key.clone()))
+                    ratelimiter_builder::structure_ratelimiter(period, ratelimiter_builder.burst.clone(), RatelimiterType::Route(key.clone()), redis)
                 }
                 RatelimiterBuilderType::Ip => {
                     let period = TimeUnit::parse(ratelimiter_builder.period.clone());
-                    ratelimiter_builder::structure_ratelimiter(period, ratelimiter_builder.burst.clone(), RatelimiterType::IpRoute)
+                    ratelimiter_builder::structure_ratelimiter(period, ratelimiter_builder.burst.clone(), RatelimiterType::IpRoute, redis)
+                }
+                RatelimiterBuilderType::Header | RatelimiterBuilderType::ApiKey | RatelimiterBuilderType::PathMethod | RatelimiterBuilderType::Claim | RatelimiterBuilderType::Consumer => {
+                    let period = TimeUnit::parse(ratelimiter_builder.period.clone());
+                    ratelimiter_builder::structure_ratelimiter(period, ratelimiter_builder.burst, ratelimiter_builder.request_key_type()?, redis)
                 }
                 _ => {
                     return Err(gateway_err!(ConfigurationFailed, "Config routes.xx.ratelimiter failed", ConfigError::new(ConfigErrorKind::RATELIMITER)));
@@ -210,9 +264,10 @@ fn initial_routes(
          /*
         initail pipes
          */
-        let (pipe_line, memory_cache_shared) = initial_pipe_line(
+        let pipe_line = initial_pipe_line(
             pipes_setting, 
             service, 
+            hosts,
             key.clone(),
             value.pipe.clone(), 
             out.clone(), 
@@ -232,9 +287,12 @@ fn initial_routes(
             r#in: r#in.clone(),
             out: out,
             pipe_line,
-            memory_cache_shared,
             ratelimiter: route_ratelimiter,
             routes_error,
+            routes_retry,
+            forwarded,
+            proxy_protocol,
+            real_ip,
         };
         ins.push((priority, value.protocol.clone(), key.clone(), r#in));
         routes.insert(key.clone(), route);
@@ -247,6 +305,7 @@ fn initial_routes(
 fn initial_pipe_line(
     pipes_setting: &HashMap<String, Vec<config::Map<String, config::Value>>>, 
     service: &mut crate::instance::service::Service, 
+    hosts: &HashMap<String, Hosts>,
     route_name: String,
     pipe_builder_name: String,
     out: Out,
@@ -257,15 +316,19 @@ fn initial_pipe_line(
                 RatelimiterCommon
         >>,
     modules: Modules,
-) -> crate::error::RResult<(PipeLine, Arc<HttpCacheShared>)> {
+) -> crate::error::RResult<PipeLine> {
     for (pipe_name, pipes) in pipes_setting {
         if pipe_builder_name.eq(pipe_name) {
-            let memory_cache = Arc::new(HttpCacheShared{
-                cache: std::sync::Mutex::new(HashMap::new()),
-            });
+            let memory_cache = service.memory_cache().unwrap_or_default();
             let mut cur_pipe_task: Option<Box<PipeTask>> = None;
             let mut task_ref: Option<&mut PipeTask> = None;
+            let mut jwt_auth = false;
             for pipe_b in pipes {
+                /* a ratelimiter keyed by claim takes the claims checked by a jwt_auth before it */
+                jwt_auth |= pipe_b.contains_key(crate::util::r#const::JWT_AUTH);
+                if !jwt_auth && pipe_ratelimiter_type(pipe_b, service, route_ratelimiter).is_some_and(|t| matches!(t, RatelimiterType::Claim(_))) {
+                    return Err(gateway_err!(ConfigurationFailed, format!("ERROR pipes.{}: a ratelimiter of type claim needs a jwt_auth before it", pipe_name), ConfigError::new(ConfigErrorKind::RATELIMITER)));
+                }
                 let p_t = initial_pipe_module(
                     route_name.clone(),
                     pipe_name.clone(),
@@ -274,6 +337,7 @@ fn initial_pipe_line(
                     server_buf_size, 
                     pipe_b, 
                     service, 
+                    hosts,
                     memory_cache.clone(),
                     route_ratelimiter,
                     modules.clone(),
@@ -294,11 +358,20 @@ fn initial_pipe_line(
                 task: pipe_task,
                 module_scheduling: modules.clone(),
             };
-            return Ok((PipeLine { id: Uuid::new_v4(), pipe_name: pipe_name.clone(), pipe_line_engine, }, memory_cache,));
+            return Ok(PipeLine { id: Uuid::new_v4(), pipe_name: pipe_name.clone(), pipe_line_engine, });
         }
     }
     return Err(gateway_err!(ConfigurationFailed, format!("Failed to parse pipes ERROR > not found pipe_line:{:#?}", pipe_builder_name.clone()).as_str(), ConfigError::new(ConfigErrorKind::PIPES)));
 }
+/* the type of the service or route ratelimiter a ratelimiter pipe_module takes */
+fn pipe_ratelimiter_type<'a>(pipe_b: &config::Map<String, config::Value>, service: &'a crate::instance::service::Service, route_ratelimiter: &'a Option<Arc<RatelimiterCommon>>) -> Option<&'a RatelimiterType> {
+    let config::ValueKind::Table(ratelimiter_sets) = &pipe_b.get(crate::util::r#const::RATELIMITER)?.kind else { return None; };
+    match ratelimiter_sets.get("type")?.to_string().as_str() {
+        "service" => { service.ratelimiter.as_ref().map(|lim| &lim.ratelimiter_type) }
+        "route" => { route_ratelimiter.as_ref().map(|lim| &lim.ratelimiter_type) }
+        _ => { None }
+    }
+}
 fn initial_pipe_module(
     route_name: String,
     pipe_name: String,
@@ -307,6 +380,7 @@ fn initial_pipe_module(
     server_buf_size: usize,
     pipe_b: &config::Map<String, config::Value>, 
     service: &mut crate::instance::service::Service,
+    hosts: &HashMap<String, Hosts>,
     memory_cache: Arc<HttpCacheShared>,
     route_ratelimiter: 
         &Option<std::sync::Arc<
@@ -437,6 +511,70 @@ fn initial_pipe_module(
                 return Err(gateway_err!(ConfigurationFailed, "ERROR not found ratelimiter", ConfigError::new(ConfigErrorKind::CACHE)));
             }
         }
+    } else if pipe_b.contains_key(&crate::util::r#const::CONCURRENCY_LIMIT.to_string()) {
+        match pipe_b.get(&crate::util::r#const::CONCURRENCY_LIMIT.to_string()) {
+            Some(v) => {
+                let profile = ConcurrencyLimitProfile {
+                    limit: initial_concurrency_limit(v, &route_name, &out, hosts)?,
+                };
+                return Ok(modules.make_pipe_task(ModuleType::ConcurrencyLimit, PipeData::ConcurrencyLimitData { profile: tokio::sync::RwLock::new(profile) }));
+            }
+            None => {
+                return Err(gateway_err!(ConfigurationFailed, "ERROR not found concurrency_limit", ConfigError::new(ConfigErrorKind::CONCURRENCYLIMIT)));
+            }
+        }
+    } else if pipe_b.contains_key(&crate::util::r#const::JWT_AUTH.to_string()) {
+        if protocol == crate::util::r#const::TCP {
+            return Err(gateway_err!(ConfigurationFailed, format!("ERROR jwt_auth needs a http route, route:{}", route_name), ConfigError::new(ConfigErrorKind::JWTAUTH)));
+        }
+        match pipe_b.get(&crate::util::r#const::JWT_AUTH.to_string()) {
+            Some(v) => {
+                let profile = initial_jwt_auth(v)?;
+                return Ok(modules.make_pipe_task(ModuleType::JwtAuth, PipeData::JwtAuthData { profile: tokio::sync::RwLock::new(profile) }));
+            }
+            None => {
+                return Err(gateway_err!(ConfigurationFailed, "ERROR not found jwt_auth", ConfigError::new(ConfigErrorKind::JWTAUTH)));
+            }
+        }
+    } else if pipe_b.contains_key(&crate::util::r#const::BASIC_AUTH.to_string()) {
+        if protocol == crate::util::r#const::TCP {
+            return Err(gateway_err!(ConfigurationFailed, format!("ERROR basic_auth needs a http route, route:{}", route_name), ConfigError::new(ConfigErrorKind::BASICAUTH)));
+        }
+        match pipe_b.get(&crate::util::r#const::BASIC_AUTH.to_string()) {
+            Some(v) => {
+                let profile = initial_basic_auth(v)?;
+                return Ok(modules.make_pipe_task(ModuleType::BasicAuth, PipeData::BasicAuthData { profile: tokio::sync::RwLock::new(profile) }));
+            }
+            None => {
+                return Err(gateway_err!(ConfigurationFailed, "ERROR not found basic_auth", ConfigError::new(ConfigErrorKind::BASICAUTH)));
+            }
+        }
+    } else if pipe_b.contains_key(&crate::util::r#const::API_KEY.to_string()) {
+        if protocol == crate::util::r#const::TCP {
+            return Err(gateway_err!(ConfigurationFailed, format!("ERROR api_key needs a http route, route:{}", route_name), ConfigError::new(ConfigErrorKind::APIKEY)));
+        }
+        match pipe_b.get(&crate::util::r#const::API_KEY.to_string()) {
+            Some(v) => {
+                let profile = initial_api_key(v, service)?;
+                return Ok(modules.make_pipe_task(ModuleType::ApiKey, PipeData::ApiKeyData { profile: tokio::sync::RwLock::new(profile) }));
+            }
+            None => {
+                return Err(gateway_err!(ConfigurationFailed, "ERROR not found api_key", ConfigError::new(ConfigErrorKind::APIKEY)));
+            }
+        }
+    } else if pipe_b.contains_key(&crate::util::r#const::FORWARD_AUTH.to_string()) {
+        if protocol == crate::util::r#const::TCP {
+            return Err(gateway_err!(ConfigurationFailed, format!("ERROR forward_auth needs a http route, route:{}", route_name), ConfigError::new(ConfigErrorKind::FORWARDAUTH)));
+        }
+        match pipe_b.get(&crate::util::r#const::FORWARD_AUTH.to_string()) {
+            Some(v) => {
+                let profile = initial_forward_auth(v, hosts, server_buf_size)?;
+                return Ok(modules.make_pipe_task(ModuleType::ForwardAuth, PipeData::ForwardAuthData { profile: tokio::sync::RwLock::new(profile) }));
+            }
+            None => {
+                return Err(gateway_err!(ConfigurationFailed, "ERROR not found forward_auth", ConfigError::new(ConfigErrorKind::FORWARDAUTH)));
+            }
+        }
     } else if pipe_b.contains_key(&crate::util::r#const::MEMORY_CACHE_GET.to_string()) {
         if !service.has_cache("memory") {
             return Err(gateway_err!(ConfigurationFailed, "ERROR not found service.cache.memory.", ConfigError::new(ConfigErrorKind::CACHE)));
@@ -470,10 +608,20 @@ fn initial_pipe_module(
                         }
                     };
                     let back = if back_str.is_empty() { false } else { back_str.parse().unwrap() };
-                    let profile = CacheProfile::new(memory_cache.clone(), expire, hit, None, back);
+                    /* coalesce: true, coalesce_timeout: 10s */
+                    let coalesce = match mcg.get("coalesce") {
+                        Some(coalesce) if coalesce.clone().into_bool()? => {
+                            Some(match mcg.get("coalesce_timeout") {
+                                Some(timeout) => { TimeUnit::parse(timeout.to_string()) }
+                                None => { crate::util::r#const::CACHE_COALESCE_TIMEOUT }
+                            })
+                        }
+                        _ => { None }
+                    };
+                    let profile = CacheProfile::new(memory_cache.clone(), expire, hit, None, back, initial_cache_policy(Some(mcg))?, coalesce);
                     return Ok(modules.make_pipe_task(ModuleType::MemoryGet, PipeData::MemoryCacheGetData { profile: tokio::sync::RwLock::new(profile) }));
                 } else {
-                    let profile = CacheProfile::new(memory_cache.clone(), std::time::Duration::from_millis(0), -1, None, false);
+                    let profile = CacheProfile::new(memory_cache.clone(), std::time::Duration::from_millis(0), -1, None, false, initial_cache_policy(None)?, None);
                     return Ok(modules.make_pipe_task(ModuleType::MemoryGet, PipeData::MemoryCacheGetData { profile: tokio::sync::RwLock::new(profile) }));
                 }
             }
@@ -505,10 +653,10 @@ fn initial_pipe_module(
                         }
                     };
                     let hit = if hit_str == "" { -1 } else { hit_str.parse::<i32>()? };
-                    let profile = CacheProfile::new(memory_cache.clone(), expire, hit, None, false);
+                    let profile = CacheProfile::new(memory_cache.clone(), expire, hit, None, false, initial_cache_policy(Some(mcg))?, None);
                     return Ok(modules.make_pipe_task(ModuleType::MemorySet, PipeData::MemoryCacheSetData { profile: tokio::sync::RwLock::new(profile) }));
                 } else {
-                    let profile = CacheProfile::new(memory_cache.clone(), std::time::Duration::from_millis(0), -1, None, false);
+                    let profile = CacheProfile::new(memory_cache.clone(), std::time::Duration::from_millis(0), -1, None, false, initial_cache_policy(None)?, None);
                     return Ok(modules.make_pipe_task(ModuleType::MemorySet, PipeData::MemoryCacheSetData { profile: tokio::sync::RwLock::new(profile) }));
                 }
             }
@@ -540,7 +688,7 @@ fn initial_pipe_module(
                         }
                     };
                     let hit = if hit_str == "" { -1 } else { hit_str.parse::<i32>()? };
-                    let redis = service.crate_redis("redis_prefix_".to_string())?;
+                    let redis = initial_redis_cache_store(Some(rcg), service)?;
                     let back_str = match rcg.get("back") {
                         Some(back_str) => {
                             back_str.to_string()
@@ -550,11 +698,11 @@ fn initial_pipe_module(
                         }
                     };
                     let back = if back_str.is_empty() { false } else { back_str.parse().unwrap() };
-                    let profile = CacheProfile::new(memory_cache.clone(), expire, hit, Some(redis), back);
+                    let profile = CacheProfile::new(memory_cache.clone(), expire, hit, Some(redis), back, initial_cache_policy(Some(rcg))?, None);
                     return Ok(modules.make_pipe_task(ModuleType::RedisGet, PipeData::RedisCacheGetData { profile: tokio::sync::RwLock::new(profile) }));
                 } else {
-                    let redis = service.crate_redis("redis_prefix_".to_string())?;
-                    let profile = CacheProfile::new(memory_cache.clone(), std::time::Duration::from_millis(0), -1, Some(redis), false);
+                    let redis = initial_redis_cache_store(None, service)?;
+                    let profile = CacheProfile::new(memory_cache.clone(), std::time::Duration::from_millis(0), -1, Some(redis), false, initial_cache_policy(None)?, None);
                     return Ok(modules.make_pipe_task(ModuleType::RedisGet, PipeData::RedisCacheGetData { profile: tokio::sync::RwLock::new(profile) }));
                 }
             },
@@ -586,12 +734,12 @@ fn initial_pipe_module(
                         }
                     };
                     let hit = if hit_str == "" { -1 } else { hit_str.parse::<i32>()? };
-                    let redis = service.crate_redis(crate::util::r#const::REDIS_PREFIX.to_string())?;
-                    let profile = CacheProfile::new(memory_cache.clone(), expire, hit, Some(redis), false);
+                    let redis = initial_redis_cache_store(Some(rcg), service)?;
+                    let profile = CacheProfile::new(memory_cache.clone(), expire, hit, Some(redis), false, initial_cache_policy(Some(rcg))?, None);
                     return Ok(modules.make_pipe_task(ModuleType::RedisSet, PipeData::RedisCacheSetData { profile: tokio::sync::RwLock::new(profile) }));
                 } else {
-                    let redis = service.crate_redis(crate::util::r#const::REDIS_PREFIX.to_string())?;
-                    let profile = CacheProfile::new(memory_cache.clone(), std::time::Duration::from_millis(0), -1, Some(redis), false);
+                    let redis = initial_redis_cache_store(None, service)?;
+                    let profile = CacheProfile::new(memory_cache.clone(), std::time::Duration::from_millis(0), -1, Some(redis), false, initial_cache_policy(None)?, None);
                     return Ok(modules.make_pipe_task(ModuleType::RedisSet, PipeData::RedisCacheSetData { profile: tokio::sync::RwLock::new(profile) }));
                 }
             },
@@ -599,6 +747,39 @@ fn initial_pipe_module(
                 return Err(gateway_err!(ConfigurationFailed, "Failed to parse pipes.redis_cache_set ERROR", ConfigError::new(ConfigErrorKind::PIPES)));
             }
         }
+    } else if pipe_b.contains_key(&crate::util::r#const::DISK_CACHE_GET.to_string()) {
+        match pipe_b.get(&crate::util::r#const::DISK_CACHE_GET.to_string()) {
+            Some(v) => {
+                let profile = initial_disk_cache(v, service)?;
+                return Ok(modules.make_pipe_task(ModuleType::DiskGet, PipeData::DiskCacheGetData { profile: tokio::sync::RwLock::new(profile) }));
+            }
+            None => {
+                return Err(gateway_err!(ConfigurationFailed, "Failed to parse pipes.disk_cache_get ERROR", ConfigError::new(ConfigErrorKind::PIPES)));
+            }
+        }
+    } else if pipe_b.contains_key(&crate::util::r#const::DISK_CACHE_SET.to_string()) {
+        match pipe_b.get(&crate::util::r#const::DISK_CACHE_SET.to_string()) {
+            Some(v) => {
+                let profile = initial_disk_cache(v, service)?;
+                return Ok(modules.make_pipe_task(ModuleType::DiskSet, PipeData::DiskCacheSetData { profile: tokio::sync::RwLock::new(profile) }));
+            }
+            None => {
+                return Err(gateway_err!(ConfigurationFailed, "Failed to parse pipes.disk_cache_set ERROR", ConfigError::new(ConfigErrorKind::PIPES)));
+            }
+        }
+    } else if pipe_b.contains_key(&crate::util::r#const::CACHE_PURGE.to_string()) {
+        if protocol == crate::util::r#const::TCP {
+            return Err(gateway_err!(ConfigurationFailed, format!("ERROR cache_purge needs a http route, route:{}", route_name), ConfigError::new(ConfigErrorKind::CACHE)));
+        }
+        match pipe_b.get(&crate::util::r#const::CACHE_PURGE.to_string()) {
+            Some(v) => {
+                let profile = initial_cache_purge(v, service)?;
+                return Ok(modules.make_pipe_task(ModuleType::CachePurge, PipeData::CachePurgeData { profile: tokio::sync::RwLock::new(profile) }));
+            }
+            None => {
+                return Err(gateway_err!(ConfigurationFailed, "ERROR not found cache_purge", ConfigError::new(ConfigErrorKind::CACHE)));
+            }
+        }
     } else if pipe_b.contains_key(&crate::util::r#const::HEADER_REQUEST.to_string()) {
         match pipe_b.get(&crate::util::r#const::HEADER_REQUEST.to_string()) {
             Some(header_request) => {
@@ -622,7 +803,18 @@ fn initial_pipe_module(
     } else if pipe_b.contains_key(&crate::util::r#const::DISPATCHE.to_string()) {
         match pipe_b.get(&crate::util::r#const::DISPATCHE.to_string()) {
             Some(dispatch) => {
-                let client = ClientProvider::new(protocol.as_str(), server_buf_size)?;
+                /* Every dispatche to the same hosts shares the connection pool of the hosts. */
+                let client = match &out {
+                    Out::Network { out_host, .. } => {
+                        match hosts.get(out_host) {
+                            Some(h) => { ClientProvider::with_pool(protocol.as_str(), server_buf_size, &h.pool)? }
+                            None => {
+                                return Err(gateway_err!(ConfigurationFailed, format!("Failed to parse pipes.dispatche ERROR > not found hosts:{:?}", out_host).as_str(), ConfigError::new(ConfigErrorKind::PIPESDISPATCHE)));
+                            }
+                        }
+                    }
+                    Out::File { .. } => { ClientProvider::new(protocol.as_str(), server_buf_size)? }
+                };
                 return parse_dispatche(out, client, dispatch, modules);
             }
             None => {
@@ -650,8 +842,8 @@ fn parse_dispatche(
     modules: Modules,
 ) -> RResult<Box<PipeTask>> {
     match out {
-        Out::File { file_system } => {
-            return Ok(modules.make_pipe_task(ModuleType::DispatchFile, PipeData::FileServerDispatcheData { profile: tokio::sync::RwLock::new(DispatcheProfile::File { file_system, content_type: ContentTypeAndExtension::new(), }) }));
+        Out::File { file_system, content_type, precompressed } => {
+            return Ok(modules.make_pipe_task(ModuleType::DispatchFile, PipeData::FileServerDispatcheData { profile: tokio::sync::RwLock::new(DispatcheProfile::File { file_system, content_type, precompressed }) }));
         },
         Out::Network { path, out_host } => {
             return Ok(modules.make_pipe_task(ModuleType::DispatchNetwork, PipeData::NetworkDispatcheData { profile: tokio::sync::RwLock::new(DispatcheProfile::Network { path, out_host, client }) }));
@@ -821,6 +1013,568 @@ fn initial_errors(errors_setting: &Option<HashMap<String, ErrBuilder>>) -> crate
     }
     Ok(err_ret)
 }
+/*
+concurrency_limit:
+  type: route     # route,ip,hosts
+  max: 100
+  queue: 50
+  queue_timeout: 5s
+  retry_after: 1s
+ */
+fn initial_concurrency_limit(v: &config::Value, route_name: &str, out: &Out, hosts: &HashMap<String, Hosts>) -> RResult<Arc<ConcurrencyLimit>> {
+    let table = match &v.kind {
+        config::ValueKind::Table(table) => { table }
+        _ => {
+            return Err(gateway_err!(ConfigurationFailed, "ERROR concurrency_limit set failed", ConfigError::new(ConfigErrorKind::CONCURRENCYLIMIT)));
+        }
+    };
+    let concurrency_type = match table.get("type") {
+        Some(t) => {
+            ConcurrencyLimitType::parse(&t.to_string()).ok_or_else(|| {
+                gateway_err!(ConfigurationFailed, "ERROR concurrency_limit.type must be route, ip or hosts", ConfigError::new(ConfigErrorKind::CONCURRENCYLIMIT))
+            })?
+        }
+        None => { ConcurrencyLimitType::Route }
+    };
+    let max = match table.get("max") {
+        Some(m) => { m.to_string().parse::<usize>()? }
+        None => {
+            return Err(gateway_err!(ConfigurationFailed, "ERROR concurrency_limit.max not found", ConfigError::new(ConfigErrorKind::CONCURRENCYLIMIT)));
+        }
+    };
+    if max == 0 {
+        return Err(gateway_err!(ConfigurationFailed, "ERROR concurrency_limit.max must be more than 0", ConfigError::new(ConfigErrorKind::CONCURRENCYLIMIT)));
+    }
+    let queue = match table.get("queue") {
+        Some(q) => { q.to_string().parse::<usize>()? }
+        None => { 0 }
+    };
+    let queue_timeout = match table.get("queue_timeout") {
+        Some(t) => { TimeUnit::parse(t.to_string()) }
+        None => { std::time::Duration::ZERO }
+    };
+    if queue > 0 && queue_timeout.is_zero() {
+        return Err(gateway_err!(ConfigurationFailed, "ERROR concurrency_limit.queue_timeout is needed by the queue", ConfigError::new(ConfigErrorKind::CONCURRENCYLIMIT)));
+    }
+    let retry_after = match table.get("retry_after") {
+        Some(t) => { TimeUnit::parse(t.to_string()) }
+        None => { crate::util::r#const::CONCURRENCY_LIMIT_RETRY_AFTER }
+    };
+    let limit = Arc::new(ConcurrencyLimit::new(concurrency_type, max, queue, queue_timeout, retry_after));
+    if concurrency_type != ConcurrencyLimitType::Hosts {
+        return Ok(limit);
+    }
+    /* the hosts group is shared, so is its limit */
+    let out_host = match out {
+        Out::Network { out_host, .. } => { out_host }
+        Out::File { .. } => {
+            return Err(gateway_err!(ConfigurationFailed, format!("ERROR concurrency_limit type hosts needs a network out, route:{}", route_name), ConfigError::new(ConfigErrorKind::CONCURRENCYLIMIT)));
+        }
+    };
+    let host = hosts.get(out_host).ok_or_else(|| {
+        gateway_err!(ConfigurationFailed, format!("ERROR concurrency_limit not found hosts:{}", out_host), ConfigError::new(ConfigErrorKind::CONCURRENCYLIMIT))
+    })?;
+    let shared = host.concurrency_limit.get_or_init(|| limit.clone());
+    if !shared.same_settings(&limit) {
+        return Err(gateway_err!(ConfigurationFailed, format!("ERROR concurrency_limit type hosts of hosts:{} is set differently by route:{}", out_host, route_name), ConfigError::new(ConfigErrorKind::CONCURRENCYLIMIT)));
+    }
+    Ok(shared.clone())
+}
+/*
+jwt_auth:
+  from: [header:Authorization, cookie:token, query:access_token]
+  secret: xxx
+  jwks_file: ./jwks.json
+  algorithms: [RS256, ES256]
+  issuer: [https://issuer]
+  audience: [api]
+  leeway: 30s
+  forward_claims: [sub=X-User-Id]
+  realm: api
+ */
+fn initial_jwt_auth(v: &config::Value) -> RResult<JwtAuthProfile> {
+    let builder = v.clone().try_deserialize::<JwtAuthBuilder>().map_err(|e| {
+        gateway_err!(ConfigurationFailed, format!("ERROR jwt_auth set failed:{}", e), ConfigError::new(ConfigErrorKind::JWTAUTH))
+    })?;
+    let from = match &builder.from {
+        Some(from) if !from.is_empty() => {
+            let mut ret = Vec::new();
+            for f in from {
+                match CredentialSource::parse(f) {
+                    Some(source) => { ret.push(source); }
+                    None => {
+                        return Err(gateway_err!(ConfigurationFailed, format!("ERROR jwt_auth.from must be header:xx, cookie:xx or query:xx, not:{}", f), ConfigError::new(ConfigErrorKind::JWTAUTH)));
+                    }
+                }
+            }
+            ret
+        }
+        _ => { vec![CredentialSource::Header(http::header::AUTHORIZATION)] }
+    };
+    if builder.secret.is_none() && builder.jwks_file.is_none() {
+        return Err(gateway_err!(ConfigurationFailed, "ERROR jwt_auth needs a secret or a jwks_file", ConfigError::new(ConfigErrorKind::JWTAUTH)));
+    }
+    let mut algorithms = Vec::new();
+    for alg in builder.algorithms.iter().flatten() {
+        match JwtAlgorithm::parse(&alg.to_uppercase()) {
+            Some(alg) => { algorithms.push(alg); }
+            None => {
+                return Err(gateway_err!(ConfigurationFailed, format!("ERROR jwt_auth.algorithms must be HS256, RS256 or ES256, not:{}", alg), ConfigError::new(ConfigErrorKind::JWTAUTH)));
+            }
+        }
+    }
+    let mut forward_claims = Vec::new();
+    for fc in builder.forward_claims.iter().flatten() {
+        let (claim, header_name) = match fc.split_once('=') {
+            Some((claim, header_name)) if !claim.trim().is_empty() => { (claim.trim(), header_name.trim()) }
+            _ => {
+                return Err(gateway_err!(ConfigurationFailed, format!("ERROR jwt_auth.forward_claims must be claim=Header-Name, not:{}", fc), ConfigError::new(ConfigErrorKind::JWTAUTH)));
+            }
+        };
+        let header_name = HeaderName::from_bytes(header_name.as_bytes()).map_err(|e| {
+            gateway_err!(ConfigurationFailed, format!("ERROR jwt_auth.forward_claims bad header:{}", header_name), e)
+        })?;
+        forward_claims.push((claim.to_string(), header_name));
+    }
+    let validation = JwtValidation {
+        algorithms,
+        issuer: builder.issuer.clone().unwrap_or_default(),
+        audience: builder.audience.clone().unwrap_or_default(),
+        leeway: builder.leeway.clone().map(TimeUnit::parse).unwrap_or_default(),
+    };
+    let realm = builder.realm.clone().unwrap_or_else(|| crate::util::r#const::AUTH_REALM.to_string());
+    JwtAuthProfile::new(from, builder.secret.clone(), builder.jwks_file.clone(), validation, forward_claims, realm).map_err(|e| {
+        gateway_err!(ConfigurationFailed, format!("ERROR jwt_auth.jwks_file failed:{}", e), ConfigError::new(ConfigErrorKind::JWTAUTH))
+    })
+}
+/*
+basic_auth:
+  file: ./htpasswd
+  realm: dashboards
+ */
+fn initial_basic_auth(v: &config::Value) -> RResult<BasicAuthProfile> {
+    let table = match &v.kind {
+        config::ValueKind::Table(table) => { table }
+        _ => {
+            return Err(gateway_err!(ConfigurationFailed, "ERROR basic_auth set failed", ConfigError::new(ConfigErrorKind::BASICAUTH)));
+        }
+    };
+    let file = match table.get("file") {
+        Some(f) => { f.to_string() }
+        None => {
+            return Err(gateway_err!(ConfigurationFailed, "ERROR basic_auth.file not found", ConfigError::new(ConfigErrorKind::BASICAUTH)));
+        }
+    };
+    let realm = match table.get("realm") {
+        Some(r) => { r.to_string() }
+        None => { crate::util::r#const::AUTH_REALM.to_string() }
+    };
+    if realm.contains('"') {
+        return Err(gateway_err!(ConfigurationFailed, "ERROR basic_auth.realm can not contain \"", ConfigError::new(ConfigErrorKind::BASICAUTH)));
+    }
+    BasicAuthProfile::new(file.clone(), realm).map_err(|e| {
+        gateway_err!(ConfigurationFailed, format!("ERROR basic_auth.file {} failed:{}", file, e), ConfigError::new(ConfigErrorKind::BASICAUTH))
+    })
+}
+/*
+api_key:
+  from: ["header:X-Api-Key", "query:api_key"]
+  keys:
+    - key: xxx
+      consumer: partner_a
+      routes: [r1]
+      ratelimiter: {period: 1s, burst: 10}
+  # file: ./api_keys.json
+  # redis: true
+ */
+fn initial_api_key(v: &config::Value, service: &crate::instance::service::Service) -> RResult<ApiKeyProfile> {
+    let builder = v.clone().try_deserialize::<ApiKeyAuthBuilder>().map_err(|e| {
+        gateway_err!(ConfigurationFailed, format!("ERROR api_key set failed:{}", e), ConfigError::new(ConfigErrorKind::APIKEY))
+    })?;
+    let from = match &builder.from {
+        Some(from) if !from.is_empty() => {
+            let mut ret = Vec::new();
+            for f in from {
+                match CredentialSource::parse(f) {
+                    Some(source) => { ret.push(source); }
+                    None => {
+                        return Err(gateway_err!(ConfigurationFailed, format!("ERROR api_key.from must be header:xx, cookie:xx or query:xx, not:{}", f), ConfigError::new(ConfigErrorKind::APIKEY)));
+                    }
+                }
+            }
+            ret
+        }
+        _ => {
+            vec![
+                CredentialSource::Header(HeaderName::from_static("x-api-key")),
+                CredentialSource::Query("api_key".to_string()),
+            ]
+        }
+    };
+    let store = match (builder.keys, builder.file, builder.redis.unwrap_or(false)) {
+        (Some(keys), None, false) => {
+            ApiKeyStore::Memory(ApiKeyProfile::parse_keys(keys).map_err(|e| {
+                gateway_err!(ConfigurationFailed, format!("ERROR api_key.keys failed:{}", e), ConfigError::new(ConfigErrorKind::APIKEY))
+            })?)
+        }
+        (None, Some(file), false) => {
+            ApiKeyStore::File { file, keys: HashMap::new(), modified: None }
+        }
+        (None, None, true) => {
+            let redis = service.crate_redis().map_err(|e| {
+                gateway_err!(ConfigurationFailed, "ERROR api_key.redis needs service.cache.redis", e)
+            })?;
+            ApiKeyStore::Redis { redis, cache: dashmap::DashMap::new() }
+        }
+        _ => {
+            return Err(gateway_err!(ConfigurationFailed, "ERROR api_key needs one of keys, file or redis", ConfigError::new(ConfigErrorKind::APIKEY)));
+        }
+    };
+    ApiKeyProfile::new(from, store).map_err(|e| {
+        gateway_err!(ConfigurationFailed, format!("ERROR api_key.file failed:{}", e), ConfigError::new(ConfigErrorKind::APIKEY))
+    })
+}
+/*
+forward_auth:
+  out_host: auth_hosts
+  path: /verify
+  request_headers: [Authorization, Cookie]
+  response_headers: [X-User, X-Roles]
+  body: false
+  timeout: 2s
+  cache: {ttl: 30s, key: [Authorization, Cookie]}
+ */
+fn initial_forward_auth(v: &config::Value, hosts: &HashMap<String, Hosts>, server_buf_size: usize) -> RResult<ForwardAuthProfile> {
+    let builder = v.clone().try_deserialize::<ForwardAuthBuilder>().map_err(|e| {
+        gateway_err!(ConfigurationFailed, format!("ERROR forward_auth set failed:{}", e), ConfigError::new(ConfigErrorKind::FORWARDAUTH))
+    })?;
+    let header_names = |names: Option<Vec<String>>, default: Vec<HeaderName>| -> RResult<Vec<HeaderName>> {
+        match names {
+            Some(names) => {
+                names.iter().map(|n| HeaderName::from_bytes(n.trim().as_bytes()).map_err(|_| {
+                    gateway_err!(ConfigurationFailed, format!("ERROR forward_auth header name:{}", n), ConfigError::new(ConfigErrorKind::FORWARDAUTH))
+                })).collect()
+            }
+            None => { Ok(default) }
+        }
+    };
+    let scheme = match builder.scheme.as_deref() {
+        None | Some(crate::util::r#const::HTTP) => { crate::util::r#const::HTTP }
+        Some(crate::util::r#const::HTTPS) => { crate::util::r#const::HTTPS }
+        Some(other) => {
+            return Err(gateway_err!(ConfigurationFailed, format!("ERROR forward_auth.scheme must be http or https, not:{}", other), ConfigError::new(ConfigErrorKind::FORWARDAUTH)));
+        }
+    };
+    let h = hosts.get(&builder.out_host).ok_or_else(|| {
+        gateway_err!(ConfigurationFailed, format!("ERROR forward_auth not found hosts:{}", builder.out_host), ConfigError::new(ConfigErrorKind::FORWARDAUTH))
+    })?;
+    let client = ClientProvider::with_pool(scheme, server_buf_size, &h.pool)?;
+    let path = builder.path.unwrap_or_default().trim_end_matches('/').to_string();
+    if !path.is_empty() && !path.starts_with('/') {
+        return Err(gateway_err!(ConfigurationFailed, format!("ERROR forward_auth.path must start with /, not:{}", path), ConfigError::new(ConfigErrorKind::FORWARDAUTH)));
+    }
+    let request_headers = header_names(builder.request_headers, vec![http::header::AUTHORIZATION, http::header::COOKIE])?;
+    let response_headers = header_names(builder.response_headers, vec![])?;
+    let cache = match builder.cache {
+        Some(cache) => {
+            let ttl = TimeUnit::parse(cache.ttl.clone());
+            if ttl.is_zero() {
+                return Err(gateway_err!(ConfigurationFailed, format!("ERROR forward_auth.cache.ttl:{}", cache.ttl), ConfigError::new(ConfigErrorKind::FORWARDAUTH)));
+            }
+            if cache.key.is_empty() {
+                return Err(gateway_err!(ConfigurationFailed, "ERROR forward_auth.cache.key needs a header at least", ConfigError::new(ConfigErrorKind::FORWARDAUTH)));
+            }
+            Some(ForwardAuthCache::new(ttl, header_names(Some(cache.key), vec![])?))
+        }
+        None => { None }
+    };
+    Ok(ForwardAuthProfile {
+        out_host: builder.out_host,
+        scheme,
+        path,
+        client,
+        request_headers,
+        response_headers,
+        body: builder.body.unwrap_or(false),
+        timeout: builder.timeout.map(TimeUnit::parse),
+        cache,
+    })
+}
+/*
+memory_cache_set / memory_cache_get / redis_cache_set / redis_cache_get:
+  key: "$method $scheme://$host$path?$query"
+  methods: [GET, HEAD]
+  status: [200, 301, 404]
+  stale_while_revalidate: 30s
+  stale_if_error: 10min
+ */
+fn initial_cache_policy(table: Option<&config::Map<String, config::Value>>) -> RResult<CachePolicy> {
+    let get = |name: &str| table.and_then(|t| t.get(name));
+    let key = match get("key") {
+        Some(key) => { key.to_string() }
+        None => { CachePolicy::default_key().to_string() }
+    };
+    let key = CacheKeyTemplate::parse(&key).map_err(|e| {
+        gateway_err!(ConfigurationFailed, format!("ERROR cache key:{} failed:{}", key, e), ConfigError::new(ConfigErrorKind::CACHE))
+    })?;
+    let methods = match get("methods") {
+        Some(methods) => {
+            methods.clone().into_array()?.iter().map(|m| {
+                Method::from_bytes(m.to_string().to_ascii_uppercase().as_bytes()).map_err(|_| {
+                    gateway_err!(ConfigurationFailed, format!("ERROR cache methods:{}", m), ConfigError::new(ConfigErrorKind::CACHE))
+                })
+            }).collect::<RResult<Vec<_>>>()?
+        }
+        None => { CachePolicy::default_methods() }
+    };
+    let status = match get("status") {
+        Some(status) => {
+            status.clone().into_array()?.iter().map(|s| {
+                s.to_string().parse::<u16>().ok().and_then(|s| StatusCode::from_u16(s).ok()).ok_or_else(|| {
+                    gateway_err!(ConfigurationFailed, format!("ERROR cache status:{}", s), ConfigError::new(ConfigErrorKind::CACHE))
+                })
+            }).collect::<RResult<Vec<_>>>()?
+        }
+        None => { CachePolicy::default_status() }
+    };
+    let stale_while_revalidate = get("stale_while_revalidate").map(|v| TimeUnit::parse(v.to_string())).unwrap_or_default();
+    let stale_if_error = get("stale_if_error").map(|v| TimeUnit::parse(v.to_string())).unwrap_or_default();
+    Ok(CachePolicy::new(key, methods, status, stale_while_revalidate, stale_if_error))
+}
+/*
+cache_purge:
+  allow: [127.0.0.1, 10.0.0.0/8]
+  key: "$method $scheme://$host$path?$query"
+  methods: [GET, HEAD]
+ */
+fn initial_cache_purge(v: &config::Value, service: &crate::instance::service::Service) -> RResult<CachePurgeProfile> {
+    let table = match &v.kind {
+        config::ValueKind::Table(table) => { Some(table) }
+        _ => { None }
+    };
+    let allow = match table.and_then(|t| t.get("allow")) {
+        Some(allow) => {
+            allow.clone().into_array()?.iter().map(|a| {
+                IpCidr::parse(&a.to_string()).ok_or_else(|| {
+                    gateway_err!(ConfigurationFailed, format!("ERROR cache_purge.allow bad ip or cidr:{}", a), ConfigError::new(ConfigErrorKind::CACHE))
+                })
+            }).collect::<RResult<Vec<_>>>()?
+        }
+        None => { ["127.0.0.1", "::1"].iter().filter_map(|a| IpCidr::parse(a)).collect() }
+    };
+    if !service.has_cache("memory") && !service.has_cache("redis") && !service.has_cache("disk") {
+        return Err(gateway_err!(ConfigurationFailed, "ERROR cache_purge needs service.cache.memory, redis or disk", ConfigError::new(ConfigErrorKind::CACHE)));
+    }
+    let redis = if service.has_cache("redis") {
+        Some(initial_redis_cache_store(table, service)?)
+    } else {
+        None
+    };
+    Ok(CachePurgeProfile {
+        allow,
+        policy: initial_cache_policy(table)?,
+        memory: service.memory_cache(),
+        disk: service.disk_cache(),
+        redis,
+    })
+}
+/*
+disk_cache_get / disk_cache_set:
+  expire: 1d            set: the expire when the response has none. get: the new expire after a hit
+  hit: 100
+  back: true            get only
+  key, methods, status: as the other cache modules
+ */
+fn initial_disk_cache(v: &config::Value, service: &crate::instance::service::Service) -> RResult<DiskCacheProfile> {
+    let disk = service.disk_cache().ok_or_else(|| {
+        gateway_err!(ConfigurationFailed, "ERROR not found service.cache.disk.", ConfigError::new(ConfigErrorKind::CACHE))
+    })?;
+    let table = match &v.kind {
+        config::ValueKind::Table(table) => { Some(table) }
+        _ => { None }
+    };
+    let get = |name: &str| table.and_then(|t| t.get(name)).map(|v| v.to_string());
+    let expire = TimeUnit::parse(get("expire").unwrap_or_default());
+    let hit = match get("hit") {
+        Some(hit) => { hit.parse::<i32>()? }
+        None => { -1 }
+    };
+    let back = match get("back") {
+        Some(back) => { back.parse::<bool>().map_err(|_| gateway_err!(ConfigurationFailed, format!("ERROR disk cache back:{}", back), ConfigError::new(ConfigErrorKind::CACHE)))? }
+        None => { false }
+    };
+    Ok(DiskCacheProfile::new(disk, expire, hit, back, initial_cache_policy(table)?))
+}
+/*
+redis_cache_set:
+  namespace: shop       the route when not set, redis_cache_get and cache_purge of the route need the same
+  compression: zstd     none, lz4, zstd or zstd:level
+ */
+fn initial_redis_cache_store(table: Option<&config::Map<String, config::Value>>, service: &crate::instance::service::Service) -> RResult<RedisCacheStore> {
+    let get = |name: &str| table.and_then(|t| t.get(name)).map(|v| v.to_string());
+    let namespace = get("namespace");
+    if namespace.as_deref().is_some_and(|ns| ns.is_empty() || ns.contains(char::is_whitespace)) {
+        return Err(gateway_err!(ConfigurationFailed, format!("ERROR cache namespace:{:?} must be a word without spaces", namespace), ConfigError::new(ConfigErrorKind::CACHE)));
+    }
+    let compression = match get("compression") {
+        Some(compression) => {
+            Compression::parse(&compression).ok_or_else(|| {
+                gateway_err!(ConfigurationFailed, format!("ERROR cache compression must be none, lz4, zstd or zstd:level, not:{}", compression), ConfigError::new(ConfigErrorKind::CACHE))
+            })?
+        }
+        None => { Compression::None }
+    };
+    Ok(RedisCacheStore::new(service.crate_redis()?, namespace, compression))
+}
+/*
+out:
+  type: file
+  content_types:        the dot is optional
+    .wasm: application/wasm
+  charset: utf-8        of the text types, by default. "" for none
+ */
+fn initial_content_type(out: &OutBuilder) -> RResult<ContentTypeAndExtension> {
+    let mut overrides = HashMap::new();
+    for (extension, content_type) in out.content_types.iter().flatten() {
+        let extension = extension.trim().trim_start_matches('.').to_ascii_lowercase();
+        if extension.is_empty() || content_type.parse::<mime_guess::mime::Mime>().is_err() || HeaderValue::from_str(content_type).is_err() {
+            return Err(gateway_err!(ConfigurationFailed, format!("ERROR routes.xx.out.content_types {:?}: {:?}, an extension and a content type are expected", extension, content_type), ConfigError::new(ConfigErrorKind::ROUTEOUT)));
+        }
+        overrides.insert(".".to_string() + &extension, content_type.clone());
+    }
+    let charset = match out.charset.as_deref().map(|c| c.trim()) {
+        Some("") => { None }
+        Some(charset) => {
+            if HeaderValue::from_str(charset).is_err() || charset.contains([';', ',', ' ', '"']) {
+                return Err(gateway_err!(ConfigurationFailed, format!("ERROR routes.xx.out.charset:{:?}", charset), ConfigError::new(ConfigErrorKind::ROUTEOUT)));
+            }
+            Some(charset.to_string())
+        }
+        None => { Some(crate::util::r#const::FILE_SERVER_CHARSET.to_string()) }
+    };
+    Ok(ContentTypeAndExtension::with_settings(overrides, charset))
+}
+fn initial_real_ip(real_ip_setting: &Option<RealIpBuilder>) -> RResult<Option<Arc<RealIp>>> {
+    let real_ip_builder = match real_ip_setting {
+        Some(r) => { r }
+        None => { return Ok(None); }
+    };
+    let header_str = real_ip_builder.header.clone().unwrap_or_else(|| "X-Forwarded-For".to_string());
+    let header = HeaderName::from_bytes(header_str.as_bytes()).map_err(|e| {
+        gateway_err!(ConfigurationFailed, format!("Config real_ip.header failed:{}", header_str), e)
+    })?;
+    let mut trusted = Vec::new();
+    for t in &real_ip_builder.trusted {
+        match IpCidr::parse(t) {
+            Some(cidr) => { trusted.push(cidr); }
+            None => {
+                return Err(gateway_err!(ConfigurationFailed, format!("Config real_ip.trusted bad ip or cidr:{}", t), ConfigError::new(ConfigErrorKind::REALIP)));
+            }
+        }
+    }
+    Ok(Some(Arc::new(RealIp { header, trusted })))
+}
+fn initial_retry(retry_setting: &Option<RetryBuilder>) -> RResult<Option<Arc<Retry>>> {
+    let retry_builder = match retry_setting {
+        Some(r) => { r }
+        None => { return Ok(None); }
+    };
+    let attempts = retry_builder.attempts.unwrap_or(2);
+    if attempts == 0 {
+        return Err(gateway_err!(ConfigurationFailed, "Config ??.xx.retry.attempts must be greater than 0", ConfigError::new(ConfigErrorKind::RETRY)));
+    }
+    let retry_on_str = retry_builder.retry_on.clone().unwrap_or_else(|| "connect_error timeout".to_string());
+    let mut retry_on = Vec::new();
+    for item in retry_on_str.split_whitespace() {
+        let r = match item {
+            "connect_error" => { RetryOn::ConnectError }
+            "timeout" => { RetryOn::Timeout }
+            sc if sc.starts_with("hsc_") => { RetryOn::Hsc(parse_hsc(sc)?) }
+            _ => {
+                return Err(gateway_err!(ConfigurationFailed, format!("Config ??.xx.retry.retry_on failed:{:?}", item).as_str(), ConfigError::new(ConfigErrorKind::RETRY)));
+            }
+        };
+        retry_on.push(r);
+    }
+    let backoff = match &retry_builder.backoff {
+        Some(b) => { TimeUnit::parse(b.clone()) }
+        None => { std::time::Duration::from_millis(0) }
+    };
+    let max_backoff = match &retry_builder.max_backoff {
+        Some(b) => { TimeUnit::parse(b.clone()) }
+        None => { std::time::Duration::from_millis(0) }
+    };
+    Ok(Some(Arc::new(Retry {
+        attempts,
+        retry_on,
+        backoff,
+        max_backoff,
+        jitter: retry_builder.jitter.unwrap_or(true),
+        non_idempotent: retry_builder.non_idempotent.unwrap_or(false),
+    })))
+}
+fn initial_breaker(breaker_setting: &Option<BreakerBuilder>) -> RResult<BreakerProfile> {
+    let mut profile = BreakerProfile::default();
+    if let Some(breaker_builder) = breaker_setting {
+        if let Some(error_rate) = breaker_builder.error_rate {
+            if error_rate == 0 || error_rate > 100 {
+                return Err(gateway_err!(ConfigurationFailed, "Config hosts.xx.breaker.error_rate must be between 1 and 100", ConfigError::new(ConfigErrorKind::BREAKER)));
+            }
+        }
+        if breaker_builder.consecutive_5xx == Some(0) || breaker_builder.half_open_trials == Some(0) {
+            return Err(gateway_err!(ConfigurationFailed, "Config hosts.xx.breaker.consecutive_5xx and half_open_trials must be greater than 0", ConfigError::new(ConfigErrorKind::BREAKER)));
+        }
+        profile.consecutive_5xx = breaker_builder.consecutive_5xx;
+        profile.error_rate = breaker_builder.error_rate;
+        if let Some(window) = &breaker_builder.window {
+            profile.window = TimeUnit::parse(window.clone());
+        }
+        if let Some(min_requests) = breaker_builder.min_requests {
+            profile.min_requests = min_requests;
+        }
+        if let Some(half_open_trials) = breaker_builder.half_open_trials {
+            profile.half_open_trials = half_open_trials;
+        }
+        if let Some(max_ejection_time) = &breaker_builder.max_ejection_time {
+            profile.max_ejection_time = TimeUnit::parse(max_ejection_time.clone());
+        }
+    }
+    Ok(profile)
+}
+fn initial_pool(pool_setting: &Option<PoolBuilder>) -> RResult<PoolSettings> {
+    let mut settings = PoolSettings::default();
+    if let Some(pool_builder) = pool_setting {
+        if let Some(max_idle_per_host) = pool_builder.max_idle_per_host {
+            settings.max_idle_per_host = max_idle_per_host;
+        }
+        if let Some(idle_timeout) = &pool_builder.idle_timeout {
+            settings.idle_timeout = Some(TimeUnit::parse(idle_timeout.clone()));
+        }
+        if let Some(version) = &pool_builder.version {
+            settings.version = match version.as_str() {
+                "auto" => { PoolVersion::Auto }
+                "http1" => { PoolVersion::Http1 }
+                "http2" => { PoolVersion::Http2 }
+                _ => {
+                    return Err(gateway_err!(ConfigurationFailed, format!("Config hosts.xx.pool.version failed:{:?}", version).as_str(), ConfigError::new(ConfigErrorKind::POOL)));
+                }
+            };
+        }
+        if let Some(keepalive) = &pool_builder.keepalive {
+            settings.keepalive = Some(TimeUnit::parse(keepalive.clone()));
+        }
+        if let Some(nodelay) = pool_builder.nodelay {
+            settings.nodelay = nodelay;
+        }
+        if let Some(connect_timeout) = &pool_builder.connect_timeout {
+            settings.connect_timeout = Some(TimeUnit::parse(connect_timeout.clone()));
+        }
+        if let Some(stats_interval) = &pool_builder.stats_interval {
+            settings.stats_interval = Some(TimeUnit::parse(stats_interval.clone()));
+        }
+    }
+    Ok(settings)
+}
 fn parse_errors(errors: Vec<&str>) -> RResult<Vec<ErrTypes>> {
     let mut ret = Vec::new();
     for e_str in errors {
@@ -856,6 +1610,7 @@ fn initial_home(
     for (key, hosts_builder) in hosts_setting {
         let hosts_vec: Vec<String> = hosts_builder.servers.clone();
         let error_name = hosts_builder.error.clone().unwrap_or_else(|| "".to_string());
+        let breaker_profile = Arc::new(initial_breaker(&hosts_builder.breaker)?);
         let mut value_arg = HashMap::new();
         let mut index = 0;
         for new_host in hosts_vec {
@@ -870,6 +1625,7 @@ fn initial_home(
                 fail_timeout, 
                 timeout, 
                 max_fails_durn: crate::common::max_fails_durn::structure_max_fails_durn(max_fails_durn, max_fails.unwrap_or_else(|| 1)), 
+                circuit: CircuitBreaker::new(breaker_profile.clone()),
             };
             value_arg.insert(index, host_item);
             index = index + 1;
@@ -879,6 +1635,8 @@ fn initial_home(
             Some(e) => { Some(e.clone()) }
             None => { None }
         };
+        let hosts_retry = initial_retry(&hosts_builder.retry)?;
+        let pool = UpstreamPool::new(initial_pool(&hosts_builder.pool)?);
         let module_name = hosts_builder.r#type.clone();
         let pipe_data = match module_name.as_str() {
             "ip_round_robin" => {
@@ -913,7 +1671,10 @@ fn initial_home(
         let lb_task = modules.make_pipe_task(ModuleType::from(module_name.as_str()), pipe_data);
         hosts.insert(key.clone(), Hosts {
             hosts_error,
+            hosts_retry,
             lb_task,
+            pool,
+            concurrency_limit: std::sync::OnceLock::new(),
             modules: modules.clone(),
         });
     }
@@ -8,8 +8,11 @@ use std::time::Duration;
 use std::sync::Arc;
 use serde::Deserialize;
 
-use crate::common::ratelimiter::RatelimiterCommon;
+use crate::common::ratelimiter::{FailPolicy, RatelimiterCommon, RedisRatelimiter};
+use crate::error::{ConfigError, ConfigErrorKind, GatewayError, RResult};
+use crate::instance::service::Service;
 use crate::modules::ratelimiter::RatelimiterType;
+use crate::util::time_unit::TimeUnit;
 
 
 #[derive(Debug, Deserialize)]
@@ -17,15 +20,77 @@ pub(crate) struct RatelimiterBuilder {
     pub(crate) r#type: RatelimiterBuilderType,
     pub(crate) period: String,
     pub(crate) burst: u32,
+    pub(crate) header: Option<String>,     //type Header
+    pub(crate) claim: Option<String>,      //type Claim
+    pub(crate) backend: Option<String>,    //memory(default) or redis
+    pub(crate) fail_policy: Option<String>, //local(default), open or closed, when redis fails
 }
 
 #[derive(Debug, Deserialize)]
 pub(crate) enum RatelimiterBuilderType {
     Service,
     Ip,
-    Route
+    Route,
+    Header,
+    #[serde(alias = "api_key")]
+    ApiKey,
+    #[serde(alias = "path_method")]
+    PathMethod,
+    Claim,
+    Consumer,
 }
-pub(crate) fn structure_ratelimiter(period: Duration, burst: u32, ratelimiter_type: RatelimiterType) -> Arc<RatelimiterCommon> {
+impl RatelimiterBuilder {
+    /* the redis of service.cache.redis, shared by every gateway of the cluster */
+    pub(crate) fn make_redis(&self, namespace: &str, service: &Service) -> RResult<Option<RedisRatelimiter>> {
+        let fail_policy = match &self.fail_policy {
+            Some(f) => {
+                FailPolicy::parse(f).ok_or_else(|| {
+                    gateway_err!(ConfigurationFailed, format!("Config {}.ratelimiter.fail_policy must be local, open or closed", namespace), ConfigError::new(ConfigErrorKind::RATELIMITER))
+                })?
+            }
+            None => { FailPolicy::Local }
+        };
+        match self.backend.as_deref() {
+            None | Some("memory") => { Ok(None) }
+            Some("redis") => {
+                let redis = service.crate_redis().map_err(|e| {
+                    gateway_err!(ConfigurationFailed, format!("Config {}.ratelimiter.backend redis needs service.cache.redis", namespace), e)
+                })?;
+                let period = TimeUnit::parse(self.period.clone());
+                Ok(Some(RedisRatelimiter::new(redis, namespace.to_string(), period, self.burst, fail_policy)))
+            }
+            Some(backend) => {
+                Err(gateway_err!(ConfigurationFailed, format!("Config {}.ratelimiter.backend {} must be memory or redis", namespace, backend), ConfigError::new(ConfigErrorKind::RATELIMITER)))
+            }
+        }
+    }
+    /* the types keyed by an attribute of the request, they mean the same in the service and in the routes */
+    pub(crate) fn request_key_type(&self) -> RResult<RatelimiterType> {
+        match self.r#type {
+            RatelimiterBuilderType::Header => {
+                let header = self.header.as_ref().and_then(|h| http::HeaderName::from_bytes(h.as_bytes()).ok()).ok_or_else(|| {
+                    gateway_err!(ConfigurationFailed, "Config ??.ratelimiter.header is needed by the type Header", ConfigError::new(ConfigErrorKind::RATELIMITER))
+                })?;
+                Ok(RatelimiterType::Header(header))
+            }
+            RatelimiterBuilderType::ApiKey => { Ok(RatelimiterType::ApiKey) }
+            RatelimiterBuilderType::PathMethod => { Ok(RatelimiterType::PathMethod) }
+            RatelimiterBuilderType::Consumer => { Ok(RatelimiterType::Consumer) }
+            RatelimiterBuilderType::Claim => {
+                match &self.claim {
+                    Some(claim) => { Ok(RatelimiterType::Claim(claim.clone())) }
+                    None => {
+                        Err(gateway_err!(ConfigurationFailed, "Config ??.ratelimiter.claim is needed by the type Claim", ConfigError::new(ConfigErrorKind::RATELIMITER)))
+                    }
+                }
+            }
+            _ => {
+                Err(gateway_err!(ConfigurationFailed, "Config ??.ratelimiter.type failed", ConfigError::new(ConfigErrorKind::RATELIMITER)))
+            }
+        }
+    }
+}
+pub(crate) fn structure_ratelimiter(period: Duration, burst: u32, ratelimiter_type: RatelimiterType, redis: Option<RedisRatelimiter>) -> Arc<RatelimiterCommon> {
     let quota = governor::Quota::with_period(period)
         .unwrap()
         .allow_burst(std::num::NonZeroU32::new(burst).unwrap());
@@ -33,11 +98,12 @@ pub(crate) fn structure_ratelimiter(period: Duration, burst: u32, ratelimiter_ty
         String,
         dashmap::DashMap<String, governor::state::InMemoryState>,
         governor::clock::QuantaClock,
-        governor::middleware::NoOpMiddleware<governor::clock::QuantaInstant>,
+        governor::middleware::StateInformationMiddleware,
     > = governor::RateLimiter::keyed(quota)
-        .with_middleware::<governor::middleware::NoOpMiddleware<governor::clock::QuantaInstant>>();
+        .with_middleware::<governor::middleware::StateInformationMiddleware>();
     Arc::new(RatelimiterCommon::new(
         Some(lim),
         ratelimiter_type,
+        redis,
     ))
 }
\ No newline at end of file
@@ -0,0 +1,13 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public
+License, v. 2.0. If a copy of the MPL was not distributed with this
+file, You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct RealIpBuilder {
+    pub(crate) header: Option<String>,          //X-Forwarded-For, X-Real-IP, Forwarded ...
+    pub(crate) trusted: Vec<String>,            //ip or cidr of the trusted proxies
+}
@@ -0,0 +1,17 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public
+License, v. 2.0. If a copy of the MPL was not distributed with this
+file, You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct RetryBuilder {
+    pub(crate) attempts: Option<u32>,
+    pub(crate) retry_on: Option<String>,    //connect_error timeout hsc_502 hsc_503 ...
+    pub(crate) backoff: Option<String>,
+    pub(crate) max_backoff: Option<String>,
+    pub(crate) jitter: Option<bool>,
+    pub(crate) non_idempotent: Option<bool>,
+}
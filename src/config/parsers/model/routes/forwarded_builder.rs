@@ -0,0 +1,19 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public
+License, v. 2.0. If a copy of the MPL was not distributed with this
+file, You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+use serde::Deserialize;
+
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ForwardedBuilder {
+    pub(crate) x_forwarded_for: Option<bool>,
+    pub(crate) x_forwarded_proto: Option<bool>,
+    pub(crate) x_forwarded_host: Option<bool>,
+    pub(crate) forwarded: Option<bool>,
+    pub(crate) preserve_host: Option<bool>,
+    pub(crate) via: Option<String>,
+    pub(crate) max_hops: Option<usize>,
+}
@@ -6,11 +6,12 @@ file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 pub mod out_builder;
 pub mod in_builder;
+pub mod forwarded_builder;
 use serde::Deserialize;
 
-use self::{in_builder::InBuilder, out_builder::OutBuilder};
+use self::{forwarded_builder::ForwardedBuilder, in_builder::InBuilder, out_builder::OutBuilder};
 
-use super::ratelimiter_builder::RatelimiterBuilder;
+use super::{ratelimiter_builder::RatelimiterBuilder, real_ip_builder::RealIpBuilder, retry_builder::RetryBuilder};
 
 #[derive(Debug, Deserialize)]
 pub(crate) struct RoutesBuilder {
@@ -20,6 +21,10 @@ pub(crate) struct RoutesBuilder {
     pub(crate) client_buf_size: Option<usize>,
     pub(crate) server_buf_size: Option<usize>,
     pub(crate) error: Option<String>,
+    pub(crate) retry: Option<RetryBuilder>,
+    pub(crate) forwarded: Option<ForwardedBuilder>,
+    pub(crate) proxy_protocol: Option<String>,
+    pub(crate) real_ip: Option<RealIpBuilder>,
     pub(crate) ratelimiter: Option<RatelimiterBuilder>,
     pub(crate) r#in: InBuilder,
     pub(crate) out: OutBuilder,
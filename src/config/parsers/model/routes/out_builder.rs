@@ -4,6 +4,8 @@ License, v. 2.0. If a copy of the MPL was not distributed with this
 file, You can obtain one at https://mozilla.org/MPL/2.0/.
 */
 
+use std::collections::HashMap;
+
 use serde::Deserialize;
 
 
@@ -14,6 +16,11 @@ pub(crate) struct OutBuilder {
     pub(crate) out_host: Option<String>,
     pub(crate) root_path: Option<String>,
     pub(crate) index_file: Option<String>,
+    pub(crate) content_types: Option<HashMap<String, String>>,  //.wasm: application/wasm
+    pub(crate) charset: Option<String>,                         //utf-8 by default, "" for none
+    pub(crate) precompressed: Option<bool>,                     //foo.js.br and foo.js.gz for foo.js
+    pub(crate) autoindex: Option<String>,                       //html, json or off, for a directory without index_file
+    pub(crate) follow_symlinks: Option<bool>,                   //to outside of root_path
 }
 
 #[derive(Debug, Deserialize)]
@@ -6,21 +6,152 @@ file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use serde::Deserialize;
 
+use crate::{common::redis::{RedisMode, RedisSettings}, error::{ConfigError, ConfigErrorKind, GatewayError, RResult}, modules::cache::{http_cache_cell::{Eviction, HttpCacheLimits}, http_cache_disk::DiskCacheLimits}, util::size_unit::SizeUnit};
+
 
 #[derive(Debug, Deserialize)]
 pub(crate) struct CacheBuilder {
     pub(crate) memory: Option<CacheMemoryBuilder>,
     pub(crate) redis: Option<CacheRedisBuilder>,
+    pub(crate) disk: Option<CacheDiskBuilder>,
 }
 
 #[derive(Debug, Deserialize)]
 pub(crate) struct CacheMemoryBuilder {
     pub(crate) clear_time_interval: String,
+    pub(crate) max_entries: Option<usize>,
+    pub(crate) max_bytes: Option<String>,           //256mb
+    pub(crate) max_object_size: Option<String>,
+    pub(crate) eviction: Option<String>,            //lru, tinylfu
+}
+
+impl CacheMemoryBuilder {
+    /* service.cache.memory not set gets the same limits as a memory without them */
+    pub(crate) fn default_limits() -> HttpCacheLimits {
+        HttpCacheLimits {
+            max_entries: 0,
+            max_bytes: crate::util::r#const::MEMORY_CACHE_MAX_BYTES,
+            max_object_size: crate::util::r#const::MEMORY_CACHE_MAX_OBJECT_SIZE,
+            eviction: Eviction::Lru,
+        }
+    }
+    pub(crate) fn limits(&self) -> RResult<HttpCacheLimits> {
+        let mut limits = Self::default_limits();
+        let size = |name: &str, v: &String| SizeUnit::parse(v).ok_or_else(|| {
+            gateway_err!(ConfigurationFailed, format!("ERROR service.cache.memory.{}:{}, a size such as 512kb or 256mb is expected", name, v), ConfigError::new(ConfigErrorKind::CACHE))
+        });
+        if let Some(max_entries) = self.max_entries {
+            limits.max_entries = max_entries;
+        }
+        if let Some(max_bytes) = &self.max_bytes {
+            limits.max_bytes = size("max_bytes", max_bytes)?;
+        }
+        if let Some(max_object_size) = &self.max_object_size {
+            limits.max_object_size = size("max_object_size", max_object_size)?;
+        }
+        limits.eviction = match self.eviction.as_deref() {
+            None | Some("lru") => { Eviction::Lru }
+            Some("tinylfu") => { Eviction::TinyLfu }
+            Some(other) => {
+                return Err(gateway_err!(ConfigurationFailed, format!("ERROR service.cache.memory.eviction must be lru or tinylfu, not:{}", other), ConfigError::new(ConfigErrorKind::CACHE)));
+            }
+        };
+        Ok(limits)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct CacheDiskBuilder {
+    pub(crate) root: String,
+    pub(crate) max_size: String,                    //10gb
+    pub(crate) max_object_size: Option<String>,
+    pub(crate) clear_time_interval: Option<String>,
+}
+
+impl CacheDiskBuilder {
+    pub(crate) fn limits(&self) -> RResult<DiskCacheLimits> {
+        let size = |name: &str, v: &String| SizeUnit::parse(v).map(|s| s as u64).ok_or_else(|| {
+            gateway_err!(ConfigurationFailed, format!("ERROR service.cache.disk.{}:{}, a size such as 512mb or 10gb is expected", name, v), ConfigError::new(ConfigErrorKind::CACHE))
+        });
+        Ok(DiskCacheLimits {
+            max_bytes: size("max_size", &self.max_size)?,
+            max_object_size: match &self.max_object_size {
+                Some(max_object_size) => { size("max_object_size", max_object_size)? }
+                None => { crate::util::r#const::DISK_CACHE_MAX_OBJECT_SIZE }
+            },
+        })
+    }
 }
 
+/* one of ip and port, cluster or sentinel */
 #[derive(Debug, Deserialize)]
 pub(crate) struct CacheRedisBuilder {
-    pub(crate) ip: String,
-    pub(crate) port: u16,
-    pub(crate) pwd: String,
-}
\ No newline at end of file
+    pub(crate) ip: Option<String>,
+    pub(crate) port: Option<u16>,
+    pub(crate) pwd: Option<String>,
+    pub(crate) username: Option<String>,        //ACL user, with pwd
+    pub(crate) db: Option<i64>,
+    pub(crate) tls: Option<bool>,
+    pub(crate) tls_insecure: Option<bool>,      //no verification of the certificate
+    pub(crate) prefix: Option<String>,
+    pub(crate) cluster: Option<Vec<String>>,    //ip:port of some of the nodes
+    pub(crate) sentinel: Option<CacheSentinelBuilder>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct CacheSentinelBuilder {
+    pub(crate) master: String,
+    pub(crate) nodes: Vec<String>,              //ip:port of the sentinels
+    pub(crate) pwd: Option<String>,
+}
+
+impl CacheRedisBuilder {
+    pub(crate) fn settings(&self) -> RResult<RedisSettings> {
+        let fail = |msg: String| gateway_err!(ConfigurationFailed, format!("ERROR service.cache.redis {}", msg), ConfigError::new(ConfigErrorKind::CACHE));
+        let nodes = |name: &str, nodes: &Vec<String>| -> RResult<Vec<(String, u16)>> {
+            if nodes.is_empty() {
+                return Err(fail(format!("{} needs at least one ip:port", name)));
+            }
+            nodes.iter().map(|node| {
+                node.rsplit_once(':')
+                    .and_then(|(ip, port)| Some((ip.trim_start_matches('[').trim_end_matches(']').to_string(), port.parse::<u16>().ok()?)))
+                    .ok_or_else(|| fail(format!("{} bad ip:port {}", name, node)))
+            }).collect()
+        };
+        let mode = match (&self.ip, &self.cluster, &self.sentinel) {
+            (Some(ip), None, None) => {
+                RedisMode::Single { ip: ip.clone(), port: self.port.unwrap_or(6379) }
+            }
+            (None, Some(cluster), None) => {
+                RedisMode::Cluster { nodes: nodes("cluster", cluster)? }
+            }
+            (None, None, Some(sentinel)) => {
+                RedisMode::Sentinel { master: sentinel.master.clone(), nodes: nodes("sentinel.nodes", &sentinel.nodes)? }
+            }
+            _ => {
+                return Err(fail("needs exactly one of ip, cluster and sentinel".to_string()));
+            }
+        };
+        let db = self.db.unwrap_or(0);
+        if db < 0 {
+            return Err(fail(format!("db {} must not be negative", db)));
+        }
+        if db != 0 && matches!(mode, RedisMode::Cluster { .. }) {
+            return Err(fail("db of a cluster can only be 0".to_string()));
+        }
+        let tls = match (self.tls.unwrap_or(false), self.tls_insecure.unwrap_or(false)) {
+            (true, insecure) => { Some(insecure) }
+            (false, false) => { None }
+            (false, true) => { return Err(fail("tls_insecure needs tls: true".to_string())); }
+        };
+        Ok(RedisSettings {
+            mode,
+            db,
+            username: self.username.clone(),
+            pwd: self.pwd.clone().unwrap_or_default(),
+            sentinel_pwd: self.sentinel.as_ref().and_then(|s| s.pwd.clone()),
+            tls,
+            prefix: self.prefix.clone().unwrap_or_else(|| crate::util::r#const::REDIS_PREFIX.to_string()),
+        })
+    }
+}
@@ -6,7 +6,7 @@ file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use serde::Deserialize;
 
-use crate::{error::{BuilderError, BuilderErrorKind, RResult, GatewayError}, instance::service::AddressInterface};
+use crate::{common::ip_cidr::IpCidr, error::{BuilderError, BuilderErrorKind, ConfigError, ConfigErrorKind, RResult, GatewayError}, instance::service::AddressInterface, servers::proxy_protocol::ProxyProtocol, util::time_unit::TimeUnit};
 
 
 #[derive(Debug, Deserialize)]
@@ -14,8 +14,39 @@ pub(crate) struct InterfaceBuilder {
     pub(crate) address: String,
     pub(crate) ssl_cert: Option<String>,
     pub(crate) ssl_key: Option<String>,
+    pub(crate) proxy_protocol: Option<ProxyProtocolBuilder>,
+    pub(crate) token: Option<String>,
+}
+#[derive(Debug, Deserialize)]
+pub(crate) struct ProxyProtocolBuilder {
+    pub(crate) trusted: Option<Vec<String>>,
+    pub(crate) timeout: Option<String>,
 }
 impl InterfaceBuilder {
+    fn make_proxy_protocol(&self) -> RResult<Option<ProxyProtocol>> {
+        let builder = match &self.proxy_protocol {
+            Some(b) => b,
+            None => { return Ok(None); }
+        };
+        let mut trusted = Vec::new();
+        for t in builder.trusted.as_ref().unwrap_or(&Vec::new()) {
+            match IpCidr::parse(t) {
+                Some(cidr) => { trusted.push(cidr); }
+                None => {
+                    return Err(gateway_err!(
+                        ConfigurationFailed,
+                        format!("proxy_protocol.trusted bad ip or cidr:{}", t),
+                        ConfigError::new(ConfigErrorKind::PROXYPROTOCOL)
+                    ));
+                }
+            }
+        }
+        let timeout = match &builder.timeout {
+            Some(t) => TimeUnit::parse(t.clone()),
+            None => crate::util::r#const::PROXY_PROTOCOL_TIMEOUT,
+        };
+        Ok(Some(ProxyProtocol { trusted, timeout }))
+    }
     pub(crate) fn make_address_interface(&self, protocol: &str) -> RResult<AddressInterface> {
         Ok(match protocol {
             "https" => AddressInterface::Https { 
@@ -28,6 +59,7 @@ impl InterfaceBuilder {
                 })?, 
                 cert: self.ssl_cert.clone().unwrap(), 
                 key: self.ssl_key.clone().unwrap(), 
+                proxy_protocol: self.make_proxy_protocol()?,
             },
             "http" => AddressInterface::Http { 
                 addr: self.address.parse().map_err(|e| {
@@ -37,6 +69,7 @@ impl InterfaceBuilder {
                         e
                     )
                 })?,
+                proxy_protocol: self.make_proxy_protocol()?,
             },
             "tcp" => AddressInterface::Tcp { 
                 addr: self.address.parse().map_err(|e| {
@@ -46,6 +79,17 @@ impl InterfaceBuilder {
                         e
                     )
                 })?, 
+                proxy_protocol: self.make_proxy_protocol()?,
+            },
+            "admin" => AddressInterface::Admin { 
+                addr: self.address.parse().map_err(|e| {
+                    gateway_err!(
+                        ConfigurationFailed,
+                        format!("Failed to parse the listener address {}", self.address),
+                        e
+                    )
+                })?, 
+                token: self.token.clone(),
             },
             _ => {
                 return Err(gateway_err!(
@@ -13,19 +13,19 @@ use serde::Deserialize;
 
 use crate::{
     instance::service::{AddressInterface, CacheType, Service, TokioSettings, TokioType}, 
-    modules::ratelimiter::RatelimiterType, 
+    modules::{cache::{http_cache_cell::HttpCacheShared, http_cache_disk::HttpCacheDisk}, ratelimiter::RatelimiterType}, 
     util::time_unit::TimeUnit, 
     RockGateway,
     error::GatewayError,
 };
 
 use self::{
-    cache_builder::CacheBuilder, 
+    cache_builder::{CacheBuilder, CacheMemoryBuilder}, 
     interfaces_builder::InterfaceBuilder, 
     tokio_settings_builder::TokioSettingsBuilder
 };
 
-use super::{ratelimiter_builder::{RatelimiterBuilder, RatelimiterBuilderType}, Builder};
+use super::{ratelimiter_builder::{RatelimiterBuilder, RatelimiterBuilderType}, real_ip_builder::RealIpBuilder, Builder};
 
 
 #[derive(Debug, Deserialize)]
@@ -34,6 +34,9 @@ pub(crate) struct ServiceBuilder {
     pub(crate) disable_upgrade_insecure_requests: Option<bool>,
     pub(crate) ratelimiter: Option<RatelimiterBuilder>,
     pub(crate) interfaces: HashMap<String, Vec<InterfaceBuilder>>,
+    pub(crate) list_watch_interval: Option<String>,
+    pub(crate) real_ip: Option<RealIpBuilder>,
+    pub(crate) access_log: Option<bool>,
     pub(crate) multi_thread: Option<TokioSettingsBuilder>,
     pub(crate) current_thread: Option<TokioSettingsBuilder>,
     pub(crate) cache: Option<CacheBuilder>,
@@ -63,13 +66,18 @@ impl Builder<Service> for ServiceBuilder {
             if let Some(ref memory) = cache.memory {
                 caches.push(CacheType::Memory { 
                     clear_time_interval: TimeUnit::parse(memory.clear_time_interval.clone()),
+                    store: std::sync::Arc::new(HttpCacheShared::new(memory.limits()?)),
                 });
             }
             if let Some(ref redis) = cache.redis {
                 caches.push(CacheType::Redis {
-                    ip: redis.ip.clone(), 
-                    port: redis.port.clone(),
-                    pwd: redis.pwd.clone(), 
+                    redis: crate::common::redis::Redis::new(redis.settings()?)?,
+                });
+            }
+            if let Some(ref disk) = cache.disk {
+                caches.push(CacheType::Disk {
+                    clear_time_interval: TimeUnit::parse(disk.clear_time_interval.clone().unwrap_or_else(|| "10min".to_string())),
+                    store: std::sync::Arc::new(HttpCacheDisk::open(std::path::PathBuf::from(&disk.root), disk.limits()?)?),
                 });
             }
             caches
@@ -77,6 +85,7 @@ impl Builder<Service> for ServiceBuilder {
             let mut caches = Vec::new();
             caches.push(CacheType::Memory { 
                 clear_time_interval: TimeUnit::parse("60min".to_string()),
+                store: std::sync::Arc::new(HttpCacheShared::new(CacheMemoryBuilder::default_limits())),
             });
             caches
         };
@@ -85,15 +94,35 @@ impl Builder<Service> for ServiceBuilder {
         } else {
             true
         };
-        let ratelimiter = if let Some(ratelimiter_builder) = &self.ratelimiter {
+        let mut service = Service {
+            external_ip: self.external_ip.clone(),
+            disable_upgrade_insecure_requests,
+            interfaces: interfaces_instance,
+            real_ip: super::initial_real_ip(&self.real_ip)?,
+            access_log: self.access_log.unwrap_or(false),
+            list_watch_interval: match &self.list_watch_interval {
+                Some(t) => { TimeUnit::parse(t.clone()) }
+                None => { crate::util::r#const::LIST_WATCH_INTERVAL }
+            },
+            tokio_type: tokio_type_instance,
+            cache: caches,
+            ratelimiter: None,
+        };
+        service.ratelimiter = if let Some(ratelimiter_builder) = &self.ratelimiter {
+            /* after the rest of the service, the redis backend comes from service.cache.redis */
+            let redis = ratelimiter_builder.make_redis("service", &service)?;
             Some(match ratelimiter_builder.r#type {
                 RatelimiterBuilderType::Service => {
                     let period = TimeUnit::parse(ratelimiter_builder.period.clone());
-                    super::ratelimiter_builder::structure_ratelimiter(period, ratelimiter_builder.burst.clone(), RatelimiterType::Service)
+                    super::ratelimiter_builder::structure_ratelimiter(period, ratelimiter_builder.burst.clone(), RatelimiterType::Service, redis)
                 }
                 RatelimiterBuilderType::Ip => {
                     let period = TimeUnit::parse(ratelimiter_builder.period.clone());
-                    super::ratelimiter_builder::structure_ratelimiter(period, ratelimiter_builder.burst.clone(), RatelimiterType::IpService)
+                    super::ratelimiter_builder::structure_ratelimiter(period, ratelimiter_builder.burst.clone(), RatelimiterType::IpService, redis)
+                }
+                RatelimiterBuilderType::Header | RatelimiterBuilderType::ApiKey | RatelimiterBuilderType::PathMethod | RatelimiterBuilderType::Claim | RatelimiterBuilderType::Consumer => {
+                    let period = TimeUnit::parse(ratelimiter_builder.period.clone());
+                    super::ratelimiter_builder::structure_ratelimiter(period, ratelimiter_builder.burst, ratelimiter_builder.request_key_type()?, redis)
                 }
                 _ => {
                     return Err(gateway_err!(ConfigurationFailed, "Config service.ratelimiter failed", crate::error::ConfigError::new(crate::error::ConfigErrorKind::RATELIMITER)));
@@ -102,13 +131,6 @@ impl Builder<Service> for ServiceBuilder {
         } else {
             None
         };
-        Ok(Service {
-            external_ip: self.external_ip.clone(),
-            disable_upgrade_insecure_requests,
-            interfaces: interfaces_instance,
-            tokio_type: tokio_type_instance,
-            cache: caches,
-            ratelimiter,
-        })
+        Ok(service)
     }
 }
\ No newline at end of file
@@ -6,7 +6,7 @@ file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use std::{net::SocketAddr, sync::Arc};
 
-use crate::RockGateway;
+use crate::{modules::cache::http_cache_cell::{CacheFlight, CacheStale}, RockGateway};
 
 use self::{request_context::RequestContext, response_context::ResponseContext, return_context::ReturnContext};
 
@@ -20,4 +20,6 @@ pub(crate) struct HttpContext {
     pub(crate) response_context: ResponseContext,
     pub(crate) return_context: ReturnContext,           
     pub(crate) cache_hit: bool,
+    pub(crate) cache_stale: CacheStale,
+    pub(crate) cache_flight: Option<Arc<CacheFlight>>,  //this request asks upstream for the others of its key
 }
\ No newline at end of file
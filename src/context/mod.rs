@@ -9,13 +9,16 @@ pub(crate) mod http_context;
 pub(crate) mod scheme;
 pub(crate) mod redirect_context;
 
-use std::{net::SocketAddr, sync::Arc};
+use std::{collections::HashMap, net::{IpAddr, SocketAddr}, sync::Arc};
 
 use hyper::http::Request;
 use hyper::body::{Bytes, Incoming};
 use http_body_util::BodyExt;
 
+use crate::common::ip_cidr::IpCidr;
+use crate::modules::cache::http_cache_cell::CacheStale;
 use crate::entitys::buf::DataBuf;
+use crate::client::tcp::TcpClient;
 use crate::gateway_err;
 use crate::{RockGateway, instance::GatewayInstance, error::{RResult, GatewayError}};
 
@@ -30,12 +33,18 @@ use self::tcp_context::TcpContext;
 #[derive(Debug)]
 pub(crate) struct GatewayContext {
     pub(crate) remote_addr: SocketAddr,
+    pub(crate) real_ip: IpAddr,     //the client, remote_addr or the one told by the trusted proxies (service.real_ip / routes.xx.real_ip)
     pub(crate) prompt_return: bool, //Immediately return the identifier. Terminate all pipe_module rows in the current pipe_line and return directly.
     pub(crate) gateway: Arc<RockGateway>,
     pub(crate) route: Option<String>,    
     pub(crate) redirect_context: RedirectContext,
+    pub(crate) concurrency_permits: ConcurrencyPermits,     //the places taken by concurrency_limit, given back when the request or the tcp session ends
+    pub(crate) user: Option<String>,    //the user checked by basic_auth
+    pub(crate) consumer: Option<String>,    //the consumer of the key checked by api_key
+    pub(crate) claims: Option<serde_json::Value>,   //the claims of the token checked by jwt_auth
     pub(crate) context_type: ContextType,
 }
+pub(crate) type ConcurrencyPermits = Arc<std::sync::Mutex<HashMap<uuid::Uuid, tokio::sync::OwnedSemaphorePermit>>>;
 // impl Default for GatewayContext {
 //     fn default() -> Self {
 //         Self {
@@ -61,13 +70,17 @@ impl Default for ContextType {
 impl GatewayContext {
     pub(crate) async fn new_tcp_context(
         remote_addr: SocketAddr,
+        local_addr: SocketAddr,
         gateway: Arc<RockGateway>,
         out_tx: Arc<tokio::sync::mpsc::Sender<DataBuf>>,
         in_tx: Arc<tokio::sync::mpsc::Sender<DataBuf>>,
         in_rx: Arc<tokio::sync::RwLock<tokio::sync::mpsc::Receiver<DataBuf>>>,
+        concurrency_permits: ConcurrencyPermits,
+        upstream: Arc<std::sync::OnceLock<TcpClient>>,
     ) -> RResult<Self> {
         Ok(Self {
             remote_addr,
+            real_ip: IpCidr::canonical(remote_addr.ip()),
             prompt_return: false,
             gateway,
             route: None,
@@ -76,15 +89,22 @@ impl GatewayContext {
                 port: None,
                 timeout: None,
                 previous_host: None,
+                tried_hosts: Vec::new(),
                 hosts: None,
                 permanent_failure: None,
                 err: None,
             },
+            concurrency_permits,
+            user: None,
+            consumer: None,
+            claims: None,
             context_type: ContextType::TcpContext(TcpContext { 
                 sender: out_tx,
                 in_tx: in_tx,
                 in_rx: in_rx,
                 in_data_buf: None,
+                local_addr,
+                upstream,
             }),
         })
     }
@@ -135,6 +155,10 @@ impl GatewayContext {
             None
         };
         
+        let real_ip = match &gateway.get_instance()?.service.real_ip {
+            Some(r) => { r.resolve(&remote_addr.ip(), &headers) }
+            None => { IpCidr::canonical(remote_addr.ip()) }
+        };
         let request_context = RequestContext {
             remote_addr,
             uri,
@@ -151,6 +175,7 @@ impl GatewayContext {
             port: None,
             timeout: None,
             previous_host: None,
+            tried_hosts: Vec::new(),
             hosts: None,
             permanent_failure: None,
             err: None,
@@ -159,15 +184,22 @@ impl GatewayContext {
         let return_context = ReturnContext::default();
         Ok(Self {
             remote_addr,
+            real_ip,
             prompt_return: false,
             gateway,
             route: None,
             redirect_context,
+            concurrency_permits: ConcurrencyPermits::default(),
+            user: None,
+            consumer: None,
+            claims: None,
             context_type: ContextType::HttpContext(HttpContext {
                 request_context,
                 response_context,
                 return_context,
                 cache_hit: false,
+                cache_stale: CacheStale::None,
+                cache_flight: None,
             })
         })
     }
@@ -14,6 +14,7 @@ pub(crate) struct RedirectContext {
     pub(crate) port: Option<Arc<u16>>,          //destination port
     pub(crate) timeout: Option<std::time::Duration>, //request timeout for client use
     pub(crate) previous_host: Option<u16>,           //last selected host_index
+    pub(crate) tried_hosts: Vec<u16>,                //host_index already tried by the current request
     pub(crate) hosts: Option<Arc<tokio::sync::RwLock<HashMap<u16, Host>>>>,                 //current hosts collection
     pub(crate) permanent_failure: Option<Arc<tokio::sync::RwLock<HashMap<u16, Host>>>>,     //current permanent_failure hosts collection
     pub(crate) err: Option<Arc<Errs>>,               //selected error_handling_plan
@@ -4,9 +4,9 @@ License, v. 2.0. If a copy of the MPL was not distributed with this
 file, You can obtain one at https://mozilla.org/MPL/2.0/.
 */
 
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 
-use crate::entitys::buf::DataBuf;
+use crate::{client::tcp::TcpClient, entitys::buf::DataBuf};
 
 #[derive(Debug, Clone)]
 pub(crate) struct TcpContext {
@@ -14,4 +14,6 @@ pub(crate) struct TcpContext {
     pub(crate) in_tx: Arc<tokio::sync::mpsc::Sender<DataBuf>>,
     pub(crate) in_rx: Arc<tokio::sync::RwLock<tokio::sync::mpsc::Receiver<DataBuf>>>,
     pub(crate) in_data_buf: Option<DataBuf>,
+    pub(crate) local_addr: SocketAddr,  //the address the client connected to, as conveyed by the PROXY protocol if any
+    pub(crate) upstream: Arc<std::sync::OnceLock<TcpClient>>,  //the session's own upstream connection, used when the route sends a PROXY header
 }
\ No newline at end of file
@@ -72,6 +72,16 @@ pub enum ConfigErrorKind {
     ROUTEOUT,           //Gateway out - Configuration Error
     ROUTEPROTOCOL,      //Gateway protocol - Configuration Error
     TOKIO,              //Tokio Settings - Configuration Error
+    RETRY,              //Retry - Configuration Error
+    BREAKER,            //Circuit breaker - Configuration Error
+    POOL,               //Upstream connection pool - Configuration Error
+    PROXYPROTOCOL,      //PROXY protocol - Configuration Error
+    REALIP,             //Real ip - Configuration Error
+    CONCURRENCYLIMIT,   //Concurrency limit - Configuration Error
+    JWTAUTH,            //Jwt auth - Configuration Error
+    BASICAUTH,          //Basic auth - Configuration Error
+    APIKEY,             //Api key - Configuration Error
+    FORWARDAUTH,        //Forward auth - Configuration Error
 }
 impl std::error::Error for ConfigError {}
 impl ConfigError {
@@ -90,6 +100,16 @@ impl ConfigError {
             ConfigErrorKind::ROUTEOUT => "error routes.xx.out",
             ConfigErrorKind::ROUTEPROTOCOL => "error routes.xx.protocol",
             ConfigErrorKind::TOKIO => "error service.multi_thread/current_thread  .bind_cpu",
+            ConfigErrorKind::RETRY => "error set ??.xx.retry",
+            ConfigErrorKind::BREAKER => "error set hosts.xx.breaker",
+            ConfigErrorKind::POOL => "error set hosts.xx.pool",
+            ConfigErrorKind::REALIP => "error set ??.real_ip",
+            ConfigErrorKind::CONCURRENCYLIMIT => "error set pipes.xx.concurrency_limit",
+            ConfigErrorKind::JWTAUTH => "error set pipes.xx.jwt_auth",
+            ConfigErrorKind::BASICAUTH => "error set pipes.xx.basic_auth",
+            ConfigErrorKind::APIKEY => "error set pipes.xx.api_key",
+            ConfigErrorKind::FORWARDAUTH => "error set pipes.xx.forward_auth",
+            ConfigErrorKind::PROXYPROTOCOL => "error set service.interfaces.xx.proxy_protocol / routes.xx.proxy_protocol",
         }
     }
     pub fn new(kind: ConfigErrorKind) -> Self {
@@ -124,6 +144,7 @@ pub(crate) enum PipeErrorKind {
     HTTPSENDERTIMEOUT,    //HTTP send timeout
     REDIS,                  //Redis cache excepton
     RATELIMITER,            //ratelimiter
+    CONCURRENCYLIMIT,       //concurrency limit
     CACHEHIT,
     DIRECTLYRETURN,
     NOAVAILABLEHOSTS,      //No available hosts
@@ -141,6 +162,7 @@ impl PipeError {
             PipeErrorKind::HTTPSENDERTIMEOUT => "error http sender timeout",
             PipeErrorKind::REDIS => "error redis",
             PipeErrorKind::RATELIMITER => "error ratelimiter",
+            PipeErrorKind::CONCURRENCYLIMIT => "error concurrency limit",
             PipeErrorKind::CACHEHIT => "error cache hit",
             PipeErrorKind::DIRECTLYRETURN => "directly return",
             PipeErrorKind::NOAVAILABLEHOSTS => "No available hosts",
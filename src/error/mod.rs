@@ -109,6 +109,19 @@ pub enum GatewayError {
         col: u32,
     },
     #[display(fmt = "[{} -> {}:{}] {}{}", module_path, line, col, message,"add_source(source)")]
+    ConcurrencyLimitArrival {
+        #[doc(hidden)]
+        message: String,
+        #[doc(hidden)]
+        source: Source,
+        #[doc(hidden)]
+        module_path: &'static str,
+        #[doc(hidden)]
+        line: u32,
+        #[doc(hidden)]
+        col: u32,
+    },
+    #[display(fmt = "[{} -> {}:{}] {}{}", module_path, line, col, message,"add_source(source)")]
     BlackAndWhiteListError {
         #[doc(hidden)]
         message: String,
@@ -6,13 +6,16 @@ file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use std::sync::Arc;
 
-use crate::modules::{Modules, PipeTask};
+use crate::{client::pool::UpstreamPool, modules::{concurrencylimit::ConcurrencyLimit, Modules, PipeTask}};
 
-use super::errors::Errs;
+use super::{errors::Errs, retry::Retry};
 
 #[derive(Debug)]
 pub(crate) struct Hosts {
     pub(crate) hosts_error: Option<Arc<Errs>>,
+    pub(crate) hosts_retry: Option<Arc<Retry>>,
     pub(crate) lb_task: Box<PipeTask>,
+    pub(crate) pool: UpstreamPool,
+    pub(crate) concurrency_limit: std::sync::OnceLock<Arc<ConcurrencyLimit>>,  //set by the first concurrency_limit of type hosts, shared by every route
     pub(crate) modules: Modules,
 }
\ No newline at end of file
@@ -9,6 +9,8 @@ pub(crate) mod routes;
 pub(crate) mod hosts;
 pub(crate) mod service;
 pub(crate) mod pipes;
+pub(crate) mod retry;
+pub(crate) mod real_ip;
 use std::{collections::HashMap, sync::Arc};
 
 use self::{errors::Errs, hosts::Hosts, routes::{r#in::In, Route}, service::Service};
@@ -0,0 +1,139 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public
+License, v. 2.0. If a copy of the MPL was not distributed with this
+file, You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+use std::net::IpAddr;
+
+use hyper::{header::HeaderName, HeaderMap};
+
+use crate::common::ip_cidr::IpCidr;
+
+/* Where the address of the client is taken from when the gateway is behind proxies or CDNs. */
+#[derive(Debug)]
+pub(crate) struct RealIp {
+    pub(crate) header: HeaderName,
+    pub(crate) trusted: Vec<IpCidr>,
+}
+impl RealIp {
+    fn is_trusted(&self, ip: &IpAddr) -> bool {
+        self.trusted.iter().any(|cidr| cidr.contains(ip))
+    }
+    /*
+    Only a trusted peer may tell the address of the client. The header is walked from the right, the proxies we trust are skipped,
+    and the first address that is not trusted is the client. Anything on its left could have been written by the client itself.
+     */
+    pub(crate) fn resolve(&self, peer: &IpAddr, headers: &HeaderMap) -> IpAddr {
+        let peer = IpCidr::canonical(*peer);
+        if !self.is_trusted(&peer) {
+            return peer;
+        }
+        let forwarded = self.header == hyper::header::FORWARDED;
+        let mut real_ip = peer;
+        /* several headers of the same name are one list in the order they come */
+        let values: Vec<&str> = headers.get_all(&self.header).iter().filter_map(|v| v.to_str().ok()).collect();
+        for item in values.iter().flat_map(|v| v.split(',')).rev() {
+            let ip = if forwarded { Self::forwarded_for(item) } else { Self::parse_ip(item) };
+            match ip {
+                Some(ip) => {
+                    real_ip = IpCidr::canonical(ip);
+                    if !self.is_trusted(&real_ip) {
+                        break;
+                    }
+                }
+                None => { break; }  //garbage or an obfuscated identifier, keep the last address we could trust
+            }
+        }
+        real_ip
+    }
+    /* 203.0.113.7, 203.0.113.7:1234, 2001:db8::1, [2001:db8::1]:1234, "[2001:db8::1]" */
+    fn parse_ip(item: &str) -> Option<IpAddr> {
+        let item = item.trim().trim_matches('"');
+        if let Ok(ip) = item.parse::<IpAddr>() {
+            return Some(ip);
+        }
+        if let Some(rest) = item.strip_prefix('[') {
+            return rest.split(']').next()?.parse().ok();
+        }
+        item.rsplit_once(':')?.0.parse().ok()
+    }
+    /* the for= of one element of RFC 7239 Forwarded: for=192.0.2.60;proto=http;by=203.0.113.43 */
+    fn forwarded_for(element: &str) -> Option<IpAddr> {
+        element.split(';').find_map(|pair| {
+            let (k, v) = pair.trim().split_once('=')?;
+            if k.trim().eq_ignore_ascii_case("for") { Self::parse_ip(v) } else { None }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::HeaderValue;
+
+    fn real_ip(header: &str) -> RealIp {
+        RealIp {
+            header: HeaderName::from_bytes(header.as_bytes()).unwrap(),
+            trusted: vec![IpCidr::parse("10.0.0.0/8").unwrap(), IpCidr::parse("fd00::/8").unwrap()],
+        }
+    }
+    fn resolve(real_ip: &RealIp, peer: &str, values: &[&'static str]) -> String {
+        let mut headers = HeaderMap::new();
+        for v in values {
+            headers.append(real_ip.header.clone(), HeaderValue::from_static(v));
+        }
+        real_ip.resolve(&peer.parse().unwrap(), &headers).to_string()
+    }
+
+    #[test]
+    fn untrusted_peer() {
+        let xff = real_ip("x-forwarded-for");
+        assert_eq!(resolve(&xff, "203.0.113.9", &["1.2.3.4"]), "203.0.113.9");
+        assert_eq!(resolve(&xff, "::ffff:203.0.113.9", &["1.2.3.4"]), "203.0.113.9");
+    }
+    #[test]
+    fn spoofed_leftmost() {
+        /* the client wrote 6.6.6.6 itself, 1.2.3.4 was added by our proxy 10.0.0.2 */
+        let xff = real_ip("x-forwarded-for");
+        assert_eq!(resolve(&xff, "10.0.0.1", &["6.6.6.6, 1.2.3.4, 10.0.0.2"]), "1.2.3.4");
+        assert_eq!(resolve(&xff, "10.0.0.1", &["6.6.6.6", "1.2.3.4,10.0.0.2"]), "1.2.3.4");
+        assert_eq!(resolve(&xff, "10.0.0.1", &["10.0.0.3, 1.2.3.4"]), "1.2.3.4");
+    }
+    #[test]
+    fn all_trusted() {
+        let xff = real_ip("x-forwarded-for");
+        assert_eq!(resolve(&xff, "10.0.0.1", &["10.0.0.3, 10.0.0.2"]), "10.0.0.3");
+        assert_eq!(resolve(&xff, "fd00::1", &["fd00::2"]), "fd00::2");
+    }
+    #[test]
+    fn malformed() {
+        /* the walk stops at garbage and keeps the last address it could trust */
+        let xff = real_ip("x-forwarded-for");
+        assert_eq!(resolve(&xff, "10.0.0.1", &["1.2.3.4, garbage, 10.0.0.2"]), "10.0.0.2");
+        assert_eq!(resolve(&xff, "10.0.0.1", &["1.2.3.4, unknown"]), "10.0.0.1");
+        assert_eq!(resolve(&xff, "10.0.0.1", &[""]), "10.0.0.1");
+    }
+    #[test]
+    fn no_header() {
+        let xff = real_ip("x-forwarded-for");
+        assert_eq!(resolve(&xff, "10.0.0.1", &[]), "10.0.0.1");
+        assert_eq!(resolve(&xff, "::ffff:10.0.0.1", &[]), "10.0.0.1");
+    }
+    #[test]
+    fn ports_and_brackets() {
+        let xff = real_ip("x-forwarded-for");
+        assert_eq!(resolve(&xff, "10.0.0.1", &["1.2.3.4:5678"]), "1.2.3.4");
+        assert_eq!(resolve(&xff, "10.0.0.1", &["[2001:db8::1]:1234"]), "2001:db8::1");
+        assert_eq!(resolve(&xff, "10.0.0.1", &["2001:db8::1"]), "2001:db8::1");
+        assert_eq!(resolve(&xff, "10.0.0.1", &["::ffff:1.2.3.4"]), "1.2.3.4");
+    }
+    #[test]
+    fn forwarded() {
+        let forwarded = real_ip("forwarded");
+        assert_eq!(resolve(&forwarded, "10.0.0.1", &["for=6.6.6.6, for=1.2.3.4;proto=https, For=10.0.0.2"]), "1.2.3.4");
+        assert_eq!(resolve(&forwarded, "10.0.0.1", &["for=\"[2001:db8::1]:4711\";by=10.0.0.1"]), "2001:db8::1");
+        assert_eq!(resolve(&forwarded, "10.0.0.1", &["for=_hidden, for=10.0.0.2"]), "10.0.0.2");
+        assert_eq!(resolve(&forwarded, "10.0.0.1", &["proto=https"]), "10.0.0.1");
+    }
+}
@@ -0,0 +1,82 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public
+License, v. 2.0. If a copy of the MPL was not distributed with this
+file, You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+use http::{Method, StatusCode};
+use rand::Rng;
+
+use crate::error::GatewayError;
+
+#[derive(Debug)]
+pub(crate) struct Retry {
+    pub(crate) attempts: u32,                   //the maximum number of requests sent upstream, including the first one
+    pub(crate) retry_on: Vec<RetryOn>,
+    pub(crate) backoff: std::time::Duration,    //base delay, doubled after each failed attempt
+    pub(crate) max_backoff: std::time::Duration,
+    pub(crate) jitter: bool,
+    pub(crate) non_idempotent: bool,            //whether POST/PATCH/... are also allowed to be replayed
+}
+#[derive(Debug, PartialEq)]
+pub(crate) enum RetryOn {
+    ConnectError,
+    Timeout,
+    Hsc(StatusCode),
+}
+impl Retry {
+    /* 
+    when no retry policy is configured, the pass_next of the error_handling_plan behaves like an unbounded retry
+    without delay. each host_point is still tried at most once per request.
+     */
+    pub(crate) fn from_pass_next(error_list: Vec<StatusCode>) -> Self {
+        let mut retry_on = vec![RetryOn::ConnectError, RetryOn::Timeout];
+        for sc in error_list {
+            retry_on.push(RetryOn::Hsc(sc));
+        }
+        Self {
+            attempts: u32::MAX,
+            retry_on,
+            backoff: std::time::Duration::from_millis(0),
+            max_backoff: std::time::Duration::from_millis(0),
+            jitter: false,
+            non_idempotent: true,
+        }
+    }
+    pub(crate) fn allow_method(&self, method: &Method) -> bool {
+        if self.non_idempotent {
+            return true;
+        }
+        matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE | Method::PUT | Method::DELETE)
+    }
+    pub(crate) fn retry_on_error(&self, err: &GatewayError) -> bool {
+        match err {
+            GatewayError::PipeExecuteTimeoutError { .. } => {
+                self.retry_on.contains(&RetryOn::Timeout)
+            }
+            GatewayError::HyperError { .. } | GatewayError::IoError { .. } | GatewayError::TcpSenderError { .. } => {
+                self.retry_on.contains(&RetryOn::ConnectError)
+            }
+            _ => { false }
+        }
+    }
+    pub(crate) fn retry_on_status(&self, status: &StatusCode) -> bool {
+        self.retry_on.contains(&RetryOn::Hsc(*status))
+    }
+    /* attempt starts from 1, which is the attempt that has just failed */
+    pub(crate) fn delay(&self, attempt: u32) -> std::time::Duration {
+        if self.backoff.is_zero() {
+            return self.backoff;
+        }
+        let exp = attempt.saturating_sub(1).min(16);
+        let mut delay = self.backoff.saturating_mul(1 << exp);
+        if !self.max_backoff.is_zero() && delay > self.max_backoff {
+            delay = self.max_backoff;
+        }
+        if self.jitter {    //full jitter: a random delay between 0 and the computed value
+            let millis = delay.as_millis() as u64;
+            delay = std::time::Duration::from_millis(rand::thread_rng().gen_range(0..=millis));
+        }
+        delay
+    }
+}
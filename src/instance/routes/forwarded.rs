@@ -0,0 +1,128 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public
+License, v. 2.0. If a copy of the MPL was not distributed with this
+file, You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+use std::net::IpAddr;
+
+use http::{HeaderMap, HeaderName, HeaderValue, Version};
+
+use crate::context::scheme::SchemeContext;
+
+/* Which forwarding headers the route adds to the request sent to the host_point. */
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Forwarded {
+    pub(crate) x_forwarded_for: bool,
+    pub(crate) x_forwarded_proto: bool,
+    pub(crate) x_forwarded_host: bool,
+    pub(crate) forwarded: bool,
+    pub(crate) preserve_host: bool,
+    pub(crate) via: Option<String>,         //the pseudonym of the gateway in the Via header
+    pub(crate) max_hops: Option<usize>,
+}
+impl Forwarded {
+    /* how many times the request has already passed through this gateway, judged by our own Via entries */
+    pub(crate) fn hops(&self, headers: &HeaderMap) -> usize {
+        let pseudonym = self.via.clone().unwrap_or_else(|| crate::util::r#const::VIA_PSEUDONYM.to_string());
+        let mut hops = 0;
+        for value in headers.get_all(http::header::VIA) {
+            if let Ok(v) = value.to_str() {
+                for entry in v.split(',') {
+                    if entry.split_whitespace().nth(1) == Some(pseudonym.as_str()) {
+                        hops += 1;
+                    }
+                }
+            }
+        }
+        hops
+    }
+    pub(crate) fn loop_detected(&self, headers: &HeaderMap) -> bool {
+        match self.max_hops {
+            Some(max_hops) => { self.hops(headers) >= max_hops }
+            None => { false }
+        }
+    }
+    /* 
+    headers: the headers of the request that will be sent to the host_point.
+    host: the host of the host_point, used as Host unless preserve_host is set.
+     */
+    pub(crate) fn apply(&self, headers: &mut HeaderMap, client_ip: &IpAddr, scheme: &SchemeContext, version: &Version, authority: Option<String>, host: &str) {
+        let proto = scheme.as_str();
+        if self.x_forwarded_for {
+            let xff = match headers.get(X_FORWARDED_FOR).and_then(|v| v.to_str().ok()) {
+                Some(prior) if !prior.trim().is_empty() => { format!("{}, {}", prior, client_ip) }
+                _ => { client_ip.to_string() }
+            };
+            insert_header(headers, X_FORWARDED_FOR, xff);
+        }
+        if self.x_forwarded_proto {
+            insert_header(headers, X_FORWARDED_PROTO, proto.to_string());
+        }
+        if self.x_forwarded_host {
+            if let Some(authority) = &authority {
+                insert_header(headers, X_FORWARDED_HOST, authority.clone());
+            }
+        }
+        if self.forwarded {
+            /* RFC 7239: an IPv6 address has to be quoted and put in brackets */
+            let node = match client_ip {
+                IpAddr::V4(ip) => { ip.to_string() }
+                IpAddr::V6(ip) => { format!("\"[{}]\"", ip) }
+            };
+            let mut element = format!("for={};proto={}", node, proto);
+            if let Some(authority) = &authority {
+                element = format!("{};host=\"{}\"", element, authority);
+            }
+            let forwarded = match headers.get(http::header::FORWARDED).and_then(|v| v.to_str().ok()) {
+                Some(prior) if !prior.trim().is_empty() => { format!("{}, {}", prior, element) }
+                _ => { element }
+            };
+            insert_header(headers, http::header::FORWARDED.as_str(), forwarded);
+        }
+        /* the loop guard relies on our own Via entries, so Via is always added when max_hops is set */
+        let via = match (&self.via, self.max_hops) {
+            (Some(pseudonym), _) => { Some(pseudonym.clone()) }
+            (None, Some(_)) => { Some(crate::util::r#const::VIA_PSEUDONYM.to_string()) }
+            (None, None) => { None }
+        };
+        if let Some(pseudonym) = via {
+            let protocol_version = match *version {
+                Version::HTTP_09 => { "0.9" }
+                Version::HTTP_10 => { "1.0" }
+                Version::HTTP_2 => { "2" }
+                Version::HTTP_3 => { "3" }
+                _ => { "1.1" }
+            };
+            let entry = format!("{} {}", protocol_version, pseudonym);
+            let via = match headers.get(http::header::VIA).and_then(|v| v.to_str().ok()) {
+                Some(prior) if !prior.trim().is_empty() => { format!("{}, {}", prior, entry) }
+                _ => { entry }
+            };
+            insert_header(headers, http::header::VIA.as_str(), via);
+        }
+        match authority {
+            Some(authority) if self.preserve_host => {
+                if !headers.contains_key(http::header::HOST) {   // http2 requests carry the host in the uri only
+                    if let Ok(h) = HeaderValue::from_str(&authority) {
+                        headers.insert(http::header::HOST, h);
+                    }
+                }
+            }
+            _ => {
+                if let Ok(h) = HeaderValue::from_str(host) {
+                    headers.insert(http::header::HOST, h);
+                }
+            }
+        }
+    }
+}
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+const X_FORWARDED_PROTO: &str = "x-forwarded-proto";
+const X_FORWARDED_HOST: &str = "x-forwarded-host";
+
+fn insert_header(headers: &mut HeaderMap, name: &'static str, value: String) {
+    if let Ok(v) = HeaderValue::from_str(&value) {
+        headers.insert(HeaderName::from_static(name), v);
+    }
+}
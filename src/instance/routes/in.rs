@@ -4,7 +4,9 @@ License, v. 2.0. If a copy of the MPL was not distributed with this
 file, You can obtain one at https://mozilla.org/MPL/2.0/.
 */
 
-use crate::{common::ip_range::IpRange, modules::blackandwhitelist::black_and_white_list::BlackAndWhiteListProfile};
+use std::sync::Arc;
+
+use crate::modules::blackandwhitelist::black_and_white_list::BlackAndWhiteListProfile;
 
 
 #[derive(Debug, Clone)]
@@ -14,11 +16,11 @@ pub(crate) enum In {
         method: Option<Vec<String>>,
     },
     Ip {
-        ranges: BlackAndWhiteListProfile,
+        ranges: Arc<tokio::sync::RwLock<BlackAndWhiteListProfile>>,    //shared with the clone in ins, so that runtime updates reach both
         method: Option<Vec<String>>,
     },
     IpFile {
-        ranges: BlackAndWhiteListProfile,
+        ranges: Arc<tokio::sync::RwLock<BlackAndWhiteListProfile>>,
         method: Option<Vec<String>>,
     },
 }
@@ -6,11 +6,12 @@ file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 pub mod out;
 pub mod r#in;
+pub mod forwarded;
 
 use std::sync::Arc;
-use crate::{common::ratelimiter::RatelimiterCommon, modules::cache::http_cache_cell::HttpCacheShared};
-use self::{out::Out, r#in::In};
-use super::{errors::Errs, pipes::PipeLine};
+use crate::{common::ratelimiter::RatelimiterCommon, servers::proxy_protocol::ProxyVersion};
+use self::{forwarded::Forwarded, out::Out, r#in::In};
+use super::{errors::Errs, pipes::PipeLine, real_ip::RealIp, retry::Retry};
 
 #[derive(Debug)]
 pub(crate) struct Route {
@@ -22,10 +23,13 @@ pub(crate) struct Route {
     pub(crate) r#in: In,
     pub(crate) out: Out,
     pub(crate) pipe_line: PipeLine,
-    pub(crate) memory_cache_shared: Arc<HttpCacheShared>,
     pub(crate) ratelimiter: 
         Option<std::sync::Arc<
                 RatelimiterCommon
         >>,
     pub(crate) routes_error: Arc<Errs>,
+    pub(crate) routes_retry: Option<Arc<Retry>>,
+    pub(crate) forwarded: Forwarded,
+    pub(crate) proxy_protocol: Option<ProxyVersion>,    //send a PROXY header in front of the data of a tcp route
+    pub(crate) real_ip: Option<Arc<RealIp>>,
 }
\ No newline at end of file
@@ -4,7 +4,7 @@ License, v. 2.0. If a copy of the MPL was not distributed with this
 file, You can obtain one at https://mozilla.org/MPL/2.0/.
 */
 
-use crate::common::file_system::FileSystem;
+use crate::common::{content_type::ContentTypeAndExtension, file_system::FileSystem};
 
 
 
@@ -16,10 +16,12 @@ pub(crate) enum Out {
     },
     File {
         file_system: FileSystem,
+        content_type: ContentTypeAndExtension,
+        precompressed: bool,
     }
 }
 impl Default for Out {
     fn default() -> Self {
-        Self::File { file_system: FileSystem::default() }
+        Self::File { file_system: FileSystem::default(), content_type: ContentTypeAndExtension::new(), precompressed: false }
     }
 }
\ No newline at end of file
@@ -4,9 +4,9 @@ License, v. 2.0. If a copy of the MPL was not distributed with this
 file, You can obtain one at https://mozilla.org/MPL/2.0/.
 */
 
-use std::{net::SocketAddr, time::Duration};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 
-use crate::{common::{ratelimiter::RatelimiterCommon, redis::Redis}, error::{GatewayError, RResult}};
+use crate::{common::{ratelimiter::RatelimiterCommon, redis::Redis}, error::{GatewayError, RResult}, instance::real_ip::RealIp, modules::cache::{http_cache_cell::HttpCacheShared, http_cache_disk::HttpCacheDisk}, servers::proxy_protocol::ProxyProtocol};
 
 
 
@@ -15,6 +15,9 @@ pub(crate) struct Service {
     pub(crate) external_ip: Option<String>,
     pub(crate) disable_upgrade_insecure_requests: bool,
     pub(crate) interfaces: Vec<AddressInterface>,
+    pub(crate) real_ip: Option<std::sync::Arc<RealIp>>,
+    pub(crate) access_log: bool,
+    pub(crate) list_watch_interval: Duration,   //how often the files of the black and white lists are checked, 0 means never
     pub(crate) tokio_type: TokioType,
     pub(crate) cache: Vec<CacheType>,
     pub(crate) ratelimiter: 
@@ -32,19 +35,35 @@ impl Service {
                 CacheType::Redis { .. } if cache_type_str == "redis" => {
                     return true;
                 },
+                CacheType::Disk { .. } if cache_type_str == "disk" => {
+                    return true;
+                },
                 _ => {}
             }
         }
         return false;
     }
-    pub(crate) fn crate_redis(&self, prefix: String) -> RResult<Redis> {
+    /* the modules share the connections of service.cache.redis */
+    pub(crate) fn crate_redis(&self) -> RResult<Redis> {
         for cache_type in &self.cache {
-            if let CacheType::Redis { ip, port, pwd } = cache_type {
-                return Ok(Redis::new(ip.clone(), port.clone(), pwd.clone(), prefix)?);
+            if let CacheType::Redis { redis } = cache_type {
+                return Ok(redis.clone());
             }
         }
         Err("ERROR: Not Found Redis Config!".into())
     }
+    pub(crate) fn memory_cache(&self) -> Option<Arc<HttpCacheShared>> {
+        self.cache.iter().find_map(|cache_type| match cache_type {
+            CacheType::Memory { store, .. } => Some(store.clone()),
+            _ => None,
+        })
+    }
+    pub(crate) fn disk_cache(&self) -> Option<Arc<HttpCacheDisk>> {
+        self.cache.iter().find_map(|cache_type| match cache_type {
+            CacheType::Disk { store, .. } => Some(store.clone()),
+            _ => None,
+        })
+    }
 }
 #[derive(Debug)]
 pub(crate) enum TokioType {
@@ -56,11 +75,14 @@ pub(crate) enum TokioType {
 pub(crate) enum CacheType {
     Memory {
         clear_time_interval: Duration,
+        store: Arc<HttpCacheShared>,
     },
     Redis {
-        ip: String,
-        port: u16,
-        pwd: String,
+        redis: Redis,
+    },
+    Disk {
+        clear_time_interval: Duration,
+        store: Arc<HttpCacheDisk>,
     }
 }
 #[derive(Debug, Default)]
@@ -86,19 +108,26 @@ pub(crate) enum TokioBindCpuType {
 pub(crate) enum AddressInterface {
     Http {
         addr: SocketAddr,
+        proxy_protocol: Option<ProxyProtocol>,
     },
     Https {
         addr: SocketAddr,
         cert: String,
         key: String,
+        proxy_protocol: Option<ProxyProtocol>,
     },
     Tcp {
-        addr: SocketAddr
+        addr: SocketAddr,
+        proxy_protocol: Option<ProxyProtocol>,
+    },
+    Admin {
+        addr: SocketAddr,
+        token: Option<String>,
     }
 }
 impl Default for AddressInterface {
     fn default() -> Self {
-        AddressInterface::Http { addr: "0.0.0.0:8888".parse().unwrap() }
+        AddressInterface::Http { addr: "0.0.0.0:8888".parse().unwrap(), proxy_protocol: None }
     }
 }
 impl Default for Service {
@@ -107,6 +136,9 @@ impl Default for Service {
             external_ip: None,
             disable_upgrade_insecure_requests: true,
             interfaces: Vec::new(), 
+            real_ip: None,
+            access_log: false,
+            list_watch_interval: crate::util::r#const::LIST_WATCH_INTERVAL,
             tokio_type: TokioType::MultiThread(TokioSettings::default()) ,
             cache: Vec::new(),
             ratelimiter: None,
@@ -0,0 +1,66 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public
+License, v. 2.0. If a copy of the MPL was not distributed with this
+file, You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+use http::{Response, StatusCode};
+use http_body_util::Full;
+
+use crate::{
+    context::ContextType, error::RResult, modules::{
+        ModuleType, PipeData, PipeModule
+    }
+};
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ApiKeyModule {}
+impl PipeModule for ApiKeyModule {
+    fn name(&self) -> ModuleType {
+        ModuleType::ApiKey
+    }
+
+    async fn execute(&self, ctx: &mut crate::context::GatewayContext, pipe_data: &crate::modules::PipeData) -> RResult<()> {
+        if let PipeData::ApiKeyData { profile } = pipe_data {
+            if let ContextType::HttpContext(http_context) = &mut ctx.context_type {
+                let profile = profile.read().await;
+                let request = &http_context.request_context;
+                let key = profile.from.iter().find_map(|from| from.get(&request.headers, &request.uri)).filter(|k| !k.is_empty());
+                let key = match key {
+                    Some(key) => key,
+                    None => {
+                        http_context.return_context.response = Some(super::unauthorized(None));
+                        return Ok(());
+                    }
+                };
+                let entry = match profile.lookup(&key).await {
+                    Some(entry) => entry,
+                    None => {
+                        log::warn!("api_key refused, route:{:?} client:{} unknown key", ctx.route, ctx.real_ip);
+                        http_context.return_context.response = Some(super::unauthorized(None));
+                        return Ok(());
+                    }
+                };
+                if !entry.routes.is_empty() && !ctx.route.as_ref().is_some_and(|r| entry.routes.contains(r)) {
+                    log::warn!("api_key refused, route:{:?} client:{} consumer:{} route not allowed", ctx.route, ctx.real_ip, entry.consumer);
+                    let (status, version, headers, body) = crate::common::four_and_four_page::response_page(StatusCode::FORBIDDEN);
+                    let mut response = Response::new(Full::new(body));
+                    *response.status_mut() = status;
+                    *response.version_mut() = version;
+                    *response.headers_mut() = headers;
+                    http_context.return_context.response = Some(response);
+                    return Ok(());
+                }
+                ctx.consumer = Some(entry.consumer.clone());
+                if let Some(lim) = profile.limiter(&key, &entry) {
+                    if let Err(state) = lim.check(&entry.consumer).await {
+                        log::warn!("api_key ratelimiter arrival, consumer:{} retry after:{:?}", entry.consumer, state.retry_after);
+                        http_context.return_context.response = Some(crate::modules::ratelimiter::ratelimiter::too_many_requests(&state)?);
+                    }
+                }
+            }
+            return Ok(());
+        }
+        unreachable!()
+    }
+}
@@ -0,0 +1,82 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public
+License, v. 2.0. If a copy of the MPL was not distributed with this
+file, You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+use base64::Engine;
+use http::header;
+
+use crate::{
+    common::htpasswd, context::ContextType, error::RResult, modules::{
+        ModuleType, PipeData, PipeModule
+    }
+};
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct BasicAuthModule {}
+impl PipeModule for BasicAuthModule {
+    fn name(&self) -> ModuleType {
+        ModuleType::BasicAuth
+    }
+
+    async fn execute(&self, ctx: &mut crate::context::GatewayContext, pipe_data: &crate::modules::PipeData) -> RResult<()> {
+        if let PipeData::BasicAuthData { profile } = pipe_data {
+            if let ContextType::HttpContext(http_context) = &mut ctx.context_type {
+                let profile = profile.read().await;
+                let credentials = http_context.request_context.headers.get(header::AUTHORIZATION)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| {
+                        let (scheme, c) = v.trim().split_once(' ')?;
+                        if scheme.eq_ignore_ascii_case("basic") { Some(c.trim()) } else { None }
+                    })
+                    .and_then(|c| base64::engine::general_purpose::STANDARD.decode(c).ok())
+                    .and_then(|c| String::from_utf8(c).ok());
+                let verified = match credentials.as_deref().and_then(|c| c.split_once(':')) {
+                    Some((user, password)) => {
+                        match profile.users.get(user) {
+                            Some(hash) => {
+                                let digest = ring::digest::digest(&ring::digest::SHA256, password.as_bytes());
+                                if profile.verified.get(user).is_some_and(|v| htpasswd::constant_time_eq(v.as_slice(), digest.as_ref())) {
+                                    Some(user.to_string())
+                                } else {
+                                    let ok = if hash.is_slow() {
+                                        let (hash, password) = (hash.clone(), password.to_string());
+                                        tokio::task::spawn_blocking(move || hash.verify(&password)).await.unwrap_or(false)
+                                    } else {
+                                        hash.verify(password)
+                                    };
+                                    if ok {
+                                        let mut v = [0u8; 32];
+                                        v.copy_from_slice(digest.as_ref());
+                                        profile.verified.insert(user.to_string(), v);
+                                        Some(user.to_string())
+                                    } else {
+                                        log::warn!("basic_auth refused, route:{:?} client:{} user:{} bad password", ctx.route, ctx.real_ip, user);
+                                        None
+                                    }
+                                }
+                            }
+                            None => {
+                                log::warn!("basic_auth refused, route:{:?} client:{} user:{} not found", ctx.route, ctx.real_ip, user);
+                                None
+                            }
+                        }
+                    }
+                    None => { None }
+                };
+                match verified {
+                    Some(user) => {
+                        ctx.user = Some(user);
+                    }
+                    None => {
+                        let challenge = format!("Basic realm=\"{}\", charset=\"UTF-8\"", profile.realm);
+                        http_context.return_context.response = Some(super::unauthorized(Some(&challenge)));
+                    }
+                }
+            }
+            return Ok(());
+        }
+        unreachable!()
+    }
+}
@@ -0,0 +1,164 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public
+License, v. 2.0. If a copy of the MPL was not distributed with this
+file, You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+use std::sync::Arc;
+
+use http::{header, HeaderMap, Request, Response, StatusCode};
+use http_body_util::Full;
+use hyper::body::Bytes;
+
+use crate::{
+    client::{RequestContent, ResponseContent}, context::{ContextType, GatewayContext}, error::RResult, modules::{
+        ModuleType, PipeData, PipeModule
+    }
+};
+
+use super::{ForwardAuthDecision, ForwardAuthProfile};
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ForwardAuthModule {}
+impl PipeModule for ForwardAuthModule {
+    fn name(&self) -> ModuleType {
+        ModuleType::ForwardAuth
+    }
+
+    async fn execute(&self, ctx: &mut crate::context::GatewayContext, pipe_data: &crate::modules::PipeData) -> RResult<()> {
+        if let PipeData::ForwardAuthData { profile } = pipe_data {
+            let profile = profile.read().await;
+            let cache_key = match (&profile.cache, &ctx.context_type) {
+                (Some(cache), ContextType::HttpContext(http_context)) => { Some(cache.key_of(&http_context.request_context.headers)) }
+                _ => { None }
+            };
+            let cached = match (&profile.cache, &cache_key) {
+                (Some(cache), Some(key)) => { cache.get(key) }
+                _ => { None }
+            };
+            let decision = match cached {
+                Some(decision) => { decision }
+                None => {
+                    let decision = match ask(ctx, &profile).await {
+                        Ok(decision) => { Arc::new(decision) }
+                        Err(e) => {
+                            log::error!("forward_auth failed, route:{:?} hosts:{} {}", ctx.route, profile.out_host, e);
+                            if let ContextType::HttpContext(http_context) = &mut ctx.context_type {
+                                http_context.return_context.response = Some(response(http_context.request_context.version, StatusCode::SERVICE_UNAVAILABLE, None));
+                            }
+                            return Ok(());
+                        }
+                    };
+                    /* an auth service in trouble is asked again next time */
+                    let cacheable = !matches!(&*decision, ForwardAuthDecision::Deny(status, ..) if status.is_server_error());
+                    if let (Some(cache), Some(key), true) = (&profile.cache, cache_key, cacheable) {
+                        cache.insert(key, decision.clone());
+                    }
+                    decision
+                }
+            };
+            if let ContextType::HttpContext(http_context) = &mut ctx.context_type {
+                /* the copied headers only come from the auth service, never from the client */
+                for name in &profile.response_headers {
+                    http_context.request_context.headers.remove(name);
+                }
+                match &*decision {
+                    ForwardAuthDecision::Allow(headers) => {
+                        for (name, value) in headers {
+                            http_context.request_context.headers.append(name, value.clone());
+                        }
+                    }
+                    ForwardAuthDecision::Deny(status, headers, body) => {
+                        log::warn!("forward_auth refused, route:{:?} client:{} status:{}", ctx.route, ctx.real_ip, status);
+                        http_context.return_context.response = Some(response(http_context.request_context.version, *status, Some((headers.clone(), body.clone()))));
+                    }
+                }
+            }
+            return Ok(());
+        }
+        unreachable!()
+    }
+}
+/* send the subrequest to a host_point of the hosts, the dispatche of the request chooses its own host_point afterwards */
+async fn ask(ctx: &mut GatewayContext, profile: &ForwardAuthProfile) -> RResult<ForwardAuthDecision> {
+    let gateway_instance = ctx.get_gateway_instance()?;
+    let hosts = gateway_instance.hosts.get(&profile.out_host).ok_or("not found hosts")?;
+    let redirect_context = std::mem::take(&mut ctx.redirect_context);
+    let balanced = crate::modules::dispatche::load_balance(ctx, hosts, hosts.lb_task.as_ref()).await;
+    let auth_redirect = std::mem::replace(&mut ctx.redirect_context, redirect_context);
+    balanced?;
+    let (host, port) = match (auth_redirect.host, auth_redirect.port) {
+        (Some(host), Some(port)) => { (host, port) }
+        _ => { return Err("no available host".into()); }
+    };
+    let http_context = match &ctx.context_type {
+        ContextType::HttpContext(http_context) => { http_context }
+        ContextType::TcpContext(_) => { unreachable!("tcp not support forward_auth") }
+    };
+    let request_context = &http_context.request_context;
+    let path_and_query = request_context.uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+    let uri: http::Uri = format!("{}://{}:{}{}{}", profile.scheme, host, port, profile.path, path_and_query).parse()?;
+    let mut headers = HeaderMap::new();
+    for name in &profile.request_headers {
+        for value in request_context.headers.get_all(name) {
+            headers.append(name, value.clone());
+        }
+    }
+    let body = if profile.body {
+        if let Some(content_type) = request_context.headers.get(header::CONTENT_TYPE) {
+            headers.insert(header::CONTENT_TYPE, content_type.clone());
+        }
+        request_context.body.clone()
+    } else {
+        Bytes::new()
+    };
+    let mut request = Request::new(Full::new(body));
+    *request.method_mut() = request_context.method.clone();
+    *request.uri_mut() = uri;
+    *request.headers_mut() = headers;
+    let timeout = profile.timeout.or(auth_redirect.timeout).unwrap_or(crate::util::r#const::DEFAULT_OUT_TIMEOUT);
+    let sent = profile.client.send(RequestContent::Http(request), timeout).await;
+    /* the host_point failures of the auth service count like the ones of the dispatche */
+    if let (Some(hosts), Some(permanent_failure)) = (&auth_redirect.hosts, &auth_redirect.permanent_failure) {
+        match &sent {
+            Ok(ResponseContent::Http((status, ..))) => {
+                crate::modules::dispatche::host_point_status_handing(hosts, permanent_failure, auth_redirect.previous_host, status).await;
+            }
+            Ok(ResponseContent::Tcp(_)) => {}
+            Err(e) => {
+                crate::modules::dispatche::host_point_exception_handing(hosts, permanent_failure, auth_redirect.previous_host, e).await?;
+            }
+        }
+    }
+    match sent? {
+        ResponseContent::Http((status, _, mut headers, body)) => {
+            if status.is_success() {
+                let copied = profile.response_headers.iter()
+                    .flat_map(|name| headers.get_all(name).iter().map(move |v| (name.clone(), v.clone())))
+                    .collect();
+                Ok(ForwardAuthDecision::Allow(copied))
+            } else {
+                for name in [header::CONNECTION, header::TRANSFER_ENCODING, header::CONTENT_LENGTH, header::UPGRADE] {
+                    headers.remove(name);
+                }
+                headers.remove("keep-alive");
+                Ok(ForwardAuthDecision::Deny(status, headers, body.unwrap_or_default()))
+            }
+        }
+        ResponseContent::Tcp(_) => { unreachable!("http response error") }
+    }
+}
+fn response(version: http::Version, status: StatusCode, answer: Option<(HeaderMap, Bytes)>) -> Response<Full<Bytes>> {
+    let (headers, body) = match answer {
+        Some(answer) => { answer }
+        None => {
+            let (_, _, headers, body) = crate::common::four_and_four_page::response_page(status);
+            (headers, body)
+        }
+    };
+    let mut response = Response::new(Full::new(body));
+    *response.status_mut() = status;
+    *response.version_mut() = version;
+    *response.headers_mut() = headers;
+    response
+}
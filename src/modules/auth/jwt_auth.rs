@@ -0,0 +1,75 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public
+License, v. 2.0. If a copy of the MPL was not distributed with this
+file, You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+use http::HeaderValue;
+
+use crate::{
+    context::ContextType, error::RResult, modules::{
+        ModuleType, PipeData, PipeModule
+    }
+};
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct JwtAuthModule {}
+impl PipeModule for JwtAuthModule {
+    fn name(&self) -> ModuleType {
+        ModuleType::JwtAuth
+    }
+
+    async fn execute(&self, ctx: &mut crate::context::GatewayContext, pipe_data: &crate::modules::PipeData) -> RResult<()> {
+        if let PipeData::JwtAuthData { profile } = pipe_data {
+            if let ContextType::HttpContext(http_context) = &mut ctx.context_type {
+                let profile = profile.read().await;
+                let request = &mut http_context.request_context;
+                /* the claims headers only come from a checked token, never from the client */
+                for (_, header_name) in &profile.forward_claims {
+                    request.headers.remove(header_name);
+                }
+                let token = profile.from.iter().find_map(|from| {
+                    let token = from.get(&request.headers, &request.uri)?;
+                    let token = match token.split_once(' ') {
+                        Some((scheme, t)) if scheme.eq_ignore_ascii_case("bearer") => t.trim().to_string(),
+                        _ => token,
+                    };
+                    if token.is_empty() { None } else { Some(token) }
+                });
+                let token = match token {
+                    Some(token) => token,
+                    None => {
+                        http_context.return_context.response = Some(super::unauthorized(Some(&format!("Bearer realm=\"{}\"", profile.realm))));
+                        return Ok(());
+                    }
+                };
+                match crate::common::jwt::verify(&token, &profile.keys, &profile.validation) {
+                    Ok(claims) => {
+                        for (claim, header_name) in &profile.forward_claims {
+                            let value = match crate::common::jwt::claim(&claims, claim) {
+                                Some(serde_json::Value::String(s)) => s.clone(),
+                                Some(serde_json::Value::Array(a)) => {
+                                    a.iter().map(|v| v.as_str().map(|s| s.to_string()).unwrap_or_else(|| v.to_string())).collect::<Vec<_>>().join(",")
+                                }
+                                Some(serde_json::Value::Null) | None => { continue; }
+                                Some(v) => v.to_string(),
+                            };
+                            if let Ok(value) = HeaderValue::from_str(&value) {
+                                request.headers.insert(header_name, value);
+                            }
+                        }
+                        ctx.claims = Some(claims);
+                    }
+                    Err(reason) => {
+                        log::warn!("jwt_auth refused, route:{:?} client:{} reason:{}", ctx.route, ctx.real_ip, reason);
+                        let challenge = format!("Bearer realm=\"{}\", error=\"invalid_token\", error_description=\"{}\"", profile.realm, reason);
+                        http_context.return_context.response = Some(super::unauthorized(Some(&challenge)));
+                        return Ok(());
+                    }
+                }
+            }
+            return Ok(());
+        }
+        unreachable!()
+    }
+}
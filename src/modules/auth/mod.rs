@@ -0,0 +1,409 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public
+License, v. 2.0. If a copy of the MPL was not distributed with this
+file, You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+pub(crate) mod jwt_auth;
+pub(crate) mod basic_auth;
+pub(crate) mod api_key;
+pub(crate) mod forward_auth;
+
+use std::{collections::HashMap, sync::Arc, time::{Duration, Instant, SystemTime}};
+
+use http::{header, HeaderMap, HeaderName, HeaderValue, Response, StatusCode, Uri};
+use http_body_util::Full;
+
+use crate::{
+    client::ClientProvider, 
+    common::{htpasswd::HtpasswdHash, jwt::{JwtKey, JwtValidation}, ratelimiter::RatelimiterCommon, redis::Redis}, 
+    config::parsers::model::api_key_builder::ApiKeyBuilder, error::RResult, instance::GatewayInstance, 
+    modules::{ratelimiter::RatelimiterType, PipeData}, util::time_unit::TimeUnit
+};
+
+/* where the credentials of the request are read */
+#[derive(Debug, Clone)]
+pub(crate) enum CredentialSource {
+    Header(HeaderName),
+    Cookie(String),
+    Query(String),
+}
+impl CredentialSource {
+    /* header:Authorization, cookie:token, query:access_token */
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        let (kind, name) = s.split_once(':')?;
+        let name = name.trim();
+        if name.is_empty() {
+            return None;
+        }
+        match kind.trim() {
+            "header" => HeaderName::from_bytes(name.as_bytes()).ok().map(CredentialSource::Header),
+            "cookie" => Some(CredentialSource::Cookie(name.to_string())),
+            "query" => Some(CredentialSource::Query(name.to_string())),
+            _ => None,
+        }
+    }
+    pub(crate) fn get(&self, headers: &HeaderMap, uri: &Uri) -> Option<String> {
+        match self {
+            CredentialSource::Header(name) => {
+                headers.get(name).and_then(|v| v.to_str().ok()).map(|v| v.trim().to_string())
+            }
+            CredentialSource::Cookie(name) => {
+                headers.get_all(header::COOKIE).iter()
+                    .filter_map(|v| v.to_str().ok())
+                    .flat_map(|v| v.split(';'))
+                    .find_map(|pair| {
+                        let (k, v) = pair.trim().split_once('=')?;
+                        if k == name { Some(v.to_string()) } else { None }
+                    })
+            }
+            CredentialSource::Query(name) => {
+                /* a base64 key comes with its + / = encoded */
+                uri.query().and_then(|q| {
+                    q.split('&').find_map(|pair| {
+                        let (k, v) = pair.split_once('=')?;
+                        if k == name { percent_encoding::percent_decode_str(v).decode_utf8().ok().map(|v| v.into_owned()) } else { None }
+                    })
+                })
+            }
+        }
+    }
+}
+#[derive(Debug)]
+pub(crate) struct JwtAuthProfile {
+    pub(crate) from: Vec<CredentialSource>,
+    pub(crate) secret: Option<String>,
+    pub(crate) jwks_file: Option<String>,
+    pub(crate) keys: Vec<JwtKey>,
+    pub(crate) validation: JwtValidation,
+    pub(crate) forward_claims: Vec<(String, HeaderName)>,     //claim, header sent upstream
+    pub(crate) realm: String,
+    jwks_modified: Option<SystemTime>,
+}
+impl JwtAuthProfile {
+    pub(crate) fn new(
+        from: Vec<CredentialSource>,
+        secret: Option<String>,
+        jwks_file: Option<String>,
+        validation: JwtValidation,
+        forward_claims: Vec<(String, HeaderName)>,
+        realm: String
+    ) -> RResult<Self> {
+        let mut profile = Self { from, secret, jwks_file, keys: Vec::new(), validation, forward_claims, realm, jwks_modified: None };
+        profile.reload_jwks()?;
+        Ok(profile)
+    }
+    pub(crate) fn jwks_changed(&self) -> bool {
+        self.jwks_file.is_some() && JwtAuthProfile::file_modified(&self.jwks_file) != self.jwks_modified
+    }
+    /* re-read the jwks file, a file that fails to parse keeps the old keys */
+    pub(crate) fn reload_jwks(&mut self) -> RResult<()> {
+        let mut keys = Vec::new();
+        if let Some(secret) = &self.secret {
+            keys.push(JwtKey::secret(secret));
+        }
+        if let Some(jwks_file) = &self.jwks_file {
+            self.jwks_modified = JwtAuthProfile::file_modified(&self.jwks_file);
+            let data = std::fs::read(jwks_file)?;
+            keys.extend(crate::common::jwt::parse_jwks(&data)?);
+        }
+        self.keys = keys;
+        Ok(())
+    }
+    fn file_modified(file: &Option<String>) -> Option<SystemTime> {
+        file.as_ref().and_then(|f| std::fs::metadata(f).and_then(|m| m.modified()).ok())
+    }
+}
+#[derive(Debug)]
+pub(crate) struct BasicAuthProfile {
+    pub(crate) file: String,
+    pub(crate) realm: String,
+    pub(crate) users: HashMap<String, HtpasswdHash>,
+    /* user -> sha256 of the password that matched last time, so bcrypt is not paid on every request */
+    pub(crate) verified: dashmap::DashMap<String, [u8; 32]>,
+    file_modified: Option<SystemTime>,
+}
+impl BasicAuthProfile {
+    pub(crate) fn new(file: String, realm: String) -> RResult<Self> {
+        let mut profile = Self { file, realm, users: HashMap::new(), verified: dashmap::DashMap::new(), file_modified: None };
+        profile.reload_file()?;
+        Ok(profile)
+    }
+    pub(crate) fn file_changed(&self) -> bool {
+        std::fs::metadata(&self.file).and_then(|m| m.modified()).ok() != self.file_modified
+    }
+    /* re-read the htpasswd file, a file that fails to parse keeps the old users */
+    pub(crate) fn reload_file(&mut self) -> RResult<()> {
+        self.file_modified = std::fs::metadata(&self.file).and_then(|m| m.modified()).ok();
+        let data = std::fs::read_to_string(&self.file)?;
+        self.users = crate::common::htpasswd::parse_htpasswd(&data)?;
+        self.verified.clear();
+        Ok(())
+    }
+}
+#[derive(Debug)]
+pub(crate) struct ApiKeyEntry {
+    pub(crate) consumer: String,
+    pub(crate) routes: Vec<String>,     //empty means every route
+    pub(crate) ratelimiter: Option<(Duration, u32)>,   //period, burst of the key
+}
+impl ApiKeyEntry {
+    pub(crate) fn from_builder(builder: ApiKeyBuilder) -> RResult<Self> {
+        let ratelimiter = match builder.ratelimiter {
+            Some(r) => {
+                let period = TimeUnit::parse(r.period.clone());
+                if period.is_zero() || r.burst == 0 {
+                    return Err(format!("api_key of {}: ratelimiter.period and burst must be more than 0", builder.consumer).into());
+                }
+                Some((period, r.burst))
+            }
+            None => { None }
+        };
+        Ok(Self { consumer: builder.consumer, routes: builder.routes.unwrap_or_default(), ratelimiter })
+    }
+}
+#[derive(Debug)]
+pub(crate) enum ApiKeyStore {
+    Memory(HashMap<String, Arc<ApiKeyEntry>>),
+    File {
+        file: String,
+        keys: HashMap<String, Arc<ApiKeyEntry>>,
+        modified: Option<SystemTime>,
+    },
+    /* the json of a key is at {prefix}api_key:{key}, kept here for a while, the missing keys too */
+    Redis {
+        redis: Redis,
+        cache: dashmap::DashMap<String, (Instant, Option<Arc<ApiKeyEntry>>)>,
+    },
+}
+#[derive(Debug)]
+pub(crate) struct ApiKeyProfile {
+    pub(crate) from: Vec<CredentialSource>,
+    pub(crate) store: ApiKeyStore,
+    /* one limiter per key with a ratelimiter, kept through the reloads while its setting does not change */
+    limiters: dashmap::DashMap<String, (Duration, u32, Arc<RatelimiterCommon>)>,
+}
+impl ApiKeyProfile {
+    pub(crate) fn new(from: Vec<CredentialSource>, mut store: ApiKeyStore) -> RResult<Self> {
+        if let ApiKeyStore::File { file, keys, modified } = &mut store {
+            *modified = ApiKeyProfile::file_modified(file);
+            *keys = ApiKeyProfile::parse_file(file)?;
+        }
+        Ok(Self { from, store, limiters: dashmap::DashMap::new() })
+    }
+    pub(crate) fn parse_keys(builders: Vec<ApiKeyBuilder>) -> RResult<HashMap<String, Arc<ApiKeyEntry>>> {
+        let mut keys = HashMap::new();
+        for builder in builders {
+            let key = match &builder.key {
+                Some(key) if !key.is_empty() => { key.clone() }
+                _ => { return Err(format!("api_key of {}: key not found", builder.consumer).into()); }
+            };
+            keys.insert(key, Arc::new(ApiKeyEntry::from_builder(builder)?));
+        }
+        Ok(keys)
+    }
+    fn parse_file(file: &str) -> RResult<HashMap<String, Arc<ApiKeyEntry>>> {
+        let data = std::fs::read(file)?;
+        ApiKeyProfile::parse_keys(serde_json::from_slice::<Vec<ApiKeyBuilder>>(&data)?)
+    }
+    fn file_modified(file: &str) -> Option<SystemTime> {
+        std::fs::metadata(file).and_then(|m| m.modified()).ok()
+    }
+    pub(crate) fn file_changed(&self) -> bool {
+        match &self.store {
+            ApiKeyStore::File { file, modified, .. } => { ApiKeyProfile::file_modified(file) != *modified }
+            _ => { false }
+        }
+    }
+    /* re-read the file, a file that fails to parse keeps the old keys */
+    pub(crate) fn reload_file(&mut self) -> RResult<()> {
+        if let ApiKeyStore::File { file, keys, modified } = &mut self.store {
+            *modified = ApiKeyProfile::file_modified(file);
+            *keys = ApiKeyProfile::parse_file(file)?;
+        }
+        Ok(())
+    }
+    pub(crate) async fn lookup(&self, key: &str) -> Option<Arc<ApiKeyEntry>> {
+        match &self.store {
+            ApiKeyStore::Memory(keys) | ApiKeyStore::File { keys, .. } => { keys.get(key).cloned() }
+            ApiKeyStore::Redis { redis, cache } => {
+                if let Some(cached) = cache.get(key) {
+                    if cached.0.elapsed() < crate::util::r#const::API_KEY_CACHE_TTL {
+                        return cached.1.clone();
+                    }
+                }
+                let redis_key = redis.prefixed(&format!("api_key:{}", key));
+                let entry = match redis.get::<String>(redis_key, false).await {
+                    Ok((json, _)) if json.is_empty() => { None }
+                    Ok((json, _)) => {
+                        match serde_json::from_str::<ApiKeyBuilder>(&json).map_err(|e| e.into()).and_then(ApiKeyEntry::from_builder) {
+                            Ok(entry) => { Some(Arc::new(entry)) }
+                            Err(e) => {
+                                log::error!("api_key redis bad metadata of a key:{}", e);
+                                None
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        /* not cached, the next request asks redis again */
+                        log::error!("api_key redis failed:{}", e);
+                        return None;
+                    }
+                };
+                if cache.len() >= crate::util::r#const::API_KEY_CACHE_SIZE {
+                    cache.retain(|_, v| v.0.elapsed() < crate::util::r#const::API_KEY_CACHE_TTL);
+                    if cache.len() >= crate::util::r#const::API_KEY_CACHE_SIZE {
+                        cache.clear();
+                    }
+                }
+                cache.insert(key.to_string(), (Instant::now(), entry.clone()));
+                entry
+            }
+        }
+    }
+    pub(crate) fn limiter(&self, key: &str, entry: &ApiKeyEntry) -> Option<Arc<RatelimiterCommon>> {
+        let (period, burst) = entry.ratelimiter?;
+        if let Some(l) = self.limiters.get(key) {
+            if l.0 == period && l.1 == burst {
+                return Some(l.2.clone());
+            }
+        }
+        let lim = crate::config::parsers::model::ratelimiter_builder::structure_ratelimiter(period, burst, RatelimiterType::Consumer, None);
+        self.limiters.insert(key.to_string(), (period, burst, lim.clone()));
+        Some(lim)
+    }
+}
+/* what the auth service answered, Allow carries the headers to copy onto the request */
+#[derive(Debug)]
+pub(crate) enum ForwardAuthDecision {
+    Allow(Vec<(HeaderName, HeaderValue)>),
+    Deny(StatusCode, HeaderMap, bytes::Bytes),
+}
+#[derive(Debug)]
+pub(crate) struct ForwardAuthCache {
+    pub(crate) ttl: Duration,
+    pub(crate) key: Vec<HeaderName>,
+    decisions: dashmap::DashMap<[u8; 32], (Instant, Arc<ForwardAuthDecision>)>,
+}
+impl ForwardAuthCache {
+    pub(crate) fn new(ttl: Duration, key: Vec<HeaderName>) -> Self {
+        Self { ttl, key, decisions: dashmap::DashMap::new() }
+    }
+    /* the values of the key headers, hashed so the credentials are not kept as they are */
+    pub(crate) fn key_of(&self, headers: &HeaderMap) -> [u8; 32] {
+        let mut ctx = ring::digest::Context::new(&ring::digest::SHA256);
+        for name in &self.key {
+            for v in headers.get_all(name) {
+                ctx.update(v.as_bytes());
+                ctx.update(b"\n");
+            }
+            ctx.update(b"\0");
+        }
+        let mut key = [0u8; 32];
+        key.copy_from_slice(ctx.finish().as_ref());
+        key
+    }
+    pub(crate) fn get(&self, key: &[u8; 32]) -> Option<Arc<ForwardAuthDecision>> {
+        let cached = self.decisions.get(key)?;
+        if cached.0.elapsed() < self.ttl { Some(cached.1.clone()) } else { None }
+    }
+    pub(crate) fn insert(&self, key: [u8; 32], decision: Arc<ForwardAuthDecision>) {
+        if self.decisions.len() >= crate::util::r#const::FORWARD_AUTH_CACHE_SIZE {
+            self.decisions.retain(|_, v| v.0.elapsed() < self.ttl);
+            if self.decisions.len() >= crate::util::r#const::FORWARD_AUTH_CACHE_SIZE {
+                self.decisions.clear();
+            }
+        }
+        self.decisions.insert(key, (Instant::now(), decision));
+    }
+}
+#[derive(Debug)]
+pub(crate) struct ForwardAuthProfile {
+    pub(crate) out_host: String,
+    pub(crate) scheme: &'static str,
+    pub(crate) path: String,
+    pub(crate) client: ClientProvider,
+    pub(crate) request_headers: Vec<HeaderName>,
+    pub(crate) response_headers: Vec<HeaderName>,
+    pub(crate) body: bool,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) cache: Option<ForwardAuthCache>,
+}
+/* 401 with the challenge of the scheme, e.g. Bearer realm="api", error="invalid_token" */
+pub(crate) fn unauthorized(challenge: Option<&str>) -> Response<Full<bytes::Bytes>> {
+    let (status, version, mut headers, body) = crate::common::four_and_four_page::response_page(StatusCode::UNAUTHORIZED);
+    if let Some(challenge) = challenge.and_then(|c| HeaderValue::from_str(c).ok()) {
+        headers.insert(header::WWW_AUTHENTICATE, challenge);
+    }
+    let mut response = Response::new(Full::new(body));
+    *response.status_mut() = status;
+    *response.version_mut() = version;
+    *response.headers_mut() = headers;
+    response
+}
+pub(crate) enum AuthFile<'a> {
+    Jwks(&'a tokio::sync::RwLock<JwtAuthProfile>),
+    Htpasswd(&'a tokio::sync::RwLock<BasicAuthProfile>),
+    ApiKeys(&'a tokio::sync::RwLock<ApiKeyProfile>),
+}
+/* Every jwt_auth, basic_auth and api_key of the instance, named pipes.xx, a pipe used by several routes has one per route */
+pub(crate) fn all_auth_files(instance: &GatewayInstance) -> Vec<(String, AuthFile<'_>)> {
+    let mut files = Vec::new();
+    for route in instance.routes.values() {
+        let mut task = Some(route.pipe_line.pipe_line_engine.task.as_ref());
+        while let Some(t) = task {
+            match &t.pipe_data {
+                PipeData::JwtAuthData { profile } => {
+                    files.push((format!("pipes.{}", route.pipe_line.pipe_name), AuthFile::Jwks(profile)));
+                }
+                PipeData::BasicAuthData { profile } => {
+                    files.push((format!("pipes.{}", route.pipe_line.pipe_name), AuthFile::Htpasswd(profile)));
+                }
+                PipeData::ApiKeyData { profile } => {
+                    files.push((format!("pipes.{}", route.pipe_line.pipe_name), AuthFile::ApiKeys(profile)));
+                }
+                _ => {}
+            }
+            task = t.next_task.as_deref();
+        }
+    }
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get(source: &str, headers: &HeaderMap, uri: &str) -> Option<String> {
+        CredentialSource::parse(source).unwrap().get(headers, &uri.parse::<Uri>().unwrap())
+    }
+
+    #[test]
+    fn query() {
+        let headers = HeaderMap::new();
+        assert_eq!(get("query:key", &headers, "/a?x=1&key=ab%2Bc%2Fd%3D%3D"), Some("ab+c/d==".to_string()));
+        assert_eq!(get("query:key", &headers, "/a?key=ab+c/d=="), Some("ab+c/d==".to_string()));
+        assert_eq!(get("query:key", &headers, "/a?akey=1&key="), Some("".to_string()));
+        assert_eq!(get("query:key", &headers, "/a?keys=1"), None);
+        assert_eq!(get("query:key", &headers, "/a?key=%ff"), None);
+        assert_eq!(get("query:key", &headers, "/a"), None);
+    }
+    #[test]
+    fn header_and_cookie() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-api-key", HeaderValue::from_static(" k1 "));
+        headers.append(header::COOKIE, HeaderValue::from_static("a=1; token=t1"));
+        headers.append(header::COOKIE, HeaderValue::from_static("b=2"));
+        assert_eq!(get("header:X-Api-Key", &headers, "/"), Some("k1".to_string()));
+        assert_eq!(get("cookie:token", &headers, "/"), Some("t1".to_string()));
+        assert_eq!(get("cookie:b", &headers, "/"), Some("2".to_string()));
+        assert_eq!(get("cookie:c", &headers, "/"), None);
+    }
+    #[test]
+    fn parse() {
+        assert!(CredentialSource::parse("query:").is_none());
+        assert!(CredentialSource::parse("body:x").is_none());
+        assert!(CredentialSource::parse("header:bad header").is_none());
+        assert!(CredentialSource::parse("token").is_none());
+    }
+}
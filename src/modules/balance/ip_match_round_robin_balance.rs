@@ -19,16 +19,24 @@ impl PipeModule for IpRoundRobinBalancer {
     
     async fn execute(&self, ctx: &mut GatewayContext, pipe_data: &crate::modules::PipeData) -> RResult<()>  {
         if let PipeData::IpRoundRobinBalancerData { profile } = pipe_data {
-            let ip = ctx.remote_addr.ip().to_string();
+            let ip = ctx.real_ip.to_string();
             let profile_read_lock = profile.read().await;
             if profile_read_lock.ip_matchs.contains_key(&ip) {
-                let dest_host_index = profile_read_lock.ip_matchs.get(&ip).unwrap();
+                let dest_host_index = *profile_read_lock.ip_matchs.get(&ip).unwrap();
                 let hosts_read_lock = profile_read_lock.hosts.read().await;
-                let host = hosts_read_lock.get(&dest_host_index);
+                // A host_point that has already failed for the current request is not selected again.
+                let host = if ctx.redirect_context.tried_hosts.contains(&dest_host_index) { None } else { hosts_read_lock.get(&dest_host_index) };
+                let host = host.filter(|h| h.circuit.available());
                 if let Some(host) = host {
+                    host.circuit.acquire();
                     ctx.redirect_context.host = Some(host.host.clone());
                     ctx.redirect_context.port = Some(host.port.clone());
                     ctx.redirect_context.timeout = host.timeout;
+                    ctx.redirect_context.previous_host = Some(dest_host_index);
+                    if ctx.redirect_context.hosts.is_none() || ctx.redirect_context.permanent_failure.is_none() {
+                        ctx.redirect_context.hosts = Some(profile_read_lock.take_hosts());
+                        ctx.redirect_context.permanent_failure = Some(profile_read_lock.take_permanent_failure());
+                    }
                     drop(hosts_read_lock);
                     drop(profile_read_lock);
                     return Ok(());
@@ -57,8 +65,7 @@ impl PipeModule for IpRoundRobinBalancer {
                 ctx.redirect_context.hosts = Some(profile_write_lock.take_hosts());
                 ctx.redirect_context.permanent_failure = Some(profile_write_lock.take_permanent_failure());
             }
-            let host_index = profile_write_lock.host_index;
-            profile_write_lock.ip_matchs.insert(ip, host_index);
+            profile_write_lock.ip_matchs.insert(ip, prev_index);
             drop(profile_write_lock);
             return Ok(());
         }
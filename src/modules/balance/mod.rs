@@ -7,7 +7,7 @@ file, You can obtain one at https://mozilla.org/MPL/2.0/.
 use std::{collections::HashMap, sync::{Arc, Mutex}};
 
 use crate::{
-    common::max_fails_durn::MaxFailsDurn, 
+    common::{circuit_breaker::CircuitBreaker, max_fails_durn::MaxFailsDurn}, 
     context::GatewayContext, 
     error::{BuilderError, BuilderErrorKind, GatewayError, PipeError, PipeErrorKind, RResult}, 
     instance::errors::Errs
@@ -40,12 +40,16 @@ impl LoadBalanceProfile {
     pub(crate) async fn take_host(&mut self, ctx: &mut GatewayContext) -> RResult<(Arc<String>, Arc<u16>, Option<std::time::Duration>, u16)> {
         //if there were no errors in the previous execution, continue to execute downwards.
         let mut map_write_lock = self.hosts.write().await;
-        if map_write_lock.len() == 0 {
+        //the host_points that have already been tried by the current request, or whose circuit does not let requests through, are skipped.
+        let mut candidates = map_write_lock.iter().filter(|(k, h)| !ctx.redirect_context.tried_hosts.contains(k) && h.circuit.available()).map(|(k, _)| *k).collect::<Vec<u16>>();
+        if candidates.len() == 0 {
             return Ok((Arc::new("".to_string()), Arc::new(0), Some(std::time::Duration::from_millis(0)), 0));
         }
-        let index = self.host_index % (map_write_lock.len() as u16);
+        candidates.sort();
+        let index = candidates[(self.host_index as usize) % candidates.len()];
         let host = map_write_lock.get_mut(&index);
         if let Some(h) = host {
+            h.circuit.acquire();
             if let Some(weight) = h.weight {
                 /* 
                 if weight is set, first increase the cur_weight of the current host.
@@ -79,4 +83,5 @@ pub(crate) struct Host {
     pub(crate) fail_timeout: Option<std::time::Duration>,
     pub(crate) timeout: Option<std::time::Duration>,
     pub(crate) max_fails_durn: MaxFailsDurn,
+    pub(crate) circuit: CircuitBreaker,
 }
@@ -22,13 +22,15 @@ impl PipeModule for RandomBalancer {
             let profile_read_lock = profile.read().await;
             let (host, port, timeout, prev_index) = {
                 let hosts_read_lock = profile_read_lock.hosts.read().await;
-                if hosts_read_lock.len() == 0 { // If no available host is found, return directly.
+                let candidates = hosts_read_lock.iter().filter(|(k, h)| !ctx.redirect_context.tried_hosts.contains(k) && h.circuit.available()).map(|(k, _)| *k).collect::<Vec<u16>>();
+                if candidates.len() == 0 { // If no available host is found, return directly.
                     ctx.redirect_context.host = None;
                     ctx.redirect_context.port = None;
                     return Ok(());
                 }
-                let index = rand::thread_rng().gen_range(0..hosts_read_lock.len()) as u16;
+                let index = candidates[rand::thread_rng().gen_range(0..candidates.len())];
                 let ret = &hosts_read_lock[&index];
+                ret.circuit.acquire();
                 (ret.host.clone(), ret.port.clone(), ret.timeout, index)
             };
             if ctx.redirect_context.hosts.is_none() || ctx.redirect_context.permanent_failure.is_none() {
@@ -4,9 +4,9 @@ License, v. 2.0. If a copy of the MPL was not distributed with this
 file, You can obtain one at https://mozilla.org/MPL/2.0/.
 */
 
-use std::io::Read;
+use std::{io::Read, net::{IpAddr, Ipv4Addr, Ipv6Addr}, time::{Duration, Instant, SystemTime}};
 use crate::{
-    common::ip_range::IpRange, context::{tcp_context, ContextType}, error::{ConfigError, ConfigErrorKind, GatewayError, RResult}, 
+    common::{ip_cidr::IpCidr, ip_range::IpRange, ip_trie::IpTrie}, context::{tcp_context, ContextType}, error::{ConfigError, ConfigErrorKind, GatewayError, RResult}, 
     modules::{ModuleType, PipeData, PipeModule,}
 };
 
@@ -22,30 +22,30 @@ impl PipeModule for BlackAndWhiteList {
         if let PipeData::BlackAndWhiteListData { profile } = pipe_data {
             match &mut ctx.context_type {
                 ContextType::HttpContext(http_context) => {
-                    let ip_str = http_context.request_context.remote_addr.ip().to_string();
+                    let ip = ctx.real_ip;
                     let profile_read_lock = profile.read().await;
                     if profile_read_lock.has_white_list() {
-                        if !profile_read_lock.check_whitelist(&ip_str) {
+                        if !profile_read_lock.check_whitelist(&ip) {
                             return Err(gateway_err!(BlackAndWhiteListError, "not in whitelist", ConfigError::new(ConfigErrorKind::BLACKANDWHITE)));
                         }
                     }
                     if profile_read_lock.has_black_list() {
-                        if profile_read_lock.check_blacklist(&ip_str) {
+                        if profile_read_lock.check_blacklist(&ip) {
                             return Err(gateway_err!(BlackAndWhiteListError, "in blacklist", ConfigError::new(ConfigErrorKind::BLACKANDWHITE)));
                         }
                     }
                     drop(profile_read_lock);
                 },
                 ContextType::TcpContext(tcp_context) => {
-                    let ip_str = ctx.remote_addr.ip().to_string();
+                    let ip = ctx.real_ip;
                     let profile_read_lock = profile.read().await;
                     if profile_read_lock.has_white_list() {
-                        if !profile_read_lock.check_whitelist(&ip_str) {
+                        if !profile_read_lock.check_whitelist(&ip) {
                             return Err(gateway_err!(BlackAndWhiteListError, "not in whitelist", ConfigError::new(ConfigErrorKind::BLACKANDWHITE)));
                         }
                     }
                     if profile_read_lock.has_black_list() {
-                        if profile_read_lock.check_blacklist(&ip_str) {
+                        if profile_read_lock.check_blacklist(&ip) {
                             return Err(gateway_err!(BlackAndWhiteListError, "in blacklist", ConfigError::new(ConfigErrorKind::BLACKANDWHITE)));
                         }
                     }
@@ -64,74 +64,82 @@ pub(crate) struct BlackAndWhiteListProfile {
     inner: BlackAndWhiteListInitData,
     black_list: Vec<BaW>,
     white_list: Vec<BaW>,
+    black_trie: IpTrie,
+    white_trie: IpTrie,
+    runtime_black: Vec<RuntimeBaW>,         //added through the admin api
+    runtime_white: Vec<RuntimeBaW>,
+    files_modified: [Option<SystemTime>; 2],    //black, white
+}
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ListKind {
+    Black,
+    White,
 }
 #[derive(Debug, Clone)]
+pub(crate) struct RuntimeBaW {
+    pub(crate) baw: BaW,
+    pub(crate) expire: Option<Instant>,
+}
+/*
+One line of a black or white list:
+    192.168.1.10 / 2001:db8::1                      single ip
+    10.0.0.0/8 / 2001:db8::/32                      cidr
+    10.0.0.1-10.0.0.99 / 2001:db8::1-2001:db8::ff   range
+0.0.0.0 (or ::) alone matches every ip. IPv4-mapped ipv6 addresses (::ffff:a.b.c.d) are the same as the ipv4 address.
+ */
+#[derive(Debug, Clone, PartialEq)]
 pub(crate) enum BaW {
-    Single((u8,u8,u8,u8)),
-    Range(IpRange)
+    Single(IpAddr),
+    Cidr(IpCidr),
+    Range(IpRange),
 }
 impl BaW {
-    pub(crate) fn validate(&self) -> bool {
-        match self {
-            BaW::Range(ip_range) => {
-                if ip_range.start_ip.0 > ip_range.end_ip.0 {
-                    return false;
-                }
-                if ip_range.start_ip.1 > ip_range.end_ip.1 {
-                    return false;
-                }
-                if ip_range.start_ip.2 > ip_range.end_ip.2 {
-                    return false;
-                }
-                if ip_range.start_ip.3 > ip_range.end_ip.3 {
-                    return false;
-                }
+    pub(crate) fn parse(line: &str) -> Option<BaW> {
+        let line = line.trim();
+        if line.contains('/') {
+            return IpCidr::parse(line).map(BaW::Cidr);
+        }
+        match line.split_once('-') {
+            Some((start_ip, end_ip)) => {
+                let start_ip = start_ip.trim().parse::<IpAddr>().ok()?;
+                let end_ip = end_ip.trim().parse::<IpAddr>().ok()?;
+                IpRange::new(start_ip, end_ip).map(BaW::Range)
             }
-            BaW::Single(ip) => {
-
+            None => {
+                line.parse::<IpAddr>().ok().map(|ip| BaW::Single(IpCidr::canonical(ip)))
             }
         }
-        true
     }
-    pub(crate) fn check_ip(&self, ip_addr: &str) -> bool {
+    pub(crate) fn to_cidrs(&self) -> Vec<IpCidr> {
         match self {
-            BaW::Range(ip_range) => {
-                if ip_range.ip_s == 0 && ip_range.ip_e == 0 {
-                    return true;
-                }
-                let ips: Vec<u8> = ip_addr.split(".").map(|item| {
-                    item.parse::<u8>().unwrap()
-                }).collect();
-                if ips.len() != 4 {
-                    return false;
-                }
-                let mut ipt: u32 = 0;
-                for i in ips {
-                    ipt = ipt << 8 | i as u32;
-                }
-                ip_range.ip_s <= ipt && ipt <= ip_range.ip_e
-            }
-            BaW::Single((a, b, c, d)) => {
-                if a==&0 && b==&0 && c==&0 && d==&0 {
-                    return true;
-                }
-                let v: Vec<&str> = ip_addr.split(".").collect();
-                if a.clone() == v.get(0).unwrap().parse::<u8>().unwrap()
-                    && b.clone() == v.get(1).unwrap().parse::<u8>().unwrap()
-                    && c.clone() == v.get(2).unwrap().parse::<u8>().unwrap()
-                    && d.clone() == v.get(3).unwrap().parse::<u8>().unwrap() {
-                    true
-                } else {
-                    false
-                }
+            BaW::Single(ip) if ip.is_unspecified() => { Self::any() }
+            BaW::Single(ip) => {
+                vec![IpCidr { addr: *ip, prefix: if ip.is_ipv4() { 32 } else { 128 } }]
             }
+            BaW::Cidr(cidr) => { vec![*cidr] }
+            BaW::Range(ip_range) if ip_range.start_ip.is_unspecified() && ip_range.end_ip.is_unspecified() => { Self::any() }
+            BaW::Range(ip_range) => { ip_range.to_cidrs() }
         }
-        
+    }
+    fn any() -> Vec<IpCidr> {
+        vec![
+            IpCidr { addr: IpAddr::V4(Ipv4Addr::UNSPECIFIED), prefix: 0 },
+            IpCidr { addr: IpAddr::V6(Ipv6Addr::UNSPECIFIED), prefix: 0 },
+        ]
     }
 }
 impl Default for BaW {
     fn default() -> Self {
-        Self::Single((0,0,0,0))
+        Self::Single(IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+    }
+}
+impl std::fmt::Display for BaW {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BaW::Single(ip) => { write!(f, "{}", ip) }
+            BaW::Cidr(cidr) => { write!(f, "{}", cidr) }
+            BaW::Range(ip_range) => { write!(f, "{}-{}", ip_range.start_ip, ip_range.end_ip) }
+        }
     }
 }
 #[derive(Debug, Clone)]
@@ -152,77 +160,164 @@ impl Default for BlackAndWhiteListInitData {
 }
 impl BlackAndWhiteListProfile {
     pub(crate) fn new(init_data: BlackAndWhiteListInitData) -> RResult<Self> {
-        let black_list = match &init_data.blacklist {
-            BawFileOrMemory::File(file_name) => {
-                BlackAndWhiteListProfile::parse_list_from_file(&file_name)?
-            }
-            BawFileOrMemory::Memory(list) => {
-                let mut bl = Vec::new();
-                for line in list {
-                    let baw = BlackAndWhiteListProfile::parse_baw(line)?;
-                    bl.push(baw);
-                }
-                bl
-            }
-            BawFileOrMemory::None => { Vec::new() }
-        };
-        let white_list = match &init_data.whitelist {
-            BawFileOrMemory::File(file_name) => {
-                BlackAndWhiteListProfile::parse_list_from_file(&file_name)?
-            }
-            BawFileOrMemory::Memory(list) => {
-                let mut wl = Vec::new();
-                for line in list {
-                    let baw = BlackAndWhiteListProfile::parse_baw(line)?;
-                    wl.push(baw);
-                }
-                wl
-            }
-            BawFileOrMemory::None => { Vec::new() }
-        };
-        Ok(Self {
+        let black_list = BlackAndWhiteListProfile::parse_list(&init_data.blacklist)?;
+        let white_list = BlackAndWhiteListProfile::parse_list(&init_data.whitelist)?;
+        let files_modified = [
+            BlackAndWhiteListProfile::file_modified(&init_data.blacklist),
+            BlackAndWhiteListProfile::file_modified(&init_data.whitelist),
+        ];
+        let mut profile = Self {
             inner: init_data,
             black_list,
             white_list,
-        })
+            files_modified,
+            ..Default::default()
+        };
+        profile.rebuild();
+        Ok(profile)
     }
-    pub(crate) fn check_whitelist(&self, ip: &str) -> bool {
-        for wl in &self.white_list {
-            if wl.check_ip(ip) {
-                return true;
-            }
-        }
-        false
+    pub(crate) fn check_whitelist(&self, ip: &IpAddr) -> bool {
+        self.white_trie.contains(ip)
     }
-    pub(crate) fn check_blacklist(&self, ip: &str) -> bool {
-        for bl in &self.black_list {
-            if bl.check_ip(ip) {
-                return true;
-            }
-        }
-        false
+    pub(crate) fn check_blacklist(&self, ip: &IpAddr) -> bool {
+        self.black_trie.contains(ip)
     }
     pub(crate) fn has_black_list(&self) -> bool {
         if let BawFileOrMemory::None = self.inner.blacklist {
-            false
+            !self.runtime_black.is_empty()
         } else {
             true
         }
     }
     pub(crate) fn has_white_list(&self) -> bool {
         if let BawFileOrMemory::None = self.inner.whitelist {
-            false
+            !self.runtime_white.is_empty()
         } else {
             true
         }
     }
+    /* the entries from the config or the file, then the runtime entries with the seconds they still live */
+    pub(crate) fn entries(&self, kind: ListKind) -> (&Vec<BaW>, Vec<(String, Option<u64>)>) {
+        let (list, runtime) = match kind {
+            ListKind::Black => (&self.black_list, &self.runtime_black),
+            ListKind::White => (&self.white_list, &self.runtime_white),
+        };
+        let now = Instant::now();
+        (list, runtime.iter().map(|r| {
+            (r.baw.to_string(), r.expire.map(|e| e.saturating_duration_since(now).as_secs()))
+        }).collect())
+    }
+    /* adding the same entry again only renews its ttl */
+    pub(crate) fn add(&mut self, kind: ListKind, baw: BaW, ttl: Option<Duration>) {
+        let runtime = match kind {
+            ListKind::Black => &mut self.runtime_black,
+            ListKind::White => &mut self.runtime_white,
+        };
+        let expire = ttl.map(|t| Instant::now() + t);
+        match runtime.iter_mut().find(|r| r.baw == baw) {
+            Some(r) => { r.expire = expire; }
+            None => { runtime.push(RuntimeBaW { baw, expire }); }
+        }
+        self.rebuild();
+    }
+    /* entries of the config or the file come back with the next reload of the file */
+    pub(crate) fn remove(&mut self, kind: ListKind, baw: &BaW) -> bool {
+        let (list, runtime) = match kind {
+            ListKind::Black => (&mut self.black_list, &mut self.runtime_black),
+            ListKind::White => (&mut self.white_list, &mut self.runtime_white),
+        };
+        let len = list.len() + runtime.len();
+        list.retain(|b| b != baw);
+        runtime.retain(|r| &r.baw != baw);
+        let removed = len != list.len() + runtime.len();
+        if removed {
+            self.rebuild();
+        }
+        removed
+    }
+    pub(crate) fn has_expired(&self) -> bool {
+        let now = Instant::now();
+        self.runtime_black.iter().chain(self.runtime_white.iter()).any(|r| r.expire.is_some_and(|e| e <= now))
+    }
+    pub(crate) fn expire(&mut self) {
+        let now = Instant::now();
+        self.runtime_black.retain(|r| r.expire.is_none_or(|e| e > now));
+        self.runtime_white.retain(|r| r.expire.is_none_or(|e| e > now));
+        self.rebuild();
+    }
+    pub(crate) fn files_changed(&self) -> bool {
+        BlackAndWhiteListProfile::file_modified(&self.inner.blacklist) != self.files_modified[0]
+            || BlackAndWhiteListProfile::file_modified(&self.inner.whitelist) != self.files_modified[1]
+    }
+    /*
+    re-read the files, a file that fails to parse keeps the old list and is read again at the next check.
+    The other file is reloaded all the same, the error is given after.
+     */
+    pub(crate) fn reload_files(&mut self) -> RResult<()> {
+        let mut ret = Ok(());
+        let modified = BlackAndWhiteListProfile::file_modified(&self.inner.blacklist);
+        if modified != self.files_modified[0] {
+            match BlackAndWhiteListProfile::parse_list(&self.inner.blacklist) {
+                Ok(list) => {
+                    self.black_list = list;
+                    self.files_modified[0] = modified;
+                }
+                Err(e) => { ret = Err(e); }
+            }
+        }
+        let modified = BlackAndWhiteListProfile::file_modified(&self.inner.whitelist);
+        if modified != self.files_modified[1] {
+            match BlackAndWhiteListProfile::parse_list(&self.inner.whitelist) {
+                Ok(list) => {
+                    self.white_list = list;
+                    self.files_modified[1] = modified;
+                }
+                Err(e) => { ret = ret.and(Err(e)); }
+            }
+        }
+        self.rebuild();
+        ret
+    }
+    fn file_modified(list: &BawFileOrMemory) -> Option<SystemTime> {
+        match list {
+            BawFileOrMemory::File(file_name) if !file_name.is_empty() => {
+                std::fs::metadata(file_name).and_then(|m| m.modified()).ok()
+            }
+            _ => { None }
+        }
+    }
+    fn rebuild(&mut self) {
+        self.black_trie = BlackAndWhiteListProfile::make_trie(self.black_list.iter().chain(self.runtime_black.iter().map(|r| &r.baw)));
+        self.white_trie = BlackAndWhiteListProfile::make_trie(self.white_list.iter().chain(self.runtime_white.iter().map(|r| &r.baw)));
+    }
+    fn make_trie<'a>(list: impl Iterator<Item = &'a BaW>) -> IpTrie {
+        let mut trie = IpTrie::default();
+        for baw in list {
+            for cidr in baw.to_cidrs() {
+                trie.insert(&cidr);
+            }
+        }
+        trie
+    }
+    fn parse_list(list: &BawFileOrMemory) -> RResult<Vec<BaW>> {
+        match list {
+            BawFileOrMemory::File(file_name) => {
+                BlackAndWhiteListProfile::parse_list_from_file(file_name)
+            }
+            BawFileOrMemory::Memory(list) => {
+                list.iter().map(|line| BlackAndWhiteListProfile::parse_baw(line)).collect()
+            }
+            BawFileOrMemory::None => { Ok(Vec::new()) }
+        }
+    }
+    /* empty lines and lines starting with # are skipped */
     pub(crate) fn parse_list_from_file(file_path: &str) -> RResult<Vec<BaW>> {
         if file_path.is_empty() {
             return Ok(Vec::new());
         }
         let mut ret = Vec::new();
         let file_content = BlackAndWhiteListProfile::read_file(file_path)?;
-        let file_content_lines: Vec<&str> = file_content.lines().filter(|s| !s.is_empty() ).collect();
+        let file_content_lines: Vec<&str> = file_content.lines().map(|s| s.trim()).filter(|s| !s.is_empty() && !s.starts_with('#')).collect();
         for line in file_content_lines {
             let baw = BlackAndWhiteListProfile::parse_baw(line)?;
             ret.push(baw);
@@ -230,64 +325,14 @@ impl BlackAndWhiteListProfile {
         Ok(ret)
     }
     fn parse_baw(line: &str) -> RResult<BaW> {
-        let v: Vec<&str> = line.split('-').filter(|c| !c.is_empty()).collect();
-        match v.len() {
-            1 => {
-                let ip = v.get(0).unwrap();
-                let ips: Vec<&str> = ip.split('.').filter(|c| !c.is_empty() ).collect();
-                if ips.len() != 4 {
-                    return Err(gateway_err!(ConfigurationFailed, "Config black or white list failed Single IP len failed.", ConfigError::new(ConfigErrorKind::BAWTCPIP)));
-                }
-                return Ok(BaW::Single((
-                    ips.get(0).unwrap().parse()?,
-                    ips.get(1).unwrap().parse()?,
-                    ips.get(2).unwrap().parse()?,
-                    ips.get(3).unwrap().parse()?,
-                )));
-            }
-            2 => {
-                let start_ip = v.get(0).unwrap();
-                let end_ip = v.get(1).unwrap();
-                let start_ips: Vec<&str> = start_ip.split('.').filter(|c| !c.is_empty() ).collect();
-                let end_ips: Vec<&str> = end_ip.split('.').filter(|c| !c.is_empty() ).collect();
-                let a = start_ips.get(0).unwrap().parse()?;
-                let b = start_ips.get(1).unwrap().parse()?;
-                let c = start_ips.get(2).unwrap().parse()?;
-                let d = start_ips.get(3).unwrap().parse()?;
-                let e = end_ips.get(0).unwrap().parse()?;
-                let f = end_ips.get(1).unwrap().parse()?;
-                let g = end_ips.get(2).unwrap().parse()?;
-                let h = end_ips.get(3).unwrap().parse()?;
-                let mut ip_s: u32 = 0;
-                ip_s = ip_s << 8 | a as u32;
-                ip_s = ip_s << 8 | b as u32;
-                ip_s = ip_s << 8 | c as u32;
-                ip_s = ip_s << 8 | d as u32;
-                let mut ip_e: u32 = 0;
-                ip_e = ip_e << 8 | e as u32;
-                ip_e = ip_e << 8 | f as u32;
-                ip_e = ip_e << 8 | g as u32;
-                ip_e = ip_e << 8 | h as u32;
-                let baw = BaW::Range ( IpRange { 
-                    start_ip: (a, b, c, d, ),
-                    ip_s,
-                    end_ip: (e, f, g, h, ),
-                    ip_e,
-                } );
-                if !baw.validate() {
-                    return Err(gateway_err!(ConfigurationFailed, "Config black or white list failed Ip Range failed", ConfigError::new(ConfigErrorKind::BAWTCPIP)));    
-                }
-                return Ok(baw)
-            }
-            _ => {
-                return Err(gateway_err!(ConfigurationFailed, "Config black or white list failed", ConfigError::new(ConfigErrorKind::BAWTCPIP)));
-            }
-        }
+        BaW::parse(line).ok_or_else(|| {
+            gateway_err!(ConfigurationFailed, format!("Config black or white list failed, bad ip, cidr or range:{}", line), ConfigError::new(ConfigErrorKind::BAWTCPIP))
+        })
     }
     fn read_file(file_path: &str) -> RResult<String> {
-        let mut file = std::fs::OpenOptions::new().read(true).open("examples/test.txt")?;
+        let mut file = std::fs::OpenOptions::new().read(true).open(file_path)?;
         let mut buffer = String::new();
-        let read_buffer = file.read_to_string(&mut buffer)?;
+        file.read_to_string(&mut buffer)?;
         Ok(buffer)
     }
-}
\ No newline at end of file
+}
@@ -6,3 +6,30 @@ file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 pub(crate) mod black_and_white_list;
 
+use crate::{instance::{routes::r#in::In, GatewayInstance}, modules::PipeData};
+
+use self::black_and_white_list::BlackAndWhiteListProfile;
+
+/*
+Every black and white list of the instance, named pipes.xx for the black_white_list pipe_modules and routes.xx for the in of type ip/ipfile.
+A pipe used by several routes has one list per route, they all carry the same name.
+ */
+pub(crate) fn all_lists(instance: &GatewayInstance) -> Vec<(String, &tokio::sync::RwLock<BlackAndWhiteListProfile>)> {
+    let mut lists = Vec::new();
+    for (route_name, route) in &instance.routes {
+        match &route.r#in {
+            In::Ip { ranges, .. } | In::IpFile { ranges, .. } => {
+                lists.push((format!("routes.{}", route_name), ranges.as_ref()));
+            }
+            In::Regex { .. } => {}
+        }
+        let mut task = Some(route.pipe_line.pipe_line_engine.task.as_ref());
+        while let Some(t) = task {
+            if let PipeData::BlackAndWhiteListData { profile } = &t.pipe_data {
+                lists.push((format!("pipes.{}", route.pipe_line.pipe_name), profile));
+            }
+            task = t.next_task.as_deref();
+        }
+    }
+    lists
+}
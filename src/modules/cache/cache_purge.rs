@@ -0,0 +1,102 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public
+License, v. 2.0. If a copy of the MPL was not distributed with this
+file, You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+use std::sync::Arc;
+
+use http::{header, Response, StatusCode};
+use http_body_util::Full;
+
+use crate::{
+    common::ip_cidr::IpCidr,
+    context::ContextType,
+    error::RResult,
+    modules::{
+        cache::{http_cache_cell::HttpCacheShared, http_cache_disk::HttpCacheDisk, http_cache_policy::CachePolicy, http_cache_purge::{self, CachePurge, PurgeCount, PurgeTarget}, redis::RedisCacheStore}, ModuleType, PipeData, PipeModule
+    }
+};
+
+#[derive(Debug)]
+pub(crate) struct CachePurgeProfile {
+    pub(crate) allow: Vec<IpCidr>,
+    pub(crate) policy: CachePolicy,         //the key and methods of the cache modules of the route
+    pub(crate) memory: Option<Arc<HttpCacheShared>>,
+    pub(crate) disk: Option<Arc<HttpCacheDisk>>,
+    pub(crate) redis: Option<RedisCacheStore>,     //the namespace of the redis cache modules of the route
+}
+
+/*
+PURGE /path          the entry of the url, for every method of the policy
+PURGE /path*          every entry of the route whose url starts with /path
+Surrogate-Key: a b   every entry tagged a or b by the upstream
+ */
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct CachePurgeModule {}
+impl PipeModule for CachePurgeModule {
+    fn name(&self) -> ModuleType {
+        ModuleType::CachePurge
+    }
+
+    async fn execute(&self, ctx: &mut crate::context::GatewayContext, pipe_data: &crate::modules::PipeData) -> RResult<()> {
+        if let PipeData::CachePurgeData { profile } = pipe_data {
+            if let ContextType::HttpContext(http_context) = &mut ctx.context_type {
+                if http_context.request_context.method.as_str() != "PURGE" {
+                    return Ok(());
+                }
+                let profile = profile.read().await;
+                if !profile.allow.iter().any(|cidr| cidr.contains(&ctx.real_ip)) {
+                    log::warn!("cache_purge refused, route:{:?} client:{}", ctx.route, ctx.real_ip);
+                    http_context.return_context.response = Some(page(StatusCode::FORBIDDEN));
+                    return Ok(());
+                }
+                let request = &mut http_context.request_context;
+                let tags = http_cache_purge::surrogate_keys(&request.headers);
+                let target = if !tags.is_empty() {
+                    PurgeTarget::Tags(tags)
+                } else if request.uri.path().ends_with('*') {
+                    let url = http_cache_purge::request_url(request);
+                    let url = url.split('?').next().unwrap_or_default();
+                    PurgeTarget::Prefix(url.trim_end_matches('*').to_string())
+                } else {
+                    /* the key is rendered as if the request had been made with the methods the cache stores */
+                    let method = request.method.clone();
+                    let mut keys = Vec::new();
+                    for m in &profile.policy.methods {
+                        request.method = m.clone();
+                        keys.push(profile.policy.key.render(request));
+                    }
+                    request.method = method;
+                    PurgeTarget::Keys(keys)
+                };
+                let purge = CachePurge::new(target, ctx.route.clone());
+                let mut count = PurgeCount::default();
+                if let Some(memory) = &profile.memory {
+                    count.memory = memory.purge(&purge);
+                }
+                if let Some(disk) = &profile.disk {
+                    count.disk = disk.purge(&purge).await;
+                }
+                if let Some(store) = &profile.redis {
+                    count.redis = purge.purge_redis(&store.redis, Some(store.namespace(ctx.route.as_deref()))).await?;
+                }
+                log::info!("cache_purge route:{:?} client:{} {:?} purged memory:{} redis:{} disk:{}", ctx.route, ctx.real_ip, purge.target, count.memory, count.redis, count.disk);
+                let body = serde_json::json!({ "purged": { "memory": count.memory, "redis": count.redis, "disk": count.disk } }).to_string();
+                let mut response = Response::new(Full::new(bytes::Bytes::from(body)));
+                response.headers_mut().insert(header::CONTENT_TYPE, http::HeaderValue::from_static("application/json"));
+                http_context.return_context.response = Some(response);
+            }
+            return Ok(());
+        }
+        unreachable!()
+    }
+}
+fn page(status: StatusCode) -> Response<Full<bytes::Bytes>> {
+    let (status, version, headers, body) = crate::common::four_and_four_page::response_page(status);
+    let mut response = Response::new(Full::new(body));
+    *response.status_mut() = status;
+    *response.version_mut() = version;
+    *response.headers_mut() = headers;
+    response
+}
@@ -0,0 +1,49 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public
+License, v. 2.0. If a copy of the MPL was not distributed with this
+file, You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+use crate::{
+    context::ContextType, 
+    error::RResult, 
+    modules::{
+        cache::memory::memory_get::memory_key, ModuleType, PipeData, PipeModule
+    }
+};
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct DiskCacheGet {}
+impl PipeModule for DiskCacheGet {
+    fn name(&self) -> ModuleType {
+        ModuleType::DiskGet
+    }
+    
+    async fn execute(&self, ctx: &mut crate::context::GatewayContext, pipe_data: &crate::modules::PipeData) -> RResult<()>  {
+        if let PipeData::DiskCacheGetData { profile } = pipe_data {
+            if let ContextType::HttpContext(http_context) = &mut ctx.context_type {
+                let profile_read_lock = profile.read().await;
+                if !profile_read_lock.policy.is_cacheable_method(&http_context.request_context.method) {
+                    return Ok(());
+                }
+                let key = memory_key(ctx.route.as_deref(), &profile_read_lock.policy.key.render(&http_context.request_context));
+                let refresh = if profile_read_lock.expire.is_zero() { None } else { Some(profile_read_lock.expire) };
+                let max_hit = if profile_read_lock.hit == -1 { None } else { Some(profile_read_lock.hit) };
+                if let Some(value) = profile_read_lock.disk.get(&key, &http_context.request_context.headers, refresh, max_hit).await {
+                    let (header, version, status, bytes) = value.to_origin();
+                    http_context.response_context.status = status;
+                    http_context.response_context.version = version;
+                    http_context.response_context.headers = header;
+                    http_context.response_context.body = bytes;
+                    http_context.response_context.refresh();
+                    http_context.cache_hit = true;
+                }
+                if http_context.cache_hit && profile_read_lock.back {
+                    ctx.prompt_return = true;
+                }
+            }
+            return Ok(());
+        }
+        unreachable!()
+    }
+}
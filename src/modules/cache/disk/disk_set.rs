@@ -0,0 +1,58 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public
+License, v. 2.0. If a copy of the MPL was not distributed with this
+file, You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+use crate::{
+    context::ContextType, 
+    error::RResult, 
+    modules::{
+        cache::{http_cache_cell::{HttpCacheMeta, HttpCacheShared}, http_cache_policy, http_cache_purge, memory::memory_get::memory_key}, ModuleType, PipeData, PipeModule
+    }
+};
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct DiskCacheSet {}
+
+impl PipeModule for DiskCacheSet {
+    fn name(&self) -> ModuleType {
+        ModuleType::DiskSet
+    }
+    
+    async fn execute(&self, ctx: &mut crate::context::GatewayContext, pipe_data: &crate::modules::PipeData) -> RResult<()>  {
+        if let PipeData::DiskCacheSetData { profile } = pipe_data {
+            if let ContextType::HttpContext(http_context) = &mut ctx.context_type {
+                let profile_read_lock = profile.read().await;
+                let response = &http_context.response_context;
+                let expire = match profile_read_lock.policy.store_for(&http_context.request_context, response.status, &response.headers, profile_read_lock.expire) {
+                    Some(expire) => { expire }
+                    None => { return Ok(()); }
+                };
+                let vary = match http_cache_policy::vary_names(&response.headers) {
+                    Some(vary) => { vary }
+                    None => { return Ok(()); }
+                };
+                let key = memory_key(ctx.route.as_deref(), &profile_read_lock.policy.key.render(&http_context.request_context));
+                let cell = HttpCacheShared::memory_cache_value(
+                    response.status, 
+                    response.version, 
+                    response.headers.clone(), 
+                    response.body.clone()
+                );
+                let meta = HttpCacheMeta {
+                    expire,
+                    hit: if profile_read_lock.hit == -1 { None } else { Some(profile_read_lock.hit) },
+                    url: http_cache_purge::request_url(&http_context.request_context),
+                    tags: http_cache_purge::surrogate_keys(&response.headers),
+                    ..Default::default()
+                };
+                if !profile_read_lock.disk.insert(&key, vary, &http_context.request_context.headers, cell, meta).await {
+                    log::debug!("disk cache does not keep key:{}", key);
+                }
+            }
+            return Ok(());
+        }
+        unreachable!()
+    }
+}
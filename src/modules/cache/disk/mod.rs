@@ -0,0 +1,26 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public
+License, v. 2.0. If a copy of the MPL was not distributed with this
+file, You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+pub(crate) mod disk_get;
+pub(crate) mod disk_set;
+
+use std::{sync::Arc, time::Duration};
+
+use super::{http_cache_disk::HttpCacheDisk, http_cache_policy::CachePolicy};
+
+#[derive(Debug)]
+pub(crate) struct DiskCacheProfile {
+    pub(crate) disk: Arc<HttpCacheDisk>,
+    pub(crate) expire: Duration,
+    pub(crate) hit: i32,
+    pub(crate) back: bool,
+    pub(crate) policy: CachePolicy,
+}
+impl DiskCacheProfile {
+    pub(crate) fn new(disk: Arc<HttpCacheDisk>, expire: Duration, hit: i32, back: bool, policy: CachePolicy) -> Self {
+        Self { disk, expire, hit, back, policy }
+    }
+}
@@ -0,0 +1,90 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public
+License, v. 2.0. If a copy of the MPL was not distributed with this
+file, You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+use std::hash::{BuildHasher, Hash, RandomState};
+
+const DEPTH: usize = 4;
+
+/*
+The count-min sketch of TinyLFU: how often a key was asked lately, in a few bytes per key.
+Every counter is halved after 10 times the width of additions, so the old popularity fades.
+ */
+#[derive(Debug)]
+pub(crate) struct FrequencySketch {
+    table: Vec<u8>,
+    width: usize,
+    additions: usize,
+    hasher: RandomState,
+}
+impl FrequencySketch {
+    pub(crate) fn new(capacity: usize) -> Self {
+        let width = capacity.max(64).next_power_of_two();
+        Self { table: vec![0; width * DEPTH], width, additions: 0, hasher: RandomState::new() }
+    }
+    fn indexes<K: Hash + ?Sized>(&self, key: &K) -> [usize; DEPTH] {
+        let hash = self.hasher.hash_one(key);
+        let mut ret = [0; DEPTH];
+        for (i, index) in ret.iter_mut().enumerate() {
+            let h = hash.rotate_left(16 * i as u32).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+            *index = i * self.width + (h >> 32) as usize % self.width;
+        }
+        ret
+    }
+    pub(crate) fn increment<K: Hash + ?Sized>(&mut self, key: &K) {
+        for i in self.indexes(key) {
+            self.table[i] = self.table[i].saturating_add(1);
+        }
+        self.additions += 1;
+        if self.additions >= self.width * 10 {
+            for c in self.table.iter_mut() {
+                *c >>= 1;
+            }
+            self.additions /= 2;
+        }
+    }
+    pub(crate) fn frequency<K: Hash + ?Sized>(&self, key: &K) -> u8 {
+        self.indexes(key).iter().map(|i| self.table[*i]).min().unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts() {
+        let mut sketch = FrequencySketch::new(64);
+        assert_eq!(sketch.frequency("a"), 0);
+        for _ in 0..3 {
+            sketch.increment("a");
+        }
+        sketch.increment("b");
+        assert!(sketch.frequency("a") >= 3);
+        assert!(sketch.frequency("b") >= 1);
+        assert!(sketch.frequency("a") > sketch.frequency("c"));
+    }
+    #[test]
+    fn saturates() {
+        let mut sketch = FrequencySketch::new(1024);
+        for _ in 0..300 {
+            sketch.increment("a");
+        }
+        assert_eq!(sketch.frequency("a"), 255);
+    }
+    #[test]
+    fn halves() {
+        let mut sketch = FrequencySketch::new(64);
+        /* 10 times the width of additions */
+        for _ in 0..64 * 10 - 1 {
+            sketch.increment("a");
+        }
+        assert_eq!(sketch.frequency("a"), 255);
+        sketch.increment("a");
+        assert_eq!(sketch.frequency("a"), 127);
+        sketch.increment("a");
+        assert_eq!(sketch.frequency("a"), 128);
+    }
+}
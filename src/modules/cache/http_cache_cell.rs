@@ -4,71 +4,390 @@ License, v. 2.0. If a copy of the MPL was not distributed with this
 file, You can obtain one at https://mozilla.org/MPL/2.0/.
 */
 
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Eviction {
+    Lru,        //the least recently used entry goes first
+    TinyLfu,    //lru, but a new entry only gets in when it is asked more often than the ones it pushes out
+}
+#[derive(Debug, Clone)]
+pub(crate) struct HttpCacheLimits {
+    pub(crate) max_entries: usize,          //0 means no limit, for the three
+    pub(crate) max_bytes: usize,
+    pub(crate) max_object_size: usize,
+    pub(crate) eviction: Eviction,
+}
+impl Default for HttpCacheLimits {
+    fn default() -> Self {
+        Self { max_entries: 0, max_bytes: 0, max_object_size: 0, eviction: Eviction::Lru }
+    }
+}
+/*
+The memory cache of the service, shared by every route.
+The keys are spread over shards by the key without the Vary part, so the variants of a key and its Vary headers sit in the same shard.
+Each shard has its part of the limits and evicts on its own.
+ */
+#[derive(Debug)]
 pub(crate) struct HttpCacheShared {
-    pub(crate) cache: std::sync::Mutex<HashMap<HttpCacheKey, HttpCacheCell>>,
+    shards: Vec<std::sync::Mutex<HttpCacheShard>>,
+    hasher: RandomState,
+    pub(crate) limits: HttpCacheLimits,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    rejected: AtomicU64,
+    coalesced: AtomicU64,
+    flights: std::sync::Mutex<HashMap<String, (u64, tokio::sync::watch::Receiver<()>)>>,   //the keys asked upstream right now
+    flight_id: AtomicU64,
+}
+/* the request that went upstream for a key. the others of the key wait until it is dropped, after memory_cache_set */
+#[derive(Debug)]
+pub(crate) struct CacheFlight {
+    store: Arc<HttpCacheShared>,
+    key: String,
+    id: u64,
+    _done: tokio::sync::watch::Sender<()>,
+}
+impl Drop for CacheFlight {
+    fn drop(&mut self) {
+        let mut flights = self.store.flights.lock().unwrap();
+        if flights.get(&self.key).is_some_and(|(id, _)| *id == self.id) {
+            flights.remove(&self.key);
+        }
+    }
+}
+pub(crate) enum FlightJoin {
+    Leader(CacheFlight),
+    Wait(tokio::sync::watch::Receiver<()>),     //changed() returns when the leader is done
 }
 #[derive(Debug, Default)]
-pub(crate) struct HttpCacheNoShared {
-    pub(crate) cache: HashMap<HttpCacheKey, HttpCacheCell>,
+struct HttpCacheShard {
+    entries: HashMap<String, HttpCacheEntry>,
+    order: BTreeMap<u64, String>,                           //the least recently used first
+    vary: HashMap<String, (Vec<HeaderName>, usize)>,        //the Vary headers of a key, with the number of its variants
+    sketch: Option<FrequencySketch>,
+    tick: u64,
+    bytes: usize,
+}
+#[derive(Debug)]
+pub(crate) struct HttpCacheEntry {
+    pub(crate) cell: HttpCacheCell,
+    pub(crate) save_point: Instant,
+    pub(crate) expire: Option<Duration>,
+    pub(crate) hit: Option<i32>,
+    url: String,
+    tags: Vec<String>,      //Surrogate-Key and Cache-Tag of the response, for the purge
+    stale_while_revalidate: Duration,
+    stale_if_error: Duration,
+    revalidating: bool,     //a background refresh is running, the others keep getting the stale copy
+    primary_len: usize,     //the key without the Vary part is key[..primary_len]
+    size: usize,
+    tick: u64,
 }
+/* how and for what an entry is kept, besides the response */
 #[derive(Debug, Default)]
+pub(crate) struct HttpCacheMeta {
+    pub(crate) expire: Option<Duration>,
+    pub(crate) hit: Option<i32>,
+    pub(crate) url: String,
+    pub(crate) tags: Vec<String>,
+    pub(crate) stale_while_revalidate: Duration,    //how long after the expire the entry is still given, while it is refreshed
+    pub(crate) stale_if_error: Duration,            //how long after the expire the entry is still given, when the upstream fails
+}
+/* what the get found for the request */
+#[derive(Debug)]
+pub(crate) enum CacheLookup {
+    Fresh(HttpCacheCell),
+    Stale { cell: HttpCacheCell, revalidate: bool },    //revalidate: this request is the one that starts the refresh
+    IfError(HttpCacheCell),
+    Miss,
+}
+/* what the cache modules tell the dispatch and the server about an expired entry */
+#[derive(Debug, Clone, Default)]
+pub(crate) enum CacheStale {
+    #[default]
+    None,
+    IfError(HttpCacheCell),                             //given to the client when the upstream fails
+    Served(Option<(Arc<HttpCacheShared>, String)>),     //the client got a stale copy, the key to refresh after the response
+    Revalidate,                                         //the refresh in the background, the cache is not looked up
+}
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct HttpCacheOccupancy {
+    pub(crate) entries: usize,
+    pub(crate) bytes: usize,
+    pub(crate) hits: u64,
+    pub(crate) misses: u64,
+    pub(crate) evictions: u64,
+    pub(crate) rejected: u64,
+    pub(crate) coalesced: u64,
+}
+#[derive(Debug, Default, Clone)]
 pub(crate) struct HttpCacheCell {
     pub(crate) header_cache: HeaderMap,
     pub(crate) status_cache: StatusCode,
     pub(crate) version_cache: Version,
     pub(crate) body_cache: Bytes,
 }
-#[derive(std::cmp::Eq, Debug, Clone)]
-pub(crate) struct HttpCacheKey {
-    pub(crate) uri: Uri,
-    pub(crate) save_point: std::cell::RefCell<Option<std::time::Instant>>,
-    pub(crate) expire: std::cell::RefCell<Option<Duration>>,
-    pub(crate) hit: std::cell::RefCell<Option<i32>>,
+impl HttpCacheEntry {
+    pub(crate) fn is_expire(&self) -> bool {
+        self.expire.is_some_and(|expire| self.save_point.elapsed() > expire)
+    }
+    /* expired for longer than it may be given stale */
+    fn is_dead(&self) -> bool {
+        self.expire.is_some_and(|expire| self.save_point.elapsed() > expire + self.stale_while_revalidate.max(self.stale_if_error))
+    }
+    fn stale_for(&self) -> Duration {
+        self.expire.map(|expire| self.save_point.elapsed().saturating_sub(expire)).unwrap_or_default()
+    }
 }
-impl HttpCacheKey {
-    pub fn is_expire(key: &HttpCacheKey) -> bool {
-        if let Some(save_point) = *key.save_point.borrow() {
-            if let Some(expire) = *key.expire.borrow() {
-                if save_point.elapsed().as_millis() > expire.as_millis() {
-                    true
-                } else {
-                    false
+impl HttpCacheShard {
+    fn remove(&mut self, key: &str) -> Option<HttpCacheEntry> {
+        let entry = self.entries.remove(key)?;
+        self.order.remove(&entry.tick);
+        self.bytes -= entry.size;
+        if entry.primary_len < key.len() {
+            let primary = &key[..entry.primary_len];
+            if let Some((_, variants)) = self.vary.get_mut(primary) {
+                *variants -= 1;
+                if *variants == 0 {
+                    self.vary.remove(primary);
                 }
-            } else {
-                false
             }
-        } else {
-            false
         }
+        Some(entry)
     }
-}
-impl std::cmp::PartialEq for HttpCacheKey {
-    fn eq(&self, other: &Self) -> bool {
-        self.uri.eq(&other.uri)
+    fn touch(&mut self, key: &str) {
+        self.tick += 1;
+        let tick = self.tick;
+        if let Some(entry) = self.entries.get_mut(key) {
+            if let Some(k) = self.order.remove(&entry.tick) {
+                self.order.insert(tick, k);
+            }
+            entry.tick = tick;
+        }
     }
 }
-impl std::hash::Hash for HttpCacheKey {
-    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        self.uri.hash(state);
+impl Default for HttpCacheShared {
+    fn default() -> Self {
+        Self::new(HttpCacheLimits::default())
     }
 }
-impl HttpCacheKey {
-    pub(crate) fn for_key(uri: Uri) -> Self {
+impl HttpCacheShared {
+    pub(crate) fn new(limits: HttpCacheLimits) -> Self {
+        /* 64 entries a shard at least, a small cache in too many pieces evicts what it should keep */
+        let shard_count = if limits.max_entries > 0 {
+            (limits.max_entries / 64).clamp(1, crate::util::r#const::MEMORY_CACHE_SHARDS)
+        } else {
+            crate::util::r#const::MEMORY_CACHE_SHARDS
+        };
+        let shards = (0..shard_count).map(|_| {
+            let sketch = if limits.eviction == Eviction::TinyLfu {
+                Some(FrequencySketch::new(if limits.max_entries > 0 { limits.max_entries / shard_count } else { 1024 }))
+            } else {
+                None
+            };
+            std::sync::Mutex::new(HttpCacheShard { sketch, ..Default::default() })
+        }).collect();
         Self {
-            uri,
-            save_point: std::cell::RefCell::new(None),
-            expire: std::cell::RefCell::new(None),
-            hit: std::cell::RefCell::new(None),
+            shards,
+            hasher: RandomState::new(),
+            limits,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+            coalesced: AtomicU64::new(0),
+            flights: std::sync::Mutex::new(HashMap::new()),
+            flight_id: AtomicU64::new(0),
         }
     }
-}
-
-impl HttpCacheShared {
-    pub(crate) fn lock(
-        &self,
-    ) -> std::sync::MutexGuard<'_, HashMap<HttpCacheKey, HttpCacheCell>> {
-        self.cache.lock().unwrap()
+    fn shard(&self, primary: &str) -> std::sync::MutexGuard<'_, HttpCacheShard> {
+        let i = self.hasher.hash_one(primary) as usize % self.shards.len();
+        self.shards[i].lock().unwrap()
+    }
+    fn per_shard(&self, limit: usize) -> usize {
+        if limit == 0 { 0 } else { limit.div_ceil(self.shards.len()) }
+    }
+    /*
+    The response stored for the request under this key. on_hit tells if the entry goes away after this use, it is not called for a stale entry.
+    An expired entry is given while in its stale windows, only the first request of the window gets revalidate.
+     */
+    pub(crate) fn get(&self, primary: &str, headers: &HeaderMap, on_hit: impl FnOnce(&mut HttpCacheEntry) -> bool) -> CacheLookup {
+        let mut shard = self.shard(primary);
+        if let Some(sketch) = &mut shard.sketch {
+            sketch.increment(primary);
+        }
+        let key = match shard.vary.get(primary) {
+            Some((vary, _)) => { variant_key(primary, vary, headers) }
+            None => { primary.to_string() }
+        };
+        let entry = match shard.entries.get_mut(&key) {
+            Some(entry) => { entry }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                return CacheLookup::Miss;
+            }
+        };
+        if entry.is_dead() {
+            shard.remove(&key);
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return CacheLookup::Miss;
+        }
+        if entry.is_expire() {
+            let stale_for = entry.stale_for();
+            if stale_for <= entry.stale_while_revalidate {
+                let revalidate = !entry.revalidating;
+                entry.revalidating = true;
+                let cell = entry.cell.clone();
+                shard.touch(&key);
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return CacheLookup::Stale { cell, revalidate };
+            }
+            let cell = entry.cell.clone();
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return CacheLookup::IfError(cell);
+        }
+        let after_remove = on_hit(entry);
+        let cell = entry.cell.clone();
+        if after_remove {
+            shard.remove(&key);
+        } else {
+            shard.touch(&key);
+        }
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        CacheLookup::Fresh(cell)
+    }
+    /* the first miss of a key goes upstream, the next ones wait for it */
+    pub(crate) fn join_flight(self: &Arc<Self>, key: &str) -> FlightJoin {
+        let mut flights = self.flights.lock().unwrap();
+        if let Some((_, done)) = flights.get(key) {
+            self.coalesced.fetch_add(1, Ordering::Relaxed);
+            return FlightJoin::Wait(done.clone());
+        }
+        let id = self.flight_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = tokio::sync::watch::channel(());
+        flights.insert(key.to_string(), (id, receiver));
+        FlightJoin::Leader(CacheFlight { store: self.clone(), key: key.to_string(), id, _done: sender })
+    }
+    /* the refresh is over, stored or not. if it was not stored, the next request tries again */
+    pub(crate) fn revalidated(&self, primary: &str, headers: &HeaderMap) {
+        let mut shard = self.shard(primary);
+        let key = match shard.vary.get(primary) {
+            Some((vary, _)) => { variant_key(primary, vary, headers) }
+            None => { primary.to_string() }
+        };
+        if let Some(entry) = shard.entries.get_mut(&key) {
+            entry.revalidating = false;
+        }
+    }
+    /* false when the response is bigger than the limits, or when tinylfu does not let it in */
+    pub(crate) fn insert(&self, primary: &str, vary: Vec<HeaderName>, headers: &HeaderMap, cell: HttpCacheCell, meta: HttpCacheMeta) -> bool {
+        let object_size = cell.body_cache.len() + cell.header_cache.iter().map(|(k, v)| k.as_str().len() + v.len()).sum::<usize>();
+        let key = variant_key(primary, &vary, headers);
+        let size = object_size + key.len() + meta.url.len() + meta.tags.iter().map(|t| t.len()).sum::<usize>() + crate::util::r#const::MEMORY_CACHE_ENTRY_OVERHEAD;
+        let max_entries = self.per_shard(self.limits.max_entries);
+        let max_bytes = self.per_shard(self.limits.max_bytes);
+        if (self.limits.max_object_size > 0 && object_size > self.limits.max_object_size) || (max_bytes > 0 && size > max_bytes) {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        let mut shard = self.shard(primary);
+        /* the old entry of the key makes room for the new one, it is not a victim and only goes once the new one gets in */
+        let old_size = shard.entries.get(&key).map(|entry| entry.size);
+        /* the least recently used entries that must go to make room */
+        let mut victims = Vec::new();
+        let (mut entries, mut bytes) = (shard.entries.len() - old_size.is_some() as usize, shard.bytes - old_size.unwrap_or(0));
+        for victim in shard.order.values() {
+            if (max_entries == 0 || entries < max_entries) && (max_bytes == 0 || bytes + size <= max_bytes) {
+                break;
+            }
+            if victim == &key {
+                continue;
+            }
+            let victim_entry = &shard.entries[victim];
+            entries -= 1;
+            bytes -= victim_entry.size;
+            victims.push((victim.clone(), victim_entry.primary_len));
+        }
+        /* a key already in is refreshed, tinylfu only decides for the new ones */
+        if let (Some(sketch), None) = (&shard.sketch, old_size) {
+            let frequency = sketch.frequency(primary);
+            if victims.iter().any(|(victim, primary_len)| sketch.frequency(&victim[..*primary_len]) >= frequency) {
+                self.rejected.fetch_add(1, Ordering::Relaxed);
+                return false;
+            }
+        }
+        shard.remove(&key);
+        self.evictions.fetch_add(victims.len() as u64, Ordering::Relaxed);
+        for (victim, _) in victims {
+            shard.remove(&victim);
+        }
+        if vary.is_empty() {
+            shard.vary.remove(primary);
+        } else {
+            let record = shard.vary.entry(primary.to_string()).or_insert_with(|| (Vec::new(), 0));
+            record.0 = vary;
+            record.1 += 1;
+        }
+        shard.tick += 1;
+        let tick = shard.tick;
+        shard.order.insert(tick, key.clone());
+        shard.bytes += size;
+        shard.entries.insert(key, HttpCacheEntry {
+            cell,
+            save_point: Instant::now(),
+            expire: meta.expire,
+            hit: meta.hit,
+            url: meta.url,
+            tags: meta.tags,
+            stale_while_revalidate: meta.stale_while_revalidate,
+            stale_if_error: meta.stale_if_error,
+            revalidating: false,
+            primary_len: primary.len(),
+            size,
+            tick,
+        });
+        true
+    }
+    pub(crate) fn clear_expired(&self) {
+        for shard in &self.shards {
+            let mut shard = shard.lock().unwrap();
+            let expired = shard.entries.iter().filter(|(_, entry)| entry.is_dead()).map(|(key, _)| key.clone()).collect::<Vec<_>>();
+            for key in expired {
+                shard.remove(&key);
+            }
+        }
+    }
+    /* remove every entry (with its variants) the purge matches, gives how many went */
+    pub(crate) fn purge(&self, purge: &CachePurge) -> usize {
+        let mut purged = 0;
+        for shard in &self.shards {
+            let mut shard = shard.lock().unwrap();
+            let matched = shard.entries.iter()
+                .filter(|(key, entry)| purge.matches_memory(&key[..entry.primary_len], &entry.url, &entry.tags))
+                .map(|(key, _)| key.clone())
+                .collect::<Vec<_>>();
+            for key in matched {
+                shard.remove(&key);
+                purged += 1;
+            }
+        }
+        purged
+    }
+    pub(crate) fn occupancy(&self) -> HttpCacheOccupancy {
+        let mut ret = HttpCacheOccupancy {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+            coalesced: self.coalesced.load(Ordering::Relaxed),
+            ..Default::default()
+        };
+        for shard in &self.shards {
+            let shard = shard.lock().unwrap();
+            ret.entries += shard.entries.len();
+            ret.bytes += shard.bytes;
+        }
+        ret
     }
     pub(crate) fn memory_cache_value(
         status_code: StatusCode,
@@ -119,13 +438,18 @@ impl HttpCacheCell {
         to_str
     }
 }
-use std::collections::HashMap;
-use std::time::Duration;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{BuildHasher, RandomState};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use bytes::Bytes;
 use futures::AsyncReadExt;
-use http::{HeaderMap, HeaderName, StatusCode, Uri, Version};
+use http::{HeaderMap, HeaderName, StatusCode, Version};
 use serde::ser::{Serialize, SerializeMap, SerializeStruct, Serializer};
+
+use super::{frequency_sketch::FrequencySketch, http_cache_policy::variant_key, http_cache_purge::CachePurge};
 impl serde::ser::Serialize for HttpCacheCell {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -241,7 +565,7 @@ impl<'de> serde::de::Deserialize<'de> for HttpCacheCell {
                 let headers = headers.ok_or_else(|| de::Error::missing_field("Header_cache"))?;
                 let body = body.ok_or_else(|| de::Error::missing_field("Body_cache"))?;
 
-                let status_code = StatusCode::from_bytes(status_code.as_bytes()).unwrap();
+                let status_code = StatusCode::from_bytes(status_code.as_bytes()).map_err(|_| de::Error::custom(format!("bad status {}", status_code)))?;
                 let version = match version.as_str() {
                     "HTTP/0.9" => Version::HTTP_09,
                     "HTTP/1.0" => Version::HTTP_10,
@@ -250,14 +574,17 @@ impl<'de> serde::de::Deserialize<'de> for HttpCacheCell {
                     "HTTP/3.0" => Version::HTTP_3,
                     _ => Version::HTTP_09,
                 };
-                let headers_map: HashMap<String, String> = serde_json::from_str(&headers).unwrap();
+                let headers_map: HashMap<String, String> = serde_json::from_str(&headers).map_err(de::Error::custom)?;
                 let mut headers = HeaderMap::new();
                 for (k, v) in headers_map {
-                    headers.insert(HeaderName::from_bytes(k.as_bytes()).unwrap(), v.parse().unwrap());
+                    let name = HeaderName::from_bytes(k.as_bytes()).map_err(|_| de::Error::custom(format!("bad header name {}", k)))?;
+                    let value = v.parse().map_err(|_| de::Error::custom(format!("bad header value of {}", k)))?;
+                    headers.insert(name, value);
                 }
-                let body = Bytes::from_iter(body.split(",").into_iter().map(|item|{
-                    item.parse::<u8>().unwrap()
-                }));
+                /* an empty body is written as "" */
+                let body = body.split(",").filter(|item| !item.is_empty()).map(|item| {
+                    item.parse::<u8>().map_err(|_| de::Error::custom(format!("bad body byte {}", item)))
+                }).collect::<Result<Bytes, _>>()?;
                 Ok(HttpCacheShared::memory_cache_value(status_code, version, headers, body))
             }
         }
@@ -265,4 +592,169 @@ impl<'de> serde::de::Deserialize<'de> for HttpCacheCell {
         const FIELDS: &'static [&'static str] = &["secs", "nanos"];
         deserializer.deserialize_struct("Duration", FIELDS, HttpCacheCellVisitor)
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(max_entries: usize, max_bytes: usize, eviction: Eviction) -> HttpCacheLimits {
+        HttpCacheLimits { max_entries, max_bytes, max_object_size: 0, eviction }
+    }
+    fn insert(store: &HttpCacheShared, key: &str, body: &'static str) -> bool {
+        let cell = HttpCacheShared::memory_cache_value(StatusCode::OK, Version::HTTP_11, HeaderMap::new(), Bytes::from_static(body.as_bytes()));
+        store.insert(key, Vec::new(), &HeaderMap::new(), cell, HttpCacheMeta::default())
+    }
+    fn lookup(store: &HttpCacheShared, key: &str, headers: &HeaderMap) -> Option<Bytes> {
+        match store.get(key, headers, |_| false) {
+            CacheLookup::Fresh(cell) => { Some(cell.body_cache) }
+            _ => { None }
+        }
+    }
+    fn get(store: &HttpCacheShared, key: &str) -> Option<Bytes> {
+        lookup(store, key, &HeaderMap::new())
+    }
+
+    #[test]
+    fn lru_eviction_order() {
+        let store = HttpCacheShared::new(limits(2, 0, Eviction::Lru));
+        assert!(insert(&store, "a", "1"));
+        assert!(insert(&store, "b", "2"));
+        assert!(get(&store, "a").is_some());
+        /* b is the least recently used now */
+        assert!(insert(&store, "c", "3"));
+        assert_eq!(get(&store, "a").as_deref(), Some(&b"1"[..]));
+        assert_eq!(get(&store, "b"), None);
+        assert_eq!(get(&store, "c").as_deref(), Some(&b"3"[..]));
+        let occupancy = store.occupancy();
+        assert_eq!((occupancy.entries, occupancy.evictions), (2, 1));
+    }
+    #[test]
+    fn max_bytes() {
+        let store = HttpCacheShared::new(limits(64, 0, Eviction::Lru));
+        insert(&store, "k1", "0123456789");
+        let size = store.occupancy().bytes;
+        /* one shard with 64 entries, so max_bytes is all for it */
+        let store = HttpCacheShared::new(limits(64, size * 3, Eviction::Lru));
+        for key in ["k1", "k2", "k3"] {
+            assert!(insert(&store, key, "0123456789"));
+        }
+        assert_eq!(store.occupancy().bytes, size * 3);
+        assert!(insert(&store, "k4", "0123456789"));
+        assert_eq!(get(&store, "k1"), None);
+        let occupancy = store.occupancy();
+        assert_eq!((occupancy.entries, occupancy.bytes, occupancy.evictions), (3, size * 3, 1));
+        /* a bigger one takes the room of two */
+        assert!(insert(&store, "k5", "0123456789012345678901234567890123456789"));
+        assert_eq!(get(&store, "k2"), None);
+        assert_eq!(get(&store, "k3"), None);
+        assert!(store.occupancy().bytes <= size * 3);
+    }
+    #[test]
+    fn max_entries_per_shard() {
+        let store = HttpCacheShared::new(limits(256, 0, Eviction::Lru));
+        assert_eq!(store.shards.len(), 4);
+        for i in 0..1000 {
+            insert(&store, &format!("key{}", i), "x");
+        }
+        for shard in &store.shards {
+            assert!(shard.lock().unwrap().entries.len() <= 64);
+        }
+        assert!(store.occupancy().entries <= 256);
+    }
+    #[test]
+    fn replace_keeps_the_accounting() {
+        let store = HttpCacheShared::new(limits(2, 0, Eviction::Lru));
+        insert(&store, "a", "1");
+        let size = store.occupancy().bytes;
+        insert(&store, "a", "22");
+        insert(&store, "a", "333");
+        let occupancy = store.occupancy();
+        assert_eq!((occupancy.entries, occupancy.bytes, occupancy.evictions), (1, size + 2, 0));
+        assert_eq!(get(&store, "a").as_deref(), Some(&b"333"[..]));
+        /* replacing a key of a full shard evicts nothing */
+        insert(&store, "b", "1");
+        insert(&store, "a", "4444");
+        assert_eq!(store.occupancy().evictions, 0);
+        assert!(get(&store, "b").is_some());
+    }
+    #[test]
+    fn rejected() {
+        let mut limits = limits(64, 0, Eviction::Lru);
+        limits.max_object_size = 5;
+        let store = HttpCacheShared::new(limits);
+        assert!(insert(&store, "small", "12345"));
+        assert!(!insert(&store, "big", "123456"));
+        assert_eq!(get(&store, "big"), None);
+        assert_eq!(store.occupancy().rejected, 1);
+        /* bigger than max_bytes */
+        let store = HttpCacheShared::new(self::limits(64, 100, Eviction::Lru));
+        assert!(!insert(&store, "big", "0123456789012345678901234567890123456789012345678901234567890123456789012345678901234567890123456789"));
+        assert_eq!(store.occupancy().rejected, 1);
+    }
+    #[test]
+    fn tinylfu_admission() {
+        let store = HttpCacheShared::new(limits(2, 0, Eviction::TinyLfu));
+        assert!(insert(&store, "a", "1"));
+        assert!(insert(&store, "b", "2"));
+        for _ in 0..5 {
+            get(&store, "a");
+            get(&store, "b");
+        }
+        /* asked less often than the one it would push out */
+        assert!(!insert(&store, "c", "3"));
+        assert_eq!(store.occupancy().rejected, 1);
+        assert!(get(&store, "a").is_some());
+        assert!(get(&store, "b").is_some());
+        /* a key already in is refreshed, it does not lose its copy */
+        assert!(insert(&store, "a", "11"));
+        assert_eq!(get(&store, "a").as_deref(), Some(&b"11"[..]));
+        assert_eq!(store.occupancy().entries, 2);
+        /* asked more often, it gets in */
+        for _ in 0..20 {
+            get(&store, "c");
+        }
+        assert!(insert(&store, "c", "3"));
+        assert!(get(&store, "c").is_some());
+        assert_eq!(store.occupancy().evictions, 1);
+    }
+    #[test]
+    fn tinylfu_replace_needing_room() {
+        let store = HttpCacheShared::new(limits(64, 0, Eviction::TinyLfu));
+        insert(&store, "a", "1");
+        let size = store.occupancy().bytes;
+        let store = HttpCacheShared::new(limits(64, size * 2 + 5, Eviction::TinyLfu));
+        assert!(insert(&store, "a", "1"));
+        assert!(insert(&store, "b", "2"));
+        for _ in 0..5 {
+            get(&store, "b");
+        }
+        /* the new copy of a needs the room of b, more asked than a: a is refreshed all the same, never lost */
+        assert!(insert(&store, "a", "1234567890"));
+        assert_eq!(get(&store, "a").as_deref(), Some(&b"1234567890"[..]));
+        assert_eq!(get(&store, "b"), None);
+        assert_eq!(store.occupancy().rejected, 0);
+    }
+    #[test]
+    fn vary_variants() {
+        let store = HttpCacheShared::new(limits(64, 0, Eviction::Lru));
+        let vary = vec![http::header::ACCEPT_ENCODING];
+        let mut keys = Vec::new();
+        for (encoding, body) in [("gzip", "g"), ("br", "b")] {
+            let mut headers = HeaderMap::new();
+            headers.insert(http::header::ACCEPT_ENCODING, encoding.parse().unwrap());
+            let cell = HttpCacheShared::memory_cache_value(StatusCode::OK, Version::HTTP_11, HeaderMap::new(), Bytes::from_static(body.as_bytes()));
+            assert!(store.insert("p", vary.clone(), &headers, cell, HttpCacheMeta::default()));
+            assert_eq!(lookup(&store, "p", &headers).as_deref(), Some(body.as_bytes()));
+            keys.push(variant_key("p", &vary, &headers));
+        }
+        let mut shard = store.shard("p");
+        assert_eq!(shard.vary.get("p").map(|(_, variants)| *variants), Some(2));
+        let bytes = shard.bytes;
+        let first = shard.remove(&keys[0]).unwrap();
+        assert_eq!(shard.bytes, bytes - first.size);
+        assert_eq!(shard.vary.get("p").map(|(_, variants)| *variants), Some(1));
+        shard.remove(&keys[1]);
+        assert!(shard.vary.get("p").is_none());
+        assert_eq!((shard.entries.len(), shard.bytes, shard.order.len()), (0, 0, 0));
+    }
+}
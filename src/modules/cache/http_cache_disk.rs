@@ -0,0 +1,539 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public
+License, v. 2.0. If a copy of the MPL was not distributed with this
+file, You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+use std::{collections::{BTreeMap, HashMap, HashSet}, path::{Path, PathBuf}, sync::atomic::{AtomicU64, Ordering}, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use bytes::Bytes;
+use http::{HeaderMap, HeaderName, HeaderValue, StatusCode, Version};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+
+use crate::error::RResult;
+
+use super::{http_cache_cell::{HttpCacheCell, HttpCacheMeta, HttpCacheOccupancy}, http_cache_policy::variant_key, http_cache_purge::CachePurge};
+
+#[derive(Debug, Clone)]
+pub(crate) struct DiskCacheLimits {
+    pub(crate) max_bytes: u64,              //0 means no limit, for the two
+    pub(crate) max_object_size: u64,
+}
+/*
+The disk cache of the service, shared by every route like the memory one, with the same "route key" keys.
+An entry is two files in root/xx/: {sha256 of the key}.meta, the json of the key, the status, the headers and how long it is kept,
+and {sha256}.body. Both are written to a .tmp file, synced then renamed, the body first, so a meta always has its whole body.
+Only one insert writes the files of a name at a time, the others give up.
+The index is in memory, rebuilt from the meta files at start. The least recently used entries go when max_bytes is reached.
+Only those names are looked at in root, anything else there is left alone.
+ */
+#[derive(Debug)]
+pub(crate) struct HttpCacheDisk {
+    root: PathBuf,
+    pub(crate) limits: DiskCacheLimits,
+    index: std::sync::Mutex<DiskIndex>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    rejected: AtomicU64,
+    tmp_id: AtomicU64,
+    writing: std::sync::Mutex<HashSet<String>>,     //the names being written
+}
+/* takes the name out of writing when the write is over, even when the insert is dropped */
+struct Writing<'a> {
+    writing: &'a std::sync::Mutex<HashSet<String>>,
+    name: String,
+}
+impl Drop for Writing<'_> {
+    fn drop(&mut self) {
+        self.writing.lock().unwrap().remove(&self.name);
+    }
+}
+#[derive(Debug)]
+enum CacheFile {
+    Meta,
+    Body,
+    Tmp,
+}
+#[derive(Debug, Default)]
+struct DiskIndex {
+    entries: HashMap<String, DiskEntry>,
+    order: BTreeMap<u64, String>,                           //the least recently used first
+    vary: HashMap<String, (Vec<HeaderName>, usize)>,        //the Vary headers of a key, with the number of its variants
+    tick: u64,
+    bytes: u64,
+}
+#[derive(Debug)]
+struct DiskEntry {
+    name: String,                   //the files are name.meta and name.body
+    expire_at: Option<SystemTime>,
+    hit: Option<i32>,
+    url: String,
+    tags: Vec<String>,
+    primary_len: usize,
+    size: u64,
+    tick: u64,
+}
+#[derive(Debug, Serialize, Deserialize)]
+struct DiskMeta {
+    key: String,
+    primary_len: usize,
+    vary: Vec<String>,
+    status: u16,
+    version: String,
+    headers: Vec<(String, DiskHeaderValue)>,
+    expire_at: Option<u64>,         //unix ms
+    saved_at: u64,
+    url: String,
+    tags: Vec<String>,
+    body_len: u64,
+}
+/* the bytes only when the value is not utf-8 */
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum DiskHeaderValue {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+impl DiskIndex {
+    fn remove(&mut self, key: &str) -> Option<DiskEntry> {
+        let entry = self.entries.remove(key)?;
+        self.order.remove(&entry.tick);
+        self.bytes -= entry.size;
+        if entry.primary_len < key.len() {
+            let primary = &key[..entry.primary_len];
+            if let Some((_, variants)) = self.vary.get_mut(primary) {
+                *variants -= 1;
+                if *variants == 0 {
+                    self.vary.remove(primary);
+                }
+            }
+        }
+        Some(entry)
+    }
+    fn touch(&mut self, key: &str) {
+        self.tick += 1;
+        let tick = self.tick;
+        if let Some(entry) = self.entries.get_mut(key) {
+            if let Some(k) = self.order.remove(&entry.tick) {
+                self.order.insert(tick, k);
+            }
+            entry.tick = tick;
+        }
+    }
+    fn add(&mut self, key: String, vary: Vec<HeaderName>, mut entry: DiskEntry) {
+        let primary = &key[..entry.primary_len];
+        if vary.is_empty() {
+            self.vary.remove(primary);
+        } else {
+            let record = self.vary.entry(primary.to_string()).or_insert_with(|| (Vec::new(), 0));
+            record.0 = vary;
+            record.1 += 1;
+        }
+        self.tick += 1;
+        entry.tick = self.tick;
+        self.order.insert(entry.tick, key.clone());
+        self.bytes += entry.size;
+        self.entries.insert(key, entry);
+    }
+    /* the least recently used entries that must go for size more bytes */
+    fn make_room(&mut self, size: u64, max_bytes: u64) -> Vec<DiskEntry> {
+        let mut victims = Vec::new();
+        while max_bytes > 0 && self.bytes + size > max_bytes {
+            let key = match self.order.values().next() {
+                Some(key) => { key.clone() }
+                None => { break; }
+            };
+            victims.extend(self.remove(&key));
+        }
+        victims
+    }
+}
+impl DiskEntry {
+    fn is_expire(&self) -> bool {
+        self.expire_at.is_some_and(|at| at <= SystemTime::now())
+    }
+}
+impl HttpCacheDisk {
+    /* the entries already in root are taken back, what is left of an interrupted write is removed */
+    pub(crate) fn open(root: PathBuf, limits: DiskCacheLimits) -> RResult<Self> {
+        std::fs::create_dir_all(&root).map_err(|e| format!("disk cache {:?}: {}", root, e))?;
+        let disk = Self {
+            root,
+            limits,
+            index: std::sync::Mutex::new(DiskIndex::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+            tmp_id: AtomicU64::new(0),
+            writing: std::sync::Mutex::new(HashSet::new()),
+        };
+        disk.rebuild()?;
+        Ok(disk)
+    }
+    fn rebuild(&self) -> RResult<()> {
+        let mut found = Vec::new();
+        let mut garbage = Vec::new();
+        for dir in std::fs::read_dir(&self.root).map_err(|e| format!("disk cache {:?}: {}", self.root, e))?.flatten() {
+            let dir_name = dir.file_name();
+            let dir_name = match dir_name.to_str() {
+                Some(dir_name) if dir_name.len() == 2 && is_hex(dir_name) && dir.path().is_dir() => { dir_name.to_string() }
+                _ => { continue; }
+            };
+            let files = match std::fs::read_dir(dir.path()) {
+                Ok(files) => { files }
+                Err(_) => { continue; }
+            };
+            for file in files.flatten() {
+                let path = file.path();
+                let kind = file.file_name().to_str().and_then(|f| cache_file(&dir_name, f));
+                match kind {
+                    Some(CacheFile::Meta) => {
+                        let body = path.with_extension("body");
+                        let meta = std::fs::read(&path).ok().and_then(|m| serde_json::from_slice::<DiskMeta>(&m).ok().map(|meta| (meta, m.len() as u64)));
+                        match meta {
+                            Some((meta, meta_len)) if std::fs::metadata(&body).is_ok_and(|b| b.len() == meta.body_len) => {
+                                found.push((meta, meta_len, path));
+                            }
+                            _ => {
+                                garbage.push(path);
+                                garbage.push(body);
+                            }
+                        }
+                    }
+                    Some(CacheFile::Body) => {
+                        if !path.with_extension("meta").exists() {
+                            garbage.push(path);
+                        }
+                    }
+                    /* left by an interrupted write */
+                    Some(CacheFile::Tmp) => { garbage.push(path); }
+                    None => { }
+                }
+            }
+        }
+        /* the oldest are the least recently used */
+        found.sort_by_key(|(meta, _, _)| meta.saved_at);
+        let mut index = self.index.lock().unwrap();
+        for (meta, meta_len, path) in found {
+            let name = match path.file_stem().and_then(|s| s.to_str()) {
+                Some(name) if name == file_name(&meta.key) && meta.key.is_char_boundary(meta.primary_len) => { name.to_string() }
+                _ => {
+                    garbage.push(path.with_extension("body"));
+                    garbage.push(path);
+                    continue;
+                }
+            };
+            let entry = DiskEntry {
+                name,
+                expire_at: meta.expire_at.map(|ms| UNIX_EPOCH + Duration::from_millis(ms)),
+                hit: None,
+                url: meta.url,
+                tags: meta.tags,
+                primary_len: meta.primary_len,
+                size: meta_len + meta.body_len,
+                tick: 0,
+            };
+            if entry.is_expire() {
+                garbage.push(path.with_extension("body"));
+                garbage.push(path);
+                continue;
+            }
+            let vary = meta.vary.iter().filter_map(|v| HeaderName::from_bytes(v.as_bytes()).ok()).collect();
+            index.add(meta.key, vary, entry);
+        }
+        /* max_bytes may have been lowered since */
+        let victims = index.make_room(0, self.limits.max_bytes);
+        log::info!("disk cache {:?} has {} entries, {} bytes", self.root, index.entries.len(), index.bytes);
+        drop(index);
+        for victim in victims {
+            garbage.push(self.path(&victim.name, "meta"));
+            garbage.push(self.path(&victim.name, "body"));
+        }
+        for path in garbage {
+            let _ = std::fs::remove_file(path);
+        }
+        Ok(())
+    }
+    fn path(&self, name: &str, extension: &str) -> PathBuf {
+        self.root.join(&name[..2]).join(format!("{}.{}", name, extension))
+    }
+    /*
+    The response stored for the request under this key, None when there is none or it expired.
+    refresh sets a new expire from now, max_hit removes the entry after that many hits, like the memory cache.
+     */
+    pub(crate) async fn get(&self, primary: &str, headers: &HeaderMap, refresh: Option<Duration>, max_hit: Option<i32>) -> Option<HttpCacheCell> {
+        let (key, name, expired, after_remove) = {
+            let mut index = self.index.lock().unwrap();
+            let key = match index.vary.get(primary) {
+                Some((vary, _)) => { variant_key(primary, vary, headers) }
+                None => { primary.to_string() }
+            };
+            let entry = match index.entries.get_mut(&key) {
+                Some(entry) => { entry }
+                None => {
+                    self.misses.fetch_add(1, Ordering::Relaxed);
+                    return None;
+                }
+            };
+            let expired = entry.is_expire();
+            let mut after_remove = false;
+            if !expired {
+                if let Some(refresh) = refresh {
+                    entry.expire_at = Some(SystemTime::now() + refresh);
+                }
+                if let Some(max_hit) = max_hit {
+                    let hit = entry.hit.unwrap_or(0);
+                    if hit >= max_hit {
+                        after_remove = true;
+                    } else {
+                        entry.hit = Some(hit + 1);
+                    }
+                }
+            }
+            let name = entry.name.clone();
+            if expired || after_remove {
+                index.remove(&key);
+            } else {
+                index.touch(&key);
+            }
+            (key, name, expired, after_remove)
+        };
+        if expired {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            self.remove_files(&name).await;
+            return None;
+        }
+        let cell = self.read(&key, &name).await;
+        if after_remove {
+            self.remove_files(&name).await;
+        }
+        match cell {
+            Some(cell) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(cell)
+            }
+            None => {
+                /* gone under it, by an eviction or by hand */
+                let mut index = self.index.lock().unwrap();
+                if index.entries.get(&key).is_some_and(|e| e.name == name) {
+                    index.remove(&key);
+                }
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+    async fn read(&self, key: &str, name: &str) -> Option<HttpCacheCell> {
+        let meta = tokio::fs::read(self.path(name, "meta")).await.ok()?;
+        let meta = serde_json::from_slice::<DiskMeta>(&meta).ok()?;
+        if meta.key != key {
+            return None;
+        }
+        let body = tokio::fs::read(self.path(name, "body")).await.ok()?;
+        if body.len() as u64 != meta.body_len {
+            return None;
+        }
+        let mut header_cache = HeaderMap::with_capacity(meta.headers.len());
+        for (k, v) in meta.headers {
+            let value = match v {
+                DiskHeaderValue::Text(text) => { HeaderValue::from_str(&text).ok()? }
+                DiskHeaderValue::Bytes(bytes) => { HeaderValue::from_bytes(&bytes).ok()? }
+            };
+            header_cache.append(HeaderName::from_bytes(k.as_bytes()).ok()?, value);
+        }
+        Some(HttpCacheCell {
+            header_cache,
+            status_cache: StatusCode::from_u16(meta.status).ok()?,
+            version_cache: parse_version(&meta.version),
+            body_cache: Bytes::from(body),
+        })
+    }
+    /* false when the response is bigger than the limits, or could not be written */
+    pub(crate) async fn insert(&self, primary: &str, vary: Vec<HeaderName>, headers: &HeaderMap, cell: HttpCacheCell, meta: HttpCacheMeta) -> bool {
+        let body_len = cell.body_cache.len() as u64;
+        let limits = &self.limits;
+        if (limits.max_object_size > 0 && body_len > limits.max_object_size) || (limits.max_bytes > 0 && body_len > limits.max_bytes) {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        let key = variant_key(primary, &vary, headers);
+        let name = file_name(&key);
+        let now = SystemTime::now();
+        let disk_meta = DiskMeta {
+            key: key.clone(),
+            primary_len: primary.len(),
+            vary: vary.iter().map(|v| v.as_str().to_string()).collect(),
+            status: cell.status_cache.as_u16(),
+            version: format!("{:?}", cell.version_cache),
+            headers: cell.header_cache.iter().map(|(k, v)| {
+                let value = match std::str::from_utf8(v.as_bytes()) {
+                    Ok(text) => { DiskHeaderValue::Text(text.to_string()) }
+                    Err(_) => { DiskHeaderValue::Bytes(v.as_bytes().to_vec()) }
+                };
+                (k.as_str().to_string(), value)
+            }).collect(),
+            expire_at: meta.expire.map(|e| (now + e).duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64),
+            saved_at: now.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64,
+            url: meta.url.clone(),
+            tags: meta.tags.clone(),
+            body_len,
+        };
+        let meta_json = match serde_json::to_vec(&disk_meta) {
+            Ok(json) => { json }
+            Err(_) => { return false; }
+        };
+        let _writing = {
+            let mut writing = self.writing.lock().unwrap();
+            if !writing.insert(name.clone()) {
+                /* the same response is being stored by another request */
+                return false;
+            }
+            Writing { writing: &self.writing, name: name.clone() }
+        };
+        if let Err(e) = self.write(&name, &meta_json, &cell.body_cache).await {
+            log::warn!("disk cache can not write key:{} {}", key, e);
+            return false;
+        }
+        let entry = DiskEntry {
+            name,
+            expire_at: meta.expire.map(|e| now + e),
+            hit: meta.hit,
+            url: meta.url,
+            tags: meta.tags,
+            primary_len: primary.len(),
+            size: meta_json.len() as u64 + body_len,
+            tick: 0,
+        };
+        let victims = {
+            let mut index = self.index.lock().unwrap();
+            /* the files of the old entry of the key were just replaced */
+            index.remove(&key);
+            let victims = index.make_room(entry.size, self.limits.max_bytes);
+            index.add(key, vary, entry);
+            victims
+        };
+        self.evictions.fetch_add(victims.len() as u64, Ordering::Relaxed);
+        for victim in victims {
+            self.remove_files(&victim.name).await;
+        }
+        true
+    }
+    async fn write(&self, name: &str, meta: &[u8], body: &[u8]) -> std::io::Result<()> {
+        let meta_path = self.path(name, "meta");
+        let body_path = self.path(name, "body");
+        if let Some(dir) = meta_path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let id = self.tmp_id.fetch_add(1, Ordering::Relaxed);
+        let meta_tmp = meta_path.with_extension(format!("meta.{}.tmp", id));
+        let body_tmp = body_path.with_extension(format!("body.{}.tmp", id));
+        let written = async {
+            write_synced(&body_tmp, body).await?;
+            write_synced(&meta_tmp, meta).await?;
+            /* the old meta goes first, it must never point at the new body */
+            let _ = tokio::fs::remove_file(&meta_path).await;
+            tokio::fs::rename(&body_tmp, &body_path).await?;
+            tokio::fs::rename(&meta_tmp, &meta_path).await?;
+            /* so the renames survive a crash too, not every system can open a dir */
+            if let Some(dir) = meta_path.parent() {
+                if let Ok(dir) = tokio::fs::File::open(dir).await {
+                    let _ = dir.sync_all().await;
+                }
+            }
+            Ok(())
+        }.await;
+        if written.is_err() {
+            let _ = tokio::fs::remove_file(&body_tmp).await;
+            let _ = tokio::fs::remove_file(&meta_tmp).await;
+        }
+        written
+    }
+    async fn remove_files(&self, name: &str) {
+        let _ = tokio::fs::remove_file(self.path(name, "meta")).await;
+        let _ = tokio::fs::remove_file(self.path(name, "body")).await;
+    }
+    pub(crate) fn clear_expired(&self) {
+        let expired = {
+            let mut index = self.index.lock().unwrap();
+            let keys = index.entries.iter().filter(|(_, entry)| entry.is_expire()).map(|(key, _)| key.clone()).collect::<Vec<_>>();
+            keys.iter().filter_map(|key| index.remove(key)).collect::<Vec<_>>()
+        };
+        for entry in expired {
+            let _ = std::fs::remove_file(self.path(&entry.name, "meta"));
+            let _ = std::fs::remove_file(self.path(&entry.name, "body"));
+        }
+    }
+    /* remove every entry (with its variants) the purge matches, gives how many went */
+    pub(crate) async fn purge(&self, purge: &CachePurge) -> usize {
+        let matched = {
+            let mut index = self.index.lock().unwrap();
+            let keys = index.entries.iter()
+                .filter(|(key, entry)| purge.matches_memory(&key[..entry.primary_len], &entry.url, &entry.tags))
+                .map(|(key, _)| key.clone())
+                .collect::<Vec<_>>();
+            keys.iter().filter_map(|key| index.remove(key)).collect::<Vec<_>>()
+        };
+        for entry in &matched {
+            self.remove_files(&entry.name).await;
+        }
+        matched.len()
+    }
+    pub(crate) fn occupancy(&self) -> HttpCacheOccupancy {
+        let index = self.index.lock().unwrap();
+        HttpCacheOccupancy {
+            entries: index.entries.len(),
+            bytes: index.bytes as usize,
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+            coalesced: 0,
+        }
+    }
+    pub(crate) fn root(&self) -> &Path {
+        &self.root
+    }
+}
+fn file_name(key: &str) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA256, key.as_bytes());
+    digest.as_ref().iter().map(|b| format!("{:02x}", b)).collect()
+}
+async fn write_synced(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let mut file = tokio::fs::File::create(path).await?;
+    file.write_all(content).await?;
+    file.sync_all().await
+}
+fn is_hex(s: &str) -> bool {
+    s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+/* what a file of the dir xx is to the cache: xx{62 hex}.meta, .body or .meta.{n}.tmp, .body.{n}.tmp */
+fn cache_file(dir: &str, file: &str) -> Option<CacheFile> {
+    let (name, extension) = file.split_once('.')?;
+    if name.len() != 64 || !is_hex(name) || !name.starts_with(dir) {
+        return None;
+    }
+    match extension {
+        "meta" => { Some(CacheFile::Meta) }
+        "body" => { Some(CacheFile::Body) }
+        _ => {
+            let id = extension.strip_prefix("meta.").or_else(|| extension.strip_prefix("body."))?.strip_suffix(".tmp")?;
+            if !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit()) {
+                Some(CacheFile::Tmp)
+            } else {
+                None
+            }
+        }
+    }
+}
+fn parse_version(version: &str) -> Version {
+    match version {
+        "HTTP/0.9" => { Version::HTTP_09 }
+        "HTTP/1.0" => { Version::HTTP_10 }
+        "HTTP/2.0" => { Version::HTTP_2 }
+        "HTTP/3.0" => { Version::HTTP_3 }
+        _ => { Version::HTTP_11 }
+    }
+}
@@ -0,0 +1,398 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public
+License, v. 2.0. If a copy of the MPL was not distributed with this
+file, You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+use std::time::Duration;
+
+use http::{header, HeaderMap, HeaderName, Method, StatusCode};
+
+use crate::{context::{http_context::request_context::RequestContext, scheme::SchemeContext}, error::RResult};
+
+/* the part of a key with the values of the Vary headers comes after this */
+pub(crate) const VARY_SEPARATOR: char = '\n';
+
+#[derive(Debug, Clone, PartialEq)]
+enum KeyPart {
+    Text(String),
+    Method,
+    Scheme,
+    Host,
+    Path,
+    Query,
+    Arg(String),
+    Header(HeaderName),
+    Cookie(String),
+    BodyHash,
+}
+/* $method $scheme://$host$path?$query, $arg_xx, $http_xx, $cookie_xx, $body_hash */
+#[derive(Debug, Clone)]
+pub(crate) struct CacheKeyTemplate {
+    parts: Vec<KeyPart>,
+}
+impl CacheKeyTemplate {
+    pub(crate) fn parse(template: &str) -> RResult<Self> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(i) = rest.find('$') {
+            if i > 0 {
+                parts.push(KeyPart::Text(rest[..i].to_string()));
+            }
+            let name_len = rest[i + 1..].find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-')).unwrap_or(rest.len() - i - 1);
+            let name = &rest[i + 1..i + 1 + name_len];
+            let part = match name {
+                "method" => KeyPart::Method,
+                "scheme" => KeyPart::Scheme,
+                "host" => KeyPart::Host,
+                "path" => KeyPart::Path,
+                "query" => KeyPart::Query,
+                "body_hash" => KeyPart::BodyHash,
+                _ => {
+                    if let Some(arg) = name.strip_prefix("arg_") {
+                        KeyPart::Arg(arg.to_string())
+                    } else if let Some(h) = name.strip_prefix("http_") {
+                        KeyPart::Header(HeaderName::from_bytes(h.replace('_', "-").as_bytes()).map_err(|_| format!("cache key: bad header ${}", name))?)
+                    } else if let Some(cookie) = name.strip_prefix("cookie_") {
+                        KeyPart::Cookie(cookie.to_string())
+                    } else {
+                        return Err(format!("cache key: unknown variable ${}", name).into());
+                    }
+                }
+            };
+            parts.push(part);
+            rest = &rest[i + 1 + name_len..];
+        }
+        if !rest.is_empty() {
+            parts.push(KeyPart::Text(rest.to_string()));
+        }
+        if parts.iter().any(|p| matches!(p, KeyPart::Text(t) if t.contains(VARY_SEPARATOR))) {
+            return Err("cache key: a new line can not be used".into());
+        }
+        Ok(Self { parts })
+    }
+    pub(crate) fn render(&self, request: &RequestContext) -> String {
+        let mut key = String::new();
+        for part in &self.parts {
+            match part {
+                KeyPart::Text(t) => { key += t; }
+                KeyPart::Method => { key += request.method.as_str(); }
+                KeyPart::Scheme => {
+                    key += match request.scheme {
+                        SchemeContext::HTTPS => { "https" }
+                        _ => { "http" }
+                    };
+                }
+                KeyPart::Host => {
+                    let host = request.headers.get(header::HOST).and_then(|h| h.to_str().ok())
+                        .or_else(|| request.uri.authority().map(|a| a.as_str()))
+                        .unwrap_or_default();
+                    key += &host.to_ascii_lowercase();
+                }
+                KeyPart::Path => { key += request.uri.path(); }
+                KeyPart::Query => { key += request.uri.query().unwrap_or_default(); }
+                KeyPart::Arg(name) => {
+                    if let Some(v) = request.uri.query().and_then(|q| q.split('&').find_map(|pair| {
+                        let (k, v) = pair.split_once('=').unwrap_or((pair, ""));
+                        if k == name { Some(v) } else { None }
+                    })) {
+                        key += v;
+                    }
+                }
+                KeyPart::Header(name) => {
+                    let values = request.headers.get_all(name).iter().filter_map(|v| v.to_str().ok()).collect::<Vec<_>>();
+                    key += &values.join(",");
+                }
+                KeyPart::Cookie(name) => {
+                    if let Some(v) = cookie(&request.headers, name) {
+                        key += v;
+                    }
+                }
+                KeyPart::BodyHash => {
+                    if !request.body.is_empty() {
+                        let digest = ring::digest::digest(&ring::digest::SHA256, &request.body);
+                        key += &digest.as_ref().iter().map(|b| format!("{:02x}", b)).collect::<String>();
+                    }
+                }
+            }
+        }
+        key
+    }
+}
+fn cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get_all(header::COOKIE).iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .find_map(|pair| {
+            let (k, v) = pair.trim().split_once('=')?;
+            if k == name { Some(v) } else { None }
+        })
+}
+/* what and how the cache modules of a pipe store */
+#[derive(Debug, Clone)]
+pub(crate) struct CachePolicy {
+    pub(crate) key: CacheKeyTemplate,
+    pub(crate) methods: Vec<Method>,
+    pub(crate) status: Vec<StatusCode>,
+    pub(crate) stale_while_revalidate: Duration,
+    pub(crate) stale_if_error: Duration,
+}
+impl CachePolicy {
+    pub(crate) fn new(key: CacheKeyTemplate, methods: Vec<Method>, status: Vec<StatusCode>, stale_while_revalidate: Duration, stale_if_error: Duration) -> Self {
+        Self { key, methods, status, stale_while_revalidate, stale_if_error }
+    }
+    pub(crate) fn default_key() -> &'static str {
+        "$method $scheme://$host$path?$query"
+    }
+    pub(crate) fn default_methods() -> Vec<Method> {
+        vec![Method::GET, Method::HEAD]
+    }
+    /* the codes RFC 9111 lets a cache store without explicit freshness, minus 206 */
+    pub(crate) fn default_status() -> Vec<StatusCode> {
+        [200, 203, 204, 300, 301, 308, 404, 405, 410, 414, 501].iter().filter_map(|s| StatusCode::from_u16(*s).ok()).collect()
+    }
+    pub(crate) fn is_cacheable_method(&self, method: &Method) -> bool {
+        self.methods.contains(method)
+    }
+    /*
+    How long the response may be kept, from Cache-Control (s-maxage, max-age), then Expires, then the expire of the module.
+    None: not stored. Some(None): stored without expiration.
+     */
+    pub(crate) fn store_for(&self, request: &RequestContext, status: StatusCode, headers: &HeaderMap, expire: Duration) -> Option<Option<Duration>> {
+        if !self.is_cacheable_method(&request.method) || !self.status.contains(&status) {
+            return None;
+        }
+        if cache_control(&request.headers).iter().any(|(d, _)| d == "no-store") {
+            return None;
+        }
+        if headers.contains_key(header::SET_COOKIE) {
+            return None;
+        }
+        let directives = cache_control(headers);
+        let has = |name: &str| directives.iter().any(|(d, _)| d == name);
+        let seconds = |name: &str| directives.iter().find(|(d, _)| d == name).and_then(|(_, v)| v.as_deref()?.parse::<u64>().ok());
+        /* no-cache needs a revalidation on every use, which is the same as no cache here */
+        if has("no-store") || has("private") || has("no-cache") {
+            return None;
+        }
+        /* a shared cache keeps an authorized answer only if the origin says so */
+        if request.headers.contains_key(header::AUTHORIZATION) && !(has("public") || has("s-maxage") || has("must-revalidate")) {
+            return None;
+        }
+        if let Some(age) = seconds("s-maxage").or_else(|| seconds("max-age")) {
+            return if age == 0 { None } else { Some(Some(Duration::from_secs(age))) };
+        }
+        if let Some(expires) = headers.get(header::EXPIRES) {
+            let parse = |v: &http::HeaderValue| v.to_str().ok().and_then(|v| chrono::DateTime::parse_from_rfc2822(v).ok());
+            /* an invalid date means already expired */
+            let expires = parse(expires)?;
+            let date = headers.get(header::DATE).and_then(parse).map(|d| d.to_utc()).unwrap_or_else(chrono::Utc::now);
+            return (expires.to_utc() - date).to_std().ok().filter(|d| !d.is_zero()).map(Some);
+        }
+        if expire.is_zero() { Some(None) } else { Some(Some(expire)) }
+    }
+    /*
+    How long after its expire the response may still be given: (while it is refreshed, when the upstream fails).
+    stale-while-revalidate and stale-if-error of Cache-Control first, then the module. must-revalidate forbids both.
+     */
+    pub(crate) fn stale_windows(&self, headers: &HeaderMap) -> (Duration, Duration) {
+        let directives = cache_control(headers);
+        if directives.iter().any(|(d, _)| d == "must-revalidate" || d == "proxy-revalidate") {
+            return (Duration::ZERO, Duration::ZERO);
+        }
+        let seconds = |name: &str| directives.iter().find(|(d, _)| d == name).and_then(|(_, v)| v.as_deref()?.parse::<u64>().ok()).map(Duration::from_secs);
+        (
+            seconds("stale-while-revalidate").unwrap_or(self.stale_while_revalidate),
+            seconds("stale-if-error").unwrap_or(self.stale_if_error),
+        )
+    }
+}
+/* lowercased directives with their value, quotes removed */
+fn cache_control(headers: &HeaderMap) -> Vec<(String, Option<String>)> {
+    headers.get_all(header::CACHE_CONTROL).iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|d| {
+            let d = d.trim();
+            if d.is_empty() {
+                return None;
+            }
+            Some(match d.split_once('=') {
+                Some((name, value)) => (name.trim().to_ascii_lowercase(), Some(value.trim().trim_matches('"').to_string())),
+                None => (d.to_ascii_lowercase(), None),
+            })
+        })
+        .collect()
+}
+/* the names of the Vary header, None for Vary: * which can never be matched */
+pub(crate) fn vary_names(headers: &HeaderMap) -> Option<Vec<HeaderName>> {
+    let mut names = Vec::new();
+    for name in headers.get_all(header::VARY).iter().filter_map(|v| v.to_str().ok()).flat_map(|v| v.split(',')) {
+        let name = name.trim();
+        if name == "*" {
+            return None;
+        }
+        if let Ok(name) = HeaderName::from_bytes(name.as_bytes()) {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    Some(names)
+}
+/* where a store without the lock of the memory (redis) keeps the Vary headers of a key */
+pub(crate) fn vary_key(key: &str) -> String {
+    format!("{}{}vary", key, VARY_SEPARATOR)
+}
+pub(crate) fn parse_vary(names: &str) -> Vec<HeaderName> {
+    names.split(',').filter_map(|n| HeaderName::from_bytes(n.trim().as_bytes()).ok()).collect()
+}
+/* the key of one variant: the key of the request, then the values the request has for the Vary headers */
+pub(crate) fn variant_key(key: &str, vary: &[HeaderName], headers: &HeaderMap) -> String {
+    if vary.is_empty() {
+        return key.to_string();
+    }
+    let mut ret = key.to_string();
+    ret.push(VARY_SEPARATOR);
+    for name in vary {
+        let values = headers.get_all(name).iter().filter_map(|v| v.to_str().ok()).map(|v| v.trim()).collect::<Vec<_>>();
+        ret += name.as_str();
+        ret.push(':');
+        ret += &values.join(",");
+        ret.push(';');
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+
+    fn policy() -> CachePolicy {
+        let key = CacheKeyTemplate::parse(CachePolicy::default_key()).unwrap();
+        CachePolicy::new(key, CachePolicy::default_methods(), CachePolicy::default_status(), Duration::ZERO, Duration::ZERO)
+    }
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (k, v) in pairs {
+            headers.append(*k, HeaderValue::from_static(v));
+        }
+        headers
+    }
+    fn store_for(request: &[(&'static str, &'static str)], response: &[(&'static str, &'static str)]) -> Option<Option<Duration>> {
+        let request = RequestContext { headers: headers(request), ..Default::default() };
+        policy().store_for(&request, StatusCode::OK, &headers(response), Duration::from_secs(30))
+    }
+    fn secs(s: u64) -> Option<Option<Duration>> {
+        Some(Some(Duration::from_secs(s)))
+    }
+
+    #[test]
+    fn store_for_refusals() {
+        assert_eq!(store_for(&[], &[("cache-control", "no-store")]), None);
+        assert_eq!(store_for(&[], &[("cache-control", "max-age=60, Private")]), None);
+        assert_eq!(store_for(&[], &[("cache-control", "no-cache")]), None);
+        assert_eq!(store_for(&[("cache-control", "no-store")], &[]), None);
+        assert_eq!(store_for(&[], &[("set-cookie", "a=1")]), None);
+        assert_eq!(store_for(&[], &[("cache-control", "max-age=0")]), None);
+        assert_eq!(store_for(&[], &[("cache-control", "s-maxage=0, max-age=60")]), None);
+        /* the request's no-cache does not forbid storing */
+        assert_eq!(store_for(&[("cache-control", "no-cache")], &[]), secs(30));
+    }
+    #[test]
+    fn store_for_method_and_status() {
+        let request = RequestContext { method: Method::POST, ..Default::default() };
+        assert_eq!(policy().store_for(&request, StatusCode::OK, &HeaderMap::new(), Duration::ZERO), None);
+        let request = RequestContext::default();
+        assert_eq!(policy().store_for(&request, StatusCode::PARTIAL_CONTENT, &HeaderMap::new(), Duration::ZERO), None);
+        assert_eq!(policy().store_for(&request, StatusCode::NOT_FOUND, &HeaderMap::new(), Duration::ZERO), Some(None));
+    }
+    #[test]
+    fn store_for_authorization() {
+        let auth = [("authorization", "Bearer x")];
+        assert_eq!(store_for(&auth, &[]), None);
+        assert_eq!(store_for(&auth, &[("cache-control", "max-age=60")]), None);
+        assert_eq!(store_for(&auth, &[("cache-control", "public, max-age=60")]), secs(60));
+        assert_eq!(store_for(&auth, &[("cache-control", "s-maxage=10")]), secs(10));
+        assert_eq!(store_for(&auth, &[("cache-control", "must-revalidate")]), secs(30));
+    }
+    #[test]
+    fn store_for_max_age() {
+        assert_eq!(store_for(&[], &[]), secs(30));
+        assert_eq!(store_for(&[], &[("cache-control", "public, max-age=60")]), secs(60));
+        assert_eq!(store_for(&[], &[("cache-control", "max-age=60, s-maxage=5")]), secs(5));
+        assert_eq!(store_for(&[], &[("cache-control", "max-age=\"60\"")]), secs(60));
+        /* a bad value is ignored */
+        assert_eq!(store_for(&[], &[("cache-control", "max-age=abc")]), secs(30));
+        /* max-age beats Expires */
+        assert_eq!(store_for(&[], &[("cache-control", "max-age=60"), ("expires", "Thu, 01 Jan 1970 00:00:00 GMT")]), secs(60));
+        let request = RequestContext::default();
+        assert_eq!(policy().store_for(&request, StatusCode::OK, &HeaderMap::new(), Duration::ZERO), Some(None));
+    }
+    #[test]
+    fn store_for_expires() {
+        let date = ("date", "Mon, 19 Oct 2026 10:00:00 GMT");
+        assert_eq!(store_for(&[], &[date, ("expires", "Mon, 19 Oct 2026 10:01:30 GMT")]), secs(90));
+        assert_eq!(store_for(&[], &[date, ("expires", "Mon, 19 Oct 2026 10:00:00 GMT")]), None);
+        assert_eq!(store_for(&[], &[date, ("expires", "Mon, 19 Oct 2026 09:00:00 GMT")]), None);
+        assert_eq!(store_for(&[], &[date, ("expires", "0")]), None);
+        /* without Date the clock of the gateway is used */
+        assert!(matches!(store_for(&[], &[("expires", "Fri, 01 Jan 2100 00:00:00 GMT")]), Some(Some(_))));
+        assert_eq!(store_for(&[], &[("expires", "Thu, 01 Jan 1970 00:00:00 GMT")]), None);
+    }
+    #[test]
+    fn stale_windows() {
+        let policy = CachePolicy { stale_while_revalidate: Duration::from_secs(5), stale_if_error: Duration::from_secs(7), ..policy() };
+        assert_eq!(policy.stale_windows(&headers(&[])), (Duration::from_secs(5), Duration::from_secs(7)));
+        let h = headers(&[("cache-control", "stale-while-revalidate=1, stale-if-error=2")]);
+        assert_eq!(policy.stale_windows(&h), (Duration::from_secs(1), Duration::from_secs(2)));
+        let h = headers(&[("cache-control", "stale-if-error=2, must-revalidate")]);
+        assert_eq!(policy.stale_windows(&h), (Duration::ZERO, Duration::ZERO));
+    }
+    #[test]
+    fn vary() {
+        assert_eq!(vary_names(&headers(&[])), Some(vec![]));
+        let names = vary_names(&headers(&[("vary", "Accept-Encoding, accept-language"), ("vary", "accept-encoding")])).unwrap();
+        assert_eq!(names, vec![header::ACCEPT_ENCODING, header::ACCEPT_LANGUAGE]);
+        assert_eq!(vary_names(&headers(&[("vary", "accept-encoding, *")])), None);
+        assert_eq!(vary_names(&headers(&[("vary", "accept-encoding"), ("vary", "*")])), None);
+        let h = headers(&[("accept-encoding", "gzip"), ("accept-encoding", " br ")]);
+        assert_eq!(variant_key("k", &names, &h), "k\naccept-encoding:gzip,br;accept-language:;");
+        assert_eq!(variant_key("k", &[], &h), "k");
+        assert_eq!(parse_vary("accept-encoding, accept-language"), names);
+    }
+    #[test]
+    fn key_template() {
+        let mut request = RequestContext {
+            method: Method::POST,
+            scheme: SchemeContext::HTTPS,
+            uri: "/a/b?x=1&y=2&flag".parse().unwrap(),
+            headers: headers(&[("host", "Example.COM"), ("x-tenant", "t1"), ("x-tenant", "t2"), ("cookie", "a=1; sid=s1")]),
+            body: "body".into(),
+            ..Default::default()
+        };
+        let render = |t: &str, request: &RequestContext| CacheKeyTemplate::parse(t).unwrap().render(request);
+        assert_eq!(render(CachePolicy::default_key(), &request), "POST https://example.com/a/b?x=1&y=2&flag");
+        assert_eq!(render("$arg_y|$arg_flag|$arg_z", &request), "2||");
+        assert_eq!(render("$http_x_tenant", &request), "t1,t2");
+        assert_eq!(render("$cookie_sid|$cookie_none", &request), "s1|");
+        assert_eq!(render("$body_hash", &request), "230d8358dc8e8890b4c58deeb62912ee2f20357ae92a5cc861b98e68fe31acb5");
+        assert_eq!(render("k:$path", &request), "k:/a/b");
+        request.body = Default::default();
+        request.scheme = SchemeContext::HTTP;
+        assert_eq!(render("$scheme $body_hash.", &request), "http .");
+        /* the host of an absolute uri when there is no Host header */
+        request.headers = HeaderMap::new();
+        request.uri = "http://Up.Example:8080/p".parse().unwrap();
+        assert_eq!(render("$host$path?$query", &request), "up.example:8080/p?");
+    }
+    #[test]
+    fn key_template_errors() {
+        assert!(CacheKeyTemplate::parse("$nope").is_err());
+        assert!(CacheKeyTemplate::parse("$http_").is_err());
+        assert!(CacheKeyTemplate::parse("$path$").is_err());
+        assert!(CacheKeyTemplate::parse("a\nb").is_err());
+        assert!(CacheKeyTemplate::parse("").is_ok());
+    }
+}
@@ -0,0 +1,164 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public
+License, v. 2.0. If a copy of the MPL was not distributed with this
+file, You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+use http::HeaderMap;
+
+use crate::{common::redis::Redis, context::{http_context::request_context::RequestContext, scheme::SchemeContext}, error::RResult};
+
+use super::{http_cache_policy::VARY_SEPARATOR, redis::CACHE_KEYS};
+
+#[derive(Debug, Clone)]
+pub(crate) enum PurgeTarget {
+    Keys(Vec<String>),          //the keys rendered by the key of the cache modules
+    Prefix(String),             //the url of the request, scheme://host/path?query
+    Regex(regex::Regex),
+    Tags(Vec<String>),          //Surrogate-Key or Cache-Tag of the response
+}
+#[derive(Debug, Clone)]
+pub(crate) struct CachePurge {
+    pub(crate) target: PurgeTarget,
+    pub(crate) route: Option<String>,   //only the memory of this route
+}
+/* the purged entries of each tier */
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct PurgeCount {
+    pub(crate) memory: usize,
+    pub(crate) redis: usize,
+    pub(crate) disk: usize,
+}
+impl CachePurge {
+    pub(crate) fn new(target: PurgeTarget, route: Option<String>) -> Self {
+        Self { target, route }
+    }
+    /* primary is the key of the memory and the disk, the route then the rendered key */
+    pub(crate) fn matches_memory(&self, primary: &str, url: &str, tags: &[String]) -> bool {
+        let (route, key) = primary.split_once(' ').unwrap_or(("", primary));
+        if self.route.as_deref().is_some_and(|r| r != route) {
+            return false;
+        }
+        self.matches(key, url, tags)
+    }
+    fn matches(&self, key: &str, url: &str, tags: &[String]) -> bool {
+        match &self.target {
+            PurgeTarget::Keys(keys) => { keys.iter().any(|k| k == key) }
+            PurgeTarget::Prefix(prefix) => { url.starts_with(prefix.as_str()) }
+            PurgeTarget::Regex(regex) => { regex.is_match(url) }
+            PurgeTarget::Tags(purge_tags) => { tags.iter().any(|t| purge_tags.contains(t)) }
+        }
+    }
+    /*
+    Besides the response, an entry of redis has the records key\nvary and key\nurl, its variants key\n...,
+    and a set of keys for every tag. Prefix and regex go through all the key\nurl of redis.
+    The keys are prefix cache:namespace key, namespace None is every namespace.
+     */
+    pub(crate) async fn purge_redis(&self, redis: &Redis, namespace: Option<&str>) -> RResult<usize> {
+        let cache = redis.prefixed(CACHE_KEYS);
+        let split = |entry: &str| -> Option<(String, String)> {
+            let (ns, key) = entry.strip_prefix(cache.as_str())?.split_once(' ')?;
+            Some((ns.to_string(), key.to_string()))
+        };
+        let in_namespace = |entry: &str| split(entry).is_some_and(|(ns, _)| namespace.is_none_or(|n| n == ns));
+        let keys = match &self.target {
+            PurgeTarget::Keys(keys) => {
+                match namespace {
+                    Some(ns) => { keys.iter().map(|k| format!("{}{} {}", cache, ns, k)).collect() }
+                    None => {
+                        let mut entries = Vec::new();
+                        for k in keys {
+                            let found = redis.scan(&format!("{}* {}", glob_escape(&cache), glob_escape(k))).await?;
+                            entries.extend(found.into_iter().filter(|entry| split(entry).is_some_and(|(_, key)| &key == k)));
+                        }
+                        entries
+                    }
+                }
+            }
+            PurgeTarget::Tags(tags) => {
+                let mut keys = Vec::new();
+                for tag in tags {
+                    let tag_key = redis.prefixed(&tag_key(tag));
+                    let mut members = redis.members(&tag_key).await?;
+                    if namespace.is_some() {
+                        /* the set is shared by the namespaces, only the members of this one leave it */
+                        members.retain(|m| in_namespace(m));
+                        redis.remove_members(&tag_key, &members).await?;
+                    } else {
+                        redis.del_many(&[tag_key], false).await?;
+                    }
+                    keys.extend(members);
+                }
+                keys
+            }
+            PurgeTarget::Prefix(_) | PurgeTarget::Regex(_) => {
+                let namespace = namespace.map(|ns| format!("{} ", glob_escape(ns))).unwrap_or_default();
+                let records = redis.scan(&format!("{}{}*{}url", glob_escape(&cache), namespace, VARY_SEPARATOR)).await?;
+                let urls = redis.get_many(&records).await?;
+                records.iter().zip(urls).filter_map(|(record, url)| {
+                    let key = record.strip_suffix(&format!("{}url", VARY_SEPARATOR))?;
+                    if self.matches(key, &url?, &[]) { Some(key.to_string()) } else { None }
+                }).collect()
+            }
+        };
+        let mut purged = 0;
+        for key in keys {
+            let mut entries = vec![key.clone()];
+            let mut records = Vec::new();
+            for k in redis.scan(&format!("{}{}*", glob_escape(&key), VARY_SEPARATOR)).await? {
+                if k.ends_with(&format!("{}vary", VARY_SEPARATOR)) || k.ends_with(&format!("{}url", VARY_SEPARATOR)) {
+                    records.push(k);
+                } else {
+                    entries.push(k);
+                }
+            }
+            purged += redis.del_many(&entries, true).await?;
+            redis.del_many(&records, false).await?;
+        }
+        Ok(purged)
+    }
+}
+/* where redis keeps the url of a key, for the purge by prefix or regex */
+pub(crate) fn url_key(key: &str) -> String {
+    format!("{}{}url", key, VARY_SEPARATOR)
+}
+/* the set of the keys with this tag, under the prefix of redis */
+pub(crate) fn tag_key(tag: &str) -> String {
+    format!("{}tag {}", VARY_SEPARATOR, tag)
+}
+/* the url a purge by prefix or regex is matched against */
+pub(crate) fn request_url(request: &RequestContext) -> String {
+    let scheme = match request.scheme {
+        SchemeContext::HTTPS => { "https" }
+        _ => { "http" }
+    };
+    let host = request.headers.get(http::header::HOST).and_then(|h| h.to_str().ok())
+        .or_else(|| request.uri.authority().map(|a| a.as_str()))
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let path_and_query = request.uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+    format!("{}://{}{}", scheme, host, path_and_query)
+}
+/* Surrogate-Key is separated by spaces, Cache-Tag by commas */
+pub(crate) fn surrogate_keys(headers: &HeaderMap) -> Vec<String> {
+    let mut tags = Vec::new();
+    let values = headers.get_all("surrogate-key").iter().filter_map(|v| v.to_str().ok()).flat_map(|v| v.split_whitespace())
+        .chain(headers.get_all("cache-tag").iter().filter_map(|v| v.to_str().ok()).flat_map(|v| v.split(',')));
+    for tag in values {
+        let tag = tag.trim();
+        if !tag.is_empty() && !tags.iter().any(|t| t == tag) {
+            tags.push(tag.to_string());
+        }
+    }
+    tags
+}
+fn glob_escape(key: &str) -> String {
+    let mut ret = String::with_capacity(key.len());
+    for c in key.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            ret.push('\\');
+        }
+        ret.push(c);
+    }
+    ret
+}
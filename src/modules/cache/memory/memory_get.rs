@@ -12,10 +12,7 @@ use crate::{
         GatewayError, PipeError, RResult
     },
     modules::{
-        cache::{http_cache_cell::{
-            HttpCacheKey, 
-            HttpCacheShared
-        }, CacheProfile}, ModuleType, PipeData, PipeModule
+        cache::{http_cache_cell::{CacheLookup, CacheStale, FlightJoin, HttpCacheEntry, HttpCacheShared}, CacheProfile}, ModuleType, PipeData, PipeModule
     }
 };
 
@@ -30,32 +27,47 @@ impl PipeModule for MemoryCacheGet {
         if let PipeData::MemoryCacheGetData { profile } = pipe_data {
             if let ContextType::HttpContext(http_context) = &mut ctx.context_type {
                 let profile_read_lock = profile.read().await;
-                let mut cache_lock = profile_read_lock.cache.lock();
-                let http_cache_key = HttpCacheKey::for_key(http_context.request_context.uri.clone());
-                let mut after_remove = false;
-                if let Some((key, value)) = cache_lock.get_key_value(&http_cache_key) {
-                    if HttpCacheKey::is_expire(key) {
-                        cache_lock.remove(&http_cache_key);
-                        return Ok(());
-                    }
-                    if profile_read_lock.expire != std::time::Duration::from_millis(0) {
-                        *key.expire.borrow_mut() = Some(profile_read_lock.expire);
-                        *key.save_point.borrow_mut() = Some(std::time::Instant::now());
-                    }
-                    if profile_read_lock.hit != -1 {
-                        let hit_borrow = key.hit.borrow();
-                        if let Some(hit) = *hit_borrow {
-                            if hit >= profile_read_lock.hit {
-                                after_remove = true;
-                            } else {
-                                drop(hit_borrow);
-                                *key.hit.borrow_mut() = Some(hit + 1);
+                if !profile_read_lock.policy.is_cacheable_method(&http_context.request_context.method) {
+                    return Ok(());
+                }
+                /* the refresh of a stale entry goes to the upstream */
+                if let CacheStale::Revalidate = http_context.cache_stale {
+                    return Ok(());
+                }
+                let key = memory_key(ctx.route.as_deref(), &profile_read_lock.policy.key.render(&http_context.request_context));
+                let mut lookup = profile_read_lock.cache.get(&key, &http_context.request_context.headers, on_hit(&profile_read_lock));
+                /* single-flight: the first miss of a key goes upstream, the others wait for what it stores */
+                if let Some(timeout) = profile_read_lock.coalesce {
+                    if matches!(lookup, CacheLookup::Miss | CacheLookup::IfError(_)) {
+                        match profile_read_lock.cache.join_flight(&key) {
+                            FlightJoin::Leader(flight) => {
+                                http_context.cache_flight = Some(Arc::new(flight));
+                            }
+                            FlightJoin::Wait(mut done) => {
+                                if tokio::time::timeout(timeout, done.changed()).await.is_err() {
+                                    log::debug!("memory cache waited too long for key:{}, goes upstream", key);
+                                }
+                                lookup = profile_read_lock.cache.get(&key, &http_context.request_context.headers, on_hit(&profile_read_lock));
                             }
-                        } else {
-                            drop(hit_borrow);
-                            *key.hit.borrow_mut() = Some(1);
                         }
                     }
+                }
+                let cell = match lookup {
+                    CacheLookup::Fresh(cell) => { Some(cell) }
+                    CacheLookup::Stale { cell, revalidate } => {
+                        /* a stale copy is given right away, the upstream is asked after the response */
+                        log::debug!("memory cache gives stale key:{} revalidate:{}", key, revalidate);
+                        http_context.cache_stale = CacheStale::Served(if revalidate { Some((profile_read_lock.cache.clone(), key)) } else { None });
+                        ctx.prompt_return = true;
+                        Some(cell)
+                    }
+                    CacheLookup::IfError(cell) => {
+                        http_context.cache_stale = CacheStale::IfError(cell);
+                        None
+                    }
+                    CacheLookup::Miss => { None }
+                };
+                if let Some(value) = cell {
                     http_context.response_context.status = value.clone_status();
                     http_context.response_context.version = value.clone_version();
                     http_context.response_context.headers = value.clone_headers();
@@ -63,10 +75,6 @@ impl PipeModule for MemoryCacheGet {
                     http_context.response_context.refresh();
                     http_context.cache_hit = true;
                 }
-                if after_remove {
-                    cache_lock.remove(&http_cache_key);
-                }
-                drop(cache_lock);
                 if http_context.cache_hit && profile_read_lock.back {
                     ctx.prompt_return = true;
                 }
@@ -76,4 +84,30 @@ impl PipeModule for MemoryCacheGet {
         }
         unreachable!()
     }
-}
\ No newline at end of file
+}
+/* refresh the expire and count the hit, true when the entry goes away after this hit */
+fn on_hit(profile: &CacheProfile) -> impl FnOnce(&mut HttpCacheEntry) -> bool + '_ {
+    move |entry| {
+        if profile.expire != std::time::Duration::from_millis(0) {
+            entry.expire = Some(profile.expire);
+            entry.save_point = std::time::Instant::now();
+        }
+        let mut after_remove = false;
+        if profile.hit != -1 {
+            if let Some(hit) = entry.hit {
+                if hit >= profile.hit {
+                    after_remove = true;
+                } else {
+                    entry.hit = Some(hit + 1);
+                }
+            } else {
+                entry.hit = Some(1);
+            }
+        }
+        after_remove
+    }
+}
+/* the memory cache is shared by the routes, every route has its own keys */
+pub(crate) fn memory_key(route: Option<&str>, key: &str) -> String {
+    format!("{} {}", route.unwrap_or_default(), key)
+}
@@ -10,10 +10,7 @@ use crate::{
     context::ContextType, 
     error::RResult, 
     modules::{
-        cache::{http_cache_cell::{
-            HttpCacheKey, 
-            HttpCacheShared
-        }, CacheProfile}, ModuleType, PipeData, PipeModule
+        cache::{http_cache_cell::{CacheStale, HttpCacheMeta, HttpCacheShared}, http_cache_policy, http_cache_purge, CacheProfile}, ModuleType, PipeData, PipeModule
     }
 };
 
@@ -28,31 +25,42 @@ impl PipeModule for MemoryCacheSet {
     async fn execute(&self, ctx: &mut crate::context::GatewayContext, pipe_data: &crate::modules::PipeData) -> RResult<()>  {
         if let PipeData::MemoryCacheSetData { profile } = pipe_data {
             if let ContextType::HttpContext(http_context) = &mut ctx.context_type {
+                /* the requests waiting for this one look at the cache again once it is stored */
+                let _flight = http_context.cache_flight.take();
+                /* the response is the stale copy of the cache */
+                if let CacheStale::Served(_) = http_context.cache_stale {
+                    return Ok(());
+                }
                 let profile_read_lock = profile.read().await;
-                let mut cache_lock = profile_read_lock.cache.lock();
-                let expire = if profile_read_lock.expire == std::time::Duration::from_nanos(0) {
-                    std::cell::RefCell::new(None)
-                } else {
-                    std::cell::RefCell::new(Some(profile_read_lock.expire))
+                let response = &http_context.response_context;
+                let expire = match profile_read_lock.policy.store_for(&http_context.request_context, response.status, &response.headers, profile_read_lock.expire) {
+                    Some(expire) => { expire }
+                    None => { return Ok(()); }
                 };
-                let hit = if profile_read_lock.hit == -1 {
-                    std::cell::RefCell::new(None)
-                } else {
-                    std::cell::RefCell::new(Some(profile_read_lock.hit))
+                let vary = match http_cache_policy::vary_names(&response.headers) {
+                    Some(vary) => { vary }
+                    None => { return Ok(()); }
                 };
-                let http_cache_key = HttpCacheKey {
-                    uri: http_context.request_context.uri.clone(),
-                    save_point: std::cell::RefCell::new(Some(std::time::Instant::now())),
+                let (stale_while_revalidate, stale_if_error) = profile_read_lock.policy.stale_windows(&response.headers);
+                let hit = if profile_read_lock.hit == -1 { None } else { Some(profile_read_lock.hit) };
+                let key = super::memory_get::memory_key(ctx.route.as_deref(), &profile_read_lock.policy.key.render(&http_context.request_context));
+                let cell = HttpCacheShared::memory_cache_value(
+                    response.status, 
+                    response.version, 
+                    response.headers.clone(), 
+                    response.body.clone()
+                );
+                let meta = HttpCacheMeta {
                     expire,
                     hit,
+                    url: http_cache_purge::request_url(&http_context.request_context),
+                    tags: http_cache_purge::surrogate_keys(&response.headers),
+                    stale_while_revalidate,
+                    stale_if_error,
                 };
-                cache_lock.insert(http_cache_key, HttpCacheShared::memory_cache_value(
-                    http_context.response_context.status, 
-                    http_context.response_context.version, 
-                    http_context.response_context.headers.clone(), 
-                    http_context.response_context.body.clone()
-                ));
-                drop(cache_lock);
+                if !profile_read_lock.cache.insert(&key, vary, &http_context.request_context.headers, cell, meta) {
+                    log::debug!("memory cache does not keep key:{}", key);
+                }
                 drop(profile_read_lock);
             }
             return Ok(());
@@ -5,14 +5,20 @@ file, You can obtain one at https://mozilla.org/MPL/2.0/.
 */
 
 pub mod http_cache_cell;
+pub(crate) mod http_cache_disk;
+pub(crate) mod frequency_sketch;
+pub(crate) mod http_cache_policy;
+pub(crate) mod http_cache_purge;
+pub(crate) mod cache_purge;
 pub(crate) mod redis;
 pub(crate) mod memory;
+pub(crate) mod disk;
 
 use std::{sync::Arc, time::Duration};
 
-use crate::{common::redis::Redis, error::{BuilderError, BuilderErrorKind, GatewayError}};
+use crate::{context::{http_context::HttpContext, redirect_context::RedirectContext, ContextType, GatewayContext}, error::{BuilderError, BuilderErrorKind, GatewayError}};
 
-use self::{http_cache_cell::HttpCacheShared, memory::{memory_get::MemoryCacheGet, memory_set::MemoryCacheSet}, redis::{redis_get::RedisCacheGet, redis_set::RedisCacheSet}};
+use self::{http_cache_cell::{CacheStale, HttpCacheShared}, http_cache_policy::CachePolicy, memory::{memory_get::MemoryCacheGet, memory_set::MemoryCacheSet}, redis::{redis_get::RedisCacheGet, redis_set::RedisCacheSet, RedisCacheStore}};
 
 use super::Modules;
 
@@ -21,11 +27,69 @@ pub(crate) struct CacheProfile {
     pub(crate) cache: Arc<HttpCacheShared>,
     pub(crate) expire: Duration,
     pub(crate) hit: i32,
-    pub(crate) redis: Option<Redis>,
+    pub(crate) redis: Option<RedisCacheStore>,
     pub(crate) back: bool,
+    pub(crate) policy: CachePolicy,
+    pub(crate) coalesce: Option<Duration>,      //memory_cache_get: how long the concurrent misses of a key wait for the first one
 }
 impl CacheProfile {
-    pub(crate) fn new(cache: Arc<HttpCacheShared>, expire: Duration, hit: i32, redis: Option<Redis>, back: bool) -> Self {
-        Self { cache, expire, hit, redis, back }
+    pub(crate) fn new(cache: Arc<HttpCacheShared>, expire: Duration, hit: i32, redis: Option<RedisCacheStore>, back: bool, policy: CachePolicy, coalesce: Option<Duration>) -> Self {
+        Self { cache, expire, hit, redis, back, policy, coalesce }
     }
-}
\ No newline at end of file
+}
+/*
+The request that started the stale window of an entry asks the upstream again after its response,
+with a copy of itself through the pipe_line of its route. memory_cache_get lets it pass, memory_cache_set stores the answer.
+ */
+pub(crate) fn revalidate_in_background(ctx: &mut GatewayContext) {
+    let http_context = match &mut ctx.context_type {
+        ContextType::HttpContext(http_context) => { http_context }
+        ContextType::TcpContext(_) => { return; }
+    };
+    let (cache, key) = match &mut http_context.cache_stale {
+        CacheStale::Served(revalidate) => {
+            match revalidate.take() {
+                Some(revalidate) => { revalidate }
+                None => { return; }
+            }
+        }
+        _ => { return; }
+    };
+    let route = match &ctx.route {
+        Some(route) => { route.clone() }
+        None => { return; }
+    };
+    let headers = http_context.request_context.headers.clone();
+    let mut background = GatewayContext {
+        remote_addr: ctx.remote_addr,
+        real_ip: ctx.real_ip,
+        prompt_return: false,
+        gateway: ctx.gateway.clone(),
+        route: Some(route.clone()),
+        redirect_context: RedirectContext::default(),
+        concurrency_permits: Default::default(),
+        user: ctx.user.clone(),
+        consumer: ctx.consumer.clone(),
+        claims: ctx.claims.clone(),
+        context_type: ContextType::HttpContext(HttpContext {
+            request_context: http_context.request_context.clone(),
+            cache_stale: CacheStale::Revalidate,
+            ..Default::default()
+        }),
+    };
+    tokio::spawn(async move {
+        let executed = match background.gateway.get_instance() {
+            Ok(instance) => {
+                match instance.routes.get(&route) {
+                    Some(r) => { r.pipe_line.pipe_line_engine.execute(&mut background).await }
+                    None => { Ok(()) }
+                }
+            }
+            Err(e) => { Err(e) }
+        };
+        if let Err(e) = executed {
+            log::error!("memory cache refresh failed, route:{} key:{} {:?}", route, key, e);
+        }
+        cache.revalidated(&key, &headers);
+    });
+}
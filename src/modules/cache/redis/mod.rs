@@ -6,4 +6,29 @@ file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 pub(crate) mod redis_set;
 pub(crate) mod redis_get;
+pub(crate) mod redis_cell;
 
+use crate::common::redis::Redis;
+
+use self::redis_cell::Compression;
+
+/* the entries of the cache modules, under the prefix of redis: cache:namespace key */
+pub(crate) const CACHE_KEYS: &str = "cache:";
+
+#[derive(Debug, Clone)]
+pub(crate) struct RedisCacheStore {
+    pub(crate) redis: Redis,
+    pub(crate) namespace: Option<String>,   //the route when not set
+    pub(crate) compression: Compression,    //redis_cache_set only, redis_cache_get reads whatever it finds
+}
+impl RedisCacheStore {
+    pub(crate) fn new(redis: Redis, namespace: Option<String>, compression: Compression) -> Self {
+        Self { redis, namespace, compression }
+    }
+    pub(crate) fn namespace<'a>(&'a self, route: Option<&'a str>) -> &'a str {
+        self.namespace.as_deref().or(route).unwrap_or_default()
+    }
+    pub(crate) fn entry_key(&self, route: Option<&str>, key: &str) -> String {
+        self.redis.prefixed(&format!("{}{} {}", CACHE_KEYS, self.namespace(route), key))
+    }
+}
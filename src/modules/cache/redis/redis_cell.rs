@@ -0,0 +1,262 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public
+License, v. 2.0. If a copy of the MPL was not distributed with this
+file, You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+use bytes::Bytes;
+use http::{HeaderMap, HeaderName, HeaderValue, StatusCode, Version};
+
+use crate::{error::RResult, modules::cache::http_cache_cell::HttpCacheCell};
+
+const MAGIC: &[u8] = b"RWC";
+const FORMAT: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Compression {
+    None,
+    Zstd(i32),      //level
+    Lz4,
+}
+impl Compression {
+    /* none, lz4, zstd or zstd:level */
+    pub(crate) fn parse(compression: &str) -> Option<Self> {
+        match compression {
+            "none" => { Some(Compression::None) }
+            "lz4" => { Some(Compression::Lz4) }
+            "zstd" => { Some(Compression::Zstd(crate::util::r#const::REDIS_CACHE_ZSTD_LEVEL)) }
+            _ => {
+                let level = compression.strip_prefix("zstd:")?.parse::<i32>().ok()?;
+                if zstd::compression_level_range().contains(&level) { Some(Compression::Zstd(level)) } else { None }
+            }
+        }
+    }
+    fn flag(&self) -> u8 {
+        match self {
+            Compression::None => { 0 }
+            Compression::Zstd(_) => { 1 }
+            Compression::Lz4 => { 2 }
+        }
+    }
+}
+/*
+RWC, the format, the compression, then the cell, compressed or not:
+the status u16, the version u8, the count of headers u32, each header as the length of its name u16, the name,
+the length of its value u32, the value, then the body till the end. Numbers are big endian.
+A cell smaller than REDIS_CACHE_COMPRESS_MIN_SIZE, or that does not get smaller, is kept as it is.
+ */
+pub(crate) fn encode(cell: &HttpCacheCell, compression: Compression) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(cell.body_cache.len() + 512);
+    payload.extend_from_slice(&cell.status_cache.as_u16().to_be_bytes());
+    payload.push(match cell.version_cache {
+        Version::HTTP_09 => { 0 }
+        Version::HTTP_10 => { 1 }
+        Version::HTTP_2 => { 3 }
+        Version::HTTP_3 => { 4 }
+        _ => { 2 }
+    });
+    payload.extend_from_slice(&(cell.header_cache.len() as u32).to_be_bytes());
+    for (name, value) in &cell.header_cache {
+        payload.extend_from_slice(&(name.as_str().len() as u16).to_be_bytes());
+        payload.extend_from_slice(name.as_str().as_bytes());
+        payload.extend_from_slice(&(value.len() as u32).to_be_bytes());
+        payload.extend_from_slice(value.as_bytes());
+    }
+    payload.extend_from_slice(&cell.body_cache);
+    let compressed = if payload.len() < crate::util::r#const::REDIS_CACHE_COMPRESS_MIN_SIZE {
+        None
+    } else {
+        match compression {
+            Compression::None => { None }
+            Compression::Zstd(level) => { zstd::bulk::compress(&payload, level).ok() }
+            Compression::Lz4 => { Some(lz4_flex::compress_prepend_size(&payload)) }
+        }
+    };
+    let (flag, payload) = match compressed {
+        Some(compressed) if compressed.len() < payload.len() => { (compression.flag(), compressed) }
+        _ => { (Compression::None.flag(), payload) }
+    };
+    let mut ret = Vec::with_capacity(payload.len() + MAGIC.len() + 2);
+    ret.extend_from_slice(MAGIC);
+    ret.push(FORMAT);
+    ret.push(flag);
+    ret.extend_from_slice(&payload);
+    ret
+}
+/* the cells written as json by older versions are still read */
+pub(crate) fn decode(data: &[u8]) -> RResult<HttpCacheCell> {
+    if data.first() == Some(&b'{') {
+        return Ok(serde_json::from_slice(data)?);
+    }
+    if data.len() < MAGIC.len() + 2 || &data[..MAGIC.len()] != MAGIC {
+        return Err("redis cache cell: unknown encoding".into());
+    }
+    if data[MAGIC.len()] != FORMAT {
+        return Err(format!("redis cache cell: unknown format {}", data[MAGIC.len()]).into());
+    }
+    let rest = &data[MAGIC.len() + 2..];
+    let payload = match data[MAGIC.len() + 1] {
+        0 => { std::borrow::Cow::Borrowed(rest) }
+        1 => { std::borrow::Cow::Owned(zstd::stream::decode_all(rest).map_err(|e| format!("redis cache cell: zstd {}", e))?) }
+        2 => { std::borrow::Cow::Owned(lz4_flex::decompress_size_prepended(rest).map_err(|e| format!("redis cache cell: lz4 {}", e))?) }
+        other => { return Err(format!("redis cache cell: unknown compression {}", other).into()); }
+    };
+    let mut reader = Reader { data: &payload };
+    let status = StatusCode::from_u16(u16::from_be_bytes(reader.array()?)).map_err(|_| "redis cache cell: bad status")?;
+    let version = match reader.array::<1>()?[0] {
+        0 => { Version::HTTP_09 }
+        1 => { Version::HTTP_10 }
+        3 => { Version::HTTP_2 }
+        4 => { Version::HTTP_3 }
+        _ => { Version::HTTP_11 }
+    };
+    let count = u32::from_be_bytes(reader.array()?);
+    let mut headers = HeaderMap::new();
+    for _ in 0..count {
+        let len = u16::from_be_bytes(reader.array()?) as usize;
+        let name = HeaderName::from_bytes(reader.take(len)?).map_err(|_| "redis cache cell: bad header name")?;
+        let len = u32::from_be_bytes(reader.array()?) as usize;
+        let value = HeaderValue::from_bytes(reader.take(len)?).map_err(|_| "redis cache cell: bad header value")?;
+        headers.append(name, value);
+    }
+    let body = Bytes::copy_from_slice(reader.data);
+    Ok(HttpCacheCell {
+        header_cache: headers,
+        status_cache: status,
+        version_cache: version,
+        body_cache: body,
+    })
+}
+struct Reader<'a> {
+    data: &'a [u8],
+}
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> RResult<&'a [u8]> {
+        if self.data.len() < len {
+            return Err("redis cache cell: truncated".into());
+        }
+        let (ret, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(ret)
+    }
+    fn array<const N: usize>(&mut self) -> RResult<[u8; N]> {
+        let mut ret = [0u8; N];
+        ret.copy_from_slice(self.take(N)?);
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::r#const::REDIS_CACHE_COMPRESS_MIN_SIZE;
+
+    fn cell(body: Vec<u8>) -> HttpCacheCell {
+        let mut headers = HeaderMap::new();
+        headers.insert("content-type", HeaderValue::from_static("text/plain"));
+        headers.append("x-multi", HeaderValue::from_static("a"));
+        headers.append("x-multi", HeaderValue::from_static("b"));
+        HttpCacheCell {
+            header_cache: headers,
+            status_cache: StatusCode::CREATED,
+            version_cache: Version::HTTP_2,
+            body_cache: Bytes::from(body),
+        }
+    }
+    fn assert_same(a: &HttpCacheCell, b: &HttpCacheCell) {
+        assert_eq!(a.status_cache, b.status_cache);
+        assert_eq!(a.version_cache, b.version_cache);
+        assert_eq!(a.header_cache, b.header_cache);
+        assert_eq!(a.body_cache, b.body_cache);
+    }
+    fn cell_compressed(compression: Compression) -> Vec<u8> {
+        let data = encode(&cell(vec![b'a'; REDIS_CACHE_COMPRESS_MIN_SIZE * 4]), compression);
+        assert_ne!(data[4], 0);
+        data
+    }
+    /* xorshift, nothing compresses it */
+    fn noise(len: usize) -> Vec<u8> {
+        let mut x = 0x2545f491u32;
+        (0..len).map(|_| {
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            x as u8
+        }).collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let body = "hello world ".repeat(REDIS_CACHE_COMPRESS_MIN_SIZE).into_bytes();
+        for (compression, flag) in [(Compression::None, 0), (Compression::Zstd(3), 1), (Compression::Lz4, 2)] {
+            let cell = cell(body.clone());
+            let data = encode(&cell, compression);
+            assert_eq!(&data[..4], b"RWC\x01");
+            assert_eq!(data[4], flag);
+            if flag != 0 {
+                assert!(data.len() < body.len());
+            }
+            assert_same(&decode(&data).unwrap(), &cell);
+        }
+    }
+    #[test]
+    fn small_or_incompressible() {
+        /* below the min size, or bigger once compressed, the cell is kept as it is */
+        for body in [b"small".to_vec(), Vec::new(), noise(4096)] {
+            let cell = cell(body);
+            for compression in [Compression::Zstd(3), Compression::Lz4] {
+                let data = encode(&cell, compression);
+                assert_eq!(data[4], 0);
+                assert_same(&decode(&data).unwrap(), &cell);
+            }
+        }
+    }
+    #[test]
+    fn truncated() {
+        let cell = cell(b"body".to_vec());
+        let data = encode(&cell, Compression::None);
+        /* the body runs to the end, cut into the headers instead */
+        let body_start = data.len() - 4;
+        for len in 0..body_start {
+            assert!(decode(&data[..len]).is_err(), "{}", len);
+        }
+        assert_eq!(decode(&data[..body_start]).unwrap().body_cache, Bytes::new());
+        let big = cell_compressed(Compression::Lz4);
+        assert!(decode(&big[..big.len() - 10]).is_err());
+        let big = cell_compressed(Compression::Zstd(3));
+        assert!(decode(&big[..big.len() - 10]).is_err());
+    }
+    #[test]
+    fn bad_header() {
+        let mut data = encode(&cell(Vec::new()), Compression::None);
+        assert!(decode(b"RWC\x02\x00").is_err());
+        assert!(decode(b"RWC\x01\x09").is_err());
+        assert!(decode(b"XYZ\x01\x00").is_err());
+        /* the first byte of the first header name */
+        data[5 + 2 + 1 + 4 + 2] = b' ';
+        assert!(decode(&data).is_err());
+    }
+    #[test]
+    fn legacy_json() {
+        let cell = cell(b"body".to_vec());
+        let data = serde_json::to_vec(&cell).unwrap();
+        let decoded = decode(&data).unwrap();
+        /* the json kept one value per header and the status, not the version 2 */
+        assert_eq!(decoded.status_cache, StatusCode::CREATED);
+        assert_eq!(decoded.body_cache, cell.body_cache);
+        assert_eq!(decoded.header_cache.get("content-type").unwrap(), "text/plain");
+        let empty = decode(br#"{"status":"204","version":"HTTP/1.1","header":"{\"a\":\"1\"}","body":""}"#).unwrap();
+        assert_eq!(empty.body_cache, Bytes::new());
+        /* garbage is an error, not a panic */
+        for bad in [
+            r#"{"status":"999x","version":"HTTP/1.1","header":"{}","body":"1"}"#,
+            r#"{"status":"200","version":"HTTP/1.1","header":"not json","body":"1"}"#,
+            r#"{"status":"200","version":"HTTP/1.1","header":"{\"a b\":\"1\"}","body":"1"}"#,
+            r#"{"status":"200","version":"HTTP/1.1","header":"{\"a\":\"\\n\"}","body":"1"}"#,
+            r#"{"status":"200","version":"HTTP/1.1","header":"{}","body":"1,300"}"#,
+            r#"{"status":"200"}"#,
+        ] {
+            assert!(decode(bad.as_bytes()).is_err(), "{}", bad);
+        }
+    }
+}
@@ -10,9 +10,7 @@ use crate::{
     context::ContextType, 
     error::{GatewayError, PipeError, PipeErrorKind, RResult}, 
     modules::{
-        cache::{http_cache_cell::{
-            HttpCacheCell, HttpCacheShared
-        }, CacheProfile}, ModuleType, PipeData, PipeModule
+        cache::{http_cache_cell::HttpCacheShared, http_cache_policy, redis::redis_cell, CacheProfile}, ModuleType, PipeData, PipeModule
     }
 };
 
@@ -28,27 +26,36 @@ impl PipeModule for RedisCacheGet {
                 let profile_read_lock = profile.read().await;
                 let is_count = if profile_read_lock.hit == -1 { false } else { true };
                 let expire = if profile_read_lock.expire.as_millis() == 0 { None } else { Some(profile_read_lock.expire.as_millis()) };
-                let cell = if let Some(redis) = &profile_read_lock.redis {
-                    let key = http_context.request_context.uri.to_string();
-                    let cell = redis.get(key.clone(), is_count).await?;
-                    if &cell.0 == "" {
+                if !profile_read_lock.policy.is_cacheable_method(&http_context.request_context.method) {
+                    return Ok(());
+                }
+                let cell = if let Some(store) = &profile_read_lock.redis {
+                    let redis = &store.redis;
+                    let key = store.entry_key(ctx.route.as_deref(), &profile_read_lock.policy.key.render(&http_context.request_context));
+                    let vary = http_cache_policy::parse_vary(&redis.get::<String>(http_cache_policy::vary_key(&key), false).await?.0);
+                    let key = http_cache_policy::variant_key(&key, &vary, &http_context.request_context.headers);
+                    let cell = redis.get::<Vec<u8>>(key.clone(), is_count).await?;
+                    if cell.0.is_empty() {
                         None
                     } else {
                         if is_count {
                             if cell.1 >= profile_read_lock.hit {
-                                redis.del(http_context.request_context.uri.to_string(), is_count).await?;
+                                redis.del(key.clone(), is_count).await?;
                             } else {
                                 redis.update_count(
-                                    http_context.request_context.uri.to_string(), 
+                                    key.clone(), 
                                     expire,
                                     cell.1 + 1).await?;
                             }
                         }
-                        redis.update_expire(key, expire).await?;
-                        // let start = std::time::Instant::now();
-                        let ret = serde_json::from_str::<HttpCacheCell>(&cell.0)?;
-                        // println!("redis_get::convert_to_obj:{:#?}ms", start.elapsed().as_millis());
-                        Some(ret)
+                        redis.update_expire(key.clone(), expire).await?;
+                        match redis_cell::decode(&cell.0) {
+                            Ok(ret) => { Some(ret) }
+                            Err(e) => {
+                                log::warn!("redis cache {:?} unreadable, treated as a miss:{:?}", key, e);
+                                None
+                            }
+                        }
                     }
                 } else { return Err(gateway_err!(PipeExecuteError,"Pipe Execute Error Redis Get Error",PipeError::new(PipeErrorKind::REDIS))); };
                 if let Some(value) = cell {
@@ -10,10 +10,7 @@ use crate::{
     context::ContextType, 
     error::RResult, 
     modules::{
-        cache::{http_cache_cell::{
-            HttpCacheKey, 
-            HttpCacheShared
-        }, CacheProfile}, ModuleType, PipeData, PipeModule
+        cache::{http_cache_cell::HttpCacheShared, http_cache_policy, http_cache_purge, redis::redis_cell, CacheProfile}, ModuleType, PipeData, PipeModule
     }
 };
 
@@ -28,19 +25,35 @@ impl PipeModule for RedisCacheSet {
     async fn execute(&self, ctx: &mut crate::context::GatewayContext, pipe_data: &crate::modules::PipeData) -> RResult<()>  {
         if let PipeData::RedisCacheSetData { profile } = pipe_data {
             if let ContextType::HttpContext(http_context) = &mut ctx.context_type {
+                let profile_read_lock = profile.read().await;
+                let response = &http_context.response_context;
+                let expire = match profile_read_lock.policy.store_for(&http_context.request_context, response.status, &response.headers, profile_read_lock.expire) {
+                    Some(expire) => { expire.map(|e| e.as_millis()) }
+                    None => { return Ok(()); }
+                };
+                let vary = match http_cache_policy::vary_names(&response.headers) {
+                    Some(vary) => { vary }
+                    None => { return Ok(()); }
+                };
                 let cell = HttpCacheShared::memory_cache_value(
                     http_context.response_context.status, 
                     http_context.response_context.version, 
                     http_context.response_context.headers.clone(), 
                     http_context.response_context.body.clone()
                 );
-                let profile_read_lock = profile.read().await;
-                if let Some(redis) = &profile_read_lock.redis {
+                if let Some(store) = &profile_read_lock.redis {
+                    let redis = &store.redis;
                     let count = if profile_read_lock.hit == -1 { None } else { Some(profile_read_lock.hit) };
-                    let expire = if profile_read_lock.expire.as_millis() == 0 { None } else { Some(profile_read_lock.expire.as_millis()) };
+                    let key = store.entry_key(ctx.route.as_deref(), &profile_read_lock.policy.key.render(&http_context.request_context));
+                    let vary_names = vary.iter().map(|n| n.as_str()).collect::<Vec<_>>().join(",");
+                    redis.set(http_cache_policy::vary_key(&key), vary_names, expire, None).await?;
+                    redis.set(http_cache_purge::url_key(&key), http_cache_purge::request_url(&http_context.request_context), expire, None).await?;
+                    for tag in http_cache_purge::surrogate_keys(&http_context.response_context.headers) {
+                        redis.add_member(&redis.prefixed(&http_cache_purge::tag_key(&tag)), &key, expire).await?;
+                    }
                     redis.set(
-                        http_context.request_context.uri.to_string(), 
-                        serde_json::to_string(&cell).unwrap(), 
+                        http_cache_policy::variant_key(&key, &vary, &http_context.request_context.headers), 
+                        redis_cell::encode(&cell, store.compression), 
                         expire, 
                         count,
                     ).await?;
@@ -0,0 +1,66 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public
+License, v. 2.0. If a copy of the MPL was not distributed with this
+file, You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+use http::{header, Response, StatusCode};
+use http_body_util::Full;
+
+use crate::{
+    context::ContextType, error::{
+        GatewayError, PipeError, PipeErrorKind, RResult
+    }, modules::{
+        ModuleType, PipeData, PipeModule
+    }
+};
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ConcurrencyLimitModule {}
+impl PipeModule for ConcurrencyLimitModule {
+    fn name(&self) -> ModuleType {
+        ModuleType::ConcurrencyLimit
+    }
+
+    async fn execute(&self, ctx: &mut crate::context::GatewayContext, pipe_data: &crate::modules::PipeData) -> RResult<()> {
+        if let PipeData::ConcurrencyLimitData { profile } = pipe_data {
+            let limit = profile.read().await.limit.clone();
+            if let ContextType::TcpContext(tcp_context) = &ctx.context_type {
+                if tcp_context.in_data_buf.as_ref().is_some_and(|d| d.len() == 0) {
+                    /* the client closed the session, give the place back now and not when the host_point closes too */
+                    ctx.concurrency_permits.lock().unwrap().remove(&limit.id);
+                    return Ok(());
+                }
+            }
+            /* the pipe_line of a tcp route runs for each data of the session, the session got its place with the first one */
+            if ctx.concurrency_permits.lock().unwrap().contains_key(&limit.id) {
+                return Ok(());
+            }
+            match limit.acquire(&ctx.real_ip).await {
+                Some(permit) => {
+                    /* given back when the context is dropped, i.e. the response is done or the tcp session is closed */
+                    ctx.concurrency_permits.lock().unwrap().insert(limit.id, permit);
+                }
+                None => {
+                    log::warn!("concurrency limit arrival, route:{:?} type:{:?} max:{} client:{}", ctx.route, limit.concurrency_type, limit.max, ctx.real_ip);
+                    match &mut ctx.context_type {
+                        ContextType::HttpContext(http_context) => {
+                            let (status, version, mut headers, body) = crate::common::four_and_four_page::response_page(StatusCode::SERVICE_UNAVAILABLE);
+                            headers.insert(header::RETRY_AFTER, limit.retry_after.as_secs().max(1).into());
+                            let mut response = Response::new(Full::new(body));
+                            *response.status_mut() = status;
+                            *response.version_mut() = version;
+                            *response.headers_mut() = headers;
+                            http_context.return_context.response = Some(response);
+                        }
+                        ContextType::TcpContext(_) => {
+                            return Err(gateway_err!(ConcurrencyLimitArrival, "concurrency limit arrival", PipeError::new(PipeErrorKind::CONCURRENCYLIMIT)));
+                        }
+                    }
+                }
+            }
+            return Ok(());
+        }
+        unreachable!()
+    }
+}
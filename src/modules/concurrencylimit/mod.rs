@@ -0,0 +1,104 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public
+License, v. 2.0. If a copy of the MPL was not distributed with this
+file, You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+use std::{net::IpAddr, sync::{atomic::{AtomicUsize, Ordering}, Arc}, time::Duration};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use uuid::Uuid;
+
+pub(crate) mod concurrency_limit;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ConcurrencyLimitType {
+    Route,      //the requests of the route
+    Ip,         //the requests of each client of the route
+    Hosts,      //the requests sent to the hosts group, by every route
+}
+impl ConcurrencyLimitType {
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        match s {
+            "route" => Some(ConcurrencyLimitType::Route),
+            "ip" => Some(ConcurrencyLimitType::Ip),
+            "hosts" => Some(ConcurrencyLimitType::Hosts),
+            _ => None,
+        }
+    }
+}
+/* the requests in flight and the ones waiting for a place */
+#[derive(Debug)]
+struct Gate {
+    semaphore: Arc<Semaphore>,
+    waiting: AtomicUsize,
+}
+impl Gate {
+    fn new(max: usize) -> Self {
+        Self { semaphore: Arc::new(Semaphore::new(max)), waiting: AtomicUsize::new(0) }
+    }
+}
+#[derive(Debug)]
+pub(crate) struct ConcurrencyLimit {
+    pub(crate) id: Uuid,    //a tcp session keeps its permit under this id for all its data
+    pub(crate) concurrency_type: ConcurrencyLimitType,
+    pub(crate) max: usize,
+    pub(crate) queue: usize,
+    pub(crate) queue_timeout: Duration,
+    pub(crate) retry_after: Duration,
+    gate: Gate,
+    ip_gates: dashmap::DashMap<IpAddr, Arc<Gate>>,
+}
+impl ConcurrencyLimit {
+    pub(crate) fn new(concurrency_type: ConcurrencyLimitType, max: usize, queue: usize, queue_timeout: Duration, retry_after: Duration) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            concurrency_type,
+            max,
+            queue,
+            queue_timeout,
+            retry_after,
+            gate: Gate::new(max),
+            ip_gates: dashmap::DashMap::new(),
+        }
+    }
+    /* None when the limit is reached and the queue is full, or the wait was longer than queue_timeout */
+    pub(crate) async fn acquire(&self, ip: &IpAddr) -> Option<OwnedSemaphorePermit> {
+        match self.concurrency_type {
+            ConcurrencyLimitType::Ip => {
+                let gate = self.ip_gates.entry(*ip).or_insert_with(|| Arc::new(Gate::new(self.max))).clone();
+                let permit = self.acquire_gate(&gate).await;
+                if self.ip_gates.len() > crate::util::r#const::CONCURRENCY_LIMIT_IP_GATES {
+                    /* nobody holds or waits on a gate that is only referenced by the map */
+                    self.ip_gates.retain(|_, gate| Arc::strong_count(gate) > 1 || Arc::strong_count(&gate.semaphore) > 1);
+                }
+                permit
+            }
+            _ => {
+                self.acquire_gate(&self.gate).await
+            }
+        }
+    }
+    async fn acquire_gate(&self, gate: &Gate) -> Option<OwnedSemaphorePermit> {
+        if let Ok(permit) = gate.semaphore.clone().try_acquire_owned() {
+            return Some(permit);
+        }
+        if gate.waiting.fetch_add(1, Ordering::SeqCst) >= self.queue {
+            gate.waiting.fetch_sub(1, Ordering::SeqCst);
+            return None;
+        }
+        let ret = tokio::time::timeout(self.queue_timeout, gate.semaphore.clone().acquire_owned()).await;
+        gate.waiting.fetch_sub(1, Ordering::SeqCst);
+        match ret {
+            Ok(Ok(permit)) => Some(permit),
+            _ => None,
+        }
+    }
+    pub(crate) fn same_settings(&self, other: &ConcurrencyLimit) -> bool {
+        self.max == other.max && self.queue == other.queue && self.queue_timeout == other.queue_timeout && self.retry_after == other.retry_after
+    }
+}
+#[derive(Debug)]
+pub(crate) struct ConcurrencyLimitProfile {
+    pub(crate) limit: Arc<ConcurrencyLimit>,
+}
@@ -33,18 +33,15 @@ impl PipeModule for FileServerDispatche {
     async fn execute(&self, ctx: &mut GatewayContext, pipe_data: &crate::modules::PipeData) -> RResult<()>  {
         if let PipeData::FileServerDispatcheData { profile } = pipe_data {
             let profile_read_lock = profile.read().await;
-            if let DispatcheProfile::File { file_system, content_type, } = &*profile_read_lock {
-                let base_path = file_system.base_path().to_string();
+            if let DispatcheProfile::File { file_system, content_type, precompressed } = &*profile_read_lock {
                 match ctx.context_type {
                     ContextType::HttpContext(ref mut http_context) => {
-                        let mut file_path = base_path + http_context.request_context.uri.path();
-                        if file_path.ends_with("/") {
-                            file_path = file_path[0..file_path.len()-1].to_string();
-                        } 
                         let http_file = HttpFile::file_response_structure(
-                            &file_path,
+                            http_context.request_context.uri.path(),
                             &file_system,
                             content_type,
+                            *precompressed,
+                            &http_context.request_context.method,
                             &http_context.request_context.headers,
                         ).await?;
                         let (status_code, headers, bytes) = http_file.to_http_response().await;
@@ -22,6 +22,7 @@ pub(crate) enum DispatcheProfile {
     File {
         file_system: FileSystem,
         content_type: ContentTypeAndExtension,
+        precompressed: bool,
     },
     Network {
         path: Option<String>,
@@ -31,7 +32,7 @@ pub(crate) enum DispatcheProfile {
 }
 
 #[inline(always)]
-async fn load_balance(ctx: &mut crate::context::GatewayContext, hosts: &crate::instance::hosts::Hosts, pipe_task: &PipeTask) -> crate::error::RResult<()> {
+pub(crate) async fn load_balance(ctx: &mut crate::context::GatewayContext, hosts: &crate::instance::hosts::Hosts, pipe_task: &PipeTask) -> crate::error::RResult<()> {
     match pipe_task.types {
         super::ModuleType::IpMatchRoundRobinLB => {
             hosts.modules.IpMatchRoundRobinLB(ctx, &pipe_task.pipe_data).await?;   // execute load_balance
@@ -58,6 +59,11 @@ pub(crate) async fn host_point_exception_handing(
         let mut map_write_lock = hosts.write().await;
         let host = map_write_lock.get_mut(&ph);
         if let Some(h) = host {
+            if h.circuit.on_failure(false) {  // the circuit breaker decides to eject the host_point
+                drop(map_write_lock);
+                fail_out_event(hosts, permanent_failure, ph, false).await;
+                return Ok(None);
+            }
             if let Some(_) = h.max_fails {
                 match h.max_fails_durn.check_key(&crate::util::r#const::MAX_FAILS_DURN_CHECK_KEY) {
                     Ok(_) => {
@@ -65,7 +71,7 @@ pub(crate) async fn host_point_exception_handing(
                     }
                     Err(_) => {
                         drop(map_write_lock);
-                        fail_out_event(hosts, permanent_failure, ph).await;
+                        fail_out_event(hosts, permanent_failure, ph, true).await;
                     }
                 }
             } else {
@@ -75,10 +81,35 @@ pub(crate) async fn host_point_exception_handing(
     }
     Ok(None)
 }
+/* Report the status of the response to the circuit breaker of the host_point. 5xx is regarded as a failure. */
+pub(crate) async fn host_point_status_handing(
+    hosts: &Arc<tokio::sync::RwLock<HashMap<u16, Host>>>, 
+    permanent_failure: &Arc<tokio::sync::RwLock<HashMap<u16, Host>>>, 
+    previous_host: Option<u16>,
+    status: &http::StatusCode) {
+    if let Some(ph) = previous_host {
+        let map_read_lock = hosts.read().await;
+        if let Some(h) = map_read_lock.get(&ph) {
+            if status.is_server_error() {
+                if h.circuit.on_failure(true) {
+                    drop(map_read_lock);
+                    fail_out_event(hosts, permanent_failure, ph, false).await;
+                }
+            } else {
+                h.circuit.on_success();
+            }
+        }
+    }
+}
+/*
+permanent: the host_point without fail_timeout is moved to permanent_failure. 
+Otherwise it is ejected for a while and then comes back in half-open, where only a few trial requests are let through.
+ */
 pub(crate) async fn fail_out_event(
     hosts: &Arc<tokio::sync::RwLock<HashMap<u16, Host>>>, 
     permanent_failure: &Arc<tokio::sync::RwLock<HashMap<u16, Host>>>, 
-    index: u16) {
+    index: u16,
+    permanent: bool) {
     let mut hosts_write_lock = hosts.write().await;
     //first: clear the host;
     let host = match hosts_write_lock.remove(&index) {  
@@ -88,17 +119,26 @@ pub(crate) async fn fail_out_event(
     drop(hosts_write_lock);
     //second: final clear task
     let hosts_clone = hosts.clone();
-    if let Some(fail_timeout) = host.fail_timeout {
+    let fail_timeout = match host.fail_timeout {
+        Some(fail_timeout) => { Some(fail_timeout) }
+        None if !permanent => { Some(crate::util::r#const::BREAKER_EJECTION_TIME) }
+        None => { None }
+    };
+    if let Some(fail_timeout) = fail_timeout {
+        let ejection_time = host.circuit.open(fail_timeout);
+        log::info!("fail_out_event host:{}:{} ejected for {:?}", host.host, host.port, ejection_time);
         tokio::spawn(async move {
-            tokio::time::sleep(fail_timeout).await;
+            tokio::time::sleep(ejection_time).await;
             log::info!("fail_out_event running...{:#?}", host);
+            host.circuit.half_open();
             let mut hosts_write_lock = hosts_clone.write().await;
             hosts_write_lock.insert(index, host);
             drop(hosts_write_lock);
         });
     } else {
+        host.circuit.open(std::time::Duration::from_millis(0));
         let mut permanent_failure_write_lock = permanent_failure.write().await;
         permanent_failure_write_lock.insert(index, host);
         drop(permanent_failure_write_lock);
     }
-}
\ No newline at end of file
+}
@@ -4,14 +4,14 @@ License, v. 2.0. If a copy of the MPL was not distributed with this
 file, You can obtain one at https://mozilla.org/MPL/2.0/.
 */
 
-use std::{error::Error, net::SocketAddr};
+use std::{error::Error, net::SocketAddr, sync::Arc};
 
 use hyper::{http::Request, body::Bytes};
 use http_body_util::Full;
 use hyper::http::Uri;
 
 use crate::{
-    client::{ClientProvider, RequestContent, ResponseContent}, common::http_file::HttpFile, context::{tcp_context, ContextType}, error::{GatewayError, PipeError, PipeErrorKind, RResult}, instance::errors::{ErrModule, ErrTypes, ReturnTypes}, modules::{ModuleType, PipeData, PipeModule}, util::uri_util
+    client::{ClientProvider, RequestContent, ResponseContent}, common::http_file::HttpFile, context::{http_context::HttpContext, tcp_context, ContextType}, error::{GatewayError, PipeError, PipeErrorKind, RResult}, instance::{errors::{ErrModule, ErrTypes, Errs, ReturnTypes}, retry::Retry}, modules::{cache::http_cache_cell::CacheStale, ModuleType, PipeData, PipeModule}, util::uri_util
 };
 
 use super::DispatcheProfile;
@@ -24,8 +24,31 @@ impl PipeModule for NetworkDispatche {
     fn name(&self) -> ModuleType {
         ModuleType::DispatchNetwork
     }
-    
+
     async fn execute(&self, ctx: &mut crate::context::GatewayContext, pipe_data: &crate::modules::PipeData) -> RResult<()>  {
+        let dispatched = self.dispatch(ctx, pipe_data).await;
+        /* stale-if-error: the cache had an expired copy, better than an error */
+        if let ContextType::HttpContext(ref mut http_context) = ctx.context_type {
+            if let CacheStale::IfError(cell) = std::mem::take(&mut http_context.cache_stale) {
+                if dispatched.is_err() || http_context.response_context.status.is_server_error() {
+                    log::warn!("upstream failed, route:{:?} status:{} gives the stale copy of the cache", ctx.route, http_context.response_context.status);
+                    let (headers, version, status, body) = cell.to_origin();
+                    http_context.response_context.status = status;
+                    http_context.response_context.version = version;
+                    http_context.response_context.headers = headers;
+                    http_context.response_context.body = body;
+                    http_context.response_context.refresh();
+                    http_context.cache_hit = true;
+                    http_context.cache_stale = CacheStale::Served(None);
+                    return Ok(());
+                }
+            }
+        }
+        dispatched
+    }
+}
+impl NetworkDispatche {
+    async fn dispatch(&self, ctx: &mut crate::context::GatewayContext, pipe_data: &crate::modules::PipeData) -> RResult<()>  {
         if let PipeData::NetworkDispatcheData { profile } = pipe_data {
             let profile_read_lock = profile.read().await;
             /* first: match whether it is network or file */
@@ -34,17 +57,64 @@ impl PipeModule for NetworkDispatche {
                 /* second: check if the specified host has been found */
                 match gateway_instance.hosts.get(out_host) {
                     Some(hosts) => {
+                        let route = gateway_instance.routes.get(&ctx.route.clone().unwrap()).unwrap();
                         /* If the error_handling_plan of hosts exists, choose the error_handling_plan of hosts; otherwise, choose the error_handling_plan at the route level. */
                         let error_handling_plan = if let Some(err) = &hosts.hosts_error {
                             err.clone()
                         } else {
-                            let route = gateway_instance.routes.get(&ctx.route.clone().unwrap()).unwrap();
                             route.routes_error.clone()
                         };
+                        /* The retry policy follows the same rule. Without any retry policy, pass_next keeps its old meaning: try the next host_point right away. */
+                        let retry = if let Some(r) = &hosts.hosts_retry {
+                            Some(r.clone())
+                        } else if let Some(r) = &route.routes_retry {
+                            Some(r.clone())
+                        } else {
+                            match &error_handling_plan.inner {
+                                ErrModule::HTTP(http_error_handling_plan) if http_error_handling_plan.pass_next => {
+                                    let error_list = http_error_handling_plan.error_list.iter().map(|et| {
+                                        match et { ErrTypes::Hsc(sc) => { *sc } }
+                                    }).collect::<Vec<http::StatusCode>>();
+                                    Some(Arc::new(Retry::from_pass_next(error_list)))
+                                }
+                                _ => { None }
+                            }
+                        };
+                        /* loop guard: the request has already passed through this gateway too many times */
+                        if let ContextType::HttpContext(ref mut http_context) = ctx.context_type {
+                            if route.forwarded.loop_detected(&http_context.request_context.headers) {
+                                log::error!("loop detected, route:{:?}", ctx.route);
+                                let (status, version, headers, body) = crate::common::four_and_four_page::response_page(http::StatusCode::LOOP_DETECTED);
+                                http_context.response_context.status = status;
+                                http_context.response_context.version = version;
+                                http_context.response_context.headers = headers;
+                                http_context.response_context.body = body;
+                                http_context.response_context.refresh();
+                                return Ok(());
+                            }
+                        }
+                        let mut attempt: u32 = 0;
+                        /* Ok: the last attempt got an error status, the response is kept in the response_context. Err: the last attempt failed on the network. */
+                        let mut last_failure: Option<RResult<()>> = None;
+                        ctx.redirect_context.tried_hosts.clear();
                         loop {
                             /* third: start routing and selecting the target host through the specified host */
                             crate::modules::dispatche::load_balance(ctx, hosts, hosts.lb_task.as_ref()).await?;
-                            if ctx.redirect_context.host.is_none() {// If no available hosts are found, start the error_handling_plan process.
+                            if ctx.redirect_context.host.is_none() {
+                                if let Some(last) = last_failure.take() {  // Every host_point has been tried, give back the last failure.
+                                    match last {
+                                        Ok(_) => {
+                                            if let ContextType::HttpContext(ref mut http_context) = ctx.context_type {
+                                                error_plan_return(http_context, &error_handling_plan);
+                                            }
+                                            return Ok(());
+                                        }
+                                        Err(e) => {
+                                            return Err(e);
+                                        }
+                                    }
+                                }
+                                // If no available hosts are found, start the error_handling_plan process.
                                 if let ErrModule::HTTP(http_error_handling_plan) = &error_handling_plan.inner {
                                     match http_error_handling_plan.r#return {
                                         ReturnTypes::Origin => { return Err(gateway_err!(NoAvailableHostsError, "no available host", PipeError::new(PipeErrorKind::NOAVAILABLEHOSTS))); }
@@ -69,192 +139,99 @@ impl PipeModule for NetworkDispatche {
                                     return Err(gateway_err!(NoAvailableHostsError, "no available host", PipeError::new(PipeErrorKind::NOAVAILABLEHOSTS)));
                                 }
                             }
+                            if let Some(ph) = ctx.redirect_context.previous_host {
+                                ctx.redirect_context.tried_hosts.push(ph);
+                            }
+                            attempt += 1;
                             let timeout = ctx.redirect_context.timeout.unwrap_or_else(|| crate::util::r#const::DEFAULT_OUT_TIMEOUT);
                             match ctx.context_type {
                                 ContextType::HttpContext(ref mut http_context) => {
-                                    match *&client {
-                                        ClientProvider::Http { client_handler } => {
-                                            let scheme = "http";
-                                            let host = ctx.redirect_context.host.clone().expect("not found host");
-                                            let host = (*host).clone();
-                                            let port = ctx.redirect_context.port.clone().expect("not found port");
-                                            let port = (*port).clone();
-                                            let uri_path = http_context.request_context.uri.path();
-                                            let uri: Uri = uri_util::assemble_uri(scheme, host.clone(), port, path.clone().unwrap_or("".to_string()), uri_path, http_context.request_context.parameters.clone());
-                                            let extensions = http_context.request_context.extensions.clone();
-                                            let version = http_context.request_context.version.clone();
-                                            let method = http_context.request_context.method.clone();
-                                            let mut headers = http_context.request_context.headers.clone();
-                                            headers.insert(hyper::header::HOST, host.parse().unwrap());
-                                            let body = http_context.request_context.body.clone();
-                                            let mut request = Request::new(Full::new(body));
-                                            *request.headers_mut() = headers;
-                                            *request.uri_mut() = uri;
-                                            *request.extensions_mut() = extensions;
-                                            *request.version_mut() = version;
-                                            *request.method_mut() = method;
-                                            match client_handler.send(RequestContent::Http(request), timeout).await {
-                                                Ok(ResponseContent::Http((status, version, headers, body_bytes))) => {
-                                                    http_context.response_context.status = status;
-                                                    http_context.response_context.version = version;
-                                                    http_context.response_context.headers = headers;
-                                                    if let Some(body_bytes) = body_bytes {
-                                                        http_context.response_context.body = body_bytes;
-                                                    } else {
-                                                        http_context.response_context.body = Bytes::new();
-                                                    }
-                                                    http_context.response_context.refresh();
-                                                }
-                                                Ok(_) => {
-                                                    unreachable!("http response error");
-                                                }
-                                                Err(e) => {
-                                                    let hosts = ctx.redirect_context.hosts.clone().unwrap();
-                                                    let permanent_failure = ctx.redirect_context.permanent_failure.clone().unwrap();
-                                                    let ret: Option<(std::sync::Arc<String>, std::sync::Arc<u16>, Option<std::time::Duration>, u16)> 
-                                                        = crate::modules::dispatche::host_point_exception_handing(
-                                                            &hosts, 
-                                                            &permanent_failure, 
-                                                            ctx.redirect_context.previous_host,
-                                                            &e,
-                                                        ).await?;
-                                                    if let ErrModule::HTTP(http_error_handling_plan) = &error_handling_plan.inner {
-                                                        if http_error_handling_plan.pass_next {
-                                                            match e {
-                                                                GatewayError::PipeExecuteTimeoutError { .. } => {
-                                                                    log::error!("PipeExecuteError timeout continue:{:#?}", e);
-                                                                    continue;
-                                                                }
-                                                                _ => {
-                                                                    log::error!("PipeExecuteError other continue:{:#?}", e);
-                                                                    continue;
-                                                                }
-                                                            }
-                                                        }
-                                                    }
-                                                    return Err(e);
-                                                }
+                                    let scheme = match *&client {
+                                        ClientProvider::Http { .. } => { "http" }
+                                        ClientProvider::Https { .. } => { "https" }
+                                        ClientProvider::Tcp { .. } => { unreachable!() }
+                                    };
+                                    let host = ctx.redirect_context.host.clone().expect("not found host");
+                                    let host = (*host).clone();
+                                    let port = ctx.redirect_context.port.clone().expect("not found port");
+                                    let port = (*port).clone();
+                                    let uri_path = http_context.request_context.uri.path();
+                                    let uri: Uri = uri_util::assemble_uri(scheme, host.clone(), port, path.clone().unwrap_or("".to_string()), uri_path, http_context.request_context.parameters.clone());
+                                    let extensions = http_context.request_context.extensions.clone();
+                                    let version = http_context.request_context.version.clone();
+                                    let method = http_context.request_context.method.clone();
+                                    let mut headers = http_context.request_context.headers.clone();
+                                    let authority = match http_context.request_context.headers.get(hyper::header::HOST).and_then(|h| h.to_str().ok()) {
+                                        Some(h) => { Some(h.to_string()) }
+                                        None => { http_context.request_context.uri.authority().map(|a| a.to_string()) }
+                                    };
+                                    route.forwarded.apply(&mut headers, &ctx.remote_addr.ip(), &http_context.request_context.scheme, &version, authority, &host);
+                                    let body = http_context.request_context.body.clone();
+                                    let mut request = Request::new(Full::new(body));
+                                    *request.headers_mut() = headers;
+                                    *request.uri_mut() = uri;
+                                    *request.extensions_mut() = extensions;
+                                    *request.version_mut() = version;
+                                    *request.method_mut() = method.clone();
+                                    /* whether another attempt is still allowed after this one */
+                                    let retryable = match &retry {
+                                        Some(r) => { attempt < r.attempts && r.allow_method(&method) }
+                                        None => { false }
+                                    };
+                                    match client.send(RequestContent::Http(request), timeout).await {
+                                        Ok(ResponseContent::Http((status, version, headers, body_bytes))) => {
+                                            http_context.response_context.status = status;
+                                            http_context.response_context.version = version;
+                                            http_context.response_context.headers = headers;
+                                            if let Some(body_bytes) = body_bytes {
+                                                http_context.response_context.body = body_bytes;
+                                            } else {
+                                                http_context.response_context.body = Bytes::new();
                                             }
-                                            if let ErrModule::HTTP(http_error_handling_plan) = &error_handling_plan.inner {
-                                                for et in &http_error_handling_plan.error_list {
-                                                    match et {
-                                                        ErrTypes::Hsc(status_code) => {
-                                                            if status_code == &http_context.response_context.status {   // error detected
-                                                                if http_error_handling_plan.pass_next {
-                                                                    continue;
-                                                                } else {
-                                                                    match http_error_handling_plan.r#return {
-                                                                        ReturnTypes::Origin => {}
-                                                                        ReturnTypes::Hsc(sc) => {
-                                                                            let (status, version, headers, body) = crate::common::four_and_four_page::response_page(sc);
-                                                                            http_context.response_context.status = status;
-                                                                            http_context.response_context.version = version;
-                                                                            http_context.response_context.headers = headers;
-                                                                            http_context.response_context.body = body;
-                                                                            http_context.response_context.refresh();
-                                                                        }
-                                                                    }
-                                                                }
-                                                            }
-                                                        }
-                                                    }
-                                                }
-                                            }
-                                            return Ok(());
+                                            http_context.response_context.refresh();
                                         }
-                                        ClientProvider::Https { client_handler } => {
-                                            let scheme = "https";
-                                            let host = ctx.redirect_context.host.clone().expect("not found host");
-                                            let host = (*host).clone();
-                                            let port = ctx.redirect_context.port.clone().expect("not found port");
-                                            let port = (*port).clone();
-                                            let uri_path = http_context.request_context.uri.path();
-                                            let uri: Uri = uri_util::assemble_uri(scheme, host.clone(), port, path.clone().unwrap_or("".to_string()), uri_path, http_context.request_context.parameters.clone());
-                                            let mut headers = http_context.request_context.headers.clone();
-                                            headers.insert(hyper::header::HOST, host.parse().unwrap()); 
-                                            let extensions = http_context.request_context.extensions.clone();
-                                            let version = http_context.request_context.version.clone();
-                                            let method = http_context.request_context.method.clone();
-                                            let body = http_context.request_context.body.clone();
-                                            let mut request = Request::new(Full::new(body));
-                                            *request.headers_mut() = headers;
-                                            *request.uri_mut() = uri;
-                                            *request.extensions_mut() = extensions;
-                                            *request.version_mut() = version;
-                                            *request.method_mut() = method;
-                                            match client_handler.send(RequestContent::Http(request), timeout).await {
-                                                Ok(ResponseContent::Http((status, version, headers, body_bytes))) => {
-                                                    http_context.response_context.status = status;
-                                                    http_context.response_context.version = version;
-                                                    http_context.response_context.headers = headers;
-                                                    if let Some(body_bytes) = body_bytes {
-                                                        http_context.response_context.body = body_bytes;
-                                                    } else {
-                                                        http_context.response_context.body = Bytes::new();
-                                                    }
-                                                    http_context.response_context.refresh();
-                                                }
-                                                Ok(_) => {
-                                                    unreachable!("http response error");
-                                                }
-                                                Err(e) => {
-                                                    let hosts = ctx.redirect_context.hosts.clone().unwrap();
-                                                    let permanent_failure = ctx.redirect_context.permanent_failure.clone().unwrap();
-                                                    let ret: Option<(std::sync::Arc<String>, std::sync::Arc<u16>, Option<std::time::Duration>, u16)> 
-                                                        = crate::modules::dispatche::host_point_exception_handing(
-                                                            &hosts, 
-                                                            &permanent_failure, 
-                                                            ctx.redirect_context.previous_host,
-                                                            &e,
-                                                        ).await?;
-                                                    if let ErrModule::HTTP(http_error_handling_plan) = &error_handling_plan.inner {
-                                                        if http_error_handling_plan.pass_next {
-                                                            match e {
-                                                                GatewayError::PipeExecuteTimeoutError { .. } => {
-                                                                    log::error!("PipeExecuteError timeout continue:{:#?}", e);
-                                                                    continue;
-                                                                }
-                                                                _ => {
-                                                                    log::error!("PipeExecuteError other continue:{:#?}", e);
-                                                                    continue;
-                                                                }
-                                                            }
-                                                        }
-                                                    }
-                                                    return Err(e);
-                                                }
-                                            }
-                                            if let ErrModule::HTTP(http_error_handling_plan) = &error_handling_plan.inner {
-                                                for et in &http_error_handling_plan.error_list {
-                                                    match et {
-                                                        ErrTypes::Hsc(status_code) => {
-                                                            if status_code == &http_context.response_context.status {   // error detected
-                                                                if http_error_handling_plan.pass_next {
-                                                                    continue;
-                                                                } else {
-                                                                    match http_error_handling_plan.r#return {
-                                                                        ReturnTypes::Origin => {}
-                                                                        ReturnTypes::Hsc(sc) => {
-                                                                            let (status, version, headers, body) = crate::common::four_and_four_page::response_page(sc);
-                                                                            http_context.response_context.status = status;
-                                                                            http_context.response_context.version = version;
-                                                                            http_context.response_context.headers = headers;
-                                                                            http_context.response_context.body = body;
-                                                                            http_context.response_context.refresh();
-                                                                        }
-                                                                    }
-                                                                }
-                                                            }
-                                                        }
-                                                    }
+                                        Ok(_) => {
+                                            unreachable!("http response error");
+                                        }
+                                        Err(e) => {
+                                            let hosts = ctx.redirect_context.hosts.clone().unwrap();
+                                            let permanent_failure = ctx.redirect_context.permanent_failure.clone().unwrap();
+                                            let ret: Option<(std::sync::Arc<String>, std::sync::Arc<u16>, Option<std::time::Duration>, u16)>
+                                                = crate::modules::dispatche::host_point_exception_handing(
+                                                    &hosts,
+                                                    &permanent_failure,
+                                                    ctx.redirect_context.previous_host,
+                                                    &e,
+                                                ).await?;
+                                            if let Some(r) = &retry {
+                                                if retryable && r.retry_on_error(&e) {
+                                                    log::error!("PipeExecuteError retry attempt:{} {:#?}", attempt, e);
+                                                    tokio::time::sleep(r.delay(attempt)).await;
+                                                    last_failure = Some(Err(e));
+                                                    continue;
                                                 }
                                             }
-                                            return Ok(());
+                                            return Err(e);
                                         }
-                                        ClientProvider::Tcp { .. } => {
-                                            unreachable!()
+                                    }
+                                    let hosts = ctx.redirect_context.hosts.clone().unwrap();
+                                    let permanent_failure = ctx.redirect_context.permanent_failure.clone().unwrap();
+                                    crate::modules::dispatche::host_point_status_handing(
+                                        &hosts,
+                                        &permanent_failure,
+                                        ctx.redirect_context.previous_host,
+                                        &http_context.response_context.status,
+                                    ).await;
+                                    if let Some(r) = &retry {
+                                        if retryable && r.retry_on_status(&http_context.response_context.status) {
+                                            log::error!("PipeExecuteError retry attempt:{} status:{}", attempt, http_context.response_context.status);
+                                            tokio::time::sleep(r.delay(attempt)).await;
+                                            last_failure = Some(Ok(()));
+                                            continue;
                                         }
                                     }
+                                    error_plan_return(http_context, &error_handling_plan);
+                                    return Ok(());
                                 }
                                 ContextType::TcpContext(ref mut tcp_context) => {
                                     match *&client {
@@ -281,7 +258,36 @@ impl PipeModule for NetworkDispatche {
                                             let sender = tcp_context.sender.clone();
                                             let in_tx = tcp_context.in_tx.clone();
                                             let in_rx = tcp_context.in_rx.clone();
-                                            let _ = client_handler.send(RequestContent::Tcp((addr, data_buf, sender, in_tx, in_rx)), timeout).await?;
+                                            let hosts = ctx.redirect_context.hosts.clone().unwrap();
+                                            let permanent_failure = ctx.redirect_context.permanent_failure.clone().unwrap();
+                                            let proxy_header = route.proxy_protocol.map(|version| {
+                                                crate::servers::proxy_protocol::make_header(version, &ctx.remote_addr, &tcp_context.local_addr)
+                                            });
+                                            /* a PROXY header describes one client, so such a session can't share the upstream connection of the others */
+                                            let client_handler = match proxy_header {
+                                                Some(_) => tcp_context.upstream.get_or_init(|| {
+                                                    crate::client::tcp::TcpClient { redirect: Arc::new(crate::client::tcp::sender::TcpSenderConnection::new(client_handler.redirect.buf_size)) }
+                                                }),
+                                                None => client_handler,
+                                            };
+                                            match client_handler.send(RequestContent::Tcp((addr, data_buf, sender, in_tx, in_rx, proxy_header)), timeout).await {
+                                                Ok(_) => {
+                                                    if let Some(ph) = ctx.redirect_context.previous_host {
+                                                        if let Some(h) = hosts.read().await.get(&ph) {
+                                                            h.circuit.on_success();
+                                                        }
+                                                    }
+                                                }
+                                                Err(e) => {
+                                                    crate::modules::dispatche::host_point_exception_handing(
+                                                        &hosts,
+                                                        &permanent_failure,
+                                                        ctx.redirect_context.previous_host,
+                                                        &e,
+                                                    ).await?;
+                                                    return Err(e);
+                                                }
+                                            }
                                             return Ok(());
                                         }
                                     }
@@ -299,4 +305,29 @@ impl PipeModule for NetworkDispatche {
         }
         unreachable!()
     }
-}
\ No newline at end of file
+}
+/* If the status of the response is in the error_list, replace the response according to the return of the error_handling_plan. */
+fn error_plan_return(http_context: &mut HttpContext, error_handling_plan: &Errs) {
+    if let ErrModule::HTTP(http_error_handling_plan) = &error_handling_plan.inner {
+        for et in &http_error_handling_plan.error_list {
+            match et {
+                ErrTypes::Hsc(status_code) => {
+                    if status_code == &http_context.response_context.status {   // error detected
+                        match http_error_handling_plan.r#return {
+                            ReturnTypes::Origin => {}
+                            ReturnTypes::Hsc(sc) => {
+                                let (status, version, headers, body) = crate::common::four_and_four_page::response_page(sc);
+                                http_context.response_context.status = status;
+                                http_context.response_context.version = version;
+                                http_context.response_context.headers = headers;
+                                http_context.response_context.body = body;
+                                http_context.response_context.refresh();
+                            }
+                        }
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -4,10 +4,16 @@ License, v. 2.0. If a copy of the MPL was not distributed with this
 file, You can obtain one at https://mozilla.org/MPL/2.0/.
 */
 
+use http::HeaderValue;
+
 use crate::{context::ContextType, error::RResult, modules::{ModuleType, PipeData, PipeModule}};
 
 use super::{HeaderProfile, HeaderActionKey};
 
+/* the variables of the add values, the user of basic_auth and the consumer of api_key */
+const REMOTE_USER: &str = "$remote_user";
+const CONSUMER: &str = "$consumer";
+
 #[derive(Debug, Clone, Copy)]
 pub(crate) struct HeaderRequest {}
 
@@ -27,6 +33,14 @@ impl PipeModule for HeaderRequest {
                 }
                 if let Some(value) = profile_read_lock.action.get(&HeaderActionKey::ADD) {
                     for (header_name, header_value) in value {
+                        if let Some(header_value) = template(header_value, ctx.user.as_deref(), ctx.consumer.as_deref()) {
+                            /* without a value for the variable the header is not sent, the one of the client neither */
+                            match header_value {
+                                Some(header_value) => { http_context.request_context.headers.insert(header_name, header_value); }
+                                None => { http_context.request_context.headers.remove(header_name); }
+                            }
+                            continue;
+                        }
                         http_context.request_context.headers.insert(header_name, header_value.clone());
                     }
                 }
@@ -35,4 +49,22 @@ impl PipeModule for HeaderRequest {
         }
         unreachable!()
     }
-}
\ No newline at end of file
+}
+/* None when the value has no variable, Some(None) when a variable has no value */
+fn template(header_value: &HeaderValue, user: Option<&str>, consumer: Option<&str>) -> Option<Option<HeaderValue>> {
+    let value = header_value.to_str().ok()?;
+    let variables = [(REMOTE_USER, user), (CONSUMER, consumer)];
+    if !variables.iter().any(|(name, _)| value.contains(name)) {
+        return None;
+    }
+    let mut ret = value.to_string();
+    for (name, v) in variables {
+        if value.contains(name) {
+            match v {
+                Some(v) => { ret = ret.replace(name, v); }
+                None => { return Some(None); }
+            }
+        }
+    }
+    Some(HeaderValue::from_str(&ret).ok())
+}
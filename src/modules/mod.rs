@@ -8,6 +8,8 @@ file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 pub(crate) mod blackandwhitelist;
 pub(crate) mod ratelimiter;
+pub(crate) mod concurrencylimit;
+pub(crate) mod auth;
 pub(crate) mod upgrade;
 pub(crate) mod route;
 pub(crate) mod r#return;
@@ -39,6 +41,15 @@ use self::{
             redis_get::RedisCacheGet, 
             redis_set::RedisCacheSet
         }, 
+        disk::{
+            disk_get::DiskCacheGet, 
+            disk_set::DiskCacheSet, 
+            DiskCacheProfile
+        }, 
+        cache_purge::{
+            CachePurgeModule,
+            CachePurgeProfile
+        },
         CacheProfile, 
     }, 
     dispatche::{
@@ -55,6 +66,20 @@ use self::{
         ratelimiter::RatelimiterModule, 
         RatelimiterProfile
     }, 
+    concurrencylimit::{
+        concurrency_limit::ConcurrencyLimitModule,
+        ConcurrencyLimitProfile
+    }, 
+    auth::{
+        jwt_auth::JwtAuthModule,
+        basic_auth::BasicAuthModule,
+        api_key::ApiKeyModule,
+        forward_auth::ForwardAuthModule,
+        JwtAuthProfile,
+        BasicAuthProfile,
+        ApiKeyProfile,
+        ForwardAuthProfile
+    }, 
     r#return::ReturnModule, 
     route::route::RouteModule, 
     upgrade::{
@@ -141,11 +166,19 @@ module_initial! {
     MemorySet("memory_cache_set")(MemoryCacheSetData)(MemoryCacheSet) -> (CacheProfile)
     RedisGet("redis_cache_get")(RedisCacheGetData)(RedisCacheGet) -> (CacheProfile)
     RedisSet("redis_cache_set")(RedisCacheSetData)(RedisCacheSet) -> (CacheProfile)
+    DiskGet("disk_cache_get")(DiskCacheGetData)(DiskCacheGet) -> (DiskCacheProfile)
+    DiskSet("disk_cache_set")(DiskCacheSetData)(DiskCacheSet) -> (DiskCacheProfile)
+    CachePurge("cache_purge")(CachePurgeData)(CachePurgeModule) -> (CachePurgeProfile)
     DispatchFile("dispatche_file")(FileServerDispatcheData)(FileServerDispatche) -> (DispatcheProfile)
     DispatchNetwork("dispatche_network")(NetworkDispatcheData)(NetworkDispatche) -> (DispatcheProfile)
     HeaderRequest("header_request")(HeaderRequestData)(HeaderRequest) -> (HeaderProfile)
     HeaderResponse("header_response")(HeaderResponseData)(HeaderResponse) -> (HeaderProfile)
     RateLimiter("ratelimiter")(RatelimiterModuleData)(RatelimiterModule) -> (RatelimiterProfile)
+    ConcurrencyLimit("concurrency_limit")(ConcurrencyLimitData)(ConcurrencyLimitModule) -> (ConcurrencyLimitProfile)
+    JwtAuth("jwt_auth")(JwtAuthData)(JwtAuthModule) -> (JwtAuthProfile)
+    BasicAuth("basic_auth")(BasicAuthData)(BasicAuthModule) -> (BasicAuthProfile)
+    ApiKey("api_key")(ApiKeyData)(ApiKeyModule) -> (ApiKeyProfile)
+    ForwardAuth("forward_auth")(ForwardAuthData)(ForwardAuthModule) -> (ForwardAuthProfile)
     Route("route")(RouteModuleData)(RouteModule) -> (())
     Return("return")(ReturnModuleData)(ReturnModule) -> (())
 }
@@ -183,7 +216,7 @@ impl PipeLineEngine {
                             return Ok(());
                         }
                     }
-                    ModuleType::MemorySet | ModuleType::RedisSet => {
+                    ModuleType::MemorySet | ModuleType::RedisSet | ModuleType::DiskSet => {
                         if http_context.cache_hit {
                             return Ok(());
                         }
@@ -20,6 +20,11 @@ pub(crate) enum RatelimiterType {
     IpRoute,
     IpService,
     Route(String),
+    Header(http::HeaderName),  //e.g. X-Api-Key
+    ApiKey,                    //X-Api-Key, or the query parameter api_key
+    PathMethod,                //the route (the path template) and the method
+    Claim(String),             //a claim of the jwt in Authorization: Bearer
+    Consumer,                  //the consumer of the api_key in front
 }
 impl RatelimiterType {
     pub(crate) fn new(ratelimiter_type_str: &str, route_name: String, pipe_name: String) -> RResult<Self> {
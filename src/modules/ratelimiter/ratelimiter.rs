@@ -4,16 +4,15 @@ License, v. 2.0. If a copy of the MPL was not distributed with this
 file, You can obtain one at https://mozilla.org/MPL/2.0/.
 */
 
-use bytes::Bytes;
-use http::{header, HeaderMap, StatusCode, Version};
-use package_info::PackageInfo;
+use std::time::Duration;
+
+use http::{header, Response, StatusCode};
+use http_body_util::Full;
 
 use crate::{
-    context::ContextType, error::{
-        GatewayError, PipeError, PipeErrorKind, RResult
-    }, modules::{
+    common::ratelimiter::RatelimiterState, context::{ContextType, http_context::HttpContext}, error::RResult, modules::{
         ModuleType, PipeData, PipeModule
-    }, util::gateway_info::CargoPackageInfo
+    }
 };
 
 use super::{RatelimiterProfile, RatelimiterType};
@@ -30,25 +29,24 @@ impl PipeModule for RatelimiterModule {
             match &mut ctx.context_type {
                 ContextType::HttpContext(ref mut http_context) => {
                     let profile_read_lock = profile.read().await;
-                    let check_ret = match &profile_read_lock.ratelimiter.ratelimiter_type {
-                        RatelimiterType::IpRoute => {
-                            profile_read_lock.ratelimiter.check_key(&http_context.request_context.remote_addr.ip().to_string())
-                        }
-                        RatelimiterType::IpService => {
-                            profile_read_lock.ratelimiter.check_key(&http_context.request_context.remote_addr.ip().to_string())
+                    let key = match &profile_read_lock.ratelimiter.ratelimiter_type {
+                        RatelimiterType::IpRoute | RatelimiterType::IpService => {
+                            ctx.real_ip.to_string()
                         }
                         RatelimiterType::Service => {
-                            profile_read_lock.ratelimiter.check_key(&crate::util::r#const::ROCKJIANG_SERVICE.to_string())
+                            crate::util::r#const::ROCKJIANG_SERVICE.to_string()
                         }
                         RatelimiterType::Route(route_name) => {
-                            profile_read_lock.ratelimiter.check_key(route_name)
+                            route_name.clone()
                         }
-                    };
-                    match check_ret {
-                        Ok(_) => {}
-                        Err(e) => {
-                            return Err(gateway_err!(RatelimiterArrival, "ratelimiter arrival", PipeError::new(PipeErrorKind::RATELIMITER)));
+                        ratelimiter_type => {
+                            /* without the attribute, the request is limited by its ip */
+                            request_key(ratelimiter_type, ctx.route.as_deref(), ctx.consumer.as_deref(), ctx.claims.as_ref(), http_context).unwrap_or_else(|| format!("ip:{}", ctx.real_ip))
                         }
+                    };
+                    if let Err(state) = profile_read_lock.ratelimiter.check(&key).await {
+                        log::warn!("ratelimiter arrival, key:{} retry after:{:?}", key, state.retry_after);
+                        http_context.return_context.response = Some(too_many_requests(&state)?);
                     }
                 }
                 ContextType::TcpContext(tcp_context) => {
@@ -60,4 +58,77 @@ impl PipeModule for RatelimiterModule {
         }
         unreachable!()
     }
-}
\ No newline at end of file
+}
+fn request_key(ratelimiter_type: &RatelimiterType, route: Option<&str>, consumer: Option<&str>, claims: Option<&serde_json::Value>, http_context: &HttpContext) -> Option<String> {
+    let request = &http_context.request_context;
+    match ratelimiter_type {
+        RatelimiterType::Header(name) => {
+            request.headers.get(name).and_then(|v| v.to_str().ok()).map(|v| format!("header:{}", v))
+        }
+        RatelimiterType::ApiKey => {
+            let from_header = request.headers.get("x-api-key").and_then(|v| v.to_str().ok()).map(|v| v.to_string());
+            let from_query = || request.uri.query().and_then(|q| {
+                q.split('&').find_map(|pair| pair.strip_prefix("api_key=").map(|v| percent_encoding::percent_decode_str(v).decode_utf8_lossy().into_owned()))
+            });
+            from_header.or_else(from_query).map(|v| format!("api_key:{}", v))
+        }
+        RatelimiterType::PathMethod => {
+            Some(format!("{} {}", request.method, route.unwrap_or_default()))
+        }
+        RatelimiterType::Claim(claim) => {
+            /* the claims of the token checked by the jwt_auth in front, never the token as the client sent it */
+            match crate::common::jwt::claim(claims?, claim)? {
+                serde_json::Value::String(s) => Some(format!("claim:{}", s)),
+                v => Some(format!("claim:{}", v)),
+            }
+        }
+        RatelimiterType::Consumer => {
+            consumer.map(|c| format!("consumer:{}", c))
+        }
+        _ => { None }
+    }
+}
+fn ceil_secs(d: Duration) -> u64 {
+    d.as_secs() + if d.subsec_nanos() > 0 { 1 } else { 0 }
+}
+pub(crate) fn too_many_requests(state: &RatelimiterState) -> RResult<Response<Full<bytes::Bytes>>> {
+    let (status, version, mut headers, body) = crate::common::four_and_four_page::response_page(StatusCode::TOO_MANY_REQUESTS);
+    headers.insert(header::RETRY_AFTER, ceil_secs(state.retry_after).max(1).into());
+    headers.insert("x-ratelimit-limit", state.limit.into());
+    headers.insert("x-ratelimit-remaining", state.remaining.into());
+    headers.insert("x-ratelimit-reset", ceil_secs(state.reset).into());
+    let mut response = Response::new(Full::new(body));
+    *response.status_mut() = status;
+    *response.version_mut() = version;
+    *response.headers_mut() = headers;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(limit: u32, remaining: u32, retry_after: Duration, reset: Duration) -> Vec<String> {
+        let response = too_many_requests(&RatelimiterState { limit, remaining, retry_after, reset }).unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        ["retry-after", "x-ratelimit-limit", "x-ratelimit-remaining", "x-ratelimit-reset"].iter()
+            .map(|name| response.headers().get(*name).unwrap().to_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn ceil() {
+        assert_eq!(ceil_secs(Duration::ZERO), 0);
+        assert_eq!(ceil_secs(Duration::from_nanos(1)), 1);
+        assert_eq!(ceil_secs(Duration::from_secs(2)), 2);
+        assert_eq!(ceil_secs(Duration::from_millis(2001)), 3);
+    }
+    #[test]
+    fn too_many_requests_headers() {
+        assert_eq!(headers(10, 0, Duration::from_millis(1200), Duration::from_millis(9500)), ["2", "10", "0", "10"]);
+        assert_eq!(headers(3, 0, Duration::from_secs(4), Duration::from_secs(12)), ["4", "3", "0", "12"]);
+        /* never Retry-After: 0, the client would come back at once */
+        assert_eq!(headers(5, 1, Duration::ZERO, Duration::ZERO), ["1", "5", "1", "0"]);
+        assert_eq!(headers(5, 0, Duration::from_micros(10), Duration::from_micros(10)), ["1", "5", "0", "1"]);
+    }
+}
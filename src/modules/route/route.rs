@@ -54,17 +54,17 @@ impl PipeModule for RouteModule {
                                     }
                                 }
                                 In::Ip { ranges, method } => {
-                                    let ip_str = ctx.remote_addr.ip().to_string();
+                                    let ip = ctx.real_ip;
                                     if self.match_method(request_method, method)
-                                        &&ranges.check_whitelist(&ip_str) {
+                                        &&ranges.read().await.check_whitelist(&ip) {
                                         ctx.route = Some(route_name.clone());
                                             return Ok(());
                                     }
                                 }
                                 In::IpFile { ranges, method } => {
-                                    let ip_str = ctx.remote_addr.ip().to_string();
+                                    let ip = ctx.real_ip;
                                     if self.match_method(request_method, method)
-                                        && ranges.check_whitelist(&ip_str) {
+                                        && ranges.read().await.check_whitelist(&ip) {
                                         ctx.route = Some(route_name.clone());
                                             return Ok(());
                                     }
@@ -82,15 +82,15 @@ impl PipeModule for RouteModule {
                                     return Err(gateway_err!(PipeExecuteError, "PipeExecuteError Regex not support Tcp", PipeError::new(PipeErrorKind::ROUTE)));
                                 }
                                 In::Ip { ranges, method } => {
-                                    let ip_str = ctx.remote_addr.ip().to_string();
-                                    if ranges.check_whitelist(&ip_str) {
+                                    let ip = ctx.real_ip;
+                                    if ranges.read().await.check_whitelist(&ip) {
                                         ctx.route = Some(route_name.clone());
                                         return Ok(());
                                     }
                                 }
                                 In::IpFile { ranges, method} => {
-                                    let ip_str = ctx.remote_addr.ip().to_string();
-                                    if ranges.check_whitelist(&ip_str) {
+                                    let ip = ctx.real_ip;
+                                    if ranges.read().await.check_whitelist(&ip) {
                                         ctx.route = Some(route_name.clone());
                                         return Ok(());
                                     }
@@ -26,7 +26,7 @@ impl PipeModule for UpgradeInsecureRequests {
                         if h_v == "1" {
                             let mut address_opt = None;
                             for interface in profile_read_lock.interfaces.iter() {
-                                if let AddressInterface::Https { addr, cert, key } = interface {
+                                if let AddressInterface::Https { addr, cert, key, .. } = interface {
                                     address_opt = Some(addr);
                                     break;
                                 }
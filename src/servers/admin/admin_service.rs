@@ -0,0 +1,259 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public
+License, v. 2.0. If a copy of the MPL was not distributed with this
+file, You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use http::{header, Method, Request, Response, StatusCode};
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Bytes, Incoming};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{
+    common::htpasswd, error::RResult, modules::{blackandwhitelist::{all_lists, black_and_white_list::{BaW, ListKind}}, cache::{http_cache_cell::Eviction, http_cache_purge::{CachePurge, PurgeCount, PurgeTarget}}}, servers::HyperResult, util::time_unit::TimeUnit, RockGateway
+};
+
+#[derive(Debug, Deserialize)]
+struct EntryBody {
+    entry: String,
+    ttl: Option<String>,
+}
+/* one of key, prefix, regex or tags */
+#[derive(Debug, Deserialize)]
+struct PurgeBody {
+    key: Option<String>,
+    prefix: Option<String>,
+    regex: Option<String>,
+    tags: Option<Vec<String>>,
+    route: Option<String>,
+    namespace: Option<String>,      //of redis, the route when not set
+}
+
+pub(crate) fn admin(request: Request<Incoming>, gateway: Arc<RockGateway>, token: Arc<Option<String>>) -> HyperResult {
+    Box::pin(admin_run(request, gateway, token))
+}
+
+async fn admin_run(request: Request<Incoming>, gateway: Arc<RockGateway>, token: Arc<Option<String>>) -> RResult<Response<Full<Bytes>>> {
+    if let Some(token) = token.as_ref() {
+        let authorized = request.headers().get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| htpasswd::constant_time_eq(v.strip_prefix("Bearer ").unwrap_or(v).as_bytes(), token.as_bytes()));
+        if !authorized {
+            return json_response(StatusCode::UNAUTHORIZED, json!({ "error": "unauthorized" }));
+        }
+    }
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let body = request.into_body().collect().await?.to_bytes();
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    match (method, segments.as_slice()) {
+        (Method::GET, ["black_white_list"]) => {
+            list_black_white_list(&gateway).await
+        }
+        (Method::POST, ["black_white_list", name, kind]) => {
+            update_black_white_list(&gateway, name, kind, &body, true).await
+        }
+        (Method::DELETE, ["black_white_list", name, kind]) => {
+            update_black_white_list(&gateway, name, kind, &body, false).await
+        }
+        (Method::GET, ["cache"]) => {
+            cache_occupancy(&gateway)
+        }
+        (Method::POST, ["cache", "purge"]) => {
+            purge_cache(&gateway, &body).await
+        }
+        (Method::GET, ["pools"]) => {
+            pool_stats(&gateway)
+        }
+        _ => {
+            json_response(StatusCode::NOT_FOUND, json!({ "error": "not found" }))
+        }
+    }
+}
+
+async fn list_black_white_list(gateway: &Arc<RockGateway>) -> RResult<Response<Full<Bytes>>> {
+    let instance = gateway.get_instance()?;
+    let mut ret = BTreeMap::new();
+    for (name, profile) in all_lists(&instance) {
+        if ret.contains_key(&name) {
+            continue;   //the lists of the same pipe in other routes are the same
+        }
+        let profile = profile.read().await;
+        let mut lists = serde_json::Map::new();
+        for (kind, kind_str) in [(ListKind::Black, "black"), (ListKind::White, "white")] {
+            let (list, runtime) = profile.entries(kind);
+            lists.insert(kind_str.to_string(), json!({
+                "list": list.iter().map(|baw| baw.to_string()).collect::<Vec<String>>(),
+                "runtime": runtime.into_iter().map(|(entry, ttl)| json!({ "entry": entry, "ttl": ttl })).collect::<Vec<Value>>(),
+            }));
+        }
+        ret.insert(name, Value::Object(lists));
+    }
+    json_response(StatusCode::OK, json!(ret))
+}
+
+/* add (with an optional ttl) or remove one entry of the lists with this name */
+async fn update_black_white_list(gateway: &Arc<RockGateway>, name: &str, kind: &str, body: &Bytes, add: bool) -> RResult<Response<Full<Bytes>>> {
+    let kind = match kind {
+        "black" => ListKind::Black,
+        "white" => ListKind::White,
+        _ => {
+            return json_response(StatusCode::BAD_REQUEST, json!({ "error": "the list is black or white" }));
+        }
+    };
+    if kind == ListKind::Black && name.starts_with("routes.") {
+        return json_response(StatusCode::BAD_REQUEST, json!({ "error": "the in of a route only has a white list" }));
+    }
+    let entry_body: EntryBody = match serde_json::from_slice(body) {
+        Ok(b) => b,
+        Err(e) => {
+            return json_response(StatusCode::BAD_REQUEST, json!({ "error": format!("bad body: {}", e) }));
+        }
+    };
+    let baw = match BaW::parse(&entry_body.entry) {
+        Some(baw) => baw,
+        None => {
+            return json_response(StatusCode::BAD_REQUEST, json!({ "error": format!("bad ip, cidr or range: {}", entry_body.entry) }));
+        }
+    };
+    let ttl = entry_body.ttl.map(TimeUnit::parse);
+    let instance = gateway.get_instance()?;
+    let mut found = 0;
+    let mut changed = 0;
+    for (list_name, profile) in all_lists(&instance) {
+        if list_name != name {
+            continue;
+        }
+        found += 1;
+        let mut profile = profile.write().await;
+        if add {
+            profile.add(kind, baw.clone(), ttl);
+            changed += 1;
+        } else if profile.remove(kind, &baw) {
+            changed += 1;
+        }
+    }
+    if found == 0 {
+        return json_response(StatusCode::NOT_FOUND, json!({ "error": format!("not found list: {}", name) }));
+    }
+    log::info!("admin {} {} {} list:{} ttl:{:?}", if add { "add" } else { "remove" }, baw, name, if kind == ListKind::Black { "black" } else { "white" }, ttl);
+    json_response(StatusCode::OK, json!({ "updated": changed }))
+}
+
+/* how full the memory and disk caches are, against their limits (0 means no limit) */
+fn cache_occupancy(gateway: &Arc<RockGateway>) -> RResult<Response<Full<Bytes>>> {
+    let instance = gateway.get_instance()?;
+    let mut ret = serde_json::Map::new();
+    if let Some(store) = instance.service.memory_cache() {
+        let occupancy = store.occupancy();
+        ret.insert("memory".to_string(), json!({
+            "entries": occupancy.entries,
+            "bytes": occupancy.bytes,
+            "max_entries": store.limits.max_entries,
+            "max_bytes": store.limits.max_bytes,
+            "max_object_size": store.limits.max_object_size,
+            "eviction": match store.limits.eviction {
+                Eviction::Lru => "lru",
+                Eviction::TinyLfu => "tinylfu",
+            },
+            "hits": occupancy.hits,
+            "misses": occupancy.misses,
+            "evictions": occupancy.evictions,
+            "rejected": occupancy.rejected,
+            "coalesced": occupancy.coalesced,
+        }));
+    }
+    if let Some(disk) = instance.service.disk_cache() {
+        let occupancy = disk.occupancy();
+        ret.insert("disk".to_string(), json!({
+            "root": disk.root().to_string_lossy(),
+            "entries": occupancy.entries,
+            "bytes": occupancy.bytes,
+            "max_bytes": disk.limits.max_bytes,
+            "max_object_size": disk.limits.max_object_size,
+            "hits": occupancy.hits,
+            "misses": occupancy.misses,
+            "evictions": occupancy.evictions,
+            "rejected": occupancy.rejected,
+        }));
+    }
+    if ret.is_empty() {
+        return json_response(StatusCode::NOT_FOUND, json!({ "error": "service.cache.memory and service.cache.disk are not set" }));
+    }
+    json_response(StatusCode::OK, Value::Object(ret))
+}
+
+/* the upstream connection pool of each hosts */
+fn pool_stats(gateway: &Arc<RockGateway>) -> RResult<Response<Full<Bytes>>> {
+    let instance = gateway.get_instance()?;
+    let mut ret = BTreeMap::new();
+    for (hosts_name, hosts) in &instance.hosts {
+        let (created, open, active, idle) = hosts.pool.stats.snapshot();
+        ret.insert(hosts_name.clone(), json!({
+            "created": created,
+            "open": open,
+            "active": active,
+            "idle": idle,
+        }));
+    }
+    json_response(StatusCode::OK, json!(ret))
+}
+
+/* the same purge as the PURGE of the routes, on the memory and redis of the service */
+async fn purge_cache(gateway: &Arc<RockGateway>, body: &Bytes) -> RResult<Response<Full<Bytes>>> {
+    let purge_body: PurgeBody = match serde_json::from_slice(body) {
+        Ok(b) => b,
+        Err(e) => {
+            return json_response(StatusCode::BAD_REQUEST, json!({ "error": format!("bad body: {}", e) }));
+        }
+    };
+    let target = match (purge_body.key, purge_body.prefix, purge_body.regex, purge_body.tags) {
+        (Some(key), None, None, None) => PurgeTarget::Keys(vec![key]),
+        (None, Some(prefix), None, None) => PurgeTarget::Prefix(prefix),
+        (None, None, Some(regex), None) => {
+            match regex::Regex::new(&regex) {
+                Ok(regex) => PurgeTarget::Regex(regex),
+                Err(e) => {
+                    return json_response(StatusCode::BAD_REQUEST, json!({ "error": format!("bad regex: {}", e) }));
+                }
+            }
+        }
+        (None, None, None, Some(tags)) if !tags.is_empty() => PurgeTarget::Tags(tags),
+        _ => {
+            return json_response(StatusCode::BAD_REQUEST, json!({ "error": "one of key, prefix, regex or tags is expected" }));
+        }
+    };
+    let namespace = purge_body.namespace.or_else(|| purge_body.route.clone());
+    let purge = CachePurge::new(target, purge_body.route);
+    let instance = gateway.get_instance()?;
+    let mut count = PurgeCount::default();
+    if let Some(store) = instance.service.memory_cache() {
+        count.memory = store.purge(&purge);
+    }
+    if let Some(disk) = instance.service.disk_cache() {
+        count.disk = disk.purge(&purge).await;
+    }
+    if instance.service.has_cache("redis") {
+        let redis = instance.service.crate_redis()?;
+        count.redis = match purge.purge_redis(&redis, namespace.as_deref()).await {
+            Ok(purged) => purged,
+            Err(e) => {
+                log::error!("admin purge redis failed:{:?}", e);
+                return json_response(StatusCode::BAD_GATEWAY, json!({ "error": "redis failed", "purged": { "memory": count.memory, "disk": count.disk } }));
+            }
+        };
+    }
+    log::info!("admin purge {:?} route:{:?} memory:{} redis:{} disk:{}", purge.target, purge.route, count.memory, count.redis, count.disk);
+    json_response(StatusCode::OK, json!({ "purged": { "memory": count.memory, "redis": count.redis, "disk": count.disk } }))
+}
+
+fn json_response(status: StatusCode, value: Value) -> RResult<Response<Full<Bytes>>> {
+    Ok(Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Full::new(Bytes::from(value.to_string())))
+        .map_err(|e| e.to_string())?)
+}
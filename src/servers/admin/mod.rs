@@ -0,0 +1,49 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public
+License, v. 2.0. If a copy of the MPL was not distributed with this
+file, You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+use std::{net::SocketAddr, sync::Arc};
+
+use crate::error::RResult;
+
+use crate::servers::{GatewayServerInterface, tokiort::TokioIo};
+
+pub(crate) mod admin_service;
+
+/* The admin api, it does not go through routes and pipes. */
+pub(crate) struct AdminServer {
+    pub(crate) addr: SocketAddr,
+    pub(crate) token: Option<String>,
+}
+impl GatewayServerInterface for AdminServer {
+    fn start(&self, gateway: &mut Arc<crate::RockGateway>) -> RResult<()> {
+        let addr = self.addr;
+        let gateway_clone = gateway.clone();
+        let token = Arc::new(self.token.clone());
+        let self_name = self.name().unwrap_or_else(|_|{"admin".to_string()});
+        gateway.spawn(async move {
+            let listener = tokio::net::TcpListener::bind(addr).await.unwrap_or_else(|e| panic!("Error ip_bind failed:{:#?} {:?}", addr, e));
+            log::info!("{}>accept:{:#?}", self_name, addr);
+            loop {
+                let (stream, _) = listener.accept().await.unwrap_or_else(|e| panic!("Error ip_accept failed:{:#?} {:?}", addr, e));
+                let io = TokioIo::new(stream);
+                let gateway_clone = gateway_clone.clone();
+                let token = token.clone();
+                tokio::task::spawn(async move {
+                    let conn_ret = hyper::server::conn::http1::Builder::new()
+                        .serve_connection(io, hyper::service::service_fn(|request| {
+                            admin_service::admin(request, gateway_clone.clone(), token.clone())
+                        })).await;
+                    if let Err(e) = conn_ret {
+                        log::error!("Error admin serving connection: {:?}", e);
+                    }
+                });
+            }
+        })
+    }
+    fn name(&self) -> RResult<String> {
+        Ok(crate::util::r#const::ADMIN.to_owned())
+    }
+}
@@ -13,28 +13,42 @@ use hyper::body::Incoming;
 
 use crate::error::RResult;
 
-use crate::servers::{GatewayServerInterface, tokiort::TokioIo};
+use crate::servers::{GatewayServerInterface, proxy_protocol::ProxyProtocol, tokiort::TokioIo};
 
 
 pub(crate) mod http_service;
 
 pub(crate) struct HttpServer {
     pub(crate) addr: SocketAddr,
+    pub(crate) proxy_protocol: Option<ProxyProtocol>,
 }
 impl GatewayServerInterface for HttpServer {
     fn start(&self, gateway: &mut Arc<crate::RockGateway>) -> RResult<()> {
         let addr = self.addr;
         let gateway_clone = gateway.clone();
         let self_name = self.name().unwrap_or_else(|_|{"Http".to_string()});
+        let proxy_protocol = self.proxy_protocol.clone();
         gateway.spawn(async move {
             let listener = tokio::net::TcpListener::bind(addr).await.expect(format!("Error ip_bind failed:{:#?}", addr).as_str());
             log::info!("{}>accept:{:#?}", self_name, addr);
             loop {
-                let (stream, remote_addr) = listener.accept().await.expect(format!("Error ip_accept failed:{:#?}", addr).as_str());
-                let io = TokioIo::new(stream);
+                let (mut stream, remote_addr) = listener.accept().await.expect(format!("Error ip_accept failed:{:#?}", addr).as_str());
                 let gateway_clone = gateway_clone.clone();
-                let instance = gateway_clone.get_instance();
+                let proxy_protocol = proxy_protocol.clone();
                 tokio::task::spawn(async move {
+                    let remote_addr = match &proxy_protocol {
+                        Some(pp) => {
+                            match pp.accept(&mut stream, remote_addr, addr).await {
+                                Ok((src, _)) => { src }
+                                Err(e) => {
+                                    log::error!("GatewayError::{:#?}", e);
+                                    return;
+                                }
+                            }
+                        }
+                        None => { remote_addr }
+                    };
+                    let io = TokioIo::new(stream);
                     /* Whenever the browser is closed or not operated for a long time, a result of conn will be returned here. */
                     let conn_ret = hyper::server::conn::http1::Builder::new()
                         .serve_connection(io, hyper::service::service_fn(|request| {
@@ -10,7 +10,7 @@ use tokio_rustls::{rustls::ServerConfig, TlsAcceptor};
 
 use crate::{error::{GatewayError, RResult}, gateway_err, servers::tokiort::TokioTimer};
 
-use super::{tokiort::TokioIo, GatewayServerInterface};
+use super::{proxy_protocol::ProxyProtocol, tokiort::TokioIo, GatewayServerInterface};
 
 pub(crate) mod https_service;
 
@@ -19,6 +19,7 @@ pub(crate) struct HttpsServer {
     pub(crate) addr: SocketAddr,
     pub(crate) cert: String,
     pub(crate) key: String,
+    pub(crate) proxy_protocol: Option<ProxyProtocol>,
 }
 impl HttpsServer {
     fn load_certs(&self, filename: &str) -> RResult<Vec<tokio_rustls::rustls::pki_types::CertificateDer<'static>>> {
@@ -83,24 +84,39 @@ impl GatewayServerInterface for HttpsServer {
         let tls_acceptor = TlsAcceptor::from(tls_cfg);
         let gateway_clone = gateway.clone();
         let self_name = self.name().unwrap_or_else(|_|{"https".to_string()});
+        let proxy_protocol = self.proxy_protocol.clone();
         gateway.spawn(async move {
             let listener = tokio::net::TcpListener::bind(addr).await.expect(format!("Error ip_bind failed:{:#?}", addr).as_str());
             log::info!("{}>accept:{:#?}", self_name, addr);
             loop {
-                let (stream, remote_addr) = listener.accept().await.expect(format!("Error ip_accept failed:{:#?}", addr).as_str());
+                let (mut stream, remote_addr) = listener.accept().await.expect(format!("Error ip_accept failed:{:#?}", addr).as_str());
                 let acceptor = tls_acceptor.clone();
-                let tls_stream = match acceptor.accept(stream).await {
-                    Ok(stream) => {
-                        stream
-                    }
-                    Err(e) => {
-                        log::error!("GatewayError::{:#?}", e);
-                        continue;
-                    }
-                };
-                let io: TokioIo<tokio_rustls::server::TlsStream<tokio::net::TcpStream>> = TokioIo::new(tls_stream);
                 let gateway_clone = gateway_clone.clone();
+                let proxy_protocol = proxy_protocol.clone();
                 tokio::task::spawn(async move {
+                    /* the PROXY header comes before the tls handshake */
+                    let remote_addr = match &proxy_protocol {
+                        Some(pp) => {
+                            match pp.accept(&mut stream, remote_addr, addr).await {
+                                Ok((src, _)) => { src }
+                                Err(e) => {
+                                    log::error!("GatewayError::{:#?}", e);
+                                    return;
+                                }
+                            }
+                        }
+                        None => { remote_addr }
+                    };
+                    let tls_stream = match acceptor.accept(stream).await {
+                        Ok(stream) => {
+                            stream
+                        }
+                        Err(e) => {
+                            log::error!("GatewayError::{:#?}", e);
+                            return;
+                        }
+                    };
+                    let io: TokioIo<tokio_rustls::server::TlsStream<tokio::net::TcpStream>> = TokioIo::new(tls_stream);
                     /* Whenever the browser is closed or not operated for a long time, a result of conn will be returned here. */
                     let conn_ret = hyper::server::conn::http1::Builder::new()
                         .serve_connection(io, hyper::service::service_fn(
@@ -9,7 +9,9 @@ pub(crate) mod pipeline;
 pub(crate) mod http;
 pub(crate) mod https;
 pub(crate) mod tcp;
+pub(crate) mod admin;
 pub(crate) mod tokiort;
+pub(crate) mod proxy_protocol;
 
 use std::pin::Pin;
 use std::sync::Arc;
@@ -29,6 +31,7 @@ pub(crate) enum GatewayServer {
     Tcp(tcp::TcpServer),
     Http(http::HttpServer),
     Https(https::HttpsServer),
+    Admin(admin::AdminServer),
 }
 pub(crate) trait GatewayServerInterface {
     fn start(&self, gateway: &mut Arc<crate::RockGateway>) -> RResult<()>;
@@ -37,21 +40,30 @@ pub(crate) trait GatewayServerInterface {
 impl GatewayServer {
     pub(crate) fn create(iface: &AddressInterface) -> RResult<GatewayServer> {
         Ok(match iface {
-            AddressInterface::Http { addr } => {
+            AddressInterface::Http { addr, proxy_protocol } => {
                 GatewayServer::Http(http::HttpServer {
-                    addr: *addr
+                    addr: *addr,
+                    proxy_protocol: proxy_protocol.clone(),
                 })
             }
-            AddressInterface::Https { addr, cert, key } => {
+            AddressInterface::Https { addr, cert, key, proxy_protocol } => {
                 GatewayServer::Https(https::HttpsServer {
                     addr: *addr,
                     cert: cert.to_owned(),
                     key: key.to_owned(),
+                    proxy_protocol: proxy_protocol.clone(),
                 })
             }
-            AddressInterface::Tcp { addr } => {
+            AddressInterface::Tcp { addr, proxy_protocol } => {
                 GatewayServer::Tcp(tcp::TcpServer {
                     addr: *addr,
+                    proxy_protocol: proxy_protocol.clone(),
+                })
+            }
+            AddressInterface::Admin { addr, token } => {
+                GatewayServer::Admin(admin::AdminServer {
+                    addr: *addr,
+                    token: token.clone(),
                 })
             }
         })
@@ -67,6 +79,9 @@ impl GatewayServer {
             GatewayServer::Tcp(tcp) => {
                 tcp.start(gateway)?;
             }
+            GatewayServer::Admin(admin) => {
+                admin.start(gateway)?;
+            }
         }
         Ok(())
     }
@@ -75,6 +90,7 @@ impl GatewayServer {
             GatewayServer::Http(http) => { http.name()? }
             GatewayServer::Https(https) => { https.name()? }
             GatewayServer::Tcp(tcp) => { tcp.name()? }
+            GatewayServer::Admin(admin) => { admin.name()? }
         })
     }
 }
\ No newline at end of file
@@ -13,7 +13,7 @@ use hyper::body::{Incoming, Bytes};
 use tokio_rustls::rustls::ContentType;
 
 use crate::{
-    context::{ContextType, GatewayContext}, error::{ GatewayError, RResult }, modules::PipeData, RockGateway
+    context::{ContextType, GatewayContext}, error::{ GatewayError, RResult }, modules::PipeData, servers::pipeline::AccessLog, RockGateway
 };
 
 
@@ -21,6 +21,19 @@ pub(crate) async fn https_run(
     remote_addr: SocketAddr,
     request: Request<Incoming>,
     gateway: Arc<RockGateway>,
+) -> RResult<Response<Full<Bytes>>> {
+    let mut access_log = AccessLog::new(&gateway, remote_addr, &request)?;
+    let ret = https_pipeline(remote_addr, request, gateway, &mut access_log).await;
+    if let Some(access_log) = &access_log {
+        access_log.log(&ret);
+    }
+    ret
+}
+async fn https_pipeline(
+    remote_addr: SocketAddr,
+    request: Request<Incoming>,
+    gateway: Arc<RockGateway>,
+    access_log: &mut Option<AccessLog>,
 ) -> RResult<Response<Full<Bytes>>> {
     let instance = gateway.get_instance()?;
     let common_module_lock = gateway.common_module.read().await;
@@ -38,8 +51,21 @@ pub(crate) async fn https_run(
     match &context.route {
         Some(route_name) => {
             let route = instance.routes.get(route_name).unwrap();
+            /* the route may take the address of the client from other proxies than the service */
+            if let Some(real_ip) = &route.real_ip {
+                if let ContextType::HttpContext(http_context) = &context.context_type {
+                    context.real_ip = real_ip.resolve(&context.remote_addr.ip(), &http_context.request_context.headers);
+                }
+                if let Some(access_log) = access_log {
+                    access_log.real_ip = context.real_ip;
+                }
+            }
             /* second execute pipe line */
-            match route.pipe_line.pipe_line_engine.execute(&mut context).await {
+            let executed = route.pipe_line.pipe_line_engine.execute(&mut context).await;
+            if let Some(access_log) = access_log {
+                access_log.user = context.user.clone().or_else(|| context.consumer.clone());
+            }
+            match executed {
                 Ok(_) => {}
                 Err(e) => {
                     match &e {
@@ -61,6 +87,8 @@ pub(crate) async fn https_run(
                     return Ok(crate::common::four_and_four_page::page404());
                 }
             };
+            /* a stale copy of the cache was given, refresh it */
+            crate::modules::cache::revalidate_in_background(&mut context);
             if let ContextType::HttpContext(http_context) = &context.context_type {
                 if http_context.return_context.response.is_none() {
                     println!("Immediately return and reorganize the data");
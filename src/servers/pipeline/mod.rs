@@ -7,3 +7,48 @@ file, You can obtain one at https://mozilla.org/MPL/2.0/.
 pub mod https;
 pub mod tcp;
 pub mod http;
+
+use std::{net::{IpAddr, SocketAddr}, time::Instant};
+
+use hyper::{Method, Request, Response, Uri};
+use http_body_util::Full;
+use hyper::body::{Bytes, Incoming};
+
+use crate::{common::ip_cidr::IpCidr, error::RResult, RockGateway};
+
+/* One line per http request when service.access_log is on. */
+pub(crate) struct AccessLog {
+    start: Instant,
+    remote_addr: SocketAddr,
+    pub(crate) real_ip: IpAddr,
+    pub(crate) user: Option<String>,
+    method: Method,
+    uri: Uri,
+}
+impl AccessLog {
+    pub(crate) fn new(gateway: &RockGateway, remote_addr: SocketAddr, request: &Request<Incoming>) -> RResult<Option<Self>> {
+        let instance = gateway.get_instance()?;
+        if !instance.service.access_log {
+            return Ok(None);
+        }
+        let real_ip = match &instance.service.real_ip {
+            Some(r) => { r.resolve(&remote_addr.ip(), request.headers()) }
+            None => { IpCidr::canonical(remote_addr.ip()) }
+        };
+        Ok(Some(Self {
+            start: Instant::now(),
+            remote_addr,
+            real_ip,
+            user: None,
+            method: request.method().clone(),
+            uri: request.uri().clone(),
+        }))
+    }
+    pub(crate) fn log(&self, ret: &RResult<Response<Full<Bytes>>>) {
+        let status = match ret {
+            Ok(response) => { response.status().as_u16().to_string() }
+            Err(_) => { "-".to_string() }
+        };
+        log::info!(target: "access", "{} {} {} \"{} {}\" {} {}ms", self.real_ip, self.remote_addr, self.user.as_deref().unwrap_or("-"), self.method, self.uri, status, self.start.elapsed().as_millis());
+    }
+}
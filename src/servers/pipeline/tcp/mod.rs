@@ -9,10 +9,11 @@ use std::{net::SocketAddr, sync::Arc};
 use futures_util::TryFutureExt;
 use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::{tcp, TcpStream}};
 
-use crate::{context::{ContextType, GatewayContext}, entitys::buf::DataBuf, error::{GatewayError, PipeError, PipeErrorKind, RResult}, gateway_err, RockGateway};
+use crate::{context::{ConcurrencyPermits, ContextType, GatewayContext}, entitys::buf::DataBuf, error::{GatewayError, PipeError, PipeErrorKind, RResult}, gateway_err, RockGateway};
 
 pub(crate) async fn tcp_run(
     remote_addr: SocketAddr,
+    local_addr: SocketAddr,
     stream: TcpStream,
     gateway: Arc<RockGateway>,
 ) -> RResult<()> {
@@ -26,9 +27,11 @@ pub(crate) async fn tcp_run(
     let out_tx = Arc::new(out_tx);
     let in_tx = Arc::new(in_tx);
     let in_rx = Arc::new(tokio::sync::RwLock::new(in_rx));
+    let concurrency_permits = ConcurrencyPermits::default();   //for the whole session
+    let upstream = Arc::new(std::sync::OnceLock::new());   //for the whole session too
     'main: loop {
         let pipe_fut = futures_util::future::ok::<GatewayContext, GatewayError>(
-            GatewayContext::new_tcp_context(remote_addr, gateway.clone(), out_tx.clone(), in_tx.clone(), in_rx.clone()).await?
+            GatewayContext::new_tcp_context(remote_addr, local_addr, gateway.clone(), out_tx.clone(), in_tx.clone(), in_rx.clone(), concurrency_permits.clone(), upstream.clone()).await?
         );
         let mut context = pipe_fut.and_then(|ctx| {
             /* first execute route */
@@ -54,6 +57,10 @@ pub(crate) async fn tcp_run(
                                 /* second execute pipe line */
                                 match route.pipe_line.pipe_line_engine.execute(&mut context).await {
                                     Ok(_) => {  }
+                                    Err(GatewayError::ConcurrencyLimitArrival { .. }) => {
+                                        stream_write_lock.shutdown().await.unwrap_or_default();
+                                        return Ok(());
+                                    }
                                     Err(e) => { 
                                         log::error!("PIPE_LINE_ERROR>{:#?}", e);
                                         stream_write_lock.write_all("not found route".as_bytes()).await.unwrap();
@@ -0,0 +1,301 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public
+License, v. 2.0. If a copy of the MPL was not distributed with this
+file, You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+use std::{net::{IpAddr, SocketAddr}, time::Duration};
+
+use tokio::{io::{AsyncRead, AsyncReadExt}, net::TcpStream};
+
+use crate::{common::ip_cidr::IpCidr, error::{GatewayError, RResult}, gateway_err};
+
+const V2_SIGNATURE: [u8; 12] = [0x0D, 0x0A, 0x0D, 0x0A, 0x00, 0x0D, 0x0A, 0x51, 0x55, 0x49, 0x54, 0x0A];
+const V1_MAX_LEN: usize = 107;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ProxyVersion {
+    V1,
+    V2,
+}
+impl ProxyVersion {
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        match s {
+            "v1" | "1" => Some(ProxyVersion::V1),
+            "v2" | "2" => Some(ProxyVersion::V2),
+            _ => None,
+        }
+    }
+}
+/* The PROXY protocol (haproxy v1/v2) accepted on a listener. */
+#[derive(Debug, Clone)]
+pub(crate) struct ProxyProtocol {
+    pub(crate) trusted: Vec<IpCidr>,   //empty means every peer must send the header
+    pub(crate) timeout: Duration,
+}
+impl ProxyProtocol {
+    pub(crate) fn is_trusted(&self, ip: &IpAddr) -> bool {
+        self.trusted.is_empty() || self.trusted.iter().any(|cidr| cidr.contains(ip))
+    }
+    /*
+    Read the header in front of the data of a new connection and return the (source, destination) it conveys.
+    Peers outside the trusted ranges are taken as they are and their data is not touched.
+     */
+    pub(crate) async fn accept(&self, stream: &mut TcpStream, remote_addr: SocketAddr, local_addr: SocketAddr) -> RResult<(SocketAddr, SocketAddr)> {
+        if !self.is_trusted(&remote_addr.ip()) {
+            return Ok((remote_addr, local_addr));
+        }
+        match tokio::time::timeout(self.timeout, read_header(stream)).await {
+            Ok(ret) => {
+                Ok(ret?.unwrap_or((remote_addr, local_addr)))
+            }
+            Err(e) => {
+                Err(gateway_err!(ParseRequestError, format!("PROXY protocol header timeout from {}", remote_addr), e))
+            }
+        }
+    }
+}
+/* None for the LOCAL command of v2 and the UNKNOWN of v1, the real addresses of the connection are kept then. */
+async fn read_header<R: AsyncRead + Unpin>(stream: &mut R) -> RResult<Option<(SocketAddr, SocketAddr)>> {
+    /* "PROXY UNKNOWN\r\n" is the shortest header, so 8 bytes can always be read */
+    let mut head = [0u8; 16];
+    stream.read_exact(&mut head[..8]).await?;
+    if head[..8] == V2_SIGNATURE[..8] {
+        stream.read_exact(&mut head[8..]).await?;
+        if head[..12] != V2_SIGNATURE {
+            return Err(gateway_err!(ParseRequestError, "PROXY protocol v2 bad signature"));
+        }
+        let len = u16::from_be_bytes([head[14], head[15]]) as usize;
+        let mut body = vec![0u8; len];
+        stream.read_exact(&mut body).await?;
+        return parse_v2(head[12], head[13], &body);
+    }
+    if &head[..6] != b"PROXY " {
+        return Err(gateway_err!(ParseRequestError, "PROXY protocol header not found"));
+    }
+    let mut line = head[..8].to_vec();
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= V1_MAX_LEN {
+            return Err(gateway_err!(ParseRequestError, "PROXY protocol v1 header too long"));
+        }
+        line.push(stream.read_u8().await?);
+    }
+    parse_v1(&line)
+}
+fn parse_v1(line: &[u8]) -> RResult<Option<(SocketAddr, SocketAddr)>> {
+    let line = std::str::from_utf8(&line[..line.len() - 2]).map_err(|e| {
+        gateway_err!(ParseRequestError, "PROXY protocol v1 header is not ascii", e)
+    })?;
+    let parts: Vec<&str> = line.split(' ').collect();
+    match parts.get(1) {
+        Some(&"UNKNOWN") => { return Ok(None); }
+        Some(&"TCP4") | Some(&"TCP6") if parts.len() == 6 => {}
+        _ => {
+            return Err(gateway_err!(ParseRequestError, format!("PROXY protocol v1 bad header:{}", line)));
+        }
+    }
+    let bad = || gateway_err!(ParseRequestError, format!("PROXY protocol v1 bad address:{}", line));
+    let src_ip: IpAddr = parts[2].parse().map_err(|_| bad())?;
+    let dst_ip: IpAddr = parts[3].parse().map_err(|_| bad())?;
+    let src_port: u16 = parts[4].parse().map_err(|_| bad())?;
+    let dst_port: u16 = parts[5].parse().map_err(|_| bad())?;
+    Ok(Some((SocketAddr::new(src_ip, src_port), SocketAddr::new(dst_ip, dst_port))))
+}
+fn parse_v2(ver_cmd: u8, family: u8, body: &[u8]) -> RResult<Option<(SocketAddr, SocketAddr)>> {
+    if ver_cmd >> 4 != 2 {
+        return Err(gateway_err!(ParseRequestError, format!("PROXY protocol v2 bad version:{}", ver_cmd >> 4)));
+    }
+    match ver_cmd & 0x0F {
+        0 => { return Ok(None); }   //LOCAL, e.g. health checks of the load balancer
+        1 => {}
+        cmd => {
+            return Err(gateway_err!(ParseRequestError, format!("PROXY protocol v2 bad command:{}", cmd)));
+        }
+    }
+    let short = || gateway_err!(ParseRequestError, "PROXY protocol v2 address block too short");
+    match family >> 4 {
+        1 => {
+            let b = body.get(..12).ok_or_else(short)?;
+            let src = SocketAddr::new(IpAddr::from([b[0], b[1], b[2], b[3]]), u16::from_be_bytes([b[8], b[9]]));
+            let dst = SocketAddr::new(IpAddr::from([b[4], b[5], b[6], b[7]]), u16::from_be_bytes([b[10], b[11]]));
+            Ok(Some((src, dst)))
+        }
+        2 => {
+            let b = body.get(..36).ok_or_else(short)?;
+            let mut src_ip = [0u8; 16];
+            let mut dst_ip = [0u8; 16];
+            src_ip.copy_from_slice(&b[..16]);
+            dst_ip.copy_from_slice(&b[16..32]);
+            let src = SocketAddr::new(IpAddr::from(src_ip), u16::from_be_bytes([b[32], b[33]]));
+            let dst = SocketAddr::new(IpAddr::from(dst_ip), u16::from_be_bytes([b[34], b[35]]));
+            Ok(Some((src, dst)))
+        }
+        _ => { Ok(None) }   //AF_UNSPEC and AF_UNIX carry no usable ip
+    }
+}
+/* The header sent in front of the data to a host_point so that it sees the real client. */
+pub(crate) fn make_header(version: ProxyVersion, src: &SocketAddr, dst: &SocketAddr) -> Vec<u8> {
+    let (src_ip, dst_ip) = match (IpCidr::canonical(src.ip()), IpCidr::canonical(dst.ip())) {
+        (IpAddr::V4(s), IpAddr::V6(d)) => (IpAddr::V6(s.to_ipv6_mapped()), IpAddr::V6(d)),
+        (IpAddr::V6(s), IpAddr::V4(d)) => (IpAddr::V6(s), IpAddr::V6(d.to_ipv6_mapped())),
+        same => same,
+    };
+    match version {
+        ProxyVersion::V1 => {
+            let family = if src_ip.is_ipv4() { "TCP4" } else { "TCP6" };
+            format!("PROXY {} {} {} {} {}\r\n", family, src_ip, dst_ip, src.port(), dst.port()).into_bytes()
+        }
+        ProxyVersion::V2 => {
+            let mut header = V2_SIGNATURE.to_vec();
+            match (src_ip, dst_ip) {
+                (IpAddr::V4(s), IpAddr::V4(d)) => {
+                    header.extend_from_slice(&[0x21, 0x11, 0, 12]);
+                    header.extend_from_slice(&s.octets());
+                    header.extend_from_slice(&d.octets());
+                }
+                (IpAddr::V6(s), IpAddr::V6(d)) => {
+                    header.extend_from_slice(&[0x21, 0x21, 0, 36]);
+                    header.extend_from_slice(&s.octets());
+                    header.extend_from_slice(&d.octets());
+                }
+                _ => { unreachable!() }
+            }
+            header.extend_from_slice(&src.port().to_be_bytes());
+            header.extend_from_slice(&dst.port().to_be_bytes());
+            header
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn read(mut data: &[u8]) -> RResult<Option<(SocketAddr, SocketAddr)>> {
+        read_header(&mut data).await
+    }
+    fn addrs(src: &str, dst: &str) -> Option<(SocketAddr, SocketAddr)> {
+        Some((src.parse().unwrap(), dst.parse().unwrap()))
+    }
+    fn v2(ver_cmd: u8, family: u8, body: &[u8]) -> Vec<u8> {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.extend_from_slice(&[ver_cmd, family]);
+        header.extend_from_slice(&(body.len() as u16).to_be_bytes());
+        header.extend_from_slice(body);
+        header
+    }
+
+    #[tokio::test]
+    async fn v1_tcp4() {
+        let ret = read(b"PROXY TCP4 192.168.0.1 10.0.0.1 56324 443\r\nGET / HTTP/1.1\r\n").await.unwrap();
+        assert_eq!(ret, addrs("192.168.0.1:56324", "10.0.0.1:443"));
+    }
+    #[tokio::test]
+    async fn v1_tcp6() {
+        let ret = read(b"PROXY TCP6 2001:db8::1 2001:db8::2 56324 443\r\n").await.unwrap();
+        assert_eq!(ret, addrs("[2001:db8::1]:56324", "[2001:db8::2]:443"));
+    }
+    #[tokio::test]
+    async fn v1_unknown() {
+        assert_eq!(read(b"PROXY UNKNOWN\r\n").await.unwrap(), None);
+        assert_eq!(read(b"PROXY UNKNOWN ffff:f...f:ffff ffff:f...f:ffff 65535 65535\r\n").await.unwrap(), None);
+    }
+    #[tokio::test]
+    async fn v1_bad() {
+        assert!(read(b"PROXY TCP4 192.168.0.1 10.0.0.1 56324\r\n").await.is_err());
+        assert!(read(b"PROXY TCP4 192.168.0.1 10.0.0.1 56324 70000\r\n").await.is_err());
+        assert!(read(b"PROXY TCP4 192.168.0.x 10.0.0.1 56324 443\r\n").await.is_err());
+        assert!(read(b"PROXY UDP4 192.168.0.1 10.0.0.1 56324 443\r\n").await.is_err());
+    }
+    #[tokio::test]
+    async fn v1_truncated() {
+        assert!(read(b"PROXY").await.is_err());
+        assert!(read(b"PROXY TCP4 192.168.0.1 10.0.0.1 56324 443").await.is_err());
+        assert!(read(b"PROXY TCP4 192.168.0.1 10.0.0.1 56324 443\r").await.is_err());
+    }
+    #[tokio::test]
+    async fn v1_too_long() {
+        let mut line = b"PROXY UNKNOWN ".to_vec();
+        line.resize(V1_MAX_LEN, b'a');
+        line.extend_from_slice(b"\r\n");
+        assert!(read(&line).await.is_err());
+        /* the longest allowed */
+        let mut line = b"PROXY UNKNOWN ".to_vec();
+        line.resize(V1_MAX_LEN - 2, b'a');
+        line.extend_from_slice(b"\r\n");
+        assert_eq!(read(&line).await.unwrap(), None);
+    }
+    #[tokio::test]
+    async fn v2_tcp4() {
+        let body = [192, 168, 0, 1, 10, 0, 0, 1, 0xdc, 0x04, 0x01, 0xbb];
+        let ret = read(&v2(0x21, 0x11, &body)).await.unwrap();
+        assert_eq!(ret, addrs("192.168.0.1:56324", "10.0.0.1:443"));
+    }
+    #[tokio::test]
+    async fn v2_tcp6() {
+        let src: std::net::Ipv6Addr = "2001:db8::1".parse().unwrap();
+        let dst: std::net::Ipv6Addr = "2001:db8::2".parse().unwrap();
+        let mut body = src.octets().to_vec();
+        body.extend_from_slice(&dst.octets());
+        body.extend_from_slice(&[0xdc, 0x04, 0x01, 0xbb]);
+        let ret = read(&v2(0x21, 0x21, &body)).await.unwrap();
+        assert_eq!(ret, addrs("[2001:db8::1]:56324", "[2001:db8::2]:443"));
+    }
+    #[tokio::test]
+    async fn v2_local_and_unspec() {
+        assert_eq!(read(&v2(0x20, 0x00, &[])).await.unwrap(), None);
+        assert_eq!(read(&v2(0x21, 0x00, &[])).await.unwrap(), None);
+    }
+    #[tokio::test]
+    async fn v2_with_tlvs() {
+        /* the tlvs after the addresses are skipped */
+        let mut body = vec![192, 168, 0, 1, 10, 0, 0, 1, 0xdc, 0x04, 0x01, 0xbb];
+        body.extend_from_slice(&[0x04, 0x00, 0x02, 0xff, 0xff]);
+        let mut data = v2(0x21, 0x11, &body);
+        data.extend_from_slice(b"GET / HTTP/1.1\r\n");
+        let mut data = data.as_slice();
+        let ret = read_header(&mut data).await.unwrap();
+        assert_eq!(ret, addrs("192.168.0.1:56324", "10.0.0.1:443"));
+        assert_eq!(data, b"GET / HTTP/1.1\r\n");
+    }
+    #[tokio::test]
+    async fn v2_bad() {
+        let body = [192, 168, 0, 1, 10, 0, 0, 1, 0xdc, 0x04, 0x01, 0xbb];
+        assert!(read(&v2(0x11, 0x11, &body)).await.is_err());     //version 1
+        assert!(read(&v2(0x22, 0x11, &body)).await.is_err());     //command 2
+        assert!(read(&v2(0x21, 0x21, &body)).await.is_err());     //too short for ipv6
+        assert!(read(&v2(0x21, 0x11, &body[..8])).await.is_err());
+    }
+    #[tokio::test]
+    async fn v2_truncated() {
+        let body = [192, 168, 0, 1, 10, 0, 0, 1, 0xdc, 0x04, 0x01, 0xbb];
+        let data = v2(0x21, 0x11, &body);
+        assert!(read(&data[..10]).await.is_err());
+        assert!(read(&data[..15]).await.is_err());
+        assert!(read(&data[..data.len() - 1]).await.is_err());
+    }
+    #[tokio::test]
+    async fn v2_oversize_length() {
+        /* the length says more than what is sent */
+        let mut data = v2(0x21, 0x11, &[192, 168, 0, 1, 10, 0, 0, 1, 0xdc, 0x04, 0x01, 0xbb]);
+        data[14..16].copy_from_slice(&u16::MAX.to_be_bytes());
+        assert!(read(&data).await.is_err());
+    }
+    #[tokio::test]
+    async fn wrong_signature() {
+        let mut data = v2(0x21, 0x11, &[192, 168, 0, 1, 10, 0, 0, 1, 0xdc, 0x04, 0x01, 0xbb]);
+        data[10] = b'X';
+        assert!(read(&data).await.is_err());
+        assert!(read(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n").await.is_err());
+        assert!(read(b"proxy TCP4 192.168.0.1 10.0.0.1 56324 443\r\n").await.is_err());
+    }
+    #[tokio::test]
+    async fn make_header_reads_back() {
+        for (src, dst) in [("192.168.0.1:56324", "10.0.0.1:443"), ("[2001:db8::1]:56324", "[2001:db8::2]:443")] {
+            let (src, dst): (SocketAddr, SocketAddr) = (src.parse().unwrap(), dst.parse().unwrap());
+            for version in [ProxyVersion::V1, ProxyVersion::V2] {
+                assert_eq!(read(&make_header(version, &src, &dst)).await.unwrap(), Some((src, dst)));
+            }
+        }
+    }
+}
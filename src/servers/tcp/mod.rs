@@ -10,25 +10,41 @@ use std::{sync::Arc, net::SocketAddr};
 
 use crate::error::RResult;
 
-use super::GatewayServerInterface;
+use super::{proxy_protocol::ProxyProtocol, GatewayServerInterface};
 
 
 pub(crate) struct TcpServer {
     pub(crate) addr: SocketAddr,
+    pub(crate) proxy_protocol: Option<ProxyProtocol>,
 }
 impl GatewayServerInterface for TcpServer {
     fn start(&self, gateway: &mut Arc<crate::RockGateway>) -> RResult<()> {
         let addr = self.addr;
         let self_name = self.name().unwrap_or_else(|_|{"tcp".to_string()});
         let gateway_clone = gateway.clone();
+        let proxy_protocol = self.proxy_protocol.clone();
         gateway.spawn(async move {
             let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
             log::info!("{}>accept:{:#?}", self_name, addr);
             loop {
-                let (stream, remote_addr) = listener.accept().await.unwrap();
+                let (mut stream, remote_addr) = listener.accept().await.unwrap();
                 let gateway_clone = gateway_clone.clone();
+                let proxy_protocol = proxy_protocol.clone();
                 tokio::spawn(async move {
-                    match tcp_service::tcp(remote_addr, stream, gateway_clone).await {
+                    let local_addr = stream.local_addr().unwrap_or(addr);
+                    let (remote_addr, local_addr) = match &proxy_protocol {
+                        Some(pp) => {
+                            match pp.accept(&mut stream, remote_addr, local_addr).await {
+                                Ok(addrs) => { addrs }
+                                Err(e) => {
+                                    log::error!("Tcp_Service ERROR:{:#?}", e);
+                                    return;
+                                }
+                            }
+                        }
+                        None => { (remote_addr, local_addr) }
+                    };
+                    match tcp_service::tcp(remote_addr, local_addr, stream, gateway_clone).await {
                         Ok(_) => {}
                         Err(e) => {
                             log::error!("Tcp_Service ERROR:{:#?}", e);
@@ -9,6 +9,6 @@ use tokio::net::TcpStream;
 
 use crate::{servers::{pipeline, TcpResult}, RockGateway};
 
-pub(crate) fn tcp(remote_addr: SocketAddr, stream: TcpStream, gateway: Arc<RockGateway>) -> TcpResult {
-    Box::pin(pipeline::tcp::tcp_run(remote_addr, stream, gateway))
+pub(crate) fn tcp(remote_addr: SocketAddr, local_addr: SocketAddr, stream: TcpStream, gateway: Arc<RockGateway>) -> TcpResult {
+    Box::pin(pipeline::tcp::tcp_run(remote_addr, local_addr, stream, gateway))
 }
\ No newline at end of file
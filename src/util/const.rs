@@ -9,10 +9,18 @@ pub(crate) const BUF_SIZE: usize = 1024;
 
 pub(crate) const BLACK_AND_WHITE_LIST: &'static str = "black_white_list";
 pub(crate) const RATELIMITER: &'static str = "ratelimiter";
+pub(crate) const CONCURRENCY_LIMIT: &'static str = "concurrency_limit";
+pub(crate) const JWT_AUTH: &'static str = "jwt_auth";
+pub(crate) const BASIC_AUTH: &'static str = "basic_auth";
+pub(crate) const API_KEY: &'static str = "api_key";
+pub(crate) const FORWARD_AUTH: &'static str = "forward_auth";
 pub(crate) const MEMORY_CACHE_GET: &'static str = "memory_cache_get";
 pub(crate) const MEMORY_CACHE_SET: &'static str = "memory_cache_set";
 pub(crate) const REDIS_CACHE_GET: &'static str = "redis_cache_get";
 pub(crate) const REDIS_CACHE_SET: &'static str = "redis_cache_set";
+pub(crate) const DISK_CACHE_GET: &'static str = "disk_cache_get";
+pub(crate) const DISK_CACHE_SET: &'static str = "disk_cache_set";
+pub(crate) const CACHE_PURGE: &'static str = "cache_purge";
 pub(crate) const HEADER_REQUEST: &'static str = "header_request";
 pub(crate) const HEADER_RESPONSE: &'static str = "header_response";
 pub(crate) const DISPATCHE: &'static str = "dispatche";
@@ -32,10 +40,36 @@ pub(crate) const DEL: &'static str = "del";
 pub(crate) const TCP: &'static str = "tcp";
 pub(crate) const HTTP: &'static str = "http";
 pub(crate) const HTTPS: &'static str = "https";
+pub(crate) const ADMIN: &'static str = "admin";
 pub(crate) const WEBSOCKET: &'static str = "websocket";
 
 pub(crate) const REDIS_PREFIX: &'static str = "redis_prefix_";
+pub(crate) const VIA_PSEUDONYM: &'static str = "rwp";
 
 pub(crate) const DEFAULT_OUT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(20);
 pub(crate) const HOST_POINT_MAX_FAILS_DURN: std::time::Duration = std::time::Duration::from_secs(60 * 60);
-pub(crate) const MAX_FAILS_DURN_CHECK_KEY: &'static str = "MAX_FAILS_DURN_CHECK_KEY";
\ No newline at end of file
+pub(crate) const MAX_FAILS_DURN_CHECK_KEY: &'static str = "MAX_FAILS_DURN_CHECK_KEY";
+pub(crate) const LIST_WATCH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+pub(crate) const CONCURRENCY_LIMIT_RETRY_AFTER: std::time::Duration = std::time::Duration::from_secs(1);
+pub(crate) const CONCURRENCY_LIMIT_IP_GATES: usize = 4096;
+pub(crate) const AUTH_REALM: &'static str = "rwp";
+pub(crate) const API_KEY_CACHE_TTL: std::time::Duration = std::time::Duration::from_secs(10);
+pub(crate) const API_KEY_CACHE_SIZE: usize = 4096;
+pub(crate) const FORWARD_AUTH_CACHE_SIZE: usize = 4096;
+pub(crate) const MEMORY_CACHE_SHARDS: usize = 16;
+pub(crate) const MEMORY_CACHE_MAX_BYTES: usize = 256 * 1024 * 1024;
+pub(crate) const MEMORY_CACHE_MAX_OBJECT_SIZE: usize = 8 * 1024 * 1024;
+pub(crate) const DISK_CACHE_MAX_OBJECT_SIZE: u64 = 1024 * 1024 * 1024;
+pub(crate) const CACHE_COALESCE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+pub(crate) const REDIS_CACHE_COMPRESS_MIN_SIZE: usize = 1024;
+pub(crate) const REDIS_CACHE_ZSTD_LEVEL: i32 = 3;
+pub(crate) const MEMORY_CACHE_ENTRY_OVERHEAD: usize = 128;      //the bytes an entry takes besides its key, headers and body
+pub(crate) const RATELIMITER_REDIS_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(200);
+pub(crate) const PROXY_PROTOCOL_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+pub(crate) const BREAKER_WINDOW: std::time::Duration = std::time::Duration::from_secs(30);
+pub(crate) const BREAKER_EJECTION_TIME: std::time::Duration = std::time::Duration::from_secs(30);
+pub(crate) const BREAKER_MAX_EJECTION_TIME: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+pub(crate) const BREAKER_MIN_REQUESTS: u32 = 20;     //so that a single failure of a quiet window does not reach the error_rate
+pub(crate) const FILE_SERVER_MAX_RANGES: usize = 16;                 //a Range of more parts is ignored, the whole file is given
+pub(crate) const FILE_SERVER_CHARSET: &'static str = "utf-8";
+pub(crate) const RATELIMITER_CLEAR_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
\ No newline at end of file
@@ -8,5 +8,6 @@ pub mod string_to_number;
 pub mod gateway_info;
 pub mod uri_util;
 pub mod time_unit;
+pub mod size_unit;
 pub mod j_unsafecell;
 pub mod r#const;
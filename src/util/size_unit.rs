@@ -0,0 +1,31 @@
+/*
+This Source Code Form is subject to the terms of the Mozilla Public
+License, v. 2.0. If a copy of the MPL was not distributed with this
+file, You can obtain one at https://mozilla.org/MPL/2.0/.
+*/
+
+/*
+    gigabyte - gb,
+    megabyte - mb,
+    kilobyte - kb,
+    byte - b or nothing
+*/
+pub(crate) struct SizeUnit;
+impl SizeUnit {
+    pub(crate) fn parse(size_str: &str) -> Option<usize> {
+        let size_str = size_str.trim().to_ascii_lowercase();
+        let (number, unit) = match size_str.find(|c: char| !c.is_ascii_digit()) {
+            Some(i) => size_str.split_at(i),
+            None => (size_str.as_str(), ""),
+        };
+        let number = number.parse::<usize>().ok()?;
+        let unit = match unit.trim() {
+            "" | "b" => 1,
+            "kb" | "k" => 1024,
+            "mb" | "m" => 1024 * 1024,
+            "gb" | "g" => 1024 * 1024 * 1024,
+            _ => { return None; }
+        };
+        number.checked_mul(unit)
+    }
+}